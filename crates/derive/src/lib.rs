@@ -1,11 +1,12 @@
 use proc_macro2::{Ident, TokenStream};
 use quote::quote;
 use quote::ToTokens;
-use syn::{parse_macro_input, Attribute, Fields, ItemStruct};
+use syn::{parse_macro_input, Attribute, Fields, GenericArgument, ItemStruct, PathArguments, Type};
 
 enum BinElAttribute {
     Attributes,
     Children,
+    Layout,
     ConvertWith(TokenStream),
     Generate(TokenStream),
     Default(TokenStream),
@@ -26,6 +27,7 @@ impl BinElAttribute {
                 "convert_with" => Some(BinElAttribute::ConvertWith(attr.tokens.clone())),
                 "default" => Some(BinElAttribute::Default(attr.tokens.clone())),
                 "generate" => Some(BinElAttribute::Generate(attr.tokens.clone())),
+                "layout" => Some(BinElAttribute::Layout),
                 "name" => Some(BinElAttribute::Name(attr.tokens.clone())),
                 "optional" => Some(BinElAttribute::Optional),
                 _ => None,
@@ -43,6 +45,7 @@ impl BinElAttribute {
         convert_with,
         default,
         generate,
+        layout,
         name,
         optional
     )
@@ -63,6 +66,17 @@ pub fn try_from_bin_el(item: proc_macro::TokenStream) -> proc_macro::TokenStream
     let mut field_names = Vec::new();
     let mut into_values = Vec::new();
     let mut name_field = None;
+    let mut object_names = Vec::new();
+    let mut container_names = Vec::new();
+
+    let layout_field = match &input.fields {
+        Fields::Named(named) => named.named.iter().find_map(|field| {
+            BinElAttribute::filter_map_iter(&field.attrs)
+                .any(|attr| matches!(attr, BinElAttribute::Layout))
+                .then(|| field.ident.clone().unwrap())
+        }),
+        _ => None,
+    };
 
     match &input.fields {
         Fields::Named(named) => {
@@ -77,6 +91,7 @@ pub fn try_from_bin_el(item: proc_macro::TokenStream) -> proc_macro::TokenStream
                 let mut generate = TokenStream::new();
                 let mut optional = false;
                 let mut attributes = false;
+                let mut layout = false;
                 let mut convert_with = convert_with.clone();
                 for attr in BinElAttribute::filter_map_iter(&field.attrs) {
                     match attr {
@@ -93,6 +108,7 @@ pub fn try_from_bin_el(item: proc_macro::TokenStream) -> proc_macro::TokenStream
                         BinElAttribute::Optional => optional = true,
                         BinElAttribute::Children => children = true,
                         BinElAttribute::Attributes => attributes = true,
+                        BinElAttribute::Layout => layout = true,
                         BinElAttribute::Generate(call) => generate = call,
                     }
                 }
@@ -100,11 +116,21 @@ pub fn try_from_bin_el(item: proc_macro::TokenStream) -> proc_macro::TokenStream
 
                 if name.is_empty() {
                     name_field = Some(ident);
-                } else {
+                } else if !layout {
                     field_names.push(name.clone());
+                    if !(skip || children || attributes || !generate.is_empty()) {
+                        if is_list(type_) {
+                            container_names.push(name.clone());
+                        } else {
+                            object_names.push(name.clone());
+                        }
+                    }
                 }
+                let kept = layout_field.as_ref().map(|layout| {
+                    quote! { || self.#layout.has(#name) }
+                });
 
-                into_values.push(if skip || !generate.is_empty() {
+                into_values.push(if skip || layout || !generate.is_empty() {
                     None
                 } else if children {
                     Some(quote! {
@@ -115,15 +141,15 @@ pub fn try_from_bin_el(item: proc_macro::TokenStream) -> proc_macro::TokenStream
                     })
                 } else if let Some(default) = &default {
                     Some(quote! {
-                        if self.#ident != #default {
+                        if self.#ident != #default #kept {
                             let serialized_field = <#convert_with>::serialize(&self.#ident);
                             GetAttrOrChild::nested_apply_attr_or_child(&mut binel, #name, serialized_field);
                         }
                     })
                 } else if attributes {
-                    Some(quote!{
-                        for (k, v) in self.#ident.clone().into_iter() {
-                            binel.attributes.insert(k, v.into());
+                    Some(quote! {
+                        for (k, v) in &self.#ident {
+                            binel.attributes.insert(k.clone(), v.into());
                         }
                     })
                 } else if optional {
@@ -147,6 +173,10 @@ pub fn try_from_bin_el(item: proc_macro::TokenStream) -> proc_macro::TokenStream
                     }
                 } else if !generate.is_empty() {
                     generate
+                } else if layout {
+                    quote! {
+                        crate::from_binel::ElementLayout::new(elem, &layout_objects, &layout_containers)
+                    }
                 } else if children {
                     quote! {
                         Vec::try_from_bin_el(elem)?
@@ -208,11 +238,23 @@ pub fn try_from_bin_el(item: proc_macro::TokenStream) -> proc_macro::TokenStream
         quote! {stringify!(#ident)}
     };
 
+    let (object_count, container_count) = (object_names.len(), container_names.len());
+    let layout_names = layout_field.as_ref().map(|_| {
+        quote! {
+            let layout_objects: [&str; #object_count] = [#(#object_names),*];
+            let layout_containers: [&str; #container_count] = [#(#container_names),*];
+        }
+    });
+    let apply_layout = layout_field.map(|layout| {
+        quote! { self.#layout.apply(&mut binel); }
+    });
+
     let impl_ = quote! {
         impl crate::from_binel::TryFromBinEl for #ident {
             fn try_from_bin_el(elem: &crate::binel::BinEl) -> Result<Self, CelesteMapError> {
                 #assertion
                 let names_list = [#(#field_names),*];
+                #layout_names
                 #(let #fields = #field_values;)*
 
                 let struct_ = Self {
@@ -228,6 +270,7 @@ pub fn try_from_bin_el(item: proc_macro::TokenStream) -> proc_macro::TokenStream
                 let mut binel = crate::binel::BinEl::new(#name);
 
                 #(#into_values)*
+                #apply_layout
 
                 binel
             }
@@ -236,3 +279,16 @@ pub fn try_from_bin_el(item: proc_macro::TokenStream) -> proc_macro::TokenStream
 
     proc_macro::TokenStream::from(impl_.into_token_stream())
 }
+
+/// Whether a field holds a list of objects, which are read from the children of one of its own.
+fn is_list(ty: &Type) -> bool {
+    let Type::Path(path) = ty else { return false };
+    let Some(last) = path.path.segments.last() else { return false };
+    match (last.ident.to_string().as_str(), &last.arguments) {
+        ("Vec", _) => true,
+        ("Option", PathArguments::AngleBracketed(args)) => {
+            matches!(args.args.first(), Some(GenericArgument::Type(inner)) if is_list(inner))
+        }
+        _ => false,
+    }
+}
@@ -13,10 +13,12 @@ use std::sync::{Arc, Mutex};
 use arborio_utils::interned::{intern_owned, Interned, InternedMap};
 use arborio_utils::units::*;
 use arborio_utils::vizia::prelude::Canvas;
-use arborio_utils::vizia::vg::{Color, ImageFlags, ImageId, ImageSource, Paint, Path};
+use arborio_utils::vizia::vg::{Color, ImageFlags, ImageId, ImageSource};
 use arborio_walker::{ConfigSource, ConfigSourceTrait};
+use tiny_skia::{ColorU8, Pixmap};
 
 use crate::autotiler::TileReference;
+use crate::render::Renderer;
 
 #[derive(Debug)]
 enum BlobData {
@@ -48,17 +50,72 @@ impl BlobData {
             BlobData::Loaded(res) => *res,
         }
     }
+
+    fn pixmap(&self) -> Result<Pixmap, String> {
+        let (width, height, pixels): (_, _, Box<dyn Iterator<Item = [u8; 4]> + '_>) = match self {
+            BlobData::Waiting(buf) => (
+                buf.width() as u32,
+                buf.height() as u32,
+                Box::new(buf.buf().iter().map(|px| [px.r, px.g, px.b, px.a])),
+            ),
+            BlobData::WaitingEncoded(dat) => (
+                dat.width(),
+                dat.height(),
+                Box::new(dat.pixels().map(|(_, _, px)| px.0)),
+            ),
+            BlobData::Loaded(_) => {
+                return Err("Image has already been sent to the GPU".to_owned());
+            }
+        };
+        let mut pixmap = Pixmap::new(width, height).ok_or("Empty atlas image")?;
+        for (dest, [r, g, b, a]) in pixmap.pixels_mut().iter_mut().zip(pixels) {
+            *dest = ColorU8::from_rgba(r, g, b, a).premultiply();
+        }
+        Ok(pixmap)
+    }
+}
+
+/// One image file of an atlas, which can be drawn either through the GPU or in memory.
+#[derive(Debug)]
+pub struct AtlasImage {
+    data: Mutex<BlobData>, // TODO: we can get rid of this mutex (and BlobData altogether) if we can somehow push image data into opengl at load time
+    pixmap: Mutex<Option<Arc<Pixmap>>>,
+}
+
+impl AtlasImage {
+    fn new(data: BlobData) -> Self {
+        Self {
+            data: Mutex::new(data),
+            pixmap: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn image_id(&self, canvas: &mut Canvas) -> Result<ImageId, String> {
+        Ok(self.data.lock().unwrap().image_id(canvas))
+    }
+
+    /// The image as premultiplied pixels, converted the first time it's asked for. This doesn't
+    /// work once the image has been drawn with femtovg, since that gives the pixels to the GPU.
+    pub(crate) fn pixmap(&self) -> Result<Arc<Pixmap>, String> {
+        let mut pixmap = self.pixmap.lock().unwrap();
+        if let Some(pixmap) = &*pixmap {
+            return Ok(pixmap.clone());
+        }
+        let result = Arc::new(self.data.lock().unwrap().pixmap()?);
+        *pixmap = Some(result.clone());
+        Ok(result)
+    }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct Atlas {
-    blobs: Vec<Arc<Mutex<BlobData>>>, // TODO: we can get rid of this mutex (and BlobData altogether) if we can somehow push image data into opengl at load time
+    blobs: Vec<Arc<AtlasImage>>,
     pub sprites_map: InternedMap<Arc<AtlasSprite>>,
 }
 
 #[derive(Debug)]
 pub struct AtlasSprite {
-    blob: Arc<Mutex<BlobData>>,
+    blob: Arc<AtlasImage>,
     bounding_box: Rect<u16, UnknownUnit>,
     trim_offset: Vector2D<i16, UnknownUnit>,
     untrimmed_size: Size2D<u16, UnknownUnit>,
@@ -120,7 +177,7 @@ impl Atlas {
             .expect("Non-unicode asset path");
 
         self.blobs
-            .push(Arc::new(Mutex::new(BlobData::WaitingEncoded(img))));
+            .push(Arc::new(AtlasImage::new(BlobData::WaitingEncoded(img))));
         self.sprites_map.insert(
             intern_owned(sprite_path),
             Arc::new(AtlasSprite {
@@ -154,10 +211,9 @@ impl Atlas {
         for _ in 0..count {
             let data_file = read_string(&mut reader)? + ".data";
             let data_path = meta_file.with_file_name(data_file);
-            self.blobs
-                .push(Arc::new(Mutex::new(BlobData::Waiting(load_data_file(
-                    config, data_path,
-                )?))));
+            self.blobs.push(Arc::new(AtlasImage::new(BlobData::Waiting(
+                load_data_file(config, data_path)?,
+            ))));
 
             let sprites = reader.read_u16::<LittleEndian>()?;
             for _ in 0..sprites {
@@ -260,7 +316,7 @@ impl MultiAtlas {
     #[allow(clippy::too_many_arguments)]
     pub fn draw_sprite(
         &self,
-        canvas: &mut Canvas,
+        canvas: &mut dyn Renderer,
         sprite_path: &str,
         point: Point2D<f32, UnknownUnit>,
         slice: Option<Rect<f32, UnknownUnit>>,
@@ -302,37 +358,24 @@ impl MultiAtlas {
         // how do we transform the entire fucking atlas to get the rectangle we want to end up inside canvas_rect?
         let atlas_offset = -atlas_center.to_vector().component_mul(scale.to_vector());
 
-        let mut image_blob = sprite.blob.lock().unwrap();
-        let image_id = image_blob.image_id(canvas);
-        let (sx, sy) = canvas.image_size(image_id).unwrap();
-        let paint = Paint::image_tint(
-            image_id,
-            atlas_offset.x,
-            atlas_offset.y,
-            sx as f32 * scale.x,
-            sy as f32 * scale.y,
-            0.0,
-            color,
-        );
-        let mut path = Path::new();
-        path.rect(
-            canvas_rect.min_x(),
-            canvas_rect.min_y(),
-            canvas_rect.width(),
-            canvas_rect.height(),
-        );
         canvas.save();
         canvas.translate(point.x, point.y);
         canvas.rotate(rot.to_radians());
-        canvas.fill_path(&mut path, &paint);
+        let result = canvas.fill_image(
+            canvas_rect,
+            &sprite.blob,
+            atlas_offset.to_point(),
+            scale.to_vector(),
+            color,
+        );
         canvas.restore();
 
-        Ok(())
+        result
     }
 
     pub fn draw_tile(
         &self,
-        canvas: &mut Canvas,
+        canvas: &mut dyn Renderer,
         tile_ref: TileReference,
         ox: f32,
         oy: f32,
@@ -1,5 +1,6 @@
 pub mod atlas_img;
 pub mod autotiler;
+pub mod render;
 
 #[cfg(test)]
 mod tests {
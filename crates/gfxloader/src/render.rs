@@ -0,0 +1,360 @@
+//! Drawing which doesn't care where it ends up: on the GPU through femtovg, or in an image in
+//! memory through tiny-skia, for when there's no display to draw to.
+
+use std::path;
+use tiny_skia::{
+    BlendMode, FillRule, FilterQuality, IntRect, Paint as SkiaPaint, PathBuilder, Pattern, Pixmap,
+    Shader, SpreadMode, Stroke, Transform,
+};
+
+use arborio_utils::units::*;
+use arborio_utils::vizia::prelude::Canvas;
+use arborio_utils::vizia::vg::{Color, Paint, Path};
+
+use crate::atlas_img::AtlasImage;
+
+/// Bezier constant for approximating a quarter of an ellipse.
+const KAPPA: f32 = 0.552_284_8;
+
+/// Something to draw onto. All coordinates are affected by the current transform, which is
+/// pushed and popped by `save` and `restore`.
+pub trait Renderer {
+    fn save(&mut self);
+    fn restore(&mut self);
+    fn translate(&mut self, x: f32, y: f32);
+    /// Clockwise, in radians.
+    fn rotate(&mut self, angle: f32);
+    fn fill_path(&mut self, shape: &Shape, color: Color);
+    /// Strokes are drawn without anti-aliasing so that thin lines stay crisp at pixel scale.
+    fn stroke_path(&mut self, shape: &Shape, color: Color, width: f32);
+    /// Fill `rect` with `image`, scaled by `scale` and with its top left corner at `origin`,
+    /// multiplied by `tint`.
+    fn fill_image(
+        &mut self,
+        rect: Rect<f32, UnknownUnit>,
+        image: &AtlasImage,
+        origin: Point2D<f32, UnknownUnit>,
+        scale: Vector2D<f32, UnknownUnit>,
+        tint: Color,
+    ) -> Result<(), String>;
+}
+
+/// An outline to fill or stroke, built the same way as a femtovg `Path`.
+#[derive(Debug, Clone, Default)]
+pub struct Shape {
+    verbs: Vec<Verb>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Verb {
+    MoveTo(f32, f32),
+    LineTo(f32, f32),
+    BezierTo(f32, f32, f32, f32, f32, f32),
+    Close,
+}
+
+impl Shape {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn move_to(&mut self, x: f32, y: f32) {
+        self.verbs.push(Verb::MoveTo(x, y));
+    }
+
+    pub fn line_to(&mut self, x: f32, y: f32) {
+        self.verbs.push(Verb::LineTo(x, y));
+    }
+
+    pub fn bezier_to(&mut self, c1x: f32, c1y: f32, c2x: f32, c2y: f32, x: f32, y: f32) {
+        self.verbs.push(Verb::BezierTo(c1x, c1y, c2x, c2y, x, y));
+    }
+
+    pub fn close(&mut self) {
+        self.verbs.push(Verb::Close);
+    }
+
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.move_to(x, y);
+        self.line_to(x, y + height);
+        self.line_to(x + width, y + height);
+        self.line_to(x + width, y);
+        self.close();
+    }
+
+    pub fn ellipse(&mut self, cx: f32, cy: f32, rx: f32, ry: f32) {
+        let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+        self.move_to(cx - rx, cy);
+        self.bezier_to(cx - rx, cy + ky, cx - kx, cy + ry, cx, cy + ry);
+        self.bezier_to(cx + kx, cy + ry, cx + rx, cy + ky, cx + rx, cy);
+        self.bezier_to(cx + rx, cy - ky, cx + kx, cy - ry, cx, cy - ry);
+        self.bezier_to(cx - kx, cy - ry, cx - rx, cy - ky, cx - rx, cy);
+        self.close();
+    }
+
+    fn to_femtovg(&self) -> Path {
+        let mut path = Path::new();
+        for verb in &self.verbs {
+            match *verb {
+                Verb::MoveTo(x, y) => path.move_to(x, y),
+                Verb::LineTo(x, y) => path.line_to(x, y),
+                Verb::BezierTo(c1x, c1y, c2x, c2y, x, y) => {
+                    path.bezier_to(c1x, c1y, c2x, c2y, x, y)
+                }
+                Verb::Close => path.close(),
+            }
+        }
+        path
+    }
+
+    fn to_skia(&self) -> Option<tiny_skia::Path> {
+        let mut path = PathBuilder::new();
+        for verb in &self.verbs {
+            match *verb {
+                Verb::MoveTo(x, y) => path.move_to(x, y),
+                Verb::LineTo(x, y) => path.line_to(x, y),
+                Verb::BezierTo(c1x, c1y, c2x, c2y, x, y) => path.cubic_to(c1x, c1y, c2x, c2y, x, y),
+                Verb::Close => path.close(),
+            }
+        }
+        path.finish()
+    }
+}
+
+impl Renderer for Canvas {
+    fn save(&mut self) {
+        Canvas::save(self);
+    }
+
+    fn restore(&mut self) {
+        Canvas::restore(self);
+    }
+
+    fn translate(&mut self, x: f32, y: f32) {
+        Canvas::translate(self, x, y);
+    }
+
+    fn rotate(&mut self, angle: f32) {
+        Canvas::rotate(self, angle);
+    }
+
+    fn fill_path(&mut self, shape: &Shape, color: Color) {
+        Canvas::fill_path(self, &mut shape.to_femtovg(), &Paint::color(color));
+    }
+
+    fn stroke_path(&mut self, shape: &Shape, color: Color, width: f32) {
+        let mut paint = Paint::color(color);
+        paint.set_line_width(width);
+        paint.set_anti_alias(false);
+        Canvas::stroke_path(self, &mut shape.to_femtovg(), &paint);
+    }
+
+    fn fill_image(
+        &mut self,
+        rect: Rect<f32, UnknownUnit>,
+        image: &AtlasImage,
+        origin: Point2D<f32, UnknownUnit>,
+        scale: Vector2D<f32, UnknownUnit>,
+        tint: Color,
+    ) -> Result<(), String> {
+        let image_id = image.image_id(self)?;
+        let (width, height) = self.image_size(image_id).map_err(|e| format!("{e:?}"))?;
+        let paint = Paint::image_tint(
+            image_id,
+            origin.x,
+            origin.y,
+            width as f32 * scale.x,
+            height as f32 * scale.y,
+            0.0,
+            tint,
+        );
+        let mut path = Path::new();
+        path.rect(rect.min_x(), rect.min_y(), rect.width(), rect.height());
+        Canvas::fill_path(self, &mut path, &paint);
+        Ok(())
+    }
+}
+
+/// Draws into an image in memory, with no GPU involved.
+pub struct PixmapRenderer {
+    pub pixmap: Pixmap,
+    transform: Transform2D<f32, UnknownUnit, UnknownUnit>,
+    saved: Vec<Transform2D<f32, UnknownUnit, UnknownUnit>>,
+}
+
+impl PixmapRenderer {
+    /// A transparent image of the given size.
+    pub fn new(width: u32, height: u32) -> Result<Self, String> {
+        Ok(Self {
+            pixmap: Pixmap::new(width, height)
+                .ok_or_else(|| format!("Cannot make a {width}x{height} image"))?,
+            transform: Transform2D::identity(),
+            saved: vec![],
+        })
+    }
+
+    pub fn save_png(&self, path: &path::Path) -> Result<(), String> {
+        self.pixmap
+            .save_png(path)
+            .map_err(|e| format!("Could not write {}: {e}", path.display()))
+    }
+
+    fn skia_transform(&self) -> Transform {
+        let t = &self.transform;
+        Transform::from_row(t.m11, t.m12, t.m21, t.m22, t.m31, t.m32)
+    }
+}
+
+impl Renderer for PixmapRenderer {
+    fn save(&mut self) {
+        self.saved.push(self.transform);
+    }
+
+    fn restore(&mut self) {
+        if let Some(transform) = self.saved.pop() {
+            self.transform = transform;
+        }
+    }
+
+    fn translate(&mut self, x: f32, y: f32) {
+        self.transform = Transform2D::translation(x, y).then(&self.transform);
+    }
+
+    fn rotate(&mut self, angle: f32) {
+        self.transform = Transform2D::rotation(Angle::radians(angle)).then(&self.transform);
+    }
+
+    fn fill_path(&mut self, shape: &Shape, color: Color) {
+        let Some(path) = shape.to_skia() else { return };
+        let mut paint = SkiaPaint::default();
+        paint.set_color(skia_color(color));
+        let transform = self.skia_transform();
+        self.pixmap
+            .fill_path(&path, &paint, FillRule::Winding, transform, None);
+    }
+
+    fn stroke_path(&mut self, shape: &Shape, color: Color, width: f32) {
+        // femtovg draws nothing for zero-width strokes, but tiny-skia would draw a hairline
+        if width <= 0.0 {
+            return;
+        }
+        let Some(path) = shape.to_skia() else { return };
+        let mut paint = SkiaPaint::default();
+        paint.set_color(skia_color(color));
+        paint.anti_alias = false;
+        let stroke = Stroke {
+            width,
+            ..Stroke::default()
+        };
+        let transform = self.skia_transform();
+        self.pixmap
+            .stroke_path(&path, &paint, &stroke, transform, None);
+    }
+
+    fn fill_image(
+        &mut self,
+        rect: Rect<f32, UnknownUnit>,
+        image: &AtlasImage,
+        origin: Point2D<f32, UnknownUnit>,
+        scale: Vector2D<f32, UnknownUnit>,
+        tint: Color,
+    ) -> Result<(), String> {
+        // flipped sprites come through with a negative size
+        let (min, max) = (rect.origin, rect.origin + rect.size.to_vector());
+        let Some(dest) = tiny_skia::Rect::from_ltrb(
+            min.x.min(max.x),
+            min.y.min(max.y),
+            min.x.max(max.x),
+            min.y.max(max.y),
+        ) else {
+            return Ok(());
+        };
+        let source = image.pixmap()?;
+
+        // tiny-skia patterns can't be tinted, so tint a copy of the part of the image in use
+        let tinted;
+        let (pixmap, offset) = if tint.r == 1.0 && tint.g == 1.0 && tint.b == 1.0 && tint.a == 1.0 {
+            (&*source, Vector2D::zero())
+        } else {
+            let corners = [(dest.left(), dest.top()), (dest.right(), dest.bottom())]
+                .map(|(x, y)| ((x - origin.x) / scale.x, (y - origin.y) / scale.y));
+            let left = corners[0].0.min(corners[1].0).floor().max(0.0) as i32;
+            let top = corners[0].1.min(corners[1].1).floor().max(0.0) as i32;
+            let right = (corners[0].0.max(corners[1].0).ceil() as i32).min(source.width() as i32);
+            let bottom = (corners[0].1.max(corners[1].1).ceil() as i32).min(source.height() as i32);
+            let Some(part) = IntRect::from_ltrb(left, top, right, bottom)
+                .and_then(|part| source.clone_rect(part))
+            else {
+                return Ok(());
+            };
+            tinted = tint_pixmap(part, tint);
+            (&tinted, Vector2D::new(left as f32, top as f32))
+        };
+
+        let pattern = Pattern::new(
+            pixmap.as_ref(),
+            SpreadMode::Pad,
+            FilterQuality::Nearest,
+            1.0,
+            Transform::from_row(
+                scale.x,
+                0.0,
+                0.0,
+                scale.y,
+                origin.x + offset.x * scale.x,
+                origin.y + offset.y * scale.y,
+            ),
+        );
+        let paint = SkiaPaint {
+            shader: pattern,
+            anti_alias: false,
+            ..SkiaPaint::default()
+        };
+        let transform = self.skia_transform();
+        self.pixmap.fill_rect(dest, &paint, transform, None);
+        Ok(())
+    }
+}
+
+fn skia_color(color: Color) -> tiny_skia::Color {
+    tiny_skia::Color::from_rgba(
+        color.r.clamp(0.0, 1.0),
+        color.g.clamp(0.0, 1.0),
+        color.b.clamp(0.0, 1.0),
+        color.a.clamp(0.0, 1.0),
+    )
+    .unwrap_or(tiny_skia::Color::TRANSPARENT)
+}
+
+/// Multiply every pixel by `tint`, the way femtovg's image tint does.
+fn tint_pixmap(mut pixmap: Pixmap, tint: Color) -> Pixmap {
+    let rect = tiny_skia::Rect::from_xywh(0.0, 0.0, pixmap.width() as f32, pixmap.height() as f32)
+        .unwrap();
+    let paint = SkiaPaint {
+        shader: Shader::SolidColor(skia_color(tint)),
+        blend_mode: BlendMode::Modulate,
+        ..SkiaPaint::default()
+    };
+    pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+    pixmap
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shapes_fill_the_right_pixels() {
+        let mut renderer = PixmapRenderer::new(16, 16).unwrap();
+        renderer.translate(4.0, 4.0);
+        let mut shape = Shape::new();
+        shape.rect(0.0, 0.0, 8.0, 8.0);
+        renderer.fill_path(&shape, Color::rgb(255, 0, 0));
+
+        let pixel = |x, y| renderer.pixmap.pixel(x, y).unwrap();
+        assert_eq!(pixel(3, 3).alpha(), 0);
+        assert_eq!(pixel(4, 4).red(), 255);
+        assert_eq!(pixel(11, 11).red(), 255);
+        assert_eq!(pixel(12, 12).alpha(), 0);
+    }
+}
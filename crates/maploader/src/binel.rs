@@ -1,12 +1,16 @@
 pub mod parser;
 pub mod writer;
+pub mod xml;
 
-use std::collections::HashMap;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
 
 /// This module is a moderately modified copy of much of the source of the now unmaintained celeste crate, by leo60228
 
 /// A value stored in an attribute inside a `BinEl`. Unlike XML, attributes are strongly typed.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum BinElAttr {
     Bool(bool),
     Int(i32),
@@ -14,49 +18,88 @@ pub enum BinElAttr {
     Text(String),
 }
 
+impl fmt::Display for BinElAttr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BinElAttr::Bool(b) => write!(f, "{b}"),
+            BinElAttr::Int(i) => write!(f, "{i}"),
+            BinElAttr::Float(x) => write!(f, "{x}"),
+            BinElAttr::Text(t) => write!(f, "{t:?}"),
+        }
+    }
+}
+
 /// An element stored in a `BinFile`. Based on XML.
-#[derive(PartialEq, Debug, Clone, Default)]
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
 pub struct BinEl {
     /// The name of the `BinEl`.
     pub name: String,
-    /// All attributes of the `BinEl`. Unlike XML, these are strongly typed.
-    pub attributes: HashMap<String, BinElAttr>,
-    children: HashMap<String, Vec<BinEl>>,
+    /// All attributes of the `BinEl`, in the order they are written. Unlike XML, these are
+    /// strongly typed.
+    pub attributes: IndexMap<String, BinElAttr>,
+    /// The text attributes which are stored with Celeste's run-length encoding.
+    pub rle_attributes: HashSet<String>,
+    children: Vec<BinEl>,
 }
 
 impl BinEl {
     pub fn new(name: &str) -> Self {
         Self {
             name: name.to_owned(),
-            attributes: HashMap::new(),
-            children: HashMap::new(),
+            attributes: IndexMap::new(),
+            rle_attributes: HashSet::new(),
+            children: Vec::new(),
         }
     }
 
     pub(crate) fn insert(&mut self, child: Self) {
-        self.children
-            .entry(child.name.clone())
-            .or_default()
-            .push(child);
+        self.children.push(child);
     }
 
     pub(crate) fn children(&self) -> impl Iterator<Item = &BinEl> {
-        self.children.values().flatten()
+        self.children.iter()
+    }
+
+    pub(crate) fn children_mut(&mut self) -> impl Iterator<Item = &mut BinEl> {
+        self.children.iter_mut()
     }
 
-    pub(crate) fn get(&self, key: &str) -> &[Self] {
-        self.children
-            .get(key)
-            .map(AsRef::as_ref)
-            .unwrap_or_default()
+    pub(crate) fn retain_children(&mut self, f: impl FnMut(&BinEl) -> bool) {
+        self.children.retain(f);
     }
 
-    pub(crate) fn get_mut(&mut self, key: &str) -> &mut Vec<Self> {
-        self.children.entry(key.to_owned()).or_default()
+    pub(crate) fn get<'a: 'k, 'k>(&'a self, key: &'k str) -> impl Iterator<Item = &'a Self> + 'k {
+        self.children.iter().filter(move |child| child.name == key)
+    }
+
+    /// Replace all children named `key` with `child`, keeping the position of the first one.
+    pub(crate) fn set(&mut self, key: &str, child: Self) {
+        match self.children.iter().position(|c| c.name == key) {
+            Some(index) => {
+                self.children[index] = child;
+                let mut position = 0;
+                self.children.retain(|c| {
+                    position += 1;
+                    position <= index + 1 || c.name != key
+                });
+            }
+            None => self.children.push(child),
+        }
+    }
+
+    pub(crate) fn get_or_insert(&mut self, key: &str) -> &mut Self {
+        let index = match self.children.iter().position(|c| c.name == key) {
+            Some(index) => index,
+            None => {
+                self.children.push(BinEl::new(key));
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[index]
     }
 
     pub fn drain(&mut self) -> impl Iterator<Item = BinEl> + '_ {
-        self.children.drain().flat_map(|(_, v)| v)
+        self.children.drain(..)
     }
 
     pub fn text(&self) -> Option<&str> {
@@ -72,4 +115,7 @@ impl BinEl {
 pub struct BinFile {
     pub package: String,
     pub root: BinEl,
+    /// The string lookup table the file was read with. Writing starts from this table so that an
+    /// unchanged file is written back byte for byte; it may be left empty for new files.
+    pub lookup: Vec<String>,
 }
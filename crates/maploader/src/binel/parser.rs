@@ -1,15 +1,132 @@
 use nom::{
     bytes::complete::{tag, take},
-    error::{Error, ErrorKind, ParseError},
+    error::{ErrorKind, ParseError as NomParseError},
     multi::{count, length_count},
     number::complete::{le_f32, le_i16, le_i32, le_u16, le_u8},
     sequence::pair,
-    IResult, Parser,
+    Parser,
 };
 use nom_varint::take_varint;
+use std::collections::HashMap;
+use std::fmt;
 
 use crate::binel::{BinEl, BinElAttr, BinFile};
 
+/// What went wrong while parsing a `BinFile`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorCause {
+    /// The file doesn't start with the `CELESTE MAP` header.
+    NotAMap,
+    /// The file ends in the middle of a value.
+    Truncated,
+    /// A string isn't valid utf-8.
+    InvalidString,
+    /// A string refers to an entry past the end of the string lookup table.
+    LookupOutOfRange { index: u16, len: usize },
+    /// An attribute has a type tag which doesn't exist.
+    UnknownAttrType(u8),
+}
+
+impl fmt::Display for ParseErrorCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorCause::NotAMap => write!(f, "Not a Celeste map"),
+            ParseErrorCause::Truncated => write!(f, "Unexpected end of file"),
+            ParseErrorCause::InvalidString => write!(f, "String is not valid utf-8"),
+            ParseErrorCause::LookupOutOfRange { index, len } => write!(
+                f,
+                "String index {index} is out of range of the lookup table ({len} entries)"
+            ),
+            ParseErrorCause::UnknownAttrType(elem_tag) => {
+                write!(f, "Unknown attribute type {elem_tag}")
+            }
+        }
+    }
+}
+
+/// A failure to parse a `BinFile`, with where in the file it happened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The offset in bytes from the start of the file.
+    pub offset: usize,
+    /// The path to the element or attribute which couldn't be parsed, such as
+    /// `Map/levels/level[3]/entities` or `Map/levels/level[3]/@name`. Elements are indexed among
+    /// their siblings of the same name, and the index is left out for the first one.
+    pub path: String,
+    pub cause: ParseErrorCause,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{} at byte {}", self.cause, self.offset)
+        } else {
+            write!(f, "{} at byte {} in {}", self.cause, self.offset, self.path)
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// The error produced by the individual parsers. It refers to the remaining input where the error
+/// happened and is turned into a `ParseError` once the whole input is known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawParseError<'a> {
+    pub input: &'a [u8],
+    pub cause: ParseErrorCause,
+    /// The path to the failed element, innermost segment first.
+    pub path: Vec<String>,
+}
+
+impl<'a> RawParseError<'a> {
+    fn new(input: &'a [u8], cause: ParseErrorCause) -> Self {
+        Self {
+            input,
+            cause,
+            path: vec![],
+        }
+    }
+
+    /// Resolve the position of the error, given the input the parse started from.
+    pub fn at(self, input: &[u8]) -> ParseError {
+        ParseError {
+            offset: input.len() - self.input.len(),
+            path: self.path.into_iter().rev().collect::<Vec<_>>().join("/"),
+            cause: self.cause,
+        }
+    }
+}
+
+impl<'a> NomParseError<&'a [u8]> for RawParseError<'a> {
+    fn from_error_kind(input: &'a [u8], kind: ErrorKind) -> Self {
+        let cause = match kind {
+            ErrorKind::Tag => ParseErrorCause::NotAMap,
+            _ => ParseErrorCause::Truncated,
+        };
+        Self::new(input, cause)
+    }
+
+    fn append(_input: &'a [u8], _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+pub type IResult<'a, T> = nom::IResult<&'a [u8], T, RawParseError<'a>>;
+
+fn fail<T>(input: &[u8], cause: ParseErrorCause) -> IResult<'_, T> {
+    Err(nom::Err::Error(RawParseError::new(input, cause)))
+}
+
+/// Add a segment to the path of an error coming out of a parser.
+fn in_path<'a, T>(result: IResult<'a, T>, segment: impl FnOnce() -> String) -> IResult<'a, T> {
+    result.map_err(|e| {
+        e.map(|mut e| {
+            e.path.push(segment());
+            e
+        })
+    })
+}
+
 /// Take a string with the length being a varint.
 ///
 /// # Examples:
@@ -20,17 +137,31 @@ use crate::binel::{BinEl, BinElAttr, BinFile};
 ///
 /// assert_eq!(take_string(&header[..]), Ok((&b""[..], "CELESTE MAP".to_string())));
 /// ```
-pub fn take_str(input: &[u8]) -> IResult<&[u8], &str> {
-    let (input, length) = take_varint(input)?;
-    take(length)
-        .and_then(|s| match std::str::from_utf8(s) {
-            Ok(s) => Ok((&[] as &[u8], s)),
-            Err(_) => Err(nom::Err::Error(Error::from_error_kind(s, ErrorKind::Fail))),
+pub fn take_str(input: &[u8]) -> IResult<'_, &str> {
+    let (input, length) = match take_varint(input) {
+        Ok(result) => result,
+        Err(_) => return fail(input, ParseErrorCause::Truncated),
+    };
+    let (rest, s) = take(length)(input)?;
+    match std::str::from_utf8(s) {
+        Ok(s) => Ok((rest, s)),
+        Err(_) => fail(input, ParseErrorCause::InvalidString),
+    }
+}
+
+/// Parse a whole `BinFile`. Anything after the root element is ignored.
+pub fn take_file(input: &[u8]) -> Result<BinFile, ParseError> {
+    take_file_raw(input)
+        .map(|(_, file)| file)
+        .map_err(|e| match e {
+            nom::Err::Error(e) | nom::Err::Failure(e) => e.at(input),
+            nom::Err::Incomplete(_) => {
+                RawParseError::new(&[], ParseErrorCause::Truncated).at(input)
+            }
         })
-        .parse(input)
 }
 
-pub fn take_file(input: &[u8]) -> IResult<&[u8], BinFile> {
+fn take_file_raw(input: &[u8]) -> IResult<'_, BinFile> {
     let (input, _) = tag(b"\x0bCELESTE MAP")(input)?;
     let (input, package) = take_str(input)?;
     let (input, lookup) = length_count(le_u16, take_str)(input)?;
@@ -40,48 +171,77 @@ pub fn take_file(input: &[u8]) -> IResult<&[u8], BinFile> {
         BinFile {
             package: package.to_owned(),
             root,
+            lookup: lookup.into_iter().map(str::to_owned).collect(),
         },
     ))
 }
 
 /// Parse a `BinEl` from a `&[u8]`. Tested solely in integration tests due to complexity.
-pub fn take_element<'a>(buf: &'a [u8], lookup: &[&'a str]) -> IResult<&'a [u8], BinEl> {
+pub fn take_element<'a>(buf: &'a [u8], lookup: &[&'a str]) -> IResult<'a, BinEl> {
     let (buf, name) = take_lookup(buf, lookup)?;
+    in_path(take_element_body(buf, lookup, name), || name.to_owned())
+}
 
+/// Parse the rest of a `BinEl` once its name is known.
+fn take_element_body<'a>(buf: &'a [u8], lookup: &[&'a str], name: &str) -> IResult<'a, BinEl> {
     let mut binel = BinEl::new(name);
 
-    // let (mut buf, attr_count) = le_u8(buf)?;
-
-    let (buf, attrs) = length_count(
-        le_u8,
-        pair((|buf| take_lookup(buf, lookup)).map(str::to_owned), |buf| {
-            take_elemattr(buf, lookup)
-        }),
-    )(buf)?;
-
-    binel.attributes.extend(attrs);
-
-    let (buf, children) = length_count(le_u16, |buf| take_element(buf, lookup))(buf)?;
+    let (mut buf, attr_count) = le_u8(buf)?;
+    for _ in 0..attr_count {
+        let (rest, attr_name) = take_lookup(buf, lookup)?;
+        let rle = rest.first() == Some(&7);
+        let (rest, attr) = in_path(take_elemattr(rest, lookup), || format!("@{attr_name}"))?;
+        if rle {
+            binel.rle_attributes.insert(attr_name.to_owned());
+        }
+        binel.attributes.insert(attr_name.to_owned(), attr);
+        buf = rest;
+    }
 
-    children.into_iter().for_each(|child| binel.insert(child));
+    let (mut buf, child_count) = le_u16(buf)?;
+    let mut seen = HashMap::new();
+    for _ in 0..child_count {
+        let (rest, child_name) = take_lookup(buf, lookup)?;
+        let index = seen.entry(child_name).or_insert(0);
+        let (rest, child) = in_path(take_element_body(rest, lookup, child_name), || {
+            if *index == 0 {
+                child_name.to_owned()
+            } else {
+                format!("{child_name}[{index}]")
+            }
+        })?;
+        *index += 1;
+        binel.insert(child);
+        buf = rest;
+    }
 
     Ok((buf, binel))
 }
 
 /// Lookup a u16 from a `&[u8]` in a string lookup table.
-pub fn take_lookup<'a, 'b>(buf: &'a [u8], lookup: &[&'b str]) -> IResult<&'a [u8], &'b str> {
-    le_u16.map(|index| lookup[index as usize]).parse(buf)
+pub fn take_lookup<'a, 'b>(buf: &'a [u8], lookup: &[&'b str]) -> IResult<'a, &'b str> {
+    let (rest, index) = le_u16(buf)?;
+    match lookup.get(index as usize) {
+        Some(s) => Ok((rest, s)),
+        None => fail(
+            buf,
+            ParseErrorCause::LookupOutOfRange {
+                index,
+                len: lookup.len(),
+            },
+        ),
+    }
 }
 
 /// Take a Celeste RLE-encoded string from a `&[u8]`
-pub fn take_rle_string(buf: &[u8]) -> IResult<&[u8], String> {
+pub fn take_rle_string(buf: &[u8]) -> IResult<'_, String> {
     let (buf, len) = le_i16(buf)?;
-    let (buf, chars) = count(take_rle_char, len as usize / 2)(buf)?;
+    let (buf, chars) = count(take_rle_char, len.max(0) as usize / 2)(buf)?;
     Ok((buf, chars.concat()))
 }
 
 /// Take a single character from a Celeste RLE-encoded string in a `&[u8]`.
-pub fn take_rle_char(buf: &[u8]) -> IResult<&[u8], String> {
+pub fn take_rle_char(buf: &[u8]) -> IResult<'_, String> {
     pair(le_u8, le_u8)
         .map(|(times, byte)| char::from(byte).to_string().repeat(times as usize))
         .parse(buf)
@@ -96,20 +256,46 @@ pub fn take_rle_char(buf: &[u8]) -> IResult<&[u8], String> {
 /// assert_eq!(parser::take_elemattr(b"\x01\x05", &[]), Ok((&b""[..], BinElAttr::Int(5))));
 /// ```
 #[allow(clippy::cognitive_complexity)]
-pub fn take_elemattr<'a>(buf: &'a [u8], lookup: &[&'a str]) -> IResult<&'a [u8], BinElAttr> {
-    let (buf, elem_tag) = le_u8(buf)?;
+pub fn take_elemattr<'a>(buf: &'a [u8], lookup: &[&'a str]) -> IResult<'a, BinElAttr> {
+    let (rest, elem_tag) = le_u8(buf)?;
     match elem_tag {
-        0 => le_u8.map(|byte| byte != 0).map(BinElAttr::Bool).parse(buf),
-        1 => le_u8.map(i32::from).map(BinElAttr::Int).parse(buf),
-        2 => le_i16.map(i32::from).map(BinElAttr::Int).parse(buf),
-        3 => le_i32.map(BinElAttr::Int).parse(buf),
-        4 => le_f32.map(BinElAttr::Float).parse(buf),
+        0 => le_u8.map(|byte| byte != 0).map(BinElAttr::Bool).parse(rest),
+        1 => le_u8.map(i32::from).map(BinElAttr::Int).parse(rest),
+        2 => le_i16.map(i32::from).map(BinElAttr::Int).parse(rest),
+        3 => le_i32.map(BinElAttr::Int).parse(rest),
+        4 => le_f32.map(BinElAttr::Float).parse(rest),
         5 => (|input| take_lookup(input, lookup))
             .map(str::to_owned)
             .map(BinElAttr::Text)
-            .parse(buf),
-        6 => take_str.map(str::to_owned).map(BinElAttr::Text).parse(buf),
-        7 => take_rle_string.map(BinElAttr::Text).parse(buf),
-        _ => todo!(),
+            .parse(rest),
+        6 => take_str.map(str::to_owned).map(BinElAttr::Text).parse(rest),
+        7 => take_rle_string.map(BinElAttr::Text).parse(rest),
+        _ => fail(buf, ParseErrorCause::UnknownAttrType(elem_tag)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn errors_have_paths() {
+        let mut file = b"\x0bCELESTE MAP\x04test\x03\x00\x03Map\x06levels\x05level".to_vec();
+        // Map with one child
+        file.extend([0, 0, 0, 1, 0]);
+        // levels with two children
+        file.extend([1, 0, 0, 2, 0]);
+        // a good level, then one with an attribute of an unknown type
+        file.extend([2, 0, 0, 0, 0]);
+        file.extend([2, 0, 1, 0, 0, 9]);
+
+        let err = take_file(&file).unwrap_err();
+        assert_eq!(err.cause, ParseErrorCause::UnknownAttrType(9));
+        assert_eq!(err.path, "Map/levels/level[1]/@Map");
+        assert_eq!(err.offset, file.len() - 1);
+
+        let err = take_file(&file[..file.len() - 3]).unwrap_err();
+        assert_eq!(err.cause, ParseErrorCause::Truncated);
+        assert_eq!(err.path, "Map/levels/level[1]");
     }
 }
@@ -1,9 +1,7 @@
 use byteorder::{LittleEndian, WriteBytesExt};
+use indexmap::IndexSet;
 use itertools::Itertools;
-use std::{
-    collections::HashMap,
-    io::{prelude::*, Error, ErrorKind},
-};
+use std::io::{prelude::*, Error, ErrorKind};
 
 use crate::binel::{BinEl, BinElAttr, BinFile};
 
@@ -62,17 +60,39 @@ pub fn put_tagged_f32(writer: &mut dyn Write, val: f32) -> std::io::Result<()> {
     Ok(())
 }
 
-/// Encode a string in Celeste's RLE format. Allocates two bytes on the heap due to a current limitation of iterators.
-pub fn encode_rle_string(string: &str) -> Vec<u8> {
-    string
-        .bytes()
-        .group_by(|e| *e)
-        .into_iter()
-        .flat_map(|(ch, run)| vec![run.count() as u8, ch])
-        .collect() // rust#25725
+/// Encode a string in Celeste's RLE format. Returns `None` if the string contains characters which
+/// don't fit in a single byte, since the format can't represent them.
+pub fn encode_rle_string(string: &str) -> Option<Vec<u8>> {
+    let mut result = vec![];
+    for (ch, run) in &string.chars().group_by(|ch| *ch) {
+        let byte = u8::try_from(u32::from(ch)).ok()?;
+        let mut count = run.count();
+        while count > 0 {
+            let chunk = count.min(u8::MAX as usize);
+            result.push(chunk as u8);
+            result.push(byte);
+            count -= chunk;
+        }
+    }
+    Some(result)
 }
 
-/// Write a string either using a lookup (stored as u16, tagged with 0x05), Celeste's RLE format (tagged with 0x07), or using a varint (tagged with 0x06).
+/// Write a string in Celeste's RLE format, tagged with 0x07.
+pub fn put_tagged_rle_str(writer: &mut dyn Write, rle: &[u8]) -> std::io::Result<()> {
+    let Ok(len) = i16::try_from(rle.len()) else {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "String is too long to be RLE-encoded",
+        ));
+    };
+    writer.write_u8(0x07)?;
+    writer.write_i16::<LittleEndian>(len)?;
+    writer.write_all(rle)?;
+
+    Ok(())
+}
+
+/// Write a string either using a lookup (stored as u16, tagged with 0x05) or using a varint (tagged with 0x06).
 pub fn put_tagged_str(
     mut writer: &mut dyn Write,
     lookup: &[&str],
@@ -82,16 +102,8 @@ pub fn put_tagged_str(
         writer.write_u8(0x05)?;
         writer.write_u16::<LittleEndian>(index as u16)?;
     } else {
-        let rle = encode_rle_string(val);
-
-        if rle.len() < val.len() && rle.len() <= i16::max_value() as usize {
-            writer.write_u8(0x07)?;
-            writer.write_i16::<LittleEndian>(rle.len() as i16)?;
-            writer.write_all(&rle)?;
-        } else {
-            writer.write_u8(0x06)?;
-            put_string(&mut writer, val)?;
-        }
+        writer.write_u8(0x06)?;
+        put_string(&mut writer, val)?;
     }
 
     Ok(())
@@ -125,7 +137,16 @@ pub fn put_element(
             BinElAttr::Bool(val) => put_tagged_bool(&mut writer, *val)?,
             BinElAttr::Int(val) => put_tagged_int(&mut writer, *val)?,
             BinElAttr::Float(val) => put_tagged_f32(&mut writer, *val)?,
-            BinElAttr::Text(val) => put_tagged_str(&mut writer, lookup, val)?,
+            BinElAttr::Text(val) => match encode_rle_string(val) {
+                Some(rle)
+                    if elem.rle_attributes.contains(attr) && rle.len() <= i16::MAX as usize =>
+                {
+                    put_tagged_rle_str(&mut writer, &rle)?
+                }
+                // innerText is kept out of the lookup, since it is almost always unique
+                _ if attr == "innerText" => put_tagged_str(&mut writer, &[], val)?,
+                _ => put_tagged_str(&mut writer, lookup, val)?,
+            },
         }
     }
 
@@ -137,14 +158,14 @@ pub fn put_element(
     Ok(())
 }
 
-fn gen_lookup_keys<'a>(binel: &'a BinEl, seen: &mut HashMap<&'a str, usize>) {
-    seen.insert(&binel.name, seen.get(binel.name.as_str()).unwrap_or(&0) + 1);
+fn gen_lookup_keys<'a>(binel: &'a BinEl, seen: &mut IndexSet<&'a str>) {
+    seen.insert(&binel.name);
 
     for (k, v) in &binel.attributes {
-        *seen.entry(k).or_default() += 1;
-        if k != "innerText" {
+        seen.insert(k);
+        if k != "innerText" && !binel.rle_attributes.contains(k) {
             if let BinElAttr::Text(text) = v {
-                *seen.entry(text).or_default() += 1;
+                seen.insert(text);
             }
         }
     }
@@ -154,13 +175,12 @@ fn gen_lookup_keys<'a>(binel: &'a BinEl, seen: &mut HashMap<&'a str, usize>) {
     }
 }
 
-/// Generate a string lookup using the attributes and element names in a `BinEl`.
-pub fn gen_lookup(binel: &BinEl) -> Vec<&str> {
-    let mut seen = HashMap::new();
+/// Generate a string lookup using the attributes and element names in a `BinEl`. The lookup
+/// starts with `existing` and has any strings missing from it appended in the order they appear.
+pub fn gen_lookup<'a>(binel: &'a BinEl, existing: &'a [String]) -> Vec<&'a str> {
+    let mut seen = existing.iter().map(String::as_str).collect();
     gen_lookup_keys(binel, &mut seen);
-    let mut vec = seen.into_iter().collect::<Vec<(&str, usize)>>();
-    vec.sort_unstable_by_key(|e| e.1);
-    vec.into_iter().rev().map(|e| e.0).collect()
+    seen.into_iter().collect()
 }
 
 /// Write a `BinFile`. Tested solely in integration tests due to complexity.
@@ -169,7 +189,7 @@ pub fn put_file(mut writer: &mut dyn Write, bin: &BinFile) -> std::io::Result<()
 
     put_string(&mut writer, &bin.package)?;
 
-    let lookup = gen_lookup(&bin.root);
+    let lookup = gen_lookup(&bin.root, &bin.lookup);
 
     writer.write_i16::<LittleEndian>(lookup.len() as i16)?;
 
@@ -194,4 +214,34 @@ mod test {
 
         assert_eq!(&buf.get_ref()[..], b"\x0bCELESTE MAP");
     }
+
+    #[test]
+    fn rewrite_is_byte_identical() {
+        use crate::binel::{parser, BinEl, BinElAttr, BinFile};
+
+        let mut root = BinEl::new("Map");
+        for name in ["b", "a", "b"] {
+            let mut child = BinEl::new(name);
+            child
+                .attributes
+                .insert("z".to_owned(), BinElAttr::Text("text".to_owned()));
+            child.attributes.insert("y".to_owned(), BinElAttr::Int(300));
+            root.insert(child);
+        }
+        let file = BinFile {
+            package: "test".to_owned(),
+            root,
+            lookup: ["unused", "y", "text", "b", "Map", "z", "a"]
+                .map(str::to_owned)
+                .to_vec(),
+        };
+
+        let mut first = vec![];
+        super::put_file(&mut first, &file).unwrap();
+        let parsed = parser::take_file(&first).unwrap();
+        assert_eq!(parsed, file);
+        let mut second = vec![];
+        super::put_file(&mut second, &parsed).unwrap();
+        assert_eq!(first, second);
+    }
 }
@@ -0,0 +1,559 @@
+//! A lossless XML representation of `BinFile`s, for reading, diffing and repairing maps by hand.
+//!
+//! Attribute values are written as plain text. On the way back in, `true`/`false` become bools,
+//! anything that parses as an i32 becomes an int, anything that parses as an f32 becomes a float,
+//! and everything else becomes text. Floats are always written with a decimal point or exponent so
+//! they survive this. When a value would be read back as the wrong type, or when a string is stored
+//! with Celeste's RLE encoding, its type is recorded in a reserved `_types` attribute, e.g.
+//! `_types="name=text innerText=rle"`. The valid types are `bool`, `int`, `float`, `text` and `rle`.
+//!
+//! An element's `innerText` attribute is written as its text content when the element has no
+//! children. Otherwise it is written as an ordinary attribute.
+
+use std::fmt;
+use std::io::{prelude::*, Error, ErrorKind};
+
+use crate::binel::{BinEl, BinElAttr, BinFile};
+
+const TYPES_ATTR: &str = "_types";
+const INNER_TEXT: &str = "innerText";
+const FILE_ELEM: &str = "BinFile";
+
+/// A failure to read a `BinFile` out of XML, with the location of the problem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlError {
+    pub line: usize,
+    pub column: usize,
+    pub description: String,
+}
+
+impl fmt::Display for XmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.description
+        )
+    }
+}
+
+impl std::error::Error for XmlError {}
+
+/// Write a `BinFile` as an XML document.
+pub fn put_file(writer: &mut dyn Write, bin: &BinFile) -> std::io::Result<()> {
+    writeln!(writer, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<{FILE_ELEM} package="{}">"#,
+        escape(&bin.package, true)
+    )?;
+    put_element(writer, &bin.root, 1)?;
+    writeln!(writer, "</{FILE_ELEM}>")?;
+
+    Ok(())
+}
+
+/// Write a `BinEl` and all its children as XML, indented by `depth` levels.
+pub fn put_element(writer: &mut dyn Write, elem: &BinEl, depth: usize) -> std::io::Result<()> {
+    if elem.attributes.contains_key(TYPES_ATTR) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Element {} uses the reserved attribute {TYPES_ATTR}",
+                elem.name
+            ),
+        ));
+    }
+
+    let indent = "  ".repeat(depth);
+    let has_children = elem.children().next().is_some();
+    let inner_text = match elem.attributes.get(INNER_TEXT) {
+        Some(BinElAttr::Text(text)) if !has_children => Some(text),
+        _ => None,
+    };
+
+    write!(writer, "{indent}<{}", elem.name)?;
+    let mut types = vec![];
+    for (name, value) in &elem.attributes {
+        let rle = elem.rle_attributes.contains(name);
+        if rle {
+            types.push(format!("{name}=rle"));
+        }
+        if inner_text.is_some() && name == INNER_TEXT {
+            continue;
+        }
+
+        let text = attr_to_string(value);
+        if !rle && !same_type(&infer_attr(&text), value) {
+            types.push(format!("{name}={}", type_name(value)));
+        }
+        write!(writer, r#" {name}="{}""#, escape(&text, true))?;
+    }
+    if !types.is_empty() {
+        write!(writer, r#" {TYPES_ATTR}="{}""#, types.join(" "))?;
+    }
+
+    if let Some(text) = inner_text {
+        writeln!(writer, ">{}</{}>", escape(text, false), elem.name)?;
+    } else if has_children {
+        writeln!(writer, ">")?;
+        for child in elem.children() {
+            put_element(writer, child, depth + 1)?;
+        }
+        writeln!(writer, "{indent}</{}>", elem.name)?;
+    } else {
+        writeln!(writer, "/>")?;
+    }
+
+    Ok(())
+}
+
+/// Read a `BinFile` from an XML document produced by `put_file`.
+pub fn take_file(input: &str) -> Result<BinFile, XmlError> {
+    let mut reader = Reader::new(input);
+    reader.skip_misc()?;
+    let mut file = reader.take_element()?;
+    reader.skip_misc()?;
+    if !reader.rest().is_empty() {
+        return Err(reader.error("Unexpected content after the end of the document"));
+    }
+
+    if file.name != FILE_ELEM {
+        return Err(reader.error_at(0, &format!("Expected a {FILE_ELEM} element")));
+    }
+    let Some(BinElAttr::Text(package)) = file.attributes.shift_remove("package") else {
+        return Err(reader.error_at(0, &format!("{FILE_ELEM} is missing a package name")));
+    };
+    let mut children = file.drain();
+    let (Some(root), None) = (children.next(), children.next()) else {
+        return Err(reader.error_at(0, &format!("{FILE_ELEM} must contain exactly one element")));
+    };
+
+    Ok(BinFile {
+        package,
+        root,
+        lookup: vec![],
+    })
+}
+
+/// Read a single `BinEl` from XML produced by `put_element`.
+pub fn take_element(input: &str) -> Result<BinEl, XmlError> {
+    take_element_with(Reader::new(input))
+}
+
+/// Reads an element of some other XML format, keeping every attribute as text rather than
+/// guessing its type.
+pub fn take_element_as_text(input: &str) -> Result<BinEl, XmlError> {
+    let mut reader = Reader::new(input);
+    reader.infer_types = false;
+    take_element_with(reader)
+}
+
+fn take_element_with(mut reader: Reader<'_>) -> Result<BinEl, XmlError> {
+    reader.skip_misc()?;
+    let elem = reader.take_element()?;
+    reader.skip_misc()?;
+    if !reader.rest().is_empty() {
+        return Err(reader.error("Unexpected content after the end of the element"));
+    }
+    Ok(elem)
+}
+
+fn attr_to_string(value: &BinElAttr) -> String {
+    match value {
+        BinElAttr::Bool(b) => b.to_string(),
+        BinElAttr::Int(i) => i.to_string(),
+        // debug formatting always includes a decimal point or exponent
+        BinElAttr::Float(f) => format!("{f:?}"),
+        BinElAttr::Text(s) => s.clone(),
+    }
+}
+
+pub(crate) fn infer_attr(text: &str) -> BinElAttr {
+    match text {
+        "true" => BinElAttr::Bool(true),
+        "false" => BinElAttr::Bool(false),
+        _ => {
+            if let Ok(i) = text.parse() {
+                BinElAttr::Int(i)
+            } else if let Ok(f) = text.parse() {
+                BinElAttr::Float(f)
+            } else {
+                BinElAttr::Text(text.to_owned())
+            }
+        }
+    }
+}
+
+fn parse_typed_attr(text: &str, ty: &str) -> Option<BinElAttr> {
+    match ty {
+        "bool" => text.parse().ok().map(BinElAttr::Bool),
+        "int" => text.parse().ok().map(BinElAttr::Int),
+        "float" => text.parse().ok().map(BinElAttr::Float),
+        "text" | "rle" => Some(BinElAttr::Text(text.to_owned())),
+        _ => None,
+    }
+}
+
+fn type_name(value: &BinElAttr) -> &'static str {
+    match value {
+        BinElAttr::Bool(_) => "bool",
+        BinElAttr::Int(_) => "int",
+        BinElAttr::Float(_) => "float",
+        BinElAttr::Text(_) => "text",
+    }
+}
+
+fn same_type(a: &BinElAttr, b: &BinElAttr) -> bool {
+    std::mem::discriminant(a) == std::mem::discriminant(b)
+}
+
+pub(crate) fn escape(text: &str, attr: bool) -> String {
+    let mut result = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' if attr => result.push_str("&quot;"),
+            '\n' | '\t' if !attr => result.push(ch),
+            _ if ch.is_control() => result.push_str(&format!("&#x{:X};", ch as u32)),
+            _ => result.push(ch),
+        }
+    }
+    result
+}
+
+fn unescape_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => {
+            let code = if let Some(hex) = entity.strip_prefix("#x") {
+                u32::from_str_radix(hex, 16).ok()?
+            } else {
+                entity.strip_prefix('#')?.parse().ok()?
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+struct Reader<'a> {
+    input: &'a str,
+    pos: usize,
+    infer_types: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            pos: 0,
+            infer_types: true,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn error(&self, description: &str) -> XmlError {
+        self.error_at(self.pos, description)
+    }
+
+    fn error_at(&self, pos: usize, description: &str) -> XmlError {
+        let before = &self.input[..pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |idx| idx + 1) + 1;
+        XmlError {
+            line,
+            column,
+            description: description.to_owned(),
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), XmlError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected {token}")))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Take everything up to `end`, consuming `end` as well.
+    fn take_until(&mut self, end: &str) -> Result<&'a str, XmlError> {
+        let Some(idx) = self.rest().find(end) else {
+            return Err(self.error(&format!("Expected {end}")));
+        };
+        let result = &self.rest()[..idx];
+        self.pos += idx + end.len();
+        Ok(result)
+    }
+
+    /// Skip over whitespace, comments, processing instructions and doctypes.
+    fn skip_misc(&mut self) -> Result<(), XmlError> {
+        loop {
+            self.skip_whitespace();
+            if self.eat("<!--") {
+                self.take_until("-->")?;
+            } else if self.eat("<?") {
+                self.take_until("?>")?;
+            } else if self.eat("<!DOCTYPE") {
+                self.take_until(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn take_name(&mut self) -> Result<&'a str, XmlError> {
+        let rest = self.rest();
+        let len = rest
+            .find(|ch: char| ch.is_whitespace() || "=/<>\"'".contains(ch))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("Expected a name"));
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn take_attr_value(&mut self) -> Result<String, XmlError> {
+        let quote = if self.eat("\"") {
+            "\""
+        } else if self.eat("'") {
+            "'"
+        } else {
+            return Err(self.error("Expected a quoted attribute value"));
+        };
+        let start = self.pos;
+        let raw = self.take_until(quote)?;
+        self.unescape(raw, start)
+    }
+
+    fn unescape(&self, raw: &str, start: usize) -> Result<String, XmlError> {
+        let mut result = String::with_capacity(raw.len());
+        let mut rest = raw;
+        while let Some(idx) = rest.find('&') {
+            result.push_str(&rest[..idx]);
+            rest = &rest[idx..];
+            let pos = start + raw.len() - rest.len();
+            let Some(end) = rest.find(';') else {
+                return Err(self.error_at(pos, "Unterminated entity"));
+            };
+            let entity = &rest[1..end];
+            let Some(ch) = unescape_entity(entity) else {
+                return Err(self.error_at(pos, &format!("Unknown entity &{entity};")));
+            };
+            result.push(ch);
+            rest = &rest[end + 1..];
+        }
+        result.push_str(rest);
+        Ok(result)
+    }
+
+    fn take_element(&mut self) -> Result<BinEl, XmlError> {
+        let start = self.pos;
+        self.expect("<")?;
+        let name = self.take_name()?;
+        let mut elem = BinEl::new(name);
+
+        let mut raw_attrs = vec![];
+        let self_closing = loop {
+            self.skip_whitespace();
+            if self.eat("/>") {
+                break true;
+            }
+            if self.eat(">") {
+                break false;
+            }
+            let attr_pos = self.pos;
+            let attr_name = self.take_name()?;
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            let value = self.take_attr_value()?;
+            if raw_attrs.iter().any(|(_, name, _)| *name == attr_name) {
+                return Err(self.error_at(attr_pos, &format!("Duplicate attribute {attr_name}")));
+            }
+            raw_attrs.push((attr_pos, attr_name, value));
+        };
+
+        let mut text = String::new();
+        let mut text_pos = None;
+        let mut has_children = false;
+        if !self_closing {
+            loop {
+                if self.eat("</") {
+                    let end_name = self.take_name()?;
+                    if end_name != name {
+                        return Err(
+                            self.error(&format!("Expected </{name}> but found </{end_name}>"))
+                        );
+                    }
+                    self.skip_whitespace();
+                    self.expect(">")?;
+                    break;
+                } else if self.eat("<!--") {
+                    self.take_until("-->")?;
+                } else if self.eat("<![CDATA[") {
+                    text_pos.get_or_insert(self.pos);
+                    text.push_str(self.take_until("]]>")?);
+                } else if self.eat("<?") {
+                    self.take_until("?>")?;
+                } else if self.rest().starts_with('<') {
+                    let child = self.take_element()?;
+                    elem.insert(child);
+                    has_children = true;
+                } else if self.rest().is_empty() {
+                    return Err(self.error(&format!("Expected </{name}>")));
+                } else {
+                    let pos = self.pos;
+                    text_pos.get_or_insert(pos);
+                    let len = self.rest().find('<').unwrap_or(self.rest().len());
+                    let raw = &self.rest()[..len];
+                    self.pos += len;
+                    text.push_str(&self.unescape(raw, pos)?);
+                }
+            }
+        }
+
+        let mut types = vec![];
+        if let Some(idx) = raw_attrs
+            .iter()
+            .position(|(_, name, _)| *name == TYPES_ATTR)
+        {
+            let (pos, _, value) = raw_attrs.remove(idx);
+            for entry in value.split_whitespace() {
+                let Some((attr_name, ty)) = entry.split_once('=') else {
+                    return Err(self.error_at(pos, &format!("Malformed type entry {entry}")));
+                };
+                types.push((attr_name.to_owned(), ty.to_owned()));
+            }
+        }
+
+        if has_children {
+            if !text.trim().is_empty() {
+                return Err(self.error_at(
+                    text_pos.unwrap_or(start),
+                    &format!("Element {name} has both text and children"),
+                ));
+            }
+        } else if !self_closing {
+            if raw_attrs.iter().any(|(_, name, _)| *name == INNER_TEXT) {
+                return Err(self.error_at(
+                    start,
+                    &format!("Element {name} has both text and an {INNER_TEXT} attribute"),
+                ));
+            }
+            // text content is always text, so don't try to infer anything from it
+            if !types.iter().any(|(name, _)| name == INNER_TEXT) {
+                types.push((INNER_TEXT.to_owned(), "text".to_owned()));
+            }
+            raw_attrs.push((text_pos.unwrap_or(start), INNER_TEXT, text));
+        }
+
+        for (pos, attr_name, value) in raw_attrs {
+            let ty = types
+                .iter()
+                .find(|(name, _)| name == attr_name)
+                .map(|(_, ty)| ty.as_str());
+            let attr = match ty {
+                None if !self.infer_types => BinElAttr::Text(value),
+                None => infer_attr(&value),
+                Some(ty) => parse_typed_attr(&value, ty)
+                    .ok_or_else(|| self.error_at(pos, &format!("Cannot read {value:?} as {ty}")))?,
+            };
+            if ty == Some("rle") {
+                elem.rle_attributes.insert(attr_name.to_owned());
+            }
+            elem.attributes.insert(attr_name.to_owned(), attr);
+        }
+        if let Some((attr_name, _)) = types
+            .iter()
+            .find(|(name, _)| !elem.attributes.contains_key(name))
+        {
+            return Err(self.error_at(
+                start,
+                &format!("Type given for missing attribute {attr_name}"),
+            ));
+        }
+
+        Ok(elem)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::binel::writer;
+
+    #[test]
+    fn round_trip() {
+        let mut root = BinEl::new("Map");
+        let mut solids = BinEl::new("solids");
+        solids.attributes.insert(
+            "innerText".to_owned(),
+            BinElAttr::Text("000\n0<0&\n\r\n".to_owned()),
+        );
+        solids.rle_attributes.insert("innerText".to_owned());
+        root.insert(solids);
+        let mut entity = BinEl::new("spring");
+        entity
+            .attributes
+            .insert("x".to_owned(), BinElAttr::Float(8.0));
+        entity
+            .attributes
+            .insert("id".to_owned(), BinElAttr::Int(-3));
+        entity
+            .attributes
+            .insert("flag".to_owned(), BinElAttr::Text("12".to_owned()));
+        entity
+            .attributes
+            .insert("quote".to_owned(), BinElAttr::Text("\"a\"\tb\n".to_owned()));
+        entity
+            .attributes
+            .insert("on".to_owned(), BinElAttr::Bool(true));
+        root.insert(entity);
+        root.insert(BinEl::new("empty"));
+        let file = BinFile {
+            package: "test".to_owned(),
+            root,
+            lookup: vec![],
+        };
+
+        let mut xml = vec![];
+        put_file(&mut xml, &file).unwrap();
+        let parsed = take_file(std::str::from_utf8(&xml).unwrap()).unwrap();
+        assert_eq!(parsed, file);
+
+        let mut bin = vec![];
+        writer::put_file(&mut bin, &parsed).unwrap();
+        let reparsed = crate::binel::parser::take_file(&bin).unwrap();
+        assert_eq!(reparsed.root, file.root);
+    }
+
+    #[test]
+    fn errors_have_positions() {
+        let err = take_element("<Map>\n  <level name=\"a\">\n</Map>").unwrap_err();
+        assert_eq!(err.line, 3);
+    }
+}
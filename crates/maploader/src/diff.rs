@@ -0,0 +1,683 @@
+//! Semantic comparison of two maps. Unlike `bin_el_fuzzy_equal`, this says what changed, room by
+//! room, in terms a reviewer can follow.
+
+use indexmap::IndexMap;
+use std::collections::HashMap;
+use std::fmt;
+
+use arborio_utils::units::*;
+
+use crate::binel::{BinEl, BinElAttr};
+use crate::from_binel::TryFromBinEl;
+use crate::map_struct::{
+    CelesteMap, CelesteMapDecal, CelesteMapEntity, CelesteMapLevel, CelesteMapStyleground,
+    LEVEL_CHILDREN,
+};
+
+/// Everything which differs between two maps.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MapDiff {
+    /// Changes which don't belong to any room: filler, stylegrounds and metadata.
+    pub map: Vec<MapChange>,
+    /// The rooms which changed, in the order of the new map followed by the removed ones.
+    pub rooms: Vec<RoomDiff>,
+}
+
+impl MapDiff {
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty() && self.rooms.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoomDiff {
+    pub room: String,
+    pub changes: Vec<RoomChange>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapChange {
+    FillerAdded(MapRectStrict),
+    FillerRemoved(MapRectStrict),
+    Styleground {
+        fg: bool,
+        index: usize,
+        change: ObjectChange,
+    },
+    /// A change to the background color or to the metadata.
+    Property(PropertyChange),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoomChange {
+    Added,
+    Removed,
+    Moved {
+        from: MapPointStrict,
+        to: MapPointStrict,
+    },
+    Resized {
+        from: MapSizeStrict,
+        to: MapSizeStrict,
+    },
+    Property(PropertyChange),
+    Object {
+        layer: ObjectLayer,
+        change: ObjectChange,
+    },
+    /// Tiles which changed in a layer, as rectangles in tiles relative to the room.
+    Tiles {
+        layer: TileLayer,
+        rects: Vec<TileRect>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectLayer {
+    Entities,
+    Triggers,
+    FgDecals,
+    BgDecals,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileLayer {
+    Solids,
+    Bg,
+    ObjectTiles,
+    FgTiles,
+    BgTiles,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectChange {
+    Added(ObjectRef),
+    Removed(ObjectRef),
+    Changed(ObjectRef, Vec<PropertyChange>),
+}
+
+/// Enough about an entity, trigger, decal or styleground to find it in the editor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectRef {
+    /// The entity name, or the texture of a decal.
+    pub name: String,
+    pub id: Option<i32>,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// A single attribute which was added, removed or changed. Attributes of child elements are named
+/// by their path, such as `node[1]/x` or `mode/AudioState/Music`. Attributes which are left at
+/// their default value are usually not saved, so setting or clearing one shows up as it being
+/// added or removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyChange {
+    pub name: String,
+    pub old: Option<BinElAttr>,
+    pub new: Option<BinElAttr>,
+}
+
+/// Compare two maps. Rooms are matched by name, entities and triggers by id and decals by texture
+/// and position.
+pub fn diff_maps(old: &CelesteMap, new: &CelesteMap) -> MapDiff {
+    let mut diff = MapDiff::default();
+
+    for rect in &old.filler {
+        if !new.filler.contains(rect) {
+            diff.map.push(MapChange::FillerRemoved(*rect));
+        }
+    }
+    for rect in &new.filler {
+        if !old.filler.contains(rect) {
+            diff.map.push(MapChange::FillerAdded(*rect));
+        }
+    }
+
+    for fg in [true, false] {
+        let old_styles = old.styles(fg).iter().map(styleground_object).collect();
+        let new_styles = new.styles(fg).iter().map(styleground_object).collect();
+        diff.map.extend(
+            diff_ordered(old_styles, new_styles)
+                .into_iter()
+                .map(|(index, change)| MapChange::Styleground { fg, index, change }),
+        );
+    }
+
+    let mut old_props = IndexMap::new();
+    let mut new_props = IndexMap::new();
+    for (map, props) in [(old, &mut old_props), (new, &mut new_props)] {
+        if let Some(color) = &map.background_color {
+            props.insert("Style/color".to_owned(), BinElAttr::Text(color.clone()));
+        }
+        if let Some(meta) = &map.meta {
+            flatten("meta", &meta.to_binel(), props);
+        }
+    }
+    diff.map.extend(
+        diff_properties(&old_props, &new_props)
+            .into_iter()
+            .map(MapChange::Property),
+    );
+
+    // pair rooms by name, and by order among rooms with the same name
+    let mut old_rooms: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, room) in old.levels.iter().enumerate().rev() {
+        old_rooms.entry(&room.name).or_default().push(index);
+    }
+    let mut paired = vec![false; old.levels.len()];
+    for room in &new.levels {
+        let changes = match old_rooms.get_mut(room.name.as_str()).and_then(Vec::pop) {
+            Some(index) => {
+                paired[index] = true;
+                diff_rooms(&old.levels[index], room)
+            }
+            None => vec![RoomChange::Added],
+        };
+        if !changes.is_empty() {
+            diff.rooms.push(RoomDiff {
+                room: room.name.clone(),
+                changes,
+            });
+        }
+    }
+    for (room, _) in old.levels.iter().zip(paired).filter(|(_, paired)| !paired) {
+        diff.rooms.push(RoomDiff {
+            room: room.name.clone(),
+            changes: vec![RoomChange::Removed],
+        });
+    }
+
+    diff
+}
+
+fn diff_rooms(old: &CelesteMapLevel, new: &CelesteMapLevel) -> Vec<RoomChange> {
+    let mut changes = vec![];
+    if old.bounds.origin != new.bounds.origin {
+        changes.push(RoomChange::Moved {
+            from: old.bounds.origin,
+            to: new.bounds.origin,
+        });
+    }
+    if old.bounds.size != new.bounds.size {
+        changes.push(RoomChange::Resized {
+            from: old.bounds.size,
+            to: new.bounds.size,
+        });
+    }
+
+    changes.extend(
+        diff_properties(&room_properties(old), &room_properties(new))
+            .into_iter()
+            .map(RoomChange::Property),
+    );
+
+    for (layer, old_objects, new_objects) in [
+        (ObjectLayer::Entities, &old.entities, &new.entities),
+        (ObjectLayer::Triggers, &old.triggers, &new.triggers),
+    ] {
+        let old_objects = old_objects.iter().map(entity_object).collect();
+        let new_objects = new_objects.iter().map(entity_object).collect();
+        changes.extend(
+            diff_objects(old_objects, new_objects)
+                .into_iter()
+                .map(|change| RoomChange::Object { layer, change }),
+        );
+    }
+    for (layer, old_objects, new_objects) in [
+        (ObjectLayer::FgDecals, &old.fg_decals, &new.fg_decals),
+        (ObjectLayer::BgDecals, &old.bg_decals, &new.bg_decals),
+    ] {
+        let old_objects = old_objects.iter().map(decal_object).collect();
+        let new_objects = new_objects.iter().map(decal_object).collect();
+        changes.extend(
+            diff_objects(old_objects, new_objects)
+                .into_iter()
+                .map(|change| RoomChange::Object { layer, change }),
+        );
+    }
+
+    for (layer, rects) in [
+        (
+            TileLayer::Solids,
+            diff_tiles(&old.solids, &new.solids, &'0'),
+        ),
+        (TileLayer::Bg, diff_tiles(&old.bg, &new.bg, &'0')),
+        (
+            TileLayer::ObjectTiles,
+            diff_tiles(&old.object_tiles, &new.object_tiles, &-1),
+        ),
+        (
+            TileLayer::FgTiles,
+            diff_tiles(&old.fg_tiles, &new.fg_tiles, &-1),
+        ),
+        (
+            TileLayer::BgTiles,
+            diff_tiles(&old.bg_tiles, &new.bg_tiles, &-1),
+        ),
+    ] {
+        if !rects.is_empty() {
+            changes.push(RoomChange::Tiles { layer, rects });
+        }
+    }
+
+    changes
+}
+
+/// The properties of a room, other than its name, bounds and contents.
+fn room_properties(room: &CelesteMapLevel) -> IndexMap<String, BinElAttr> {
+    let mut binel = room.to_binel();
+    for key in ["x", "y", "width", "height", "name"] {
+        binel.attributes.shift_remove(key);
+    }
+    binel.retain_children(|child| !LEVEL_CHILDREN.contains(&child.name.as_str()));
+    let mut result = IndexMap::new();
+    flatten("", &binel, &mut result);
+    result
+}
+
+/// An object to be matched up between two maps.
+struct Object {
+    reference: ObjectRef,
+    properties: IndexMap<String, BinElAttr>,
+}
+
+fn entity_object(entity: &CelesteMapEntity) -> Object {
+    let mut properties = IndexMap::new();
+    flatten("", &entity.to_binel(), &mut properties);
+    Object {
+        reference: ObjectRef {
+            name: entity.name.clone(),
+            id: Some(entity.id),
+            x: entity.x,
+            y: entity.y,
+        },
+        properties,
+    }
+}
+
+fn decal_object(decal: &CelesteMapDecal) -> Object {
+    let mut properties = IndexMap::new();
+    flatten("", &decal.to_binel(), &mut properties);
+    Object {
+        reference: ObjectRef {
+            name: decal.texture.clone(),
+            id: None,
+            x: decal.x,
+            y: decal.y,
+        },
+        properties,
+    }
+}
+
+fn styleground_object(style: &CelesteMapStyleground) -> Object {
+    let mut properties = IndexMap::new();
+    let binel = style.to_binel();
+    flatten("", &binel, &mut properties);
+    let name = match binel.attributes.get("texture") {
+        Some(BinElAttr::Text(texture)) if !texture.is_empty() => {
+            format!("{} {texture}", style.name)
+        }
+        _ => style.name.clone(),
+    };
+    Object {
+        reference: ObjectRef {
+            name,
+            id: None,
+            x: style.x as i32,
+            y: style.y as i32,
+        },
+        properties,
+    }
+}
+
+/// Match up two sets of objects. Objects with ids are paired by id, then unchanged objects are
+/// paired, then any remaining objects without ids are paired by name in order.
+fn diff_objects(old: Vec<Object>, new: Vec<Object>) -> Vec<ObjectChange> {
+    let mut pairs: Vec<Option<usize>> = vec![None; new.len()];
+    let mut used = vec![false; old.len()];
+    let passes: [fn(&Object, &Object) -> bool; 3] = [
+        |a, b| a.reference.id.is_some() && a.reference.id == b.reference.id,
+        |a, b| a.properties == b.properties,
+        |a, b| a.reference.id.is_none() && b.reference.id.is_none(),
+    ];
+    for pass in passes {
+        for (new_obj, pair) in new.iter().zip(pairs.iter_mut()) {
+            if pair.is_some() {
+                continue;
+            }
+            *pair =
+                old.iter()
+                    .zip(used.iter_mut())
+                    .enumerate()
+                    .find_map(|(index, (old_obj, used))| {
+                        let matches = !*used
+                            && old_obj.reference.name == new_obj.reference.name
+                            && pass(old_obj, new_obj);
+                        matches.then(|| {
+                            *used = true;
+                            index
+                        })
+                    });
+        }
+    }
+
+    let mut changes = old
+        .iter()
+        .zip(&used)
+        .filter(|(_, used)| !**used)
+        .map(|(obj, _)| ObjectChange::Removed(obj.reference.clone()))
+        .collect::<Vec<_>>();
+    for (new_obj, pair) in new.iter().zip(pairs) {
+        match pair {
+            Some(index) => {
+                let properties = diff_properties(&old[index].properties, &new_obj.properties);
+                if !properties.is_empty() {
+                    changes.push(ObjectChange::Changed(new_obj.reference.clone(), properties));
+                }
+            }
+            None => changes.push(ObjectChange::Added(new_obj.reference.clone())),
+        }
+    }
+    changes
+}
+
+/// Compare two lists of objects whose identity is their position in the list, such as
+/// stylegrounds. Returns the changes along with the index they happened at.
+fn diff_ordered(old: Vec<Object>, new: Vec<Object>) -> Vec<(usize, ObjectChange)> {
+    let mut changes = vec![];
+    for index in 0..old.len().max(new.len()) {
+        match (old.get(index), new.get(index)) {
+            (Some(old_obj), Some(new_obj)) => {
+                let properties = diff_properties(&old_obj.properties, &new_obj.properties);
+                if !properties.is_empty() {
+                    changes.push((
+                        index,
+                        ObjectChange::Changed(new_obj.reference.clone(), properties),
+                    ));
+                }
+            }
+            (Some(old_obj), None) => {
+                changes.push((index, ObjectChange::Removed(old_obj.reference.clone())))
+            }
+            (None, Some(new_obj)) => {
+                changes.push((index, ObjectChange::Added(new_obj.reference.clone())))
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+    changes
+}
+
+/// Collect the attributes of an element and its children, keyed by their path.
+fn flatten(prefix: &str, elem: &BinEl, result: &mut IndexMap<String, BinElAttr>) {
+    let join = |name: &str| {
+        if prefix.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{prefix}/{name}")
+        }
+    };
+    for (key, value) in &elem.attributes {
+        result.insert(join(key), value.clone());
+    }
+    let mut seen = HashMap::new();
+    for child in elem.children() {
+        let index = seen.entry(child.name.as_str()).or_insert(0);
+        let name = if *index == 0 {
+            child.name.clone()
+        } else {
+            format!("{}[{index}]", child.name)
+        };
+        *index += 1;
+        flatten(&join(&name), child, result);
+    }
+}
+
+fn diff_properties(
+    old: &IndexMap<String, BinElAttr>,
+    new: &IndexMap<String, BinElAttr>,
+) -> Vec<PropertyChange> {
+    let mut changes = vec![];
+    for (name, old_value) in old {
+        let new_value = new.get(name);
+        if new_value != Some(old_value) {
+            changes.push(PropertyChange {
+                name: name.clone(),
+                old: Some(old_value.clone()),
+                new: new_value.cloned(),
+            });
+        }
+    }
+    for (name, new_value) in new {
+        if !old.contains_key(name) {
+            changes.push(PropertyChange {
+                name: name.clone(),
+                old: None,
+                new: Some(new_value.clone()),
+            });
+        }
+    }
+    changes
+}
+
+/// Find the tiles which differ between two grids, merged into rectangles. Tiles outside one of
+/// the grids count as `empty`.
+fn diff_tiles<T: PartialEq>(old: &TileGrid<T>, new: &TileGrid<T>, empty: &T) -> Vec<TileRect> {
+    let size = TileSize::new(
+        old.size().width.max(new.size().width),
+        old.size().height.max(new.size().height),
+    );
+    tile_rects(size, |pt| {
+        old.get(pt).unwrap_or(empty) != new.get(pt).unwrap_or(empty)
+    })
+}
+
+/// Merge the tiles within `size` for which `f` returns true into rectangles.
+pub(crate) fn tile_rects(size: TileSize, f: impl Fn(TilePoint) -> bool) -> Vec<TileRect> {
+    let mut done: Vec<TileRect> = vec![];
+    // rectangles which reach the previous row, keyed by their horizontal extent
+    let mut open: HashMap<(i32, i32), TileRect> = HashMap::new();
+    for y in 0..size.height {
+        let mut still_open = HashMap::new();
+        let mut x = 0;
+        while x < size.width {
+            if !f(TilePoint::new(x, y)) {
+                x += 1;
+                continue;
+            }
+            let start = x;
+            while x < size.width && f(TilePoint::new(x, y)) {
+                x += 1;
+            }
+            let rect = match open.remove(&(start, x)) {
+                Some(mut rect) => {
+                    rect.size.height += 1;
+                    rect
+                }
+                None => TileRect::new(TilePoint::new(start, y), TileSize::new(x - start, 1)),
+            };
+            still_open.insert((start, x), rect);
+        }
+        done.extend(open.into_values());
+        open = still_open;
+    }
+    done.extend(open.into_values());
+    done.sort_by_key(|rect| (rect.origin.y, rect.origin.x));
+    done
+}
+
+impl fmt::Display for MapChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapChange::FillerAdded(rect) => write!(f, "filler: added {}", MapRect(rect)),
+            MapChange::FillerRemoved(rect) => write!(f, "filler: removed {}", MapRect(rect)),
+            MapChange::Styleground { fg, index, change } => write!(
+                f,
+                "{} styleground {index}: {change}",
+                if *fg { "foreground" } else { "background" }
+            ),
+            MapChange::Property(change) => write!(f, "{change}"),
+        }
+    }
+}
+
+impl fmt::Display for RoomChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoomChange::Added => write!(f, "added"),
+            RoomChange::Removed => write!(f, "removed"),
+            RoomChange::Moved { from, to } => write!(
+                f,
+                "moved from ({}, {}) to ({}, {})",
+                from.x, from.y, to.x, to.y
+            ),
+            RoomChange::Resized { from, to } => write!(
+                f,
+                "resized from {}x{} to {}x{}",
+                from.width, from.height, to.width, to.height
+            ),
+            RoomChange::Property(change) => write!(f, "{change}"),
+            RoomChange::Object { layer, change } => write!(f, "{layer}: {change}"),
+            RoomChange::Tiles { layer, rects } => {
+                write!(f, "{layer}: changed")?;
+                for (i, rect) in rects.iter().enumerate() {
+                    let sep = if i == 0 { " " } else { ", " };
+                    write!(
+                        f,
+                        "{sep}{}x{} at ({}, {})",
+                        rect.size.width, rect.size.height, rect.origin.x, rect.origin.y
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for ObjectLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ObjectLayer::Entities => "entities",
+            ObjectLayer::Triggers => "triggers",
+            ObjectLayer::FgDecals => "fgdecals",
+            ObjectLayer::BgDecals => "bgdecals",
+        })
+    }
+}
+
+impl fmt::Display for TileLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TileLayer::Solids => "solids",
+            TileLayer::Bg => "bg",
+            TileLayer::ObjectTiles => "objtiles",
+            TileLayer::FgTiles => "fgtiles",
+            TileLayer::BgTiles => "bgtiles",
+        })
+    }
+}
+
+impl fmt::Display for ObjectChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjectChange::Added(object) => write!(f, "added {object}"),
+            ObjectChange::Removed(object) => write!(f, "removed {object}"),
+            ObjectChange::Changed(object, changes) => {
+                write!(f, "changed {object}:")?;
+                for change in changes {
+                    write!(f, " {change};")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for ObjectRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(id) = self.id {
+            write!(f, " (id {id})")?;
+        }
+        write!(f, " at ({}, {})", self.x, self.y)
+    }
+}
+
+impl fmt::Display for PropertyChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.old, &self.new) {
+            (Some(old), Some(new)) => write!(f, "{} {old} -> {new}", self.name),
+            (None, Some(new)) => write!(f, "{} set to {new}", self.name),
+            (Some(old), None) => write!(f, "{} cleared (was {old})", self.name),
+            (None, None) => write!(f, "{} unchanged", self.name),
+        }
+    }
+}
+
+struct MapRect<'a>(&'a MapRectStrict);
+
+impl fmt::Display for MapRect<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{} at ({}, {})",
+            self.0.size.width, self.0.size.height, self.0.origin.x, self.0.origin.y
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn changes_are_found_room_by_room() {
+        let mut old = CelesteMap::default();
+        let mut room = CelesteMapLevel {
+            name: "a-00".to_owned(),
+            ..CelesteMapLevel::default()
+        };
+        room.entities.push(CelesteMapEntity::new(1, "spring", 8, 8));
+        old.levels.push(room.clone());
+        room.name = "a-01".to_owned();
+        old.levels.push(room);
+
+        let mut new = CelesteMap {
+            levels: old.levels.clone(),
+            ..CelesteMap::default()
+        };
+        new.levels[0].entities[0].x = 16;
+        new.levels[0].dark = true;
+        for x in 2..5 {
+            for y in 1..3 {
+                *new.levels[0].solids.get_mut(TilePoint::new(x, y)).unwrap() = '1';
+            }
+        }
+        new.levels.remove(1);
+
+        let diff = diff_maps(&old, &new);
+        assert!(diff.map.is_empty());
+        let rooms = diff
+            .rooms
+            .iter()
+            .map(|room| {
+                let changes = room.changes.iter().map(ToString::to_string);
+                (room.room.as_str(), changes.collect::<Vec<_>>())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rooms,
+            [
+                (
+                    "a-00",
+                    vec![
+                        "dark set to true".to_owned(),
+                        "entities: changed spring (id 1) at (16, 8): x 8 -> 16;".to_owned(),
+                        "solids: changed 3x2 at (2, 1)".to_owned(),
+                    ]
+                ),
+                ("a-01", vec!["removed".to_owned()])
+            ]
+        );
+    }
+}
@@ -5,6 +5,8 @@ use crate::{
     map_struct::{get_child_mut, get_optional_child, CelesteMapError},
 };
 pub use arborio_derive::TryFromBinEl;
+use arborio_utils::vizia::prelude::Data;
+use indexmap::IndexMap;
 use itertools::Itertools;
 use once_cell::sync::Lazy;
 
@@ -81,7 +83,7 @@ pub trait GetAttrOrChild: Sized {
 
 impl GetAttrOrChild for BinEl {
     fn attr_or_child<'b>(elem: &'b BinEl, key: &str) -> Option<&'b Self> {
-        let (x,) = elem.get(key).iter().collect_tuple()?;
+        let (x,) = elem.get(key).collect_tuple()?;
         Some(x)
     }
 
@@ -89,7 +91,7 @@ impl GetAttrOrChild for BinEl {
         if value.name.is_empty() {
             value.name = key.to_owned();
         }
-        *elem.get_mut(key) = vec![value];
+        elem.set(key, value);
     }
 }
 
@@ -134,6 +136,16 @@ pub trait TwoWayConverter<T> {
             Self::set_bin_el(elem, key, value);
         }
     }
+    /// Like `set_bin_el_default`, but a default value is still written if the element the object
+    /// was read from had one.
+    fn set_bin_el_kept<'a>(elem: &mut BinEl, key: &'a str, value: &'a T, layout: &ElementLayout)
+    where
+        T: Default + PartialEq,
+    {
+        if *value != T::default() || layout.has(key) {
+            Self::set_bin_el(elem, key, value);
+        }
+    }
 }
 
 // entires in this list are not parsed by celeste.exe
@@ -376,6 +388,244 @@ pub fn bin_el_fuzzy_equal(parent: &str, first: &BinEl, second: &BinEl) -> bool {
     true
 }
 
+/// How an element was laid out in the file it was read from: the order and encoding of its
+/// attributes and the order of its children. Objects keep the layout of the element they were read
+/// from in a `#[layout]` field, so that an object which hasn't changed is written back exactly as
+/// it was read, whatever happened to the objects around it. Objects which weren't read from a
+/// file have an empty layout.
+///
+/// Children which only hold some of an object's fields, such as a room's list of entities or one
+/// of its tile grids, aren't objects of their own, so their layouts are kept here too. So are any
+/// of their attributes which no field reads.
+#[derive(Debug, Clone, Default)]
+pub struct ElementLayout {
+    attributes: IndexMap<String, BinElAttr>,
+    rle_attributes: HashSet<String>,
+    children: Vec<ChildLayout>,
+}
+
+#[derive(Debug, Clone)]
+enum ChildLayout {
+    /// A child which is read into an object, which keeps its own layout.
+    Object(String),
+    /// A child which holds some of the object's fields.
+    Container(String, ElementLayout),
+    /// The next child which comes from a list, such as an object's `#[children]`.
+    Listed,
+}
+
+impl ChildLayout {
+    fn name(&self) -> Option<&str> {
+        match self {
+            ChildLayout::Object(name) | ChildLayout::Container(name, _) => Some(name),
+            ChildLayout::Listed => None,
+        }
+    }
+}
+
+// how an object was written doesn't change what it is
+impl PartialEq for ElementLayout {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Data for ElementLayout {
+    fn same(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// The rest of the names in `names` which start with the child `name`.
+fn nested<'a>(name: &str, names: &[&'a str]) -> Vec<&'a str> {
+    names
+        .iter()
+        .filter_map(|path| path.strip_prefix(name)?.strip_prefix('/'))
+        .collect()
+}
+
+impl ElementLayout {
+    /// Record the layout of `elem`. `objects` are the names of the fields which are read from
+    /// objects of their own and `containers` the names of those which are read from the children
+    /// of a child, like lists. A nested name such as `Style/Foregrounds` makes every child along
+    /// the way a container.
+    pub fn new(elem: &BinEl, objects: &[&str], containers: &[&str]) -> Self {
+        let mut children = elem
+            .children()
+            .map(|child| {
+                let name = child.name.as_str();
+                let (inner_objects, inner_containers) =
+                    (nested(name, objects), nested(name, containers));
+                if containers.contains(&name)
+                    || !inner_objects.is_empty()
+                    || !inner_containers.is_empty()
+                {
+                    let layout = Self::new(child, &inner_objects, &inner_containers);
+                    ChildLayout::Container(name.to_owned(), layout)
+                } else if objects.contains(&name) {
+                    ChildLayout::Object(name.to_owned())
+                } else {
+                    ChildLayout::Listed
+                }
+            })
+            .collect::<Vec<_>>();
+        // children which all come from lists are written in the order of the lists
+        if children
+            .iter()
+            .all(|child| matches!(child, ChildLayout::Listed))
+        {
+            children.clear();
+        }
+
+        Self {
+            attributes: elem.attributes.clone(),
+            rle_attributes: elem.rle_attributes.clone(),
+            children,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.attributes.is_empty() && self.children.is_empty()
+    }
+
+    /// Whether the element had the attribute or child `key`. Fields which hold their default
+    /// value are still written if the element had them.
+    pub fn has(&self, key: &str) -> bool {
+        self.attributes.contains_key(key)
+            || self.children.iter().any(|child| child.name() == Some(key))
+    }
+
+    /// The layout of the child `key`, if it holds some of the object's fields.
+    pub fn container(&self, key: &str) -> Option<&ElementLayout> {
+        self.children.iter().find_map(|child| match child {
+            ChildLayout::Container(name, layout) if name == key => Some(layout),
+            _ => None,
+        })
+    }
+
+    /// Lay out a freshly serialized object like the element it was read from. Attributes and
+    /// children keep their original order, with new ones after them, and values which mean the
+    /// same thing as the original ones are written the way the original ones were.
+    pub fn apply(&self, elem: &mut BinEl) {
+        self.apply_to(elem, false);
+    }
+
+    fn apply_to(&self, elem: &mut BinEl, container: bool) {
+        let mut attributes = IndexMap::with_capacity(elem.attributes.len());
+        for (key, original) in &self.attributes {
+            let value = match elem.attributes.shift_remove(key) {
+                Some(value) if attr_equivalent(&elem.name, key, original, &value) => {
+                    original.clone()
+                }
+                Some(value) => value,
+                // no field reads this, so it is only known here
+                None if container => original.clone(),
+                None => continue,
+            };
+            if self.rle_attributes.contains(key) {
+                elem.rle_attributes.insert(key.clone());
+            } else {
+                elem.rle_attributes.remove(key);
+            }
+            attributes.insert(key.clone(), value);
+        }
+        attributes.extend(elem.attributes.drain(..));
+        elem.attributes = attributes;
+
+        if self.children.is_empty() {
+            return;
+        }
+        let fields = self
+            .children
+            .iter()
+            .filter_map(ChildLayout::name)
+            .collect::<HashSet<_>>();
+        let mut remaining = elem.drain().map(Some).collect::<Vec<_>>();
+        let mut take = |pred: &dyn Fn(&BinEl) -> bool| {
+            remaining
+                .iter_mut()
+                .find(|child| matches!(child, Some(child) if pred(child)))
+                .and_then(Option::take)
+        };
+        let mut children = vec![];
+        for layout in &self.children {
+            children.extend(match layout {
+                ChildLayout::Object(name) => take(&|child| child.name == *name),
+                ChildLayout::Container(name, layout) => {
+                    take(&|child| child.name == *name).map(|mut child| {
+                        layout.apply_to(&mut child, true);
+                        child
+                    })
+                }
+                ChildLayout::Listed => take(&|child| !fields.contains(child.name.as_str())),
+            });
+        }
+        for child in children.into_iter().chain(remaining.into_iter().flatten()) {
+            elem.insert(child);
+        }
+    }
+}
+
+/// Whether an attribute which was read from a file and the value it would be written with now
+/// mean the same thing to celeste, so that the original can be written instead. Only lossless
+/// conversions count.
+fn attr_equivalent(
+    elem_name: &str,
+    attr_name: &str,
+    original: &BinElAttr,
+    value: &BinElAttr,
+) -> bool {
+    match (original, value) {
+        (BinElAttr::Text(first), BinElAttr::Text(second))
+            if attr_name == "innerText" && ["bg", "solids"].contains(&elem_name) =>
+        {
+            normalize_tiles(first, "", "0") == normalize_tiles(second, "", "0")
+        }
+        (BinElAttr::Text(first), BinElAttr::Text(second))
+            if attr_name == "innerText"
+                && ["bgtiles", "fgtiles", "objtiles"].contains(&elem_name) =>
+        {
+            normalize_tiles(first, ",", "-1") == normalize_tiles(second, ",", "-1")
+        }
+        (BinElAttr::Int(i), BinElAttr::Float(f)) | (BinElAttr::Float(f), BinElAttr::Int(i)) => {
+            *i as f32 == *f
+        }
+        (BinElAttr::Int(i), BinElAttr::Text(t)) | (BinElAttr::Text(t), BinElAttr::Int(i)) => {
+            *t == i.to_string()
+        }
+        (BinElAttr::Float(f), BinElAttr::Text(t)) | (BinElAttr::Text(t), BinElAttr::Float(f)) => {
+            *t == f.to_string()
+        }
+        (BinElAttr::Bool(b), BinElAttr::Text(t)) | (BinElAttr::Text(t), BinElAttr::Bool(b)) => {
+            t.eq_ignore_ascii_case(&b.to_string())
+        }
+        _ => original == value,
+    }
+}
+
+/// Split a tile grid into rows of tiles, dropping the trailing empty tiles and rows which aren't
+/// saved.
+fn normalize_tiles<'a>(text: &'a str, separator: &str, empty: &str) -> Vec<Vec<&'a str>> {
+    let mut rows = text
+        .lines()
+        .map(|line| {
+            // splitting on "" gives each character on its own
+            let mut row = line
+                .split(separator)
+                .filter(|tile| !tile.is_empty())
+                .collect::<Vec<_>>();
+            while row.last() == Some(&empty) {
+                row.pop();
+            }
+            row
+        })
+        .collect::<Vec<_>>();
+    while matches!(rows.last(), Some(row) if row.is_empty()) {
+        rows.pop();
+    }
+    rows
+}
+
 fn compare_char_tiles(first: Option<&BinElAttr>, second: Option<&BinElAttr>) -> bool {
     let default;
 
@@ -484,18 +734,18 @@ fn make_like_default(like: &BinElAttr) -> BinElAttr {
 fn bin_el_attr_fuzzy_equal_required(first: Option<&BinElAttr>, second: Option<&BinElAttr>) -> bool {
     let (Some(first), Some(second)) = (first, second) else { return false };
     match (first, second) {
-        (BinElAttr::Bool(_), BinElAttr::Int(_)) => todo!(),
-        (BinElAttr::Bool(_), BinElAttr::Float(_)) => todo!(),
-        (BinElAttr::Bool(_), BinElAttr::Text(_)) => todo!(),
-        (BinElAttr::Int(_), BinElAttr::Bool(_)) => todo!(),
+        (BinElAttr::Bool(_), BinElAttr::Int(_)) => false,
+        (BinElAttr::Bool(_), BinElAttr::Float(_)) => false,
+        (BinElAttr::Bool(_), BinElAttr::Text(_)) => false,
+        (BinElAttr::Int(_), BinElAttr::Bool(_)) => false,
         (BinElAttr::Int(i), BinElAttr::Float(f)) => *i as f32 == *f && *f as i32 == *i,
         (BinElAttr::Int(i), BinElAttr::Text(t)) => *t == i.to_string(),
-        (BinElAttr::Float(_), BinElAttr::Bool(_)) => todo!(),
+        (BinElAttr::Float(_), BinElAttr::Bool(_)) => false,
         (BinElAttr::Float(f), BinElAttr::Int(i)) => *f as i32 == *i,
-        (BinElAttr::Float(_), BinElAttr::Text(_)) => todo!(),
-        (BinElAttr::Text(_), BinElAttr::Bool(_)) => todo!(),
+        (BinElAttr::Float(_), BinElAttr::Text(_)) => false,
+        (BinElAttr::Text(_), BinElAttr::Bool(_)) => false,
         (BinElAttr::Text(t), BinElAttr::Int(i)) => *t == i.to_string(),
-        (BinElAttr::Text(_), BinElAttr::Float(_)) => todo!(),
+        (BinElAttr::Text(_), BinElAttr::Float(_)) => false,
         _ => first == second,
     }
 }
@@ -0,0 +1,226 @@
+//! Keeping entity ids unique. Celeste remembers which entities are gone for good, such as
+//! collected strawberries, by their id, so two entities sharing one can make both vanish. Ids are
+//! handed out across the whole map, and entities and triggers share them.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::map_struct::{Attribute, CelesteMapEntity, CelesteMapLevel};
+
+/// The id to give the next entity added to a map made of `rooms`.
+pub fn next_entity_id<'a>(rooms: impl IntoIterator<Item = &'a CelesteMapLevel>) -> i32 {
+    rooms
+        .into_iter()
+        .flat_map(|room| room.entities.iter().chain(&room.triggers))
+        .map(|entity| entity.id)
+        .max()
+        .unwrap_or(0)
+        + 1
+}
+
+/// Every id used in `rooms`.
+pub fn entity_ids<'a>(rooms: impl IntoIterator<Item = &'a CelesteMapLevel>) -> HashSet<i32> {
+    rooms
+        .into_iter()
+        .flat_map(|room| room.entities.iter().chain(&room.triggers))
+        .map(|entity| entity.id)
+        .collect()
+}
+
+/// Give each entity in `room` whose id is in `taken`, or which shares an id with an entity before it
+/// in the room, a new id starting from `next_id`. All of the room's ids are added to `taken`.
+/// Returns how many ids changed.
+pub fn reassign_taken_ids(
+    room: &mut CelesteMapLevel,
+    taken: &mut HashSet<i32>,
+    next_id: &mut i32,
+) -> usize {
+    // don't hand out an id which comes later in this room
+    *next_id = (*next_id).max(next_entity_id([&*room]));
+    renumber_room(room, |id| {
+        if taken.insert(id) {
+            None
+        } else {
+            let new_id = *next_id;
+            *next_id += 1;
+            taken.insert(new_id);
+            Some(new_id)
+        }
+    })
+}
+
+/// Make every id in `rooms` unique. The first entity with each id keeps it and the others get new
+/// ones, which keeps what players have already collected in a released map. With `compact`, every
+/// entity is numbered again from 1 in order instead. Attributes which refer to a renumbered entity
+/// in the same room are updated. Returns how many ids changed.
+pub fn renumber_entity_ids<'a>(
+    rooms: impl IntoIterator<Item = &'a mut CelesteMapLevel>,
+    compact: bool,
+) -> usize {
+    let mut rooms = rooms.into_iter().collect::<Vec<_>>();
+    if compact {
+        let mut next_id = 1;
+        return rooms
+            .iter_mut()
+            .map(|room| assign_new_ids(room, &mut next_id))
+            .sum();
+    }
+
+    let mut next_id = next_entity_id(rooms.iter().map(|room| &**room));
+    let mut taken = HashSet::new();
+    rooms
+        .iter_mut()
+        .map(|room| reassign_taken_ids(room, &mut taken, &mut next_id))
+        .sum()
+}
+
+/// Give every entity in `room` a new id counting up from `next_id`, and update attributes which
+/// refer to them. This is for objects such as a placed prefab, whose ids mean nothing in the map
+/// they're being added to. Returns how many ids changed.
+pub fn assign_new_ids(room: &mut CelesteMapLevel, next_id: &mut i32) -> usize {
+    renumber_room(room, |_| {
+        *next_id += 1;
+        Some(*next_id - 1)
+    })
+}
+
+/// Change the ids of a room's entities to whatever `new_id` says, and update references to them.
+fn renumber_room(room: &mut CelesteMapLevel, mut new_id: impl FnMut(i32) -> Option<i32>) -> usize {
+    let mut changes = HashMap::new();
+    let mut kept = HashSet::new();
+    for entity in room.entities.iter_mut().chain(room.triggers.iter_mut()) {
+        match new_id(entity.id) {
+            Some(id) if id != entity.id => {
+                changes.entry(entity.id).or_insert(id);
+                entity.id = id;
+            }
+            _ => {
+                kept.insert(entity.id);
+            }
+        }
+    }
+    let count = changes.len();
+
+    // a reference to an id which some entity kept is taken to mean that entity
+    changes.retain(|old, _| !kept.contains(old));
+    if !changes.is_empty() {
+        for entity in room.entities.iter_mut().chain(room.triggers.iter_mut()) {
+            update_references(entity, &changes);
+        }
+    }
+    count
+}
+
+/// Whether an attribute holds the ids of other entities in the room. There's no way to know for
+/// sure, so this goes by the names mods tend to use. Strawberries' `checkpointID`, for one, is not
+/// an entity id.
+fn is_id_reference(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    [
+        "entityid",
+        "entityids",
+        "targetid",
+        "targetids",
+        "linkedid",
+        "linkedids",
+    ]
+    .iter()
+    .any(|suffix| name.ends_with(suffix))
+}
+
+fn update_references(entity: &mut CelesteMapEntity, changes: &HashMap<i32, i32>) {
+    for (name, value) in entity.attributes.iter_mut() {
+        if !is_id_reference(name) {
+            continue;
+        }
+        match value {
+            Attribute::Int(id) => {
+                if let Some(new_id) = changes.get(id) {
+                    *id = *new_id;
+                }
+            }
+            Attribute::Text(text) => {
+                // a list of ids, separated by commas
+                if let Ok(ids) = text
+                    .split(',')
+                    .map(|id| id.trim().parse::<i32>())
+                    .collect::<Result<Vec<_>, _>>()
+                {
+                    *text = ids
+                        .iter()
+                        .map(|id| changes.get(id).unwrap_or(id).to_string())
+                        .collect::<Vec<_>>()
+                        .join(",");
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entity(id: i32, attributes: &[(&str, Attribute)]) -> CelesteMapEntity {
+        CelesteMapEntity::new(id, "strawberry", 0, 0).with_attributes(attributes)
+    }
+
+    fn ids(room: &CelesteMapLevel) -> Vec<i32> {
+        room.entities
+            .iter()
+            .chain(&room.triggers)
+            .map(|e| e.id)
+            .collect()
+    }
+
+    #[test]
+    fn duplicates_get_new_ids() {
+        let mut a = CelesteMapLevel::default();
+        a.entities.push(entity(1, &[]));
+        a.entities.push(entity(5, &[]));
+        let mut b = CelesteMapLevel::default();
+        b.entities.push(entity(5, &[]));
+        b.entities.push(entity(
+            2,
+            &[
+                ("linkedEntityID", Attribute::Int(5)),
+                ("targetIds", Attribute::Text("5, 9".to_owned())),
+                ("checkpointID", Attribute::Int(5)),
+            ],
+        ));
+        b.triggers.push(entity(1, &[]));
+
+        assert_eq!(renumber_entity_ids([&mut a, &mut b], false), 2);
+        assert_eq!(ids(&a), [1, 5]);
+        assert_eq!(ids(&b), [6, 2, 7]);
+        let attrs = &b.entities[1].attributes;
+        assert_eq!(attrs["linkedEntityID"], Attribute::Int(6));
+        assert_eq!(attrs["targetIds"], Attribute::Text("6,9".to_owned()));
+        assert_eq!(attrs["checkpointID"], Attribute::Int(5));
+
+        assert_eq!(renumber_entity_ids([&mut a, &mut b], true), 4);
+        assert_eq!(ids(&a), [1, 2]);
+        assert_eq!(ids(&b), [3, 4, 5]);
+        assert_eq!(
+            b.entities[1].attributes["linkedEntityID"],
+            Attribute::Int(3)
+        );
+    }
+
+    #[test]
+    fn placed_objects_keep_referring_to_each_other() {
+        let mut room = CelesteMapLevel::default();
+        room.entities.push(entity(4, &[]));
+        room.triggers
+            .push(entity(9, &[("targetIds", Attribute::Text("4".to_owned()))]));
+
+        let mut next_id = 20;
+        assert_eq!(assign_new_ids(&mut room, &mut next_id), 2);
+        assert_eq!(ids(&room), [20, 21]);
+        assert_eq!(next_id, 22);
+        assert_eq!(
+            room.triggers[0].attributes["targetIds"],
+            Attribute::Text("20".to_owned())
+        );
+    }
+}
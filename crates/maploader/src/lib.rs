@@ -1,4 +1,12 @@
 #[macro_use]
 pub mod from_binel;
 pub mod binel;
+pub mod diff;
+pub mod ids;
 pub mod map_struct;
+pub mod merge;
+pub mod meta_yaml;
+pub mod repair;
+pub mod replace;
+pub mod stats;
+pub mod tmx;
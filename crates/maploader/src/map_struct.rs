@@ -1,9 +1,10 @@
 #![allow(unused_parens)] // TODO: ???
 
 use arborio_utils::vizia::prelude::{Data, Lens};
+use indexmap::IndexMap;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use std::convert::{TryFrom, TryInto};
 use std::error::Error;
 use std::fmt::Debug;
@@ -18,7 +19,9 @@ use arborio_utils::vizia::prelude::*;
 
 use crate::binel::parser::take_file;
 use crate::binel::{BinEl, BinElAttr, BinFile};
-use crate::from_binel::{GetAttrOrChild, TryFromBinEl, TwoWayConverter};
+use crate::from_binel::{ElementLayout, GetAttrOrChild, TryFromBinEl, TwoWayConverter};
+use crate::meta_yaml::{self, sidecar_path, MapMetaYaml};
+use crate::repair::{repair_map, MapWarning};
 
 #[derive(Clone, Debug, TryFromBinEl)]
 #[convert_with(MapComponentConverter)]
@@ -47,10 +50,22 @@ pub struct CelesteMap {
     #[optional]
     #[name("meta")]
     pub meta: Option<CelesteMapMeta>,
+    #[layout]
+    pub layout: ElementLayout,
+    /// The package name the map was loaded with, which is kept when it is saved.
+    #[bin_el_skip]
+    pub package: Option<String>,
+    /// The string lookup the map was loaded with. Saving starts from it so that an unchanged map
+    /// is written back byte for byte.
+    #[bin_el_skip]
+    pub lookup: Vec<String>,
+    /// The map's `map.meta.yaml` sidecar, if it has one. It is saved next to the map.
+    #[bin_el_skip]
+    pub meta_yaml: Option<MapMetaYaml>,
 }
 
 // this is a fucking mess.
-#[derive(Debug, TryFromBinEl)]
+#[derive(Debug, Clone, TryFromBinEl, Default)]
 #[name("meta")]
 pub struct CelesteMapMeta {
     #[name("OverrideASideMeta")]
@@ -119,14 +134,16 @@ pub struct CelesteMapMeta {
     #[name("PostcardSoundID")]
     #[optional]
     pub postcard_sound_id: Option<String>,
-    // TODO more fields that ahorn doesn't let you change but everest will read from map.meta.yaml
+    // the fields which only everest reads are in the map.meta.yaml sidecar; see meta_yaml
     #[optional]
     pub mode: Option<CelesteMapMetaMode>,
     #[optional]
     pub modes: Option<Vec<CelesteMapMetaMode>>,
+    #[layout]
+    pub layout: ElementLayout,
 }
 
-#[derive(Debug, TryFromBinEl, Default)]
+#[derive(Debug, Clone, TryFromBinEl, Default)]
 #[name("mode")]
 pub struct CelesteMapMetaMode {
     #[name("HeartIsEnd")]
@@ -150,15 +167,19 @@ pub struct CelesteMapMetaMode {
     #[name("audiostate")]
     #[optional]
     pub audio_state: Option<CelesteMapMetaAudioState>,
+    #[layout]
+    pub layout: ElementLayout,
 }
 
-#[derive(Debug, TryFromBinEl)]
+#[derive(Debug, Clone, TryFromBinEl)]
 #[name("audiostate")]
 pub struct CelesteMapMetaAudioState {
     #[name("Ambience")]
     pub ambience: String,
     #[name("Music")]
     pub music: String,
+    #[layout]
+    pub layout: ElementLayout,
 }
 
 #[derive(Debug, Lens, Serialize, Deserialize, Clone)]
@@ -193,6 +214,13 @@ pub struct CelesteMapLevel {
     pub bg_decals: Vec<CelesteMapDecal>,
     pub fg_tiles: TileGrid<i32>,
     pub bg_tiles: TileGrid<i32>,
+
+    /// Attributes and children which aren't understood, such as ones added by helper mods or
+    /// other editors. They are kept so that saving doesn't lose them.
+    pub attributes: IndexMap<String, Attribute>,
+    pub children: Vec<BinEl>,
+    #[serde(skip)]
+    pub layout: ElementLayout,
 }
 
 impl Default for CelesteMapLevel {
@@ -227,6 +255,9 @@ impl Default for CelesteMapLevel {
             bg_decals: vec![],
             fg_tiles: TileGrid::new(tile_size, -1),
             bg_tiles: TileGrid::new(tile_size, -1),
+            attributes: IndexMap::new(),
+            children: vec![],
+            layout: ElementLayout::default(),
         }
     }
 }
@@ -299,7 +330,7 @@ impl CelesteMapLevel {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, TryFromBinEl, Lens, Serialize, Deserialize, Data)]
+#[derive(Debug, Clone, PartialEq, TryFromBinEl, Lens, Serialize, Deserialize)]
 pub struct CelesteMapEntity {
     pub id: i32,
     #[name]
@@ -311,9 +342,44 @@ pub struct CelesteMapEntity {
     #[default]
     pub height: u32,
     #[attributes]
-    pub attributes: HashMap<String, Attribute>,
+    pub attributes: IndexMap<String, Attribute>,
     #[children]
     pub nodes: Vec<Node>,
+    #[layout]
+    #[serde(skip)]
+    pub layout: ElementLayout,
+}
+
+impl Data for CelesteMapEntity {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl CelesteMapEntity {
+    /// An entity or trigger with no size, attributes or nodes, for code which builds maps itself
+    /// rather than loading them.
+    pub fn new(id: i32, name: &str, x: i32, y: i32) -> Self {
+        Self {
+            id,
+            name: name.to_owned(),
+            x,
+            y,
+            width: 0,
+            height: 0,
+            attributes: IndexMap::new(),
+            nodes: vec![],
+            layout: ElementLayout::default(),
+        }
+    }
+
+    /// Add or replace attributes. New ones are kept in the order they are given in.
+    pub fn with_attributes(mut self, attributes: &[(&str, Attribute)]) -> Self {
+        for (key, value) in attributes {
+            self.attributes.insert((*key).to_owned(), value.clone());
+        }
+        self
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, TryFromBinEl, Serialize, Deserialize, Data)]
@@ -340,6 +406,26 @@ pub struct CelesteMapDecal {
     #[name("scaleY")]
     pub scale_y: f32,
     pub texture: String,
+    /// Clockwise, in degrees.
+    #[default]
+    pub rotation: f32,
+    /// A hex tint, either RRGGBB or RRGGBBAA.
+    #[optional]
+    pub color: Option<String>,
+    /// Overrides the depth of the decal layer the decal is in.
+    #[optional]
+    pub depth: Option<i32>,
+    #[layout]
+    #[serde(skip)]
+    pub layout: ElementLayout,
+}
+
+impl CelesteMapDecal {
+    /// The depth of the decal, which is the depth of its layer unless it sets its own. Larger
+    /// depths are further back.
+    pub fn effective_depth(&self, fg: bool) -> i32 {
+        self.depth.unwrap_or(if fg { -10500 } else { 9000 })
+    }
 }
 
 #[derive(Debug, TryFromBinEl, Lens, Clone)]
@@ -409,9 +495,11 @@ pub struct CelesteMapStyleground {
     pub fade_y: FadeDirectives,
 
     #[attributes]
-    pub attributes: HashMap<String, Attribute>,
+    pub attributes: IndexMap<String, Attribute>,
     #[children]
     pub children: Vec<BinEl>,
+    #[layout]
+    pub layout: ElementLayout,
 }
 
 #[derive(Debug, Clone)]
@@ -479,6 +567,7 @@ impl Default for CelesteMapStyleground {
             fade_y: Default::default(),
             attributes: Default::default(),
             children: vec![],
+            layout: Default::default(),
         }
     }
 }
@@ -602,17 +691,6 @@ impl CelesteMapLevel {
         tiles.get(pt).copied()
     }
 
-    pub fn next_id(&self) -> i32 {
-        let mut highest_entity = -1;
-        for e in &self.entities {
-            highest_entity = highest_entity.max(e.id);
-        }
-        for e in &self.triggers {
-            highest_entity = highest_entity.max(e.id);
-        }
-        highest_entity + 1
-    }
-
     pub fn occupancy_field(&self) -> TileGrid<FieldEntry> {
         let mut result = TileGrid::new_default(size_room_to_tile(&self.bounds.size.cast_unit()));
         for entity in &self.entities {
@@ -679,45 +757,87 @@ impl CelesteMap {
         }
         None
     }
+
+    /// The meta Everest plays the map with: the one in the .bin, with the `map.meta.yaml` sidecar
+    /// applied over it. This is a copy; the sidecar's values are never saved into the .bin.
+    pub fn effective_meta(&self) -> Option<CelesteMapMeta> {
+        let Some(meta_yaml) = &self.meta_yaml else {
+            return self.meta.clone();
+        };
+        let mut meta = self.meta.clone().unwrap_or_default();
+        meta_yaml.apply_to(&mut meta);
+        Some(meta)
+    }
+}
+
+pub fn from_reader(reader: impl std::io::Read) -> Result<CelesteMap, std::io::Error> {
+    read_binfile(reader).and_then(|binfile| from_binfile(binfile).map_err(validation_error))
 }
 
-pub fn from_reader(mut reader: impl std::io::Read) -> Result<CelesteMap, std::io::Error> {
+/// Like `from_reader`, but skips or repairs anything which would stop the map from loading. What
+/// was done is returned alongside the map.
+pub fn from_reader_tolerant(
+    reader: impl std::io::Read,
+) -> Result<(CelesteMap, Vec<MapWarning>), std::io::Error> {
+    read_binfile(reader)
+        .and_then(|binfile| from_binfile_tolerant(binfile).map_err(validation_error))
+}
+
+fn read_binfile(mut reader: impl std::io::Read) -> Result<BinFile, std::io::Error> {
     let mut file = vec![];
     reader.read_to_end(&mut file)?;
-    let (_, binfile) = take_file(file.as_slice())
-        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "Not a Celeste map"))?;
-    let map = from_binfile(binfile).map_err(|e| {
-        std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            format!("Data validation error: {e}"),
-        )
-    })?;
+    take_file(file.as_slice()).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
 
-    Ok(map)
+fn validation_error(e: CelesteMapError) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("Data validation error: {e}"),
+    )
 }
 
 pub fn from_binfile(binfile: BinFile) -> Result<CelesteMap, CelesteMapError> {
     expect_elem!(binfile.root, "Map");
-    CelesteMap::try_from_bin_el(&binfile.root)
+    let mut map = CelesteMap::try_from_bin_el(&binfile.root)?;
+    map.package = Some(binfile.package);
+    map.lookup = binfile.lookup;
+    Ok(map)
+}
+
+/// Like `from_binfile`, but skips or repairs anything which would stop the map from loading.
+pub fn from_binfile_tolerant(
+    mut binfile: BinFile,
+) -> Result<(CelesteMap, Vec<MapWarning>), CelesteMapError> {
+    expect_elem!(binfile.root, "Map");
+    let warnings = repair_map(&mut binfile.root);
+    Ok((from_binfile(binfile)?, warnings))
 }
 
+/// Save a map to `path`, along with its `map.meta.yaml` sidecar if it has one.
 pub fn save_as(map: &CelesteMap, package: &str, path: &Path) -> Result<(), io::Error> {
     save_to(
         map,
         package,
         &mut io::BufWriter::new(std::fs::File::create(path)?),
-    )
+    )?;
+    if let Some(meta) = &map.meta_yaml {
+        let file = io::BufWriter::new(std::fs::File::create(sidecar_path(path))?);
+        meta_yaml::to_writer(file, meta).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    }
+    Ok(())
 }
 
+/// Write a map to `writer`. If the map was loaded from a file, its package name and string lookup
+/// are kept and `package` is ignored.
 pub fn save_to<W: io::Write>(
     map: &CelesteMap,
     package: &str,
     writer: &mut W,
 ) -> Result<(), io::Error> {
-    let binel: BinEl = map.to_binel();
     let file = BinFile {
-        root: binel,
-        package: package.to_owned(),
+        root: map.to_binel(),
+        package: map.package.clone().unwrap_or_else(|| package.to_owned()),
+        lookup: map.lookup.clone(),
     };
 
     crate::binel::writer::put_file(writer, &file)
@@ -731,6 +851,12 @@ impl TryFromBinEl for CelesteMapLevel {
         let y = get_attr(elem, "y")?;
         let width = get_attr(elem, "width")?;
         let height = get_attr(elem, "height")?;
+        if width < 0 || height < 0 {
+            return Err(CelesteMapError {
+                kind: CelesteMapErrorType::OutOfRange,
+                description: format!("Level has a negative size {width}x{height}"),
+            });
+        }
         let bounds = MapRectStrict {
             origin: Point2D::new(x, y),
             size: Size2D::new(width, height),
@@ -808,6 +934,19 @@ impl TryFromBinEl for CelesteMapLevel {
         let entities = DefaultConverter::from_bin_el(elem, "entities")?;
         let triggers = TryFromBinEl::try_from_bin_el(get_child(elem, "triggers")?)?;
 
+        let attributes = elem
+            .attributes
+            .iter()
+            .filter(|(k, _)| !LEVEL_ATTRIBUTES.contains(&k.as_str()))
+            .map(|(k, v)| (k.to_owned(), v.clone().into()))
+            .collect();
+        let children = elem
+            .children()
+            .filter(|child| !LEVEL_CHILDREN.contains(&child.name.as_str()))
+            .cloned()
+            .collect();
+        let layout = ElementLayout::new(elem, &[], LEVEL_CHILDREN);
+
         Ok(CelesteMapLevel {
             bounds,
             name,
@@ -839,6 +978,9 @@ impl TryFromBinEl for CelesteMapLevel {
             bg_decals,
             fg_tiles,
             bg_tiles,
+            attributes,
+            children,
+            layout,
         })
     }
 
@@ -858,82 +1000,147 @@ impl TryFromBinEl for CelesteMapLevel {
         DefaultConverter::set_bin_el(&mut elem, "y", y);
         DefaultConverter::set_bin_el(&mut elem, "width", width);
         DefaultConverter::set_bin_el(&mut elem, "height", height);
-        DefaultConverter::set_bin_el(&mut elem, "fgdecals", &self.fg_decals);
-        DefaultConverter::set_bin_el(&mut elem, "bgdecals", &self.bg_decals);
+        // rooms made by other tools may leave out empty lists and tile grids
+        let new = self.layout.is_empty();
+        if new || self.layout.has("fgdecals") || !self.fg_decals.is_empty() {
+            DefaultConverter::set_bin_el(&mut elem, "fgdecals", &self.fg_decals);
+        }
+        if new || self.layout.has("bgdecals") || !self.bg_decals.is_empty() {
+            DefaultConverter::set_bin_el(&mut elem, "bgdecals", &self.bg_decals);
+        }
         DefaultConverter::set_bin_el(&mut elem, "name", &self.name);
         DefaultConverter::set_bin_el(&mut elem, "c", &self.color);
-        DefaultConverter::set_bin_el_default(&mut elem, "cameraOffsetX", &self.camera_offset_x);
-        DefaultConverter::set_bin_el_default(&mut elem, "cameraOffsetY", &self.camera_offset_y);
-        DefaultConverter::set_bin_el_default(&mut elem, "windPattern", &self.wind_pattern);
-        DefaultConverter::set_bin_el_default(&mut elem, "space", &self.space);
-        DefaultConverter::set_bin_el_default(&mut elem, "underwater", &self.underwater);
-        DefaultConverter::set_bin_el_default(&mut elem, "whisper", &self.whisper);
-        DefaultConverter::set_bin_el_default(&mut elem, "dark", &self.dark);
-        DefaultConverter::set_bin_el_default(&mut elem, "space", &self.space);
-        DefaultConverter::set_bin_el_default(
+        let layout = &self.layout;
+        DefaultConverter::set_bin_el_kept(
+            &mut elem,
+            "cameraOffsetX",
+            &self.camera_offset_x,
+            layout,
+        );
+        DefaultConverter::set_bin_el_kept(
+            &mut elem,
+            "cameraOffsetY",
+            &self.camera_offset_y,
+            layout,
+        );
+        DefaultConverter::set_bin_el_kept(&mut elem, "windPattern", &self.wind_pattern, layout);
+        DefaultConverter::set_bin_el_kept(&mut elem, "space", &self.space, layout);
+        DefaultConverter::set_bin_el_kept(&mut elem, "underwater", &self.underwater, layout);
+        DefaultConverter::set_bin_el_kept(&mut elem, "whisper", &self.whisper, layout);
+        DefaultConverter::set_bin_el_kept(&mut elem, "dark", &self.dark, layout);
+        DefaultConverter::set_bin_el_kept(
             &mut elem,
             "disableDownTransition",
             &self.disable_down_transition,
+            layout,
         );
-        DefaultConverter::set_bin_el_default(
+        DefaultConverter::set_bin_el_kept(
             &mut elem,
             "enforceDashNumber",
             &self.enforce_dash_number,
+            layout,
         );
 
-        DefaultConverter::set_bin_el_default(&mut elem, "music", &self.music);
-        DefaultConverter::set_bin_el_default(&mut elem, "alt_music", &self.alt_music);
-        DefaultConverter::set_bin_el_default(&mut elem, "ambience", &self.ambience);
-        DefaultConverter::set_bin_el(&mut elem, "musicLayer1", &self.music_layers[0]);
-        DefaultConverter::set_bin_el(&mut elem, "musicLayer2", &self.music_layers[1]);
-        DefaultConverter::set_bin_el(&mut elem, "musicLayer3", &self.music_layers[2]);
-        DefaultConverter::set_bin_el(&mut elem, "musicLayer4", &self.music_layers[3]);
+        DefaultConverter::set_bin_el_kept(&mut elem, "music", &self.music, layout);
+        DefaultConverter::set_bin_el_kept(&mut elem, "alt_music", &self.alt_music, layout);
+        DefaultConverter::set_bin_el_kept(&mut elem, "ambience", &self.ambience, layout);
+        for (idx, key) in ["musicLayer1", "musicLayer2", "musicLayer3", "musicLayer4"]
+            .into_iter()
+            .enumerate()
+        {
+            if new || layout.has(key) || self.music_layers[idx] {
+                DefaultConverter::set_bin_el(&mut elem, key, &self.music_layers[idx]);
+            }
+        }
         //DefaultConverter::set_bin_el(&mut elem, "musicLayer5", &self.music_layers[4]);
         //DefaultConverter::set_bin_el(&mut elem, "musicLayer6", &self.music_layers[5]);
-        DefaultConverter::set_bin_el_default(&mut elem, "musicProgress", &self.music_progress);
-        DefaultConverter::set_bin_el_default(
+        DefaultConverter::set_bin_el_kept(&mut elem, "musicProgress", &self.music_progress, layout);
+        DefaultConverter::set_bin_el_kept(
             &mut elem,
             "ambienceProgress",
             &self.ambience_progress,
+            layout,
         );
-        DefaultConverter::set_bin_el_default(
+        DefaultConverter::set_bin_el_kept(
             &mut elem,
             "delayAltMusicFade",
             &self.delay_alt_music_fade,
+            layout,
         );
 
-        GetAttrOrChild::nested_apply_attr_or_child(
-            &mut elem,
-            "solids",
-            serialize_tiles(&self.solids, '0', ""),
-        );
-        GetAttrOrChild::nested_apply_attr_or_child(
-            &mut elem,
-            "bg",
-            serialize_tiles(&self.bg, '0', ""),
-        );
-        GetAttrOrChild::nested_apply_attr_or_child(
-            &mut elem,
-            "objtiles",
-            serialize_tiles(&self.object_tiles, -1, ","),
-        );
-        GetAttrOrChild::nested_apply_attr_or_child(
-            &mut elem,
-            "fgtiles",
-            serialize_tiles(&self.fg_tiles, -1, ","),
-        );
-        GetAttrOrChild::nested_apply_attr_or_child(
-            &mut elem,
-            "bgtiles",
-            serialize_tiles(&self.bg_tiles, -1, ","),
-        );
+        // celeste stores the fg and bg tiles run-length encoded
+        let mut solids = serialize_tiles(&self.solids, '0', "");
+        solids.rle_attributes.insert("innerText".to_owned());
+        let mut bg = serialize_tiles(&self.bg, '0', "");
+        bg.rle_attributes.insert("innerText".to_owned());
+        for (key, mut grid) in [
+            ("solids", solids),
+            ("bg", bg),
+            ("objtiles", serialize_tiles(&self.object_tiles, -1, ",")),
+            ("fgtiles", serialize_tiles(&self.fg_tiles, -1, ",")),
+            ("bgtiles", serialize_tiles(&self.bg_tiles, -1, ",")),
+        ] {
+            let kept = layout.container(key);
+            let required = ["solids", "bg"].contains(&key);
+            if !(new || required || kept.is_some() || grid.text().is_some()) {
+                continue;
+            }
+            // a grid which was read with its text keeps it, even when it's empty
+            if matches!(kept, Some(kept) if kept.has("innerText")) {
+                grid.attributes
+                    .entry("innerText".to_owned())
+                    .or_insert_with(|| BinElAttr::Text(String::new()));
+            }
+            GetAttrOrChild::nested_apply_attr_or_child(&mut elem, key, grid);
+        }
         DefaultConverter::set_bin_el(&mut elem, "entities", &self.entities);
         DefaultConverter::set_bin_el(&mut elem, "triggers", &self.triggers);
 
+        for (k, v) in &self.attributes {
+            elem.attributes.insert(k.clone(), v.into());
+        }
+        for child in &self.children {
+            elem.insert(child.clone());
+        }
+        self.layout.apply(&mut elem);
+
         elem
     }
 }
 
+// the attributes and children which are read into the fields of CelesteMapLevel
+pub(crate) const LEVEL_ATTRIBUTES: &[&str] = &[
+    "x",
+    "y",
+    "width",
+    "height",
+    "name",
+    "c",
+    "cameraOffsetX",
+    "cameraOffsetY",
+    "windPattern",
+    "space",
+    "underwater",
+    "whisper",
+    "dark",
+    "disableDownTransition",
+    "enforceDashNumber",
+    "music",
+    "alt_music",
+    "ambience",
+    "musicLayer1",
+    "musicLayer2",
+    "musicLayer3",
+    "musicLayer4",
+    "musicProgress",
+    "ambienceProgress",
+    "delayAltMusicFade",
+];
+pub(crate) const LEVEL_CHILDREN: &[&str] = &[
+    "fgdecals", "bgdecals", "solids", "bg", "objtiles", "fgtiles", "bgtiles", "entities",
+    "triggers",
+];
+
 fn serialize_tiles<T: Copy + PartialEq + ToString>(
     tiles: &TileGrid<T>,
     default: T,
@@ -1250,7 +1457,12 @@ impl<T: Copy + TryFrom<i32> + TryInto<i32>> TwoWayConverter<T> for MapComponentC
 
     fn try_parse(elem: &Self::BinType) -> Result<T, CelesteMapError> {
         if let Some(i) = i32::try_coerce(elem) {
-            Ok((i * 8).try_into().ok().unwrap())
+            i.checked_mul(8)
+                .and_then(|i| i.try_into().ok())
+                .ok_or_else(|| CelesteMapError {
+                    kind: CelesteMapErrorType::OutOfRange,
+                    description: format!("{i} is out of range"),
+                })
         } else {
             Err(CelesteMapError {
                 kind: CelesteMapErrorType::BadAttrType,
@@ -1284,10 +1496,7 @@ impl<T: Copy + TryFrom<i32> + TryInto<i32>> TwoWayConverter<T> for MapComponentC
 
 pub fn get_optional_child<'a>(elem: &'a BinEl, name: &str) -> Option<&'a BinEl> {
     // if there is exactly one child
-    let [ref child] = elem.get(name) else {
-        return None
-    };
-    Some(child)
+    elem.get(name).exactly_one().ok()
 }
 
 fn get_child<'a>(elem: &'a BinEl, name: &str) -> Result<&'a BinEl, CelesteMapError> {
@@ -1295,12 +1504,7 @@ fn get_child<'a>(elem: &'a BinEl, name: &str) -> Result<&'a BinEl, CelesteMapErr
 }
 
 pub fn get_child_mut<'a>(elem: &'a mut BinEl, name: &str) -> &'a mut BinEl {
-    let children = elem.get_mut(name);
-    if children.is_empty() {
-        children.push(BinEl::new(name));
-    }
-    let [child] = children.as_mut_slice() else { panic!() };
-    child
+    elem.get_or_insert(name)
 }
 
 fn get_optional_attr<T>(elem: &BinEl, name: &str) -> Result<Option<T>, CelesteMapError>
@@ -1403,3 +1607,232 @@ impl AttrCoercion for String {
         BinElAttr::Text(self.clone())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::binel::writer::put_file;
+
+    fn elem(name: &str, attrs: &[(&str, BinElAttr)], children: Vec<BinEl>) -> BinEl {
+        let mut elem = BinEl::new(name);
+        for (key, value) in attrs {
+            elem.attributes.insert((*key).to_owned(), value.clone());
+        }
+        for child in children {
+            elem.insert(child);
+        }
+        elem
+    }
+
+    fn text(s: &str) -> BinElAttr {
+        BinElAttr::Text(s.to_owned())
+    }
+
+    fn entity(id: i32, attrs: &[(&str, BinElAttr)]) -> BinEl {
+        let mut result = elem(
+            "spring",
+            &[("y", BinElAttr::Int(16)), ("id", BinElAttr::Int(id))],
+            vec![],
+        );
+        for (key, value) in attrs {
+            result.attributes.insert((*key).to_owned(), value.clone());
+        }
+        result
+            .attributes
+            .insert("x".to_owned(), BinElAttr::Int(8 * id));
+        result
+    }
+
+    /// A map laid out the way other tools write them, in an order unlike the one Arborio uses.
+    fn unusual_map() -> BinEl {
+        let mut solids = elem("solids", &[("innerText", text("0000\n0110"))], vec![]);
+        solids.rle_attributes.insert("innerText".to_owned());
+        let level = elem(
+            "level",
+            &[
+                ("name", text("lvl_a-00")),
+                ("width", BinElAttr::Int(32)),
+                ("height", BinElAttr::Int(16)),
+                ("x", BinElAttr::Int(0)),
+                ("y", BinElAttr::Int(0)),
+                ("cameraOffsetX", BinElAttr::Int(4)),
+                ("helperFlag", text("on")),
+                ("space", BinElAttr::Bool(false)),
+                ("c", BinElAttr::Int(2)),
+                ("musicLayer1", BinElAttr::Bool(true)),
+            ],
+            vec![
+                solids,
+                elem(
+                    "entities",
+                    &[],
+                    vec![
+                        entity(1, &[("count", text("5")), ("width", BinElAttr::Int(0))]),
+                        entity(2, &[("mode", text("fast"))]),
+                    ],
+                ),
+                elem("helperData", &[("kind", text("extra"))], vec![]),
+                elem("triggers", &[], vec![]),
+                elem("bg", &[], vec![]),
+                elem(
+                    "fgdecals",
+                    &[("tileset", text("scenery"))],
+                    vec![elem(
+                        "decal",
+                        &[
+                            ("texture", text("1-forsakencity/sign.png")),
+                            ("x", BinElAttr::Int(4)),
+                            ("y", BinElAttr::Int(4)),
+                            ("scaleX", BinElAttr::Int(1)),
+                            ("scaleY", BinElAttr::Float(-1.0)),
+                        ],
+                        vec![],
+                    )],
+                ),
+            ],
+        );
+        elem(
+            "Map",
+            &[],
+            vec![
+                elem(
+                    "meta",
+                    &[
+                        ("Dreaming", BinElAttr::Bool(false)),
+                        ("ColorGrade", text("none")),
+                    ],
+                    vec![elem(
+                        "mode",
+                        &[("HeartIsEnd", BinElAttr::Bool(true))],
+                        vec![],
+                    )],
+                ),
+                elem("levels", &[], vec![level]),
+                elem(
+                    "Style",
+                    &[("color", text("000000"))],
+                    vec![
+                        elem("Backgrounds", &[], vec![]),
+                        elem(
+                            "Foregrounds",
+                            &[],
+                            vec![elem(
+                                "parallax",
+                                &[("texture", text("bgs/stars")), ("alpha", BinElAttr::Int(1))],
+                                vec![],
+                            )],
+                        ),
+                    ],
+                ),
+                elem("Filler", &[], vec![]),
+            ],
+        )
+    }
+
+    fn write(root: BinEl, lookup: Vec<String>) -> Vec<u8> {
+        let mut bytes = vec![];
+        let file = BinFile {
+            root,
+            package: "unusual".to_owned(),
+            lookup,
+        };
+        put_file(&mut bytes, &file).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn an_unchanged_map_is_saved_byte_for_byte() {
+        let bytes = write(unusual_map(), vec![]);
+        let map = from_reader(bytes.as_slice()).unwrap();
+        let mut saved = vec![];
+        save_to(&map, "ignored", &mut saved).unwrap();
+        assert_eq!(saved, bytes);
+    }
+
+    #[test]
+    fn deleting_an_entity_keeps_the_layout_of_the_rest() {
+        let bytes = write(unusual_map(), vec![]);
+        let mut map = from_reader(bytes.as_slice()).unwrap();
+        let lookup = map.lookup.clone();
+        map.levels[0].entities.remove(0);
+        let mut saved = vec![];
+        save_to(&map, "ignored", &mut saved).unwrap();
+
+        let mut expected = unusual_map();
+        let level = expected.get_or_insert("levels").get_or_insert("level");
+        level
+            .get_or_insert("entities")
+            .retain_children(|entity| entity.attributes.get("id") != Some(&BinElAttr::Int(1)));
+        assert_eq!(saved, write(expected, lookup));
+    }
+
+    #[test]
+    fn unknown_level_attributes_and_children_survive() {
+        let level = elem(
+            "level",
+            &[
+                ("name", text("lvl_a-00")),
+                ("x", BinElAttr::Int(0)),
+                ("y", BinElAttr::Int(0)),
+                ("width", BinElAttr::Int(32)),
+                ("height", BinElAttr::Int(16)),
+                ("helperFlag", text("on")),
+                ("helperCount", BinElAttr::Int(3)),
+            ],
+            vec![
+                elem("solids", &[], vec![]),
+                elem("bg", &[], vec![]),
+                elem("entities", &[], vec![]),
+                elem("triggers", &[], vec![]),
+                elem(
+                    "helperData",
+                    &[("kind", text("extra"))],
+                    vec![elem("item", &[], vec![])],
+                ),
+            ],
+        );
+        let mut room = CelesteMapLevel::try_from_bin_el(&level).unwrap();
+        assert_eq!(room.attributes.len(), 2);
+        assert_eq!(room.children.len(), 1);
+
+        // editing the room doesn't drop what it doesn't understand
+        room.name = "lvl_a-01".to_owned();
+        room.attributes
+            .insert("helperCount".to_owned(), Attribute::Int(4));
+        let saved = room.to_binel();
+        assert_eq!(saved.attributes.get("helperFlag"), Some(&text("on")));
+        assert_eq!(
+            saved.attributes.get("helperCount"),
+            Some(&BinElAttr::Int(4))
+        );
+        let helper = saved.get("helperData").next().unwrap();
+        assert_eq!(Some(helper), level.get("helperData").next());
+
+        let reloaded = CelesteMapLevel::try_from_bin_el(&saved).unwrap();
+        assert_eq!(reloaded.attributes, room.attributes);
+        assert_eq!(reloaded.children, room.children);
+    }
+
+    #[test]
+    fn decal_rotation_color_and_depth_survive_a_round_trip() {
+        let bytes = write(unusual_map(), vec![]);
+        let mut map = from_reader(bytes.as_slice()).unwrap();
+        let decal = &mut map.levels[0].fg_decals[0];
+        assert_eq!(decal.rotation, 0.0);
+        assert_eq!(decal.color, None);
+        assert_eq!(decal.depth, None);
+        decal.rotation = 90.0;
+        decal.color = Some("ff000080".to_owned());
+        decal.depth = Some(-10500);
+
+        let mut saved = vec![];
+        save_to(&map, "ignored", &mut saved).unwrap();
+        let reloaded = from_reader(saved.as_slice()).unwrap();
+        let decal = &reloaded.levels[0].fg_decals[0];
+        assert_eq!(decal.rotation, 90.0);
+        assert_eq!(decal.color.as_deref(), Some("ff000080"));
+        assert_eq!(decal.depth, Some(-10500));
+        assert_eq!(decal.texture, "1-forsakencity/sign.png");
+        assert_eq!(decal.scale_y, -1.0);
+    }
+}
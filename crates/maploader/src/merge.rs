@@ -0,0 +1,591 @@
+//! Three-way merging of maps, so that people editing different parts of the same map at the same
+//! time don't have to redo each other's work.
+//!
+//! Rooms are matched by name, entities and triggers by id, decals by all of their attributes and
+//! stylegrounds by position. Anything changed on only one side is taken from that side. Anything
+//! changed differently on both sides is a conflict: our side is kept and the conflict is reported.
+
+use indexmap::IndexMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+
+use arborio_utils::units::*;
+
+use crate::binel::{BinEl, BinElAttr};
+use crate::diff::tile_rects;
+use crate::from_binel::TryFromBinEl;
+use crate::ids::renumber_entity_ids;
+use crate::map_struct::{
+    CelesteMap, CelesteMapEntity, CelesteMapLevel, CelesteMapMeta, CelesteMapStyleground,
+    LEVEL_CHILDREN,
+};
+
+/// Something which was changed differently on both sides of a merge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeConflict {
+    /// The room the conflict is in, or `None` for the map as a whole.
+    pub room: Option<String>,
+    pub description: String,
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.room {
+            Some(room) => write!(f, "room {room}: {}", self.description),
+            None => write!(f, "map: {}", self.description),
+        }
+    }
+}
+
+/// Merge the changes made in `ours` and `theirs` since `base`. Where both sides made different
+/// changes to the same thing, our side is kept and the conflict is returned. The result is saved
+/// using the layout of `ours`.
+pub fn merge_maps(
+    base: &CelesteMap,
+    ours: &CelesteMap,
+    theirs: &CelesteMap,
+) -> (CelesteMap, Vec<MergeConflict>) {
+    let mut conflicts = vec![];
+    let mut conflict = |description: String| {
+        conflicts.push(MergeConflict {
+            room: None,
+            description,
+        })
+    };
+
+    let mut filler = ours.filler.clone();
+    filler.retain(|rect| !base.filler.contains(rect) || theirs.filler.contains(rect));
+    for rect in &theirs.filler {
+        if !base.filler.contains(rect) && !filler.contains(rect) {
+            filler.push(*rect);
+        }
+    }
+
+    let background_color = pick(
+        &base.background_color,
+        &ours.background_color,
+        &theirs.background_color,
+    )
+    .unwrap_or_else(|| {
+        conflict("Style/color changed on both sides".to_owned());
+        &ours.background_color
+    })
+    .clone();
+
+    let mut styles = [vec![], vec![]];
+    for (fg, styles) in [true, false].into_iter().zip(styles.iter_mut()) {
+        let layer = if fg { "foreground" } else { "background" };
+        let [base, ours, theirs] = [base, ours, theirs].map(|map| map.styles(fg));
+        if ours.len() == theirs.len() && ours.len() == base.len() {
+            for (index, ((base, ours), theirs)) in base.iter().zip(ours).zip(theirs).enumerate() {
+                let (style, problems) = merge_objects(base, ours, theirs);
+                for problem in problems {
+                    conflict(format!("{layer} styleground {index}: {problem}"));
+                }
+                styles.push(style);
+            }
+        } else {
+            let [base, ours, theirs] = [base, ours, theirs]
+                .map(|list| list.iter().map(TryFromBinEl::to_binel).collect::<Vec<_>>());
+            *styles = if ours == base {
+                theirs
+            } else {
+                if theirs != base && theirs != ours {
+                    conflict(format!(
+                        "{layer} stylegrounds were added or removed on both sides"
+                    ));
+                }
+                ours
+            }
+            .iter()
+            .filter_map(|style| CelesteMapStyleground::try_from_bin_el(style).ok())
+            .collect();
+        }
+    }
+    let [foregrounds, backgrounds] = styles;
+
+    let meta = match [&base.meta, &ours.meta, &theirs.meta] {
+        [Some(base), Some(ours), Some(theirs)] => {
+            let (meta, problems) = merge_objects(base, ours, theirs);
+            for problem in problems {
+                conflict(format!("meta: {problem}"));
+            }
+            Some(meta)
+        }
+        metas => {
+            let [base, ours, theirs] = metas.map(|meta| meta.as_ref().map(TryFromBinEl::to_binel));
+            pick(&base, &ours, &theirs)
+                .unwrap_or_else(|| {
+                    conflict(
+                        "meta was added or removed on one side and changed on the other".to_owned(),
+                    );
+                    &ours
+                })
+                .as_ref()
+                .and_then(|meta| CelesteMapMeta::try_from_bin_el(meta).ok())
+        }
+    };
+
+    let meta_yaml = pick(&base.meta_yaml, &ours.meta_yaml, &theirs.meta_yaml)
+        .unwrap_or_else(|| {
+            conflict("map.meta.yaml was changed on both sides".to_owned());
+            &ours.meta_yaml
+        })
+        .clone();
+
+    let (levels, room_conflicts) = merge_keyed(
+        &base.levels,
+        &ours.levels,
+        &theirs.levels,
+        |room| room.name.clone(),
+        |base, ours, theirs| {
+            let (room, problems) = merge_rooms(base, ours, theirs);
+            let problems = problems.into_iter().map(|description| MergeConflict {
+                room: Some(ours.name.clone()),
+                description,
+            });
+            (room, problems.collect())
+        },
+        |room, problem| MergeConflict {
+            room: Some(room.name.clone()),
+            description: format!("room {problem}"),
+        },
+        false,
+    );
+    conflicts.extend(room_conflicts);
+
+    let mut map = CelesteMap {
+        filler,
+        background_color,
+        foregrounds,
+        backgrounds,
+        levels,
+        meta,
+        layout: ours.layout.clone(),
+        package: ours.package.clone(),
+        lookup: ours.lookup.clone(),
+        meta_yaml,
+    };
+    // ids are unique across the whole map, but both sides may have handed out the same new ones
+    renumber_entity_ids(&mut map.levels, false);
+
+    (map, conflicts)
+}
+
+/// Merge two changed versions of a room.
+fn merge_rooms(
+    base: &CelesteMapLevel,
+    ours: &CelesteMapLevel,
+    theirs: &CelesteMapLevel,
+) -> (CelesteMapLevel, Vec<String>) {
+    let mut conflicts = vec![];
+
+    // everything except the contents of the room is merged as elements, which covers attributes
+    // and children which aren't otherwise understood
+    let [base_el, ours_el, theirs_el] = [base, ours, theirs].map(|room| {
+        let mut binel = room.to_binel();
+        binel.retain_children(|child| !LEVEL_CHILDREN.contains(&child.name.as_str()));
+        binel
+    });
+    let (merged_el, problems) = merge_elements(&base_el, &ours_el, &theirs_el);
+    conflicts.extend(problems);
+    let mut room = if merged_el == ours_el {
+        ours.clone()
+    } else {
+        let mut binel = ours.to_binel();
+        binel.attributes = merged_el.attributes.clone();
+        binel.retain_children(|child| LEVEL_CHILDREN.contains(&child.name.as_str()));
+        for child in merged_el.children() {
+            binel.insert(child.clone());
+        }
+        CelesteMapLevel::try_from_bin_el(&binel).unwrap_or_else(|e| {
+            conflicts.push(format!("could not merge the room's properties ({e})"));
+            ours.clone()
+        })
+    };
+
+    for (layer, base, ours, theirs, result) in [
+        (
+            "entities",
+            &base.entities,
+            &ours.entities,
+            &theirs.entities,
+            &mut room.entities,
+        ),
+        (
+            "triggers",
+            &base.triggers,
+            &ours.triggers,
+            &theirs.triggers,
+            &mut room.triggers,
+        ),
+    ] {
+        let describe = |entity: &CelesteMapEntity| format!("{} (id {})", entity.name, entity.id);
+        let (merged, problems) = merge_keyed(
+            base,
+            ours,
+            theirs,
+            |entity| entity.id,
+            |base, ours, theirs| {
+                let (entity, problems) = merge_objects(base, ours, theirs);
+                let problems = problems
+                    .into_iter()
+                    .map(|problem| format!("{layer}: {}: {problem}", describe(ours)));
+                (entity, problems.collect())
+            },
+            |entity, problem| format!("{layer}: {} {problem}", describe(entity)),
+            true,
+        );
+        *result = merged;
+        conflicts.extend(problems);
+    }
+
+    for (layer, base, ours, theirs, result) in [
+        (
+            "fgdecals",
+            &base.fg_decals,
+            &ours.fg_decals,
+            &theirs.fg_decals,
+            &mut room.fg_decals,
+        ),
+        (
+            "bgdecals",
+            &base.bg_decals,
+            &ours.bg_decals,
+            &theirs.bg_decals,
+            &mut room.bg_decals,
+        ),
+    ] {
+        // decals have no identity beyond their attributes, so any change is a removal and an
+        // addition, and the only conflict left is both sides adding the same decal
+        let (merged, problems) = merge_keyed(
+            base,
+            ours,
+            theirs,
+            |decal| format!("{:?}", decal.to_binel().attributes),
+            |_, ours, _| (ours.clone(), vec![]),
+            |decal, problem| {
+                format!(
+                    "{layer}: {} at ({}, {}) {problem}",
+                    decal.texture, decal.x, decal.y
+                )
+            },
+            true,
+        );
+        *result = merged;
+        conflicts.extend(problems);
+    }
+
+    let size = rect_room_to_tile(&room.room_bounds()).size;
+    for (layer, base, ours, theirs, result) in [
+        (
+            "solids",
+            &base.solids,
+            &ours.solids,
+            &theirs.solids,
+            &mut room.solids,
+        ),
+        ("bg", &base.bg, &ours.bg, &theirs.bg, &mut room.bg),
+    ] {
+        *result = merge_tiles(base, ours, theirs, '0', size, |problem| {
+            conflicts.push(format!("{layer}: {problem}"))
+        });
+    }
+    for (layer, base, ours, theirs, result) in [
+        (
+            "objtiles",
+            &base.object_tiles,
+            &ours.object_tiles,
+            &theirs.object_tiles,
+            &mut room.object_tiles,
+        ),
+        (
+            "fgtiles",
+            &base.fg_tiles,
+            &ours.fg_tiles,
+            &theirs.fg_tiles,
+            &mut room.fg_tiles,
+        ),
+        (
+            "bgtiles",
+            &base.bg_tiles,
+            &ours.bg_tiles,
+            &theirs.bg_tiles,
+            &mut room.bg_tiles,
+        ),
+    ] {
+        *result = merge_tiles(base, ours, theirs, -1, size, |problem| {
+            conflicts.push(format!("{layer}: {problem}"))
+        });
+    }
+
+    (room, conflicts)
+}
+
+/// Merge a tile grid cell by cell, then fit it to the merged size of the room.
+fn merge_tiles<T: PartialEq + Clone>(
+    base: &TileGrid<T>,
+    ours: &TileGrid<T>,
+    theirs: &TileGrid<T>,
+    empty: T,
+    size: TileSize,
+    mut conflict: impl FnMut(String),
+) -> TileGrid<T> {
+    let mut result = if ours.size() == base.size() && theirs.size() == base.size() {
+        let mut result = ours.clone();
+        for (index, tile) in result.tiles.iter_mut().enumerate() {
+            if let Some(merged) = pick(&base.tiles[index], &ours.tiles[index], &theirs.tiles[index])
+            {
+                *tile = merged.clone();
+            }
+        }
+        let rects = tile_rects(base.size(), |pt| {
+            let [base, ours, theirs] = [base, ours, theirs].map(|grid| grid.get(pt));
+            pick(&base, &ours, &theirs).is_none()
+        });
+        for rect in rects {
+            conflict(format!(
+                "both sides changed {}x{} at ({}, {})",
+                rect.size.width, rect.size.height, rect.origin.x, rect.origin.y
+            ));
+        }
+        result
+    } else {
+        // the room was resized on one side, so the tiles no longer line up
+        let same = |a: &TileGrid<T>, b: &TileGrid<T>| a.stride == b.stride && a.tiles == b.tiles;
+        if same(ours, base) {
+            theirs.clone()
+        } else {
+            if !same(theirs, base) && !same(theirs, ours) {
+                conflict("the room was resized and the tiles changed on both sides".to_owned());
+            }
+            ours.clone()
+        }
+    };
+    if result.size() != size {
+        result.resize(size, empty);
+    }
+    result
+}
+
+/// Merge objects which can be turned into elements, attribute by attribute. If the merged
+/// element can't be read back, our side is kept and that is reported as a conflict.
+fn merge_objects<T: TryFromBinEl + Clone>(base: &T, ours: &T, theirs: &T) -> (T, Vec<String>) {
+    let [base_el, ours_el, theirs_el] = [base, ours, theirs].map(TryFromBinEl::to_binel);
+    let (merged, conflicts) = merge_elements(&base_el, &ours_el, &theirs_el);
+    match T::try_from_bin_el(&merged) {
+        Ok(result) => (result, conflicts),
+        Err(e) => (
+            ours.clone(),
+            vec![format!("could not combine the changes ({e})")],
+        ),
+    }
+}
+
+/// Merge the attributes of an element one by one. Children are only merged as a whole. Returns
+/// the merged element, keeping our side of anything which conflicts, and what conflicted.
+fn merge_elements(base: &BinEl, ours: &BinEl, theirs: &BinEl) -> (BinEl, Vec<String>) {
+    let mut conflicts = vec![];
+    let mut result = BinEl::new(&ours.name);
+
+    let mut keys = ours.attributes.keys().collect::<Vec<_>>();
+    keys.extend(
+        theirs
+            .attributes
+            .keys()
+            .filter(|key| !ours.attributes.contains_key(*key)),
+    );
+    let mut attributes: IndexMap<String, BinElAttr> = IndexMap::new();
+    for key in keys {
+        let [base_value, ours_value, theirs_value] =
+            [base, ours, theirs].map(|elem| elem.attributes.get(key));
+        let value = pick(&base_value, &ours_value, &theirs_value).unwrap_or_else(|| {
+            conflicts.push(format!("{key} changed on both sides"));
+            &ours_value
+        });
+        if let Some(value) = value {
+            attributes.insert(key.clone(), (*value).clone());
+            if ours.rle_attributes.contains(key) || theirs.rle_attributes.contains(key) {
+                result.rle_attributes.insert(key.clone());
+            }
+        }
+    }
+    result.attributes = attributes;
+
+    let [base_children, ours_children, theirs_children] =
+        [base, ours, theirs].map(|elem| elem.children().collect::<Vec<_>>());
+    let children = pick(&base_children, &ours_children, &theirs_children).unwrap_or_else(|| {
+        conflicts.push("children changed on both sides".to_owned());
+        &ours_children
+    });
+    for child in children {
+        result.insert((*child).clone());
+    }
+
+    (result, conflicts)
+}
+
+/// Merge lists of things which can be matched up by a key. Things in all three lists are merged
+/// with `merge`. Things removed on one side and changed on the other are kept and reported with
+/// `conflict`. If `keep_both` is set, things added on both sides with the same key are both kept,
+/// otherwise the addition is a conflict and ours is kept.
+///
+/// Returns the merged list, in our order followed by their additions, and the conflicts.
+fn merge_keyed<T: Clone + TryFromBinEl, K: Hash + Eq + Clone, C>(
+    base: &[T],
+    ours: &[T],
+    theirs: &[T],
+    key: impl Fn(&T) -> K,
+    mut merge: impl FnMut(&T, &T, &T) -> (T, Vec<C>),
+    conflict: impl Fn(&T, &str) -> C,
+    keep_both: bool,
+) -> (Vec<T>, Vec<C>) {
+    // things with the same key are matched up in order
+    let keyed = |list: &[T]| {
+        let mut seen = HashMap::new();
+        list.iter()
+            .map(|item| {
+                let key = key(item);
+                let index = seen.entry(key.clone()).or_insert(0);
+                *index += 1;
+                ((key, *index), item.clone())
+            })
+            .collect::<Vec<_>>()
+    };
+    let base = keyed(base).into_iter().collect::<HashMap<_, _>>();
+    let ours = keyed(ours);
+    let ours_keys = ours.iter().map(|(key, _)| key).collect::<HashSet<_>>();
+    let theirs = keyed(theirs);
+    let theirs_lookup = theirs
+        .iter()
+        .map(|(key, item)| (key, item))
+        .collect::<HashMap<_, _>>();
+    let same = |a: &T, b: &T| a.to_binel() == b.to_binel();
+
+    let mut result = vec![];
+    let mut conflicts = vec![];
+    for (key, item) in &ours {
+        match (base.get(key), theirs_lookup.get(key)) {
+            (Some(base), Some(theirs)) => {
+                let (merged, problems) = merge(base, item, theirs);
+                result.push(merged);
+                conflicts.extend(problems);
+            }
+            (Some(base), None) => {
+                if !same(base, item) {
+                    conflicts.push(conflict(item, "was removed by them but changed by us"));
+                    result.push(item.clone());
+                }
+            }
+            (None, Some(theirs)) => {
+                result.push(item.clone());
+                if !same(item, theirs) {
+                    if keep_both {
+                        result.push((*theirs).clone());
+                    } else {
+                        conflicts.push(conflict(item, "was added differently on both sides"));
+                    }
+                }
+            }
+            (None, None) => result.push(item.clone()),
+        }
+    }
+    for (key, item) in &theirs {
+        if ours_keys.contains(key) {
+            continue;
+        }
+        match base.get(key) {
+            Some(base) => {
+                if !same(base, item) {
+                    conflicts.push(conflict(item, "was removed by us but changed by them"));
+                    result.push(item.clone());
+                }
+            }
+            None => result.push(item.clone()),
+        }
+    }
+    (result, conflicts)
+}
+
+/// The usual three-way rule: take whichever side changed. Returns `None` if both sides changed
+/// differently.
+fn pick<'a, T: PartialEq>(base: &'a T, ours: &'a T, theirs: &'a T) -> Option<&'a T> {
+    if ours == theirs || theirs == base {
+        Some(ours)
+    } else if ours == base {
+        Some(theirs)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map_struct::Attribute;
+
+    fn spring(id: i32, x: i32) -> CelesteMapEntity {
+        CelesteMapEntity::new(id, "spring", x, 8)
+    }
+
+    #[test]
+    fn separate_edits_merge() {
+        let mut base = CelesteMap::default();
+        for name in ["a-00", "a-01"] {
+            let mut room = CelesteMapLevel {
+                name: name.to_owned(),
+                ..CelesteMapLevel::default()
+            };
+            room.entities.push(spring(base.levels.len() as i32 + 1, 8));
+            base.levels.push(room);
+        }
+        let copy = |map: &CelesteMap| CelesteMap {
+            levels: map.levels.clone(),
+            ..CelesteMap::default()
+        };
+
+        let mut ours = copy(&base);
+        ours.levels[0].entities[0].x = 16;
+        ours.levels[0].entities.push(spring(3, 0));
+        *ours.levels[0].solids.get_mut(TilePoint::new(0, 0)).unwrap() = '1';
+        let mut theirs = copy(&base);
+        theirs.levels[1].dark = true;
+        theirs.levels[1].entities.push(spring(3, 0));
+        theirs.levels[1]
+            .entities
+            .push(spring(4, 0).with_attributes(&[("linkedEntityID", Attribute::Int(3))]));
+        *theirs.levels[0]
+            .solids
+            .get_mut(TilePoint::new(5, 0))
+            .unwrap() = '1';
+
+        let (merged, conflicts) = merge_maps(&base, &ours, &theirs);
+        assert_eq!(conflicts, []);
+        assert_eq!(merged.levels[0].entities[0].x, 16);
+        assert_eq!(merged.levels[0].tile(TilePoint::new(0, 0), true), Some('1'));
+        assert_eq!(merged.levels[0].tile(TilePoint::new(5, 0), true), Some('1'));
+        assert!(merged.levels[1].dark);
+        let ids = merged
+            .levels
+            .iter()
+            .flat_map(|room| room.entities.iter().map(|entity| entity.id))
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [1, 3, 2, 5, 4]);
+        assert_eq!(
+            merged.levels[1].entities[2].attributes["linkedEntityID"],
+            Attribute::Int(5)
+        );
+
+        theirs.levels[0].entities[0].x = 24;
+        let (merged, conflicts) = merge_maps(&base, &ours, &theirs);
+        assert_eq!(merged.levels[0].entities[0].x, 16);
+        assert_eq!(
+            conflicts
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["room a-00: entities: spring (id 1): x changed on both sides"]
+        );
+    }
+}
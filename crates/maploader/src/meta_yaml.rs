@@ -0,0 +1,425 @@
+//! Everest's `map.meta.yaml`, the sidecar file next to a map's .bin which holds metadata the .bin
+//! format has no room for: per-side modes and checkpoints, the mountain, the complete and loading
+//! screens, and the cassette modifier. Everest applies it over the meta stored in the .bin.
+//!
+//! Only the fields the editor knows how to change are typed. Everything else in each section is
+//! kept in `other` so that it is written back unchanged.
+
+use arborio_utils::vizia::prelude::Data;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::map_struct::{CelesteMap, CelesteMapMeta};
+use crate::repair::MapWarning;
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MapMetaYaml {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jumpthru: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cassette_checkpoint_index: Option<i32>,
+
+    #[serde(
+        rename = "OverrideASideMeta",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub override_aside_meta: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color_grade: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dreaming: Option<bool>,
+    #[serde(
+        rename = "ForegroundTiles",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub fg_tiles: Option<String>,
+    #[serde(
+        rename = "BackgroundTiles",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub bg_tiles: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animated_tiles: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sprites: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub portraits: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intro_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cassette_note_color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title_text_color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title_base_color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title_accent_color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interlude: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wipe: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bloom_base: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bloom_strength: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub darkness_alpha: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cassette_song: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core_mode: Option<String>,
+    #[serde(
+        rename = "PostcardSoundID",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub postcard_sound_id: Option<String>,
+
+    /// One entry per side: A, B and C.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modes: Option<Vec<MapMetaModeYaml>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mountain: Option<MapMetaMountainYaml>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub complete_screen: Option<MapMetaScreenYaml>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loading_vignette_screen: Option<MapMetaScreenYaml>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cassette_modifier: Option<MapMetaCassetteModifierYaml>,
+
+    #[serde(flatten)]
+    pub other: IndexMap<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MapMetaModeYaml {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heart_is_end: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inventory: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_level: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seeker_slowdown: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theo_in_bubble: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ignore_level_audio_layer_data: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(rename = "PoemID", default, skip_serializing_if = "Option::is_none")]
+    pub poem_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_state: Option<MapMetaAudioStateYaml>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checkpoints: Option<Vec<MapMetaCheckpointYaml>>,
+
+    #[serde(flatten)]
+    pub other: IndexMap<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MapMetaAudioStateYaml {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub music: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ambience: Option<String>,
+
+    #[serde(flatten)]
+    pub other: IndexMap<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MapMetaCheckpointYaml {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dreaming: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inventory: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core_mode: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_state: Option<MapMetaAudioStateYaml>,
+
+    #[serde(flatten)]
+    pub other: IndexMap<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MapMetaMountainYaml {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mountain_model_directory: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mountain_texture_directory: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background_music: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background_ambience: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub star_fog_color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub show_core: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub show_snow: Option<bool>,
+
+    /// The camera positions, fog and star colors and so on.
+    #[serde(flatten)]
+    pub other: IndexMap<String, Value>,
+}
+
+/// Used for both `CompleteScreen` and `LoadingVignetteScreen`.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MapMetaScreenYaml {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub atlas: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<MapMetaScreenTitleYaml>,
+
+    /// The layers, their positions and the music.
+    #[serde(flatten)]
+    pub other: IndexMap<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MapMetaScreenTitleYaml {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub a_side: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub b_side: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub c_side: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_clear: Option<String>,
+
+    #[serde(flatten)]
+    pub other: IndexMap<String, Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MapMetaCassetteModifierYaml {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tempo_mult: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lead_beats: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beats_per_tick: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ticks_per_swap: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blocks: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beats_max: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beat_index_offset: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_behavior: Option<bool>,
+
+    #[serde(flatten)]
+    pub other: IndexMap<String, Value>,
+}
+
+impl Data for MapMetaYaml {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+/// Calls `$m!` with the fields shared between `MapMetaYaml` and `CelesteMapMeta`.
+macro_rules! shared_fields {
+    ($m:ident) => {
+        $m!(
+            override_aside_meta,
+            color_grade,
+            dreaming,
+            fg_tiles,
+            bg_tiles,
+            animated_tiles,
+            sprites,
+            portraits,
+            intro_type,
+            cassette_note_color,
+            title_text_color,
+            title_base_color,
+            title_accent_color,
+            icon,
+            interlude,
+            wipe,
+            bloom_base,
+            bloom_strength,
+            darkness_alpha,
+            cassette_song,
+            core_mode,
+            postcard_sound_id
+        )
+    };
+}
+
+impl MapMetaYaml {
+    /// Apply the values set in this file over those from the map's .bin, as Everest does.
+    pub fn apply_to(&self, meta: &mut CelesteMapMeta) {
+        macro_rules! apply {
+            ($($field:ident),*) => {
+                $(
+                    if let Some(value) = &self.$field {
+                        meta.$field = Some(value.clone());
+                    }
+                )*
+            };
+        }
+        shared_fields!(apply);
+    }
+
+    /// Copy the values from `meta` into the fields which this file already sets, so that it
+    /// doesn't override them with stale values.
+    pub fn update_from(&mut self, meta: &CelesteMapMeta) {
+        macro_rules! update {
+            ($($field:ident),*) => {
+                $(
+                    if self.$field.is_some() {
+                        self.$field = meta.$field.clone();
+                    }
+                )*
+            };
+        }
+        shared_fields!(update);
+    }
+
+    /// Put the values `original` had back into the fields which this file sets, since their
+    /// values are saved here instead of in the .bin.
+    pub fn restore_bin_values(&self, meta: &mut CelesteMapMeta, original: Option<&CelesteMapMeta>) {
+        macro_rules! restore {
+            ($($field:ident),*) => {
+                $(
+                    if self.$field.is_some() {
+                        meta.$field = original.and_then(|original| original.$field.clone());
+                    }
+                )*
+            };
+        }
+        shared_fields!(restore);
+    }
+}
+
+/// The path of the sidecar for the map at `bin_path`.
+pub fn sidecar_path(bin_path: &Path) -> PathBuf {
+    bin_path.with_extension("meta.yaml")
+}
+
+pub fn from_reader<R: io::Read>(reader: R) -> Result<MapMetaYaml, serde_yaml::Error> {
+    serde_yaml::from_reader(reader)
+}
+
+pub fn to_writer<W: io::Write>(writer: W, meta: &MapMetaYaml) -> Result<(), serde_yaml::Error> {
+    serde_yaml::to_writer(writer, meta)
+}
+
+/// Read the sidecar at `path` into `map`. A sidecar which can't be read is left out, and the
+/// problem is returned.
+pub fn load_into<R: io::Read>(map: &mut CelesteMap, reader: R, path: &str) -> Option<MapWarning> {
+    match from_reader(reader) {
+        Ok(meta) => {
+            map.meta_yaml = Some(meta);
+            None
+        }
+        Err(e) => Some(MapWarning {
+            path: path.to_owned(),
+            description: format!("{e}; ignored"),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unknown_fields_round_trip() {
+        let text = "\
+Icon: areas/mymod/icon
+Modes:
+- HeartIsEnd: true
+  PoemID: mymod_poem
+  Checkpoints:
+  - Level: b-00
+    Name: mymod_cp1
+    Flags: [lit]
+  AudioState:
+    Music: event:/music/lvl1/main
+CassetteModifier:
+  TempoMult: 1.5
+Mountain:
+  ShowCore: true
+  Idle:
+    Position: [1, 2, 3]
+LoadingVignetteText: mymod_loading
+";
+        let meta = from_reader(text.as_bytes()).unwrap();
+        let modes = meta.modes.as_ref().unwrap();
+        assert_eq!(modes[0].heart_is_end, Some(true));
+        assert_eq!(modes[0].poem_id.as_deref(), Some("mymod_poem"));
+        let checkpoint = &modes[0].checkpoints.as_ref().unwrap()[0];
+        assert_eq!(checkpoint.level.as_deref(), Some("b-00"));
+        assert!(checkpoint.other.contains_key("Flags"));
+        assert_eq!(
+            meta.cassette_modifier.as_ref().unwrap().tempo_mult,
+            Some(1.5)
+        );
+        assert!(meta.mountain.as_ref().unwrap().other.contains_key("Idle"));
+        assert!(meta.other.contains_key("LoadingVignetteText"));
+
+        let mut written = vec![];
+        to_writer(&mut written, &meta).unwrap();
+        assert_eq!(from_reader(written.as_slice()).unwrap(), meta);
+    }
+
+    #[test]
+    fn sidecar_values_stay_in_the_sidecar() {
+        let mut map = CelesteMap {
+            meta: Some(CelesteMapMeta {
+                color_grade: Some("none".to_owned()),
+                icon: Some("areas/mymod/icon".to_owned()),
+                ..CelesteMapMeta::default()
+            }),
+            meta_yaml: Some(from_reader("ColorGrade: cold\n".as_bytes()).unwrap()),
+            ..CelesteMap::default()
+        };
+        let effective = map.effective_meta().unwrap();
+        assert_eq!(effective.color_grade.as_deref(), Some("cold"));
+        assert_eq!(effective.icon.as_deref(), Some("areas/mymod/icon"));
+        assert_eq!(
+            map.meta.as_ref().unwrap().color_grade.as_deref(),
+            Some("none")
+        );
+
+        // an edit to a value the sidecar sets goes to the sidecar, not the .bin
+        let mut edited = effective;
+        edited.color_grade = Some("hot".to_owned());
+        let meta_yaml = map.meta_yaml.as_mut().unwrap();
+        meta_yaml.update_from(&edited);
+        meta_yaml.restore_bin_values(&mut edited, map.meta.as_ref());
+        assert_eq!(meta_yaml.color_grade.as_deref(), Some("hot"));
+        assert_eq!(edited.color_grade.as_deref(), Some("none"));
+        assert_eq!(meta_yaml.icon, None);
+    }
+}
@@ -0,0 +1,323 @@
+//! Tolerant map loading. Damaged parts of a map are skipped or repaired before it is parsed, and
+//! each problem is reported as a `MapWarning` so that one bad entity doesn't make a whole map
+//! unopenable.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::binel::{BinEl, BinElAttr};
+use crate::from_binel::TryFromBinEl;
+use crate::map_struct::{
+    CelesteMapDecal, CelesteMapEntity, CelesteMapLevel, CelesteMapMeta, CelesteMapStyleground,
+    LEVEL_ATTRIBUTES,
+};
+use arborio_utils::units::MapRectStrict;
+
+/// A problem found while loading a map, and what was done about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapWarning {
+    /// The path to the element with the problem, such as `Map/levels/level[3]/entities/spinner`.
+    /// Elements are indexed among their siblings of the same name, and the index is left out for
+    /// the first one.
+    pub path: String,
+    pub description: String,
+}
+
+impl fmt::Display for MapWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.description)
+    }
+}
+
+/// Skip or repair everything in a map's element tree which would stop it from loading.
+pub fn repair_map(root: &mut BinEl) -> Vec<MapWarning> {
+    let mut warnings = vec![];
+    let path = root.name.clone();
+
+    for name in ["Filler", "Style", "levels"] {
+        ensure_child(root, &path, name, &mut warnings);
+    }
+    retain_parsable::<CelesteMapMeta>(root, &path, "meta", &mut warnings);
+
+    let paths = child_paths(&path, root);
+    for (path, child) in paths.iter().zip(root.children_mut()) {
+        match child.name.as_str() {
+            "Filler" => retain_parsable::<MapRectStrict>(child, path, "rect", &mut warnings),
+            "Style" => {
+                if !matches!(
+                    child.attributes.get("color"),
+                    None | Some(BinElAttr::Text(_))
+                ) {
+                    child.attributes.shift_remove("color");
+                    warnings.push(MapWarning {
+                        path: path.clone(),
+                        description: "Background color is not text; removed".to_owned(),
+                    });
+                }
+                for name in ["Foregrounds", "Backgrounds"] {
+                    ensure_child(child, path, name, &mut warnings);
+                }
+                let paths = child_paths(path, child);
+                for (path, list) in paths.iter().zip(child.children_mut()) {
+                    if ["Foregrounds", "Backgrounds"].contains(&list.name.as_str()) {
+                        retain_all_parsable::<CelesteMapStyleground>(list, path, &mut warnings);
+                    }
+                }
+            }
+            "levels" => {
+                let paths = child_paths(path, child);
+                let keep = paths
+                    .iter()
+                    .zip(child.children_mut())
+                    .map(|(path, level)| repair_level(level, path, &mut warnings))
+                    .collect::<Vec<_>>();
+                let mut keep = keep.into_iter();
+                child.retain_children(|_| keep.next().unwrap_or(true));
+            }
+            _ => {}
+        }
+    }
+
+    warnings
+}
+
+/// Repair a single level. Returns whether the level can be kept at all.
+fn repair_level(level: &mut BinEl, path: &str, warnings: &mut Vec<MapWarning>) -> bool {
+    const REQUIRED: [&str; 5] = ["x", "y", "width", "height", "name"];
+
+    for (key, value) in level.attributes.clone() {
+        if !LEVEL_ATTRIBUTES.contains(&key.as_str()) || level_attr_ok(&key, &value) {
+            continue;
+        }
+        if REQUIRED.contains(&key.as_str()) {
+            warnings.push(MapWarning {
+                path: path.to_owned(),
+                description: format!("Could not read attribute {key} ({value:?}); room skipped"),
+            });
+            return false;
+        }
+        level.attributes.shift_remove(&key);
+        warnings.push(MapWarning {
+            path: path.to_owned(),
+            description: format!("Could not read attribute {key} ({value:?}); using the default"),
+        });
+    }
+
+    for name in ["solids", "bg", "entities", "triggers"] {
+        ensure_child(level, path, name, warnings);
+    }
+    for name in [
+        "solids", "bg", "objtiles", "fgtiles", "bgtiles", "entities", "triggers", "fgdecals",
+        "bgdecals",
+    ] {
+        remove_duplicates(level, path, name, warnings);
+    }
+
+    let paths = child_paths(path, level);
+    for (path, child) in paths.iter().zip(level.children_mut()) {
+        match child.name.as_str() {
+            "entities" | "triggers" => {
+                retain_all_parsable::<CelesteMapEntity>(child, path, warnings)
+            }
+            "fgdecals" | "bgdecals" => {
+                retain_all_parsable::<CelesteMapDecal>(child, path, warnings)
+            }
+            "objtiles" | "fgtiles" | "bgtiles" => repair_int_tiles(child, path, warnings),
+            _ => {}
+        }
+    }
+
+    if let Err(e) = CelesteMapLevel::try_from_bin_el(level) {
+        warnings.push(MapWarning {
+            path: path.to_owned(),
+            description: format!("{e}; room skipped"),
+        });
+        return false;
+    }
+    true
+}
+
+/// Check whether a level attribute can be read, by loading an otherwise empty level with it.
+fn level_attr_ok(key: &str, value: &BinElAttr) -> bool {
+    let mut level = BinEl::new("level");
+    for name in ["x", "y", "width", "height"] {
+        level.attributes.insert(name.to_owned(), BinElAttr::Int(0));
+    }
+    level
+        .attributes
+        .insert("name".to_owned(), BinElAttr::Text(String::new()));
+    level.attributes.insert(key.to_owned(), value.clone());
+    for name in ["solids", "bg", "entities", "triggers"] {
+        level.insert(BinEl::new(name));
+    }
+    CelesteMapLevel::try_from_bin_el(&level).is_ok()
+}
+
+/// Clear any tiles in an object tile grid which aren't numbers.
+fn repair_int_tiles(elem: &mut BinEl, path: &str, warnings: &mut Vec<MapWarning>) {
+    let Some(BinElAttr::Text(text)) = elem.attributes.get_mut("innerText") else {
+        return;
+    };
+    let mut bad = 0;
+    let repaired = text
+        .lines()
+        .map(|line| {
+            line.split(',')
+                .map(|tile| {
+                    if tile.is_empty() || tile.parse::<i32>().is_ok() {
+                        tile
+                    } else {
+                        bad += 1;
+                        "-1"
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if bad != 0 {
+        *text = repaired;
+        warnings.push(MapWarning {
+            path: path.to_owned(),
+            description: format!("{bad} tile(s) could not be read and were cleared"),
+        });
+    }
+}
+
+/// Add an empty child named `name` if there isn't one.
+fn ensure_child(elem: &mut BinEl, path: &str, name: &str, warnings: &mut Vec<MapWarning>) {
+    if elem.get(name).next().is_none() {
+        elem.insert(BinEl::new(name));
+        warnings.push(MapWarning {
+            path: path.to_owned(),
+            description: format!("Missing {name}; added an empty one"),
+        });
+    }
+}
+
+/// Remove all but the first child named `name`.
+fn remove_duplicates(elem: &mut BinEl, path: &str, name: &str, warnings: &mut Vec<MapWarning>) {
+    let count = elem.get(name).count();
+    if count > 1 {
+        let mut seen = false;
+        elem.retain_children(|child| child.name != name || !std::mem::replace(&mut seen, true));
+        warnings.push(MapWarning {
+            path: path.to_owned(),
+            description: format!("Found {count} {name} elements; only kept the first"),
+        });
+    }
+}
+
+/// Remove the children named `name` which can't be parsed as a `T`.
+fn retain_parsable<T: TryFromBinEl>(
+    elem: &mut BinEl,
+    path: &str,
+    name: &str,
+    warnings: &mut Vec<MapWarning>,
+) {
+    let mut paths = child_paths(path, elem).into_iter();
+    elem.retain_children(|child| {
+        let path = paths.next().unwrap_or_default();
+        if child.name != name {
+            return true;
+        }
+        match T::try_from_bin_el(child) {
+            Ok(_) => true,
+            Err(e) => {
+                warnings.push(MapWarning {
+                    path,
+                    description: format!("{e}; skipped"),
+                });
+                false
+            }
+        }
+    });
+}
+
+/// Remove all the children which can't be parsed as a `T`.
+fn retain_all_parsable<T: TryFromBinEl>(
+    elem: &mut BinEl,
+    path: &str,
+    warnings: &mut Vec<MapWarning>,
+) {
+    let mut paths = child_paths(path, elem).into_iter();
+    elem.retain_children(|child| {
+        let path = paths.next().unwrap_or_default();
+        match T::try_from_bin_el(child) {
+            Ok(_) => true,
+            Err(e) => {
+                warnings.push(MapWarning {
+                    path,
+                    description: format!("{e}; skipped"),
+                });
+                false
+            }
+        }
+    });
+}
+
+/// The paths of each of the children of `elem`, in order.
+fn child_paths(path: &str, elem: &BinEl) -> Vec<String> {
+    let mut seen = HashMap::new();
+    elem.children()
+        .map(|child| {
+            let index = seen.entry(child.name.as_str()).or_insert(0);
+            *index += 1;
+            if *index == 1 {
+                format!("{path}/{}", child.name)
+            } else {
+                format!("{path}/{}[{}]", child.name, *index - 1)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map_struct::{from_binfile_tolerant, CelesteMap};
+
+    #[test]
+    fn bad_parts_are_skipped() {
+        let mut root = CelesteMap::default().to_binel();
+        let mut level = CelesteMapLevel::default().to_binel();
+        level
+            .attributes
+            .insert("name".to_owned(), BinElAttr::Text("a-01".to_owned()));
+        level
+            .attributes
+            .insert("dark".to_owned(), BinElAttr::Text("yes".to_owned()));
+        let entities = level.get_or_insert("entities");
+        let mut spring = BinEl::new("spring");
+        spring.attributes.insert("id".to_owned(), BinElAttr::Int(1));
+        spring.attributes.insert("x".to_owned(), BinElAttr::Int(8));
+        spring.attributes.insert("y".to_owned(), BinElAttr::Int(8));
+        entities.insert(spring.clone());
+        spring.attributes.shift_remove("id");
+        entities.insert(spring);
+        root.get_or_insert("levels").insert(level);
+        let mut broken = BinEl::new("level");
+        broken
+            .attributes
+            .insert("x".to_owned(), BinElAttr::Text("left".to_owned()));
+        root.get_or_insert("levels").insert(broken);
+
+        let (map, warnings) = from_binfile_tolerant(crate::binel::BinFile {
+            root,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(map.levels.len(), 1);
+        assert_eq!(map.levels[0].entities.len(), 1);
+        let paths = warnings.iter().map(|w| w.path.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "Map/levels/level",
+                "Map/levels/level/entities/spring[1]",
+                "Map/levels/level[1]"
+            ]
+        );
+    }
+}
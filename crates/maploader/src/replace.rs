@@ -0,0 +1,327 @@
+//! Find and replace inside a room. Nothing is changed in place: each match comes back as the
+//! object or tiles it would become, so that the caller can show them first and then apply them in
+//! whatever way can be undone.
+
+use arborio_utils::units::TileGrid;
+use arborio_utils::vizia::prelude::{Data, Lens};
+use std::fmt;
+
+use crate::binel::xml::infer_attr;
+use crate::map_struct::{Attribute, CelesteMapDecal, CelesteMapEntity, CelesteMapLevel};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Data)]
+pub enum ReplaceTarget {
+    EntityName,
+    Attribute,
+    FgTile,
+    BgTile,
+    DecalTexture,
+}
+
+impl fmt::Display for ReplaceTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplaceTarget::EntityName => write!(f, "Entity/trigger type"),
+            ReplaceTarget::Attribute => write!(f, "Attribute"),
+            ReplaceTarget::FgTile => write!(f, "Foreground tile"),
+            ReplaceTarget::BgTile => write!(f, "Background tile"),
+            ReplaceTarget::DecalTexture => write!(f, "Decal texture"),
+        }
+    }
+}
+
+/// What to look for and what to put in its place.
+///
+/// - Entity and trigger types are matched exactly.
+/// - Attributes are found as `name=value`, or just `name` for any value. The replacement is either
+///   a new value or `name=value`, which renames the attribute as well.
+/// - Tiles are a single tileset character each.
+/// - Decal textures are matched exactly, or by prefix if `find` ends in `/`, so that a whole folder
+///   of decals can be moved at once.
+#[derive(Debug, Clone, PartialEq, Lens, Data)]
+pub struct Replacement {
+    pub target: ReplaceTarget,
+    pub find: String,
+    pub replace: String,
+}
+
+/// One match in a room, as it would be after the replacement.
+#[derive(Debug, Clone)]
+pub struct RoomChange {
+    pub object: ChangedObject,
+    pub description: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum ChangedObject {
+    Entity {
+        entity: CelesteMapEntity,
+        trigger: bool,
+    },
+    /// The room's whole tile layer, with `'\0'` wherever a tile stays the same.
+    Tiles {
+        fg: bool,
+        tiles: TileGrid<char>,
+    },
+    Decal {
+        decal: CelesteMapDecal,
+        fg: bool,
+    },
+}
+
+impl Replacement {
+    pub fn check(&self) -> Result<(), String> {
+        if self.find.is_empty() {
+            return Err("Nothing to find".to_owned());
+        }
+        match self.target {
+            ReplaceTarget::FgTile | ReplaceTarget::BgTile => {
+                if self.find.chars().count() != 1 || self.replace.chars().count() != 1 {
+                    return Err("Tiles are a single character".to_owned());
+                }
+            }
+            ReplaceTarget::Attribute => {
+                if self.find.starts_with('=') || self.replace.starts_with('=') {
+                    return Err("Attributes need a name".to_owned());
+                }
+            }
+            ReplaceTarget::EntityName | ReplaceTarget::DecalTexture => {
+                if self.replace.is_empty() {
+                    return Err("Nothing to replace with".to_owned());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn in_room(&self, room: &CelesteMapLevel) -> Vec<RoomChange> {
+        if self.check().is_err() {
+            return vec![];
+        }
+        match self.target {
+            ReplaceTarget::EntityName | ReplaceTarget::Attribute => {
+                let entities = room.entities.iter().map(|entity| (entity, false));
+                let triggers = room.triggers.iter().map(|trigger| (trigger, true));
+                entities
+                    .chain(triggers)
+                    .filter_map(|(entity, trigger)| self.in_entity(entity, trigger))
+                    .collect()
+            }
+            ReplaceTarget::FgTile => self.in_tiles(&room.solids, true).into_iter().collect(),
+            ReplaceTarget::BgTile => self.in_tiles(&room.bg, false).into_iter().collect(),
+            ReplaceTarget::DecalTexture => {
+                let fg = room.fg_decals.iter().map(|decal| (decal, true));
+                let bg = room.bg_decals.iter().map(|decal| (decal, false));
+                fg.chain(bg)
+                    .filter_map(|(decal, fg)| self.in_decal(decal, fg))
+                    .collect()
+            }
+        }
+    }
+
+    fn in_entity(&self, entity: &CelesteMapEntity, trigger: bool) -> Option<RoomChange> {
+        let mut changed = entity.clone();
+        let description = if self.target == ReplaceTarget::EntityName {
+            if entity.name != self.find {
+                return None;
+            }
+            changed.name = self.replace.clone();
+            format!("{} -> {}", entity.name, self.replace)
+        } else {
+            let (name, value) = split_attribute(&self.find);
+            let old = entity.attributes.get(name)?;
+            if matches!(value, Some(value) if attribute_text(old) != value) {
+                return None;
+            }
+            let (new_name, new_value) = match split_attribute(&self.replace) {
+                (name, Some(value)) => (name, value),
+                (value, None) => (name, value),
+            };
+            changed.attributes.shift_remove(name);
+            changed
+                .attributes
+                .insert(new_name.to_owned(), infer_attr(new_value).into());
+            format!("{name}={} -> {new_name}={new_value}", attribute_text(old))
+        };
+        (changed != *entity).then(|| RoomChange {
+            description: format!("{} {}: {description}", entity.name, entity.id),
+            object: ChangedObject::Entity {
+                entity: changed,
+                trigger,
+            },
+        })
+    }
+
+    fn in_tiles(&self, tiles: &TileGrid<char>, fg: bool) -> Option<RoomChange> {
+        let find = self.find.chars().next()?;
+        let replace = self.replace.chars().next()?;
+        if find == replace {
+            return None;
+        }
+        let changed = TileGrid {
+            tiles: tiles
+                .tiles
+                .iter()
+                .map(|&tile| if tile == find { replace } else { '\0' })
+                .collect::<Vec<_>>(),
+            stride: tiles.stride,
+        };
+        let count = changed.tiles.iter().filter(|&&tile| tile != '\0').count();
+        (count != 0).then(|| RoomChange {
+            description: format!(
+                "{count} {} tile(s): {find} -> {replace}",
+                if fg { "foreground" } else { "background" }
+            ),
+            object: ChangedObject::Tiles { fg, tiles: changed },
+        })
+    }
+
+    fn in_decal(&self, decal: &CelesteMapDecal, fg: bool) -> Option<RoomChange> {
+        let texture = if self.find.ends_with('/') {
+            let rest = decal.texture.strip_prefix(&self.find)?;
+            format!("{}{rest}", self.replace)
+        } else if decal.texture == self.find {
+            self.replace.clone()
+        } else {
+            return None;
+        };
+        (texture != decal.texture).then(|| RoomChange {
+            description: format!("decal {} -> {texture}", decal.texture),
+            object: ChangedObject::Decal {
+                decal: CelesteMapDecal {
+                    texture,
+                    ..decal.clone()
+                },
+                fg,
+            },
+        })
+    }
+}
+
+fn split_attribute(text: &str) -> (&str, Option<&str>) {
+    match text.split_once('=') {
+        Some((name, value)) => (name.trim(), Some(value)),
+        None => (text.trim(), None),
+    }
+}
+
+fn attribute_text(value: &Attribute) -> String {
+    match value {
+        Attribute::Bool(b) => b.to_string(),
+        Attribute::Int(i) => i.to_string(),
+        Attribute::Float(f) => f.to_string(),
+        Attribute::Text(s) => s.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arborio_utils::units::TileSize;
+
+    fn room() -> CelesteMapLevel {
+        let mut room = CelesteMapLevel {
+            solids: TileGrid::new(TileSize::new(2, 2), '1'),
+            ..CelesteMapLevel::default()
+        };
+        room.solids.tiles[3] = '3';
+        room.entities.push(
+            CelesteMapEntity::new(1, "strawberry", 0, 0)
+                .with_attributes(&[("flag", Attribute::Text("foo".to_owned()))]),
+        );
+        room.triggers.push(
+            CelesteMapEntity::new(2, "flagTrigger", 0, 0)
+                .with_attributes(&[("flag", Attribute::Text("bar".to_owned()))]),
+        );
+        room
+    }
+
+    fn replacement(target: ReplaceTarget, find: &str, replace: &str) -> Replacement {
+        Replacement {
+            target,
+            find: find.to_owned(),
+            replace: replace.to_owned(),
+        }
+    }
+
+    fn entity(change: &RoomChange) -> (&CelesteMapEntity, bool) {
+        match &change.object {
+            ChangedObject::Entity { entity, trigger } => (entity, *trigger),
+            other => panic!("{other:?}"),
+        }
+    }
+
+    #[test]
+    fn replacements_find_what_they_should() {
+        let room = room();
+
+        let changes = replacement(ReplaceTarget::Attribute, "flag=foo", "flag=baz").in_room(&room);
+        assert_eq!(changes.len(), 1);
+        let (changed, trigger) = entity(&changes[0]);
+        assert!(!trigger);
+        assert_eq!(
+            changed.attributes["flag"],
+            Attribute::Text("baz".to_owned())
+        );
+
+        // without a value every entity with the attribute matches, and numbers stay numbers
+        let changes = replacement(ReplaceTarget::Attribute, "flag", "7").in_room(&room);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(entity(&changes[1]).0.attributes["flag"], Attribute::Int(7));
+
+        let changes = replacement(ReplaceTarget::EntityName, "flagTrigger", "x").in_room(&room);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            entity(&changes[0]),
+            (
+                &CelesteMapEntity {
+                    name: "x".to_owned(),
+                    ..room.triggers[0].clone()
+                },
+                true
+            )
+        );
+
+        let changes = replacement(ReplaceTarget::FgTile, "3", "4").in_room(&room);
+        assert_eq!(changes.len(), 1);
+        match &changes[0].object {
+            ChangedObject::Tiles { fg: true, tiles } => {
+                assert_eq!(tiles.tiles, ['\0', '\0', '\0', '4'])
+            }
+            other => panic!("{other:?}"),
+        }
+        assert!(replacement(ReplaceTarget::BgTile, "3", "4")
+            .in_room(&room)
+            .is_empty());
+        assert!(replacement(ReplaceTarget::FgTile, "34", "4")
+            .check()
+            .is_err());
+    }
+
+    #[test]
+    fn decal_folders_move_together() {
+        let mut room = room();
+        room.fg_decals.push(CelesteMapDecal {
+            id: 1,
+            x: 0,
+            y: 0,
+            scale_x: 1.0,
+            scale_y: 1.0,
+            texture: "old/sign.png".to_owned(),
+            rotation: 0.0,
+            color: None,
+            depth: None,
+            layout: Default::default(),
+        });
+
+        let changes = replacement(ReplaceTarget::DecalTexture, "old/", "new/").in_room(&room);
+        assert_eq!(changes.len(), 1);
+        match &changes[0].object {
+            ChangedObject::Decal { decal, fg: true } => assert_eq!(decal.texture, "new/sign.png"),
+            other => panic!("{other:?}"),
+        }
+        assert!(replacement(ReplaceTarget::DecalTexture, "old", "new")
+            .in_room(&room)
+            .is_empty());
+    }
+}
@@ -0,0 +1,285 @@
+//! Counts of the things in a map which players and release checklists care about.
+
+use arborio_utils::vizia::prelude::Data;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::map_struct::{Attribute, CelesteMap, CelesteMapEntity};
+
+/// Entity names which are a crystal heart.
+const HEART_NAMES: &[&str] = &["blackGem", "birdForsakenCityGem"];
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MapStats {
+    pub rooms: usize,
+    pub filler: usize,
+    /// The total area of all the rooms, in tiles.
+    pub tile_area: i64,
+    pub stylegrounds: usize,
+    pub checkpoints: usize,
+    /// Normal strawberries, in the order the game counts them.
+    pub strawberries: Vec<Strawberry>,
+    pub golden_berries: usize,
+    pub moon_berries: usize,
+    pub cassettes: usize,
+    pub hearts: usize,
+    /// Every entity, trigger and decal, counted by name, for each room in map order.
+    pub room_stats: Vec<RoomStats>,
+}
+
+impl Data for MapStats {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strawberry {
+    pub room: String,
+    pub id: i32,
+    pub checkpoint: i32,
+    /// The position within the checkpoint, or -1 if the game should number it.
+    pub order: i32,
+    pub winged: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RoomStats {
+    pub name: String,
+    pub entities: BTreeMap<String, usize>,
+    pub triggers: BTreeMap<String, usize>,
+    /// Decals by texture, fg and bg together.
+    pub decals: BTreeMap<String, usize>,
+}
+
+impl MapStats {
+    pub fn entities(&self) -> usize {
+        self.room_stats
+            .iter()
+            .flat_map(|room| room.entities.values())
+            .sum()
+    }
+
+    pub fn triggers(&self) -> usize {
+        self.room_stats
+            .iter()
+            .flat_map(|room| room.triggers.values())
+            .sum()
+    }
+
+    pub fn decals(&self) -> usize {
+        self.room_stats
+            .iter()
+            .flat_map(|room| room.decals.values())
+            .sum()
+    }
+
+    /// How many strawberries each checkpoint has.
+    pub fn strawberries_by_checkpoint(&self) -> BTreeMap<i32, usize> {
+        let mut result = BTreeMap::new();
+        for berry in &self.strawberries {
+            *result.entry(berry.checkpoint).or_default() += 1;
+        }
+        result
+    }
+}
+
+pub fn map_stats(map: &CelesteMap) -> MapStats {
+    let mut stats = MapStats {
+        rooms: map.levels.len(),
+        filler: map.filler.len(),
+        stylegrounds: map.foregrounds.len() + map.backgrounds.len(),
+        ..MapStats::default()
+    };
+
+    for room in &map.levels {
+        stats.tile_area += (room.bounds.width() / 8) as i64 * (room.bounds.height() / 8) as i64;
+
+        let mut room_stats = RoomStats {
+            name: room.name.clone(),
+            ..RoomStats::default()
+        };
+        for entity in &room.entities {
+            *room_stats.entities.entry(entity.name.clone()).or_default() += 1;
+            match entity.name.as_str() {
+                "strawberry" if bool_attr(entity, "moon") => stats.moon_berries += 1,
+                "strawberry" => stats.strawberries.push(Strawberry {
+                    room: room.name.clone(),
+                    id: entity.id,
+                    checkpoint: int_attr(entity, "checkpointID").unwrap_or(0),
+                    order: int_attr(entity, "order").unwrap_or(-1),
+                    winged: bool_attr(entity, "winged"),
+                }),
+                "goldenBerry" => stats.golden_berries += 1,
+                "cassette" => stats.cassettes += 1,
+                "checkpoint" => stats.checkpoints += 1,
+                name if HEART_NAMES.contains(&name) => stats.hearts += 1,
+                _ => {}
+            }
+        }
+        for trigger in &room.triggers {
+            *room_stats.triggers.entry(trigger.name.clone()).or_default() += 1;
+        }
+        for decal in room.fg_decals.iter().chain(room.bg_decals.iter()) {
+            *room_stats.decals.entry(decal.texture.clone()).or_default() += 1;
+        }
+        stats.room_stats.push(room_stats);
+    }
+
+    // the game numbers unordered berries after the ordered ones in their checkpoint
+    stats
+        .strawberries
+        .sort_by_key(|berry| (berry.checkpoint, berry.order < 0, berry.order));
+    stats
+}
+
+fn int_attr(entity: &CelesteMapEntity, name: &str) -> Option<i32> {
+    match entity.attributes.get(name)? {
+        Attribute::Int(i) => Some(*i),
+        Attribute::Float(f) => Some(*f as i32),
+        Attribute::Text(s) => s.parse().ok(),
+        Attribute::Bool(_) => None,
+    }
+}
+
+fn bool_attr(entity: &CelesteMapEntity, name: &str) -> bool {
+    match entity.attributes.get(name) {
+        Some(Attribute::Bool(b)) => *b,
+        Some(Attribute::Text(s)) => s.eq_ignore_ascii_case("true"),
+        _ => false,
+    }
+}
+
+impl fmt::Display for MapStats {
+    /// The totals, one per line. The per-room breakdown is [RoomStats]'s job.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "rooms:          {}", self.rooms)?;
+        writeln!(f, "filler:         {}", self.filler)?;
+        writeln!(f, "tile area:      {}", self.tile_area)?;
+        writeln!(f, "checkpoints:    {}", self.checkpoints)?;
+        write!(f, "strawberries:   {}", self.strawberries.len())?;
+        let by_checkpoint = self.strawberries_by_checkpoint();
+        if by_checkpoint.len() > 1 {
+            let counts = by_checkpoint
+                .iter()
+                .map(|(checkpoint, count)| format!("checkpoint {checkpoint}: {count}"))
+                .collect::<Vec<_>>();
+            write!(f, " ({})", counts.join(", "))?;
+        }
+        writeln!(f)?;
+        writeln!(f, "golden berries: {}", self.golden_berries)?;
+        writeln!(f, "moon berries:   {}", self.moon_berries)?;
+        writeln!(f, "cassettes:      {}", self.cassettes)?;
+        writeln!(f, "hearts:         {}", self.hearts)?;
+        writeln!(f, "entities:       {}", self.entities())?;
+        writeln!(f, "triggers:       {}", self.triggers())?;
+        writeln!(f, "decals:         {}", self.decals())?;
+        write!(f, "stylegrounds:   {}", self.stylegrounds)
+    }
+}
+
+impl fmt::Display for Strawberry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let order = if self.order < 0 {
+            "unordered".to_owned()
+        } else {
+            format!("order {}", self.order)
+        };
+        write!(
+            f,
+            "checkpoint {}, {order}: id {} in room {}{}",
+            self.checkpoint,
+            self.id,
+            self.room,
+            if self.winged { " (winged)" } else { "" }
+        )
+    }
+}
+
+impl fmt::Display for RoomStats {
+    /// One line per layer which has anything in it, e.g. `entities: player x1, spring x2`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let layers = [
+            ("entities", &self.entities),
+            ("triggers", &self.triggers),
+            ("decals", &self.decals),
+        ];
+        let mut first = true;
+        for (layer, counts) in layers {
+            if counts.is_empty() {
+                continue;
+            }
+            if !first {
+                writeln!(f)?;
+            }
+            first = false;
+            let counts = counts
+                .iter()
+                .map(|(name, count)| format!("{name} x{count}"))
+                .collect::<Vec<_>>();
+            write!(f, "{layer}: {}", counts.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::map_struct::CelesteMapLevel;
+
+    fn entity(id: i32, name: &str, attributes: &[(&str, Attribute)]) -> CelesteMapEntity {
+        CelesteMapEntity::new(id, name, 8, 8).with_attributes(attributes)
+    }
+
+    #[test]
+    fn berries_are_counted_in_game_order() {
+        let mut room = CelesteMapLevel {
+            name: "a-00".to_owned(),
+            ..CelesteMapLevel::default()
+        };
+        room.entities.push(entity(1, "player", &[]));
+        room.entities.push(entity(
+            2,
+            "strawberry",
+            &[
+                ("checkpointID", Attribute::Int(1)),
+                ("order", Attribute::Int(0)),
+            ],
+        ));
+        room.entities
+            .push(entity(3, "strawberry", &[("order", Attribute::Int(-1))]));
+        room.entities
+            .push(entity(4, "strawberry", &[("order", Attribute::Int(2))]));
+        room.entities
+            .push(entity(5, "strawberry", &[("moon", Attribute::Bool(true))]));
+        room.entities.push(entity(6, "goldenBerry", &[]));
+        room.entities.push(entity(7, "blackGem", &[]));
+        room.triggers.push(entity(8, "cameraOffsetTrigger", &[]));
+        let map = CelesteMap {
+            levels: vec![room],
+            ..CelesteMap::default()
+        };
+
+        let stats = map_stats(&map);
+        assert_eq!(
+            stats
+                .strawberries
+                .iter()
+                .map(|berry| berry.id)
+                .collect::<Vec<_>>(),
+            [4, 3, 2]
+        );
+        assert_eq!(
+            stats.strawberries_by_checkpoint(),
+            BTreeMap::from([(0, 2), (1, 1)])
+        );
+        assert_eq!(stats.moon_berries, 1);
+        assert_eq!(stats.golden_berries, 1);
+        assert_eq!(stats.hearts, 1);
+        assert_eq!(stats.tile_area, 40 * 23);
+        assert_eq!(stats.entities(), 7);
+        assert_eq!(stats.triggers(), 1);
+        assert_eq!(stats.room_stats[0].entities["strawberry"], 4);
+    }
+}
@@ -0,0 +1,651 @@
+//! Converting maps to and from the TMX format of the Tiled map editor.
+//!
+//! Each room is a group layer named after the room and offset to its position. Inside it, the tile
+//! layers `fg`, `bg` and `objtiles` hold the room's tiles, and the object layers `entities` and
+//! `triggers` hold its entities and triggers. An object's class (its type, in Tiled before 1.9) is
+//! the entity name and its custom properties are the entity's attributes. Nodes are kept in a
+//! `nodes` property as `x,y` pairs separated by spaces.
+//!
+//! Fg and bg tiles come from tilesets whose tiles have a `char` property naming the tile. Tiles
+//! without one stand for the char at their index in [TILE_CHARS]. Object tiles come from a tileset
+//! named `scenery`, laid out like Celeste's scenery tileset.
+//!
+//! Only the layout of rooms is carried over. Room settings such as music, decals, filler,
+//! stylegrounds and metadata are not.
+
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::Write;
+
+use arborio_utils::units::*;
+
+use crate::binel::xml::{escape, infer_attr, take_element_as_text};
+use crate::binel::{BinEl, BinElAttr};
+use crate::ids::{next_entity_id, reassign_taken_ids};
+use crate::map_struct::{Attribute, CelesteMap, CelesteMapEntity, CelesteMapLevel, Node};
+
+/// The tile chars which tiles without a `char` property stand for, by their index in the tileset.
+pub const TILE_CHARS: &str = "123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// The bits of a tile id which Tiled uses to flip and rotate tiles.
+const FLIP_MASK: u32 = 0xf000_0000;
+
+const SCENERY: &str = "scenery";
+const NODES: &str = "nodes";
+
+struct Tileset {
+    name: String,
+    first_gid: u32,
+    chars: Vec<(u32, char)>,
+}
+
+/// Object ids in Tiled are unique across the whole map, while entities and triggers may share ids
+/// with each other. Objects keep their entity's id unless it is taken, and otherwise get the next
+/// free one.
+struct ObjectIds {
+    used: HashSet<i32>,
+    next: i32,
+}
+
+impl ObjectIds {
+    fn assign(&mut self, id: i32) -> i32 {
+        if id > 0 && self.used.insert(id) {
+            return id;
+        }
+        let id = self.next;
+        self.next += 1;
+        self.used.insert(id);
+        id
+    }
+}
+
+/// Write the rooms of a map as a Tiled map.
+pub fn map_to_tmx(map: &CelesteMap) -> String {
+    let chars = map
+        .levels
+        .iter()
+        .flat_map(|room| room.solids.tiles.iter().chain(room.bg.tiles.iter()))
+        .filter(|tile| **tile != '0')
+        .copied()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    let scenery_count = map
+        .levels
+        .iter()
+        .flat_map(|room| room.object_tiles.tiles.iter())
+        .max()
+        .map_or(0, |max| *max + 1)
+        .max(1);
+    let scenery_first_gid = chars.len() as u32 + 1;
+    let bounds = map
+        .levels
+        .iter()
+        .map(|room| room.bounds)
+        .reduce(|a, b| a.union(&b))
+        .unwrap_or_default();
+    let mut object_ids = ObjectIds {
+        used: HashSet::new(),
+        next: map
+            .levels
+            .iter()
+            .flat_map(|room| room.entities.iter().chain(room.triggers.iter()))
+            .map(|entity| entity.id)
+            .max()
+            .unwrap_or(0)
+            .max(0)
+            + 1,
+    };
+
+    // writing to a string can't fail. The header needs the next free object id, so the rooms are
+    // written first
+    let mut rooms = String::new();
+    let mut layer_id = 0;
+    let mut next_layer_id = || {
+        layer_id += 1;
+        layer_id
+    };
+    for room in &map.levels {
+        let name = room.name.strip_prefix("lvl_").unwrap_or(&room.name);
+        let _ = writeln!(
+            rooms,
+            r#" <group id="{}" name="{}" offsetx="{}" offsety="{}">"#,
+            next_layer_id(),
+            escape(name, true),
+            room.bounds.min_x(),
+            room.bounds.min_y()
+        );
+        let gid = |ch: &char| match chars.iter().position(|other| other == ch) {
+            Some(idx) => idx as u32 + 1,
+            None => 0,
+        };
+        let solids = room.solids.tiles.iter().map(gid).collect::<Vec<_>>();
+        let bg = room.bg.tiles.iter().map(gid).collect::<Vec<_>>();
+        let scenery = room
+            .object_tiles
+            .tiles
+            .iter()
+            .map(|tile| match tile {
+                0.. => *tile as u32 + scenery_first_gid,
+                _ => 0,
+            })
+            .collect::<Vec<_>>();
+        for (layer, gids, stride) in [
+            ("fg", solids, room.solids.stride),
+            ("bg", bg, room.bg.stride),
+            ("objtiles", scenery, room.object_tiles.stride),
+        ] {
+            put_tile_layer(&mut rooms, next_layer_id(), layer, &gids, stride);
+        }
+        put_object_layer(
+            &mut rooms,
+            next_layer_id(),
+            "entities",
+            &room.entities,
+            &mut object_ids,
+        );
+        put_object_layer(
+            &mut rooms,
+            next_layer_id(),
+            "triggers",
+            &room.triggers,
+            &mut object_ids,
+        );
+        let _ = writeln!(rooms, " </group>");
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        out,
+        r#"<map version="1.8" orientation="orthogonal" renderorder="right-down" width="{}" height="{}" tilewidth="8" tileheight="8" infinite="0" nextlayerid="{}" nextobjectid="{}">"#,
+        bounds.max_x().max(0) / 8,
+        bounds.max_y().max(0) / 8,
+        map.levels.len() * 6 + 1,
+        object_ids.next,
+    );
+    if !chars.is_empty() {
+        let _ = writeln!(
+            out,
+            r#" <tileset firstgid="1" name="tiles" tilewidth="8" tileheight="8" tilecount="{}" columns="0">"#,
+            chars.len()
+        );
+        for (id, ch) in chars.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                r#"  <tile id="{id}"><properties><property name="char" value="{}"/></properties></tile>"#,
+                escape(&ch.to_string(), true)
+            );
+        }
+        let _ = writeln!(out, " </tileset>");
+    }
+    let _ = writeln!(
+        out,
+        r#" <tileset firstgid="{scenery_first_gid}" name="{SCENERY}" tilewidth="8" tileheight="8" tilecount="{scenery_count}" columns="32"/>"#,
+    );
+
+    out.push_str(&rooms);
+    let _ = writeln!(out, "</map>");
+    out
+}
+
+fn put_tile_layer(out: &mut String, id: usize, name: &str, gids: &[u32], stride: usize) {
+    let stride = stride.max(1);
+    let _ = writeln!(
+        out,
+        r#"  <layer id="{id}" name="{name}" width="{stride}" height="{}">"#,
+        gids.len() / stride
+    );
+    let rows = gids
+        .chunks(stride)
+        .map(|row| row.iter().map(u32::to_string).collect::<Vec<_>>().join(","))
+        .collect::<Vec<_>>();
+    let _ = writeln!(
+        out,
+        "   <data encoding=\"csv\">\n{}\n   </data>",
+        rows.join(",\n")
+    );
+    let _ = writeln!(out, "  </layer>");
+}
+
+fn put_object_layer(
+    out: &mut String,
+    id: usize,
+    name: &str,
+    entities: &[CelesteMapEntity],
+    object_ids: &mut ObjectIds,
+) {
+    let _ = writeln!(out, r#"  <objectgroup id="{id}" name="{name}">"#);
+    for entity in entities {
+        let _ = write!(
+            out,
+            r#"   <object id="{}" type="{}" x="{}" y="{}""#,
+            object_ids.assign(entity.id),
+            escape(&entity.name, true),
+            entity.x,
+            entity.y
+        );
+        if entity.width != 0 || entity.height != 0 {
+            let _ = write!(
+                out,
+                r#" width="{}" height="{}""#,
+                entity.width, entity.height
+            );
+        }
+
+        let mut attributes = entity.attributes.iter().collect::<Vec<_>>();
+        attributes.sort_by_key(|(name, _)| name.as_str());
+        if attributes.is_empty() && entity.nodes.is_empty() {
+            let _ = writeln!(out, "/>");
+            continue;
+        }
+        let _ = writeln!(out, ">\n    <properties>");
+        for (name, value) in attributes {
+            let (ty, value) = match value {
+                Attribute::Bool(b) => (Some("bool"), b.to_string()),
+                Attribute::Int(i) => (Some("int"), i.to_string()),
+                Attribute::Float(f) => (Some("float"), format!("{f:?}")),
+                // untyped properties are guessed when read back, so mark text which would be
+                // read as something else
+                Attribute::Text(s) => match infer_attr(s) {
+                    BinElAttr::Text(_) => (None, s.clone()),
+                    _ => (Some("string"), s.clone()),
+                },
+            };
+            put_property(out, name, ty, &value);
+        }
+        if !entity.nodes.is_empty() {
+            let nodes = entity
+                .nodes
+                .iter()
+                .map(|node| format!("{},{}", node.x, node.y))
+                .collect::<Vec<_>>();
+            put_property(out, NODES, None, &nodes.join(" "));
+        }
+        let _ = writeln!(out, "    </properties>\n   </object>");
+    }
+    let _ = writeln!(out, "  </objectgroup>");
+}
+
+fn put_property(out: &mut String, name: &str, ty: Option<&str>, value: &str) {
+    let ty = ty.map_or(String::new(), |ty| format!(r#" type="{ty}""#));
+    let _ = writeln!(
+        out,
+        r#"     <property name="{}"{ty} value="{}"/>"#,
+        escape(name, true),
+        escape(value, true)
+    );
+}
+
+/// Read a map out of a Tiled map. Objects without an id, or whose id is already taken, are given
+/// a new one.
+pub fn map_from_tmx(text: &str) -> Result<CelesteMap, String> {
+    let root = take_element_as_text(text).map_err(|e| format!("Not a valid TMX file: {e}"))?;
+    if root.name != "map" {
+        return Err("Not a TMX file: the root element must be <map>".to_owned());
+    }
+    if attr_text(&root, "infinite").as_deref() == Some("1") {
+        return Err("Infinite Tiled maps are not supported".to_owned());
+    }
+
+    let mut tilesets = vec![];
+    for tileset in root.get("tileset") {
+        if let Some(source) = attr_text(tileset, "source") {
+            return Err(format!(
+                "External tileset {source} is not supported, embed it in the map instead"
+            ));
+        }
+        let mut chars = vec![];
+        for tile in tileset.get("tile") {
+            let id = attr_num(tile, "id").ok_or("Tileset tile without an id")? as u32;
+            let ch = properties(tile)
+                .find(|(name, _, _)| name == "char")
+                .and_then(|(_, _, value)| value.chars().next());
+            if let Some(ch) = ch {
+                chars.push((id, ch));
+            }
+        }
+        tilesets.push(Tileset {
+            name: attr_text(tileset, "name").unwrap_or_default(),
+            first_gid: attr_num(tileset, "firstgid").ok_or("Tileset without a firstgid")? as u32,
+            chars,
+        });
+    }
+    tilesets.sort_by_key(|tileset| tileset.first_gid);
+
+    let mut map = CelesteMap::default();
+    for group in root.get("group") {
+        map.levels.push(room_from_group(group, &tilesets)?);
+    }
+    if map.levels.is_empty() {
+        return Err("The map has no group layers to make rooms from".to_owned());
+    }
+
+    // object ids in Tiled are unique across the whole map, but be safe with hand-edited files.
+    // objects without an id read as 0, so that one is always replaced
+    let mut next_id = next_entity_id(&map.levels);
+    let mut taken = HashSet::from([0]);
+    for room in &mut map.levels {
+        reassign_taken_ids(room, &mut taken, &mut next_id);
+    }
+
+    Ok(map)
+}
+
+fn room_from_group(group: &BinEl, tilesets: &[Tileset]) -> Result<CelesteMapLevel, String> {
+    let name = attr_text(group, "name")
+        .filter(|name| !name.is_empty())
+        .ok_or("A group layer has no name")?;
+    let position = MapPointStrict::new(
+        attr_num(group, "offsetx").unwrap_or(0.0).round() as i32,
+        attr_num(group, "offsety").unwrap_or(0.0).round() as i32,
+    );
+
+    let layers = group
+        .get("layer")
+        .map(|layer| {
+            let layer_name = attr_text(layer, "name").unwrap_or_default();
+            let (size, gids) =
+                layer_gids(layer).map_err(|e| format!("Room {name}, layer {layer_name}: {e}"))?;
+            Ok((layer_name, size, gids))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let size = layers
+        .iter()
+        .map(|(_, size, _)| *size)
+        .reduce(|a, b| TileSize::new(a.width.max(b.width), a.height.max(b.height)))
+        .unwrap_or_else(|| TileSize::new(40, 23));
+
+    let mut room = CelesteMapLevel {
+        name: if name.starts_with("lvl_") {
+            name.clone()
+        } else {
+            format!("lvl_{name}")
+        },
+        bounds: MapRectStrict::new(position, size_tile_to_room(&size).cast_unit()),
+        solids: TileGrid::new(size, '0'),
+        bg: TileGrid::new(size, '0'),
+        object_tiles: TileGrid::new(size, -1),
+        fg_tiles: TileGrid::new(size, -1),
+        bg_tiles: TileGrid::new(size, -1),
+        ..CelesteMapLevel::default()
+    };
+
+    for (layer_name, layer_size, gids) in layers {
+        let error = |e: String| format!("Room {name}, layer {layer_name}: {e}");
+        let points = rect_point_iter(TileRect::new(TilePoint::zero(), layer_size), 1);
+        match layer_name.to_lowercase().as_str() {
+            "fg" | "bg" => {
+                let target = if layer_name.eq_ignore_ascii_case("fg") {
+                    &mut room.solids
+                } else {
+                    &mut room.bg
+                };
+                for (pt, gid) in points.zip(gids) {
+                    *target.get_mut(pt).unwrap() = tile_char(gid, tilesets).map_err(error)?;
+                }
+            }
+            "objtiles" => {
+                for (pt, gid) in points.zip(gids) {
+                    *room.object_tiles.get_mut(pt).unwrap() =
+                        object_tile(gid, tilesets).map_err(error)?;
+                }
+            }
+            _ => {
+                return Err(error(
+                    "Tile layers must be named fg, bg or objtiles".to_owned(),
+                ))
+            }
+        }
+    }
+
+    for objects in group.get("objectgroup") {
+        let layer_name = attr_text(objects, "name").unwrap_or_default();
+        let target = match layer_name.to_lowercase().as_str() {
+            "entities" => &mut room.entities,
+            "triggers" => &mut room.triggers,
+            _ => {
+                return Err(format!(
+                "Room {name}, layer {layer_name}: Object layers must be named entities or triggers"
+            ))
+            }
+        };
+        for object in objects.get("object") {
+            target.push(entity_from_object(object).map_err(|e| format!("Room {name}: {e}"))?);
+        }
+    }
+
+    Ok(room)
+}
+
+/// The size of a tile layer and the tile ids in it, row by row.
+fn layer_gids(layer: &BinEl) -> Result<(TileSize, Vec<u32>), String> {
+    let size = TileSize::new(
+        attr_num(layer, "width").ok_or("No width")? as i32,
+        attr_num(layer, "height").ok_or("No height")? as i32,
+    );
+    let data = layer.get("data").next().ok_or("No tile data")?;
+    if data.get("chunk").next().is_some() {
+        return Err("Infinite Tiled maps are not supported".to_owned());
+    }
+    let gids = match attr_text(data, "encoding").as_deref() {
+        Some("csv") => data
+            .text()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|gid| !gid.is_empty())
+            .map(|gid| gid.parse().map_err(|_| format!("{gid} is not a tile id")))
+            .collect::<Result<Vec<u32>, _>>()?,
+        None => data
+            .get("tile")
+            .map(|tile| attr_num(tile, "gid").unwrap_or(0.0) as u32)
+            .collect(),
+        Some(encoding) => {
+            return Err(format!(
+                "Tile data is stored as {encoding}, change the layer format to CSV"
+            ))
+        }
+    };
+    if gids.len() != (size.width * size.height) as usize {
+        return Err(format!(
+            "Expected {} tiles but found {}",
+            size.width * size.height,
+            gids.len()
+        ));
+    }
+    Ok((size, gids))
+}
+
+fn tileset_of(gid: u32, tilesets: &[Tileset]) -> Result<(&Tileset, u32), String> {
+    let tileset = tilesets
+        .iter()
+        .rev()
+        .find(|tileset| tileset.first_gid <= gid)
+        .ok_or_else(|| format!("Tile id {gid} is not in any tileset"))?;
+    Ok((tileset, gid - tileset.first_gid))
+}
+
+fn tile_char(gid: u32, tilesets: &[Tileset]) -> Result<char, String> {
+    let gid = gid & !FLIP_MASK;
+    if gid == 0 {
+        return Ok('0');
+    }
+    let (tileset, id) = tileset_of(gid, tilesets)?;
+    tileset
+        .chars
+        .iter()
+        .find(|(other, _)| *other == id)
+        .map(|(_, ch)| *ch)
+        .or_else(|| TILE_CHARS.chars().nth(id as usize))
+        .ok_or_else(|| format!("Tile {id} of tileset {} has no char property", tileset.name))
+}
+
+fn object_tile(gid: u32, tilesets: &[Tileset]) -> Result<i32, String> {
+    let gid = gid & !FLIP_MASK;
+    if gid == 0 {
+        return Ok(-1);
+    }
+    let (tileset, id) = tileset_of(gid, tilesets)?;
+    if tileset.name != SCENERY {
+        return Err(format!(
+            "Object tiles must come from the {SCENERY} tileset, not {}",
+            tileset.name
+        ));
+    }
+    Ok(id as i32)
+}
+
+fn entity_from_object(object: &BinEl) -> Result<CelesteMapEntity, String> {
+    let id = attr_num(object, "id").unwrap_or(0.0) as i32;
+    let name = ["class", "type", "name"]
+        .into_iter()
+        .filter_map(|attr| attr_text(object, attr))
+        .find(|name| !name.is_empty())
+        .ok_or_else(|| format!("Object {id} has no class"))?;
+    let mut entity = CelesteMapEntity {
+        id,
+        name,
+        x: attr_num(object, "x").unwrap_or(0.0).round() as i32,
+        y: attr_num(object, "y").unwrap_or(0.0).round() as i32,
+        width: attr_num(object, "width").unwrap_or(0.0).round().max(0.0) as u32,
+        height: attr_num(object, "height").unwrap_or(0.0).round().max(0.0) as u32,
+        attributes: Default::default(),
+        nodes: vec![],
+        layout: Default::default(),
+    };
+
+    for (name, ty, value) in properties(object) {
+        if name == NODES {
+            for node in value.split_whitespace() {
+                let coords = node
+                    .split_once(',')
+                    .and_then(|(x, y)| Some((x.trim().parse().ok()?, y.trim().parse().ok()?)));
+                let Some((x, y)) = coords else {
+                    return Err(format!("Object {id}: {node} is not a node, expected x,y"));
+                };
+                entity.nodes.push(Node { x, y });
+            }
+            continue;
+        }
+        let attribute = match ty.as_deref() {
+            Some("bool") => value.parse().ok().map(Attribute::Bool),
+            Some("int") => value.parse().ok().map(Attribute::Int),
+            Some("float") => value.parse().ok().map(Attribute::Float),
+            Some(_) => Some(Attribute::Text(value.clone())),
+            // Tiled leaves out the type of string properties, so guess like the game would
+            None => Some(match infer_attr(&value) {
+                BinElAttr::Bool(b) => Attribute::Bool(b),
+                BinElAttr::Int(i) => Attribute::Int(i),
+                BinElAttr::Float(f) => Attribute::Float(f),
+                BinElAttr::Text(s) => Attribute::Text(s),
+            }),
+        };
+        let attribute = attribute.ok_or_else(|| {
+            format!(
+                "Object {id}: property {name} is not a {}",
+                ty.unwrap_or_default()
+            )
+        })?;
+        entity.attributes.insert(name, attribute);
+    }
+
+    Ok(entity)
+}
+
+/// The name, type and value of each custom property of a Tiled element.
+fn properties(elem: &BinEl) -> impl Iterator<Item = (String, Option<String>, String)> + '_ {
+    elem.get("properties")
+        .flat_map(|properties| properties.get("property"))
+        .map(|property| {
+            let value = attr_text(property, "value")
+                .or_else(|| property.text().map(str::to_owned))
+                .unwrap_or_default();
+            (
+                attr_text(property, "name").unwrap_or_default(),
+                attr_text(property, "type"),
+                value,
+            )
+        })
+}
+
+fn attr_text(elem: &BinEl, name: &str) -> Option<String> {
+    match elem.attributes.get(name)? {
+        BinElAttr::Text(s) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+fn attr_num(elem: &BinEl, name: &str) -> Option<f32> {
+    attr_text(elem, name)?.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rooms_survive_a_round_trip() {
+        let mut room = CelesteMapLevel {
+            name: "lvl_a-00".to_owned(),
+            bounds: MapRectStrict::new(MapPointStrict::new(-16, 8), MapSizeStrict::new(24, 16)),
+            solids: TileGrid::new(TileSize::new(3, 2), '0'),
+            bg: TileGrid::new(TileSize::new(3, 2), 'b'),
+            object_tiles: TileGrid::new(TileSize::new(3, 2), -1),
+            ..CelesteMapLevel::default()
+        };
+        room.solids.tiles[1] = '3';
+        room.object_tiles.tiles[5] = 40;
+        room.entities.push(CelesteMapEntity {
+            nodes: vec![Node { x: 4, y: 4 }, Node { x: -8, y: 12 }],
+            ..CelesteMapEntity::new(3, "spring", 8, 16).with_attributes(&[
+                ("orientation", Attribute::Text("Floor".to_owned())),
+                ("count", Attribute::Text("5".to_owned())),
+                ("speed", Attribute::Float(1.0)),
+            ])
+        });
+        room.triggers.push(CelesteMapEntity {
+            width: 16,
+            height: 8,
+            ..CelesteMapEntity::new(3, "windTrigger", 0, 0)
+                .with_attributes(&[("enabled", Attribute::Bool(true))])
+        });
+        let map = CelesteMap {
+            levels: vec![room],
+            ..CelesteMap::default()
+        };
+
+        let tmx = map_to_tmx(&map);
+        assert!(tmx.contains(r#"<group id="1" name="a-00" offsetx="-16" offsety="8">"#));
+        let result = map_from_tmx(&tmx).unwrap();
+        let (before, after) = (&map.levels[0], &result.levels[0]);
+        assert_eq!(after.name, before.name);
+        assert_eq!(after.bounds, before.bounds);
+        assert_eq!(after.solids.tiles, before.solids.tiles);
+        assert_eq!(after.bg.tiles, before.bg.tiles);
+        assert_eq!(after.object_tiles.tiles, before.object_tiles.tiles);
+        assert_eq!(after.entities[0].name, "spring");
+        assert_eq!(after.entities[0].nodes, before.entities[0].nodes);
+        // text which looks like a number is written as a string property, so it stays text
+        assert!(tmx.contains(r#"<property name="count" type="string" value="5"/>"#));
+        assert_eq!(
+            after.entities[0].attributes["count"],
+            Attribute::Text("5".to_owned())
+        );
+        assert_eq!(after.entities[0].attributes["speed"], Attribute::Float(1.0));
+        assert_eq!(
+            after.entities[0].attributes["orientation"],
+            Attribute::Text("Floor".to_owned())
+        );
+        assert_eq!(after.triggers[0].width, 16);
+        assert_eq!(
+            after.triggers[0].attributes["enabled"],
+            Attribute::Bool(true)
+        );
+        // the trigger's id clashed with the entity's, so it got the next free object id
+        assert!(tmx.contains(r#"<object id="3" type="spring""#));
+        assert!(tmx.contains(r#"<object id="4" type="windTrigger""#));
+        assert!(tmx.contains(r#"nextobjectid="5""#));
+        assert_eq!(after.entities[0].id, 3);
+        assert_eq!(after.triggers[0].id, 4);
+    }
+}
@@ -11,12 +11,15 @@ use arborio_utils::interned::{intern_str, Interned, InternedMap};
 use arborio_walker::{open_module, ConfigSourceTrait};
 
 use crate::config::{EntityConfig, StylegroundConfig, TriggerConfig};
+use crate::dependencies::resolve_module_dependencies;
 use crate::module::{CelesteModule, ModuleID};
 use crate::selectable::{DecalSelectable, EntitySelectable, TileSelectable, TriggerSelectable};
+use crate::sprites::{resolve_sprite_frame, SpriteDefinition, SpriteFrame};
 
 #[derive(Lens, Clone)]
 pub struct ModuleAggregate {
     pub gameplay_atlas: MultiAtlas,
+    pub sprites: InternedMap<Arc<SpriteDefinition>>,
     pub autotilers: InternedMap<Arc<Autotiler>>,
     pub entity_config: InternedMap<Arc<EntityConfig>>,
     pub trigger_config: InternedMap<Arc<TriggerConfig>>,
@@ -39,24 +42,14 @@ impl ModuleAggregate {
         current_module: ModuleID,
         emit_logs: bool,
     ) -> Self {
-        if let Some(mymod) = modules.get(&current_module) {
-            for dep in mymod.everest_metadata.dependencies.iter() {
-                if dep.name == "Everest" {
-                    continue;
-                }
-                if modules_lookup.get(&dep.name).is_none() {
-                    log::warn!(
-                        "{} missing dependency {}",
-                        &modules.get(&current_module).unwrap().everest_metadata.name,
-                        &dep.name
-                    );
-                }
-            }
+        let resolved = resolve_module_dependencies(modules, modules_lookup, current_module);
+        for problem in &resolved.problems {
+            log::warn!("{}", problem);
         }
 
         Self::new_core(
             map_meta,
-            dep_mods(modules, modules_lookup, current_module),
+            dep_mods(modules, modules_lookup, current_module, resolved.modules),
             emit_logs,
         )
     }
@@ -82,6 +75,12 @@ impl ModuleAggregate {
             |module| module.gameplay_atlas.sprites_map.iter(),
             emit_logs,
         ));
+        let sprites = build_palette_map(
+            "Sprite",
+            deps.clone(),
+            |module| module.sprites.iter(),
+            emit_logs,
+        );
         let mut autotilers = build_palette_map(
             "Tiler Config",
             deps.clone(),
@@ -155,6 +154,7 @@ impl ModuleAggregate {
 
         let result = Self {
             gameplay_atlas,
+            sprites,
             autotilers,
             entity_config,
             trigger_config,
@@ -172,6 +172,13 @@ impl ModuleAggregate {
         result
     }
 
+    /// The first frame of an animation of a sprite from Sprites.xml, as a gameplay texture.
+    pub fn sprite_frame(&self, id: &str, animation: Option<&str>) -> Result<SpriteFrame, String> {
+        resolve_sprite_frame(&self.sprites, id, animation, |texture| {
+            self.gameplay_atlas.sprite_dimensions(texture)
+        })
+    }
+
     pub fn sanity_check(&self) {
         assert!(self.autotilers.get("fg").is_some());
         assert!(self.autotilers.get("bg").is_some());
@@ -273,10 +280,12 @@ fn extract_triggers_palette(config: &InternedMap<Arc<TriggerConfig>>) -> Vec<Tri
         .collect()
 }
 
+/// Arborio and Celeste, then `deps` in order, then the module itself.
 fn dep_mods<'a>(
     modules: &'a HashMap<ModuleID, CelesteModule>,
     modules_lookup: &'a HashMap<String, ModuleID>,
     current_module: ModuleID,
+    deps: Vec<String>,
 ) -> impl Clone + Iterator<Item = (&'a str, &'a CelesteModule)> {
     let x = modules_lookup;
     let y = modules;
@@ -289,15 +298,12 @@ fn dep_mods<'a>(
     }
     let a = get(x, y, "Arborio").into_iter().map(|m| ("Arborio", m));
     let b = get(x, y, "Celeste").into_iter().map(|m| ("Celeste", m));
-    let c = modules.get(&current_module).into_iter().flat_map(move |m| {
-        m.everest_metadata
-            .dependencies
-            .iter()
-            .filter(|dep| dep.name != "Celeste" && dep.name != "Everest")
-            .filter_map(move |dep| {
-                get(x, y, &dep.name).map(|module| (module.everest_metadata.name.as_str(), module))
-            })
-    });
+    let c = deps
+        .into_iter()
+        .filter(|dep| dep != "Celeste")
+        .filter_map(move |dep| {
+            get(x, y, &dep).map(|module| (module.everest_metadata.name.as_str(), module))
+        });
     let d = modules
         .get(&current_module)
         .into_iter()
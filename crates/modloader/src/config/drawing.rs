@@ -77,6 +77,20 @@ pub enum DrawElement {
         #[serde(default = "expr_zero")]
         rot: Expression,
     },
+    /// The first frame of an animation from Sprites.xml, lined up the way the game does it.
+    DrawSprite {
+        sprite: Expression,
+        /// Evaluates to an empty string for the animation the sprite starts with.
+        #[serde(default = "expr_empty")]
+        animation: Expression,
+        point: Vec2,
+        #[serde(default = "one_one")]
+        scale: Vec2,
+        #[serde(default)]
+        color: Color,
+        #[serde(default = "expr_zero")]
+        rot: Expression,
+    },
     DrawRectCustom {
         interval: f32,
         rect: Rect,
@@ -151,3 +165,6 @@ fn repeat() -> Expression {
 fn expr_zero() -> Expression {
     Expression::mk_const(0)
 }
+fn expr_empty() -> Expression {
+    Expression::Const(Const::String(String::new()))
+}
@@ -15,6 +15,7 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt::Formatter;
 use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
@@ -481,6 +482,16 @@ impl<'de> Deserialize<'de> for Expression {
     }
 }
 
+impl FromStr for Expression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        expression(s)
+            .map(|(_, parsed)| parsed)
+            .map_err(|e| e.to_string())
+    }
+}
+
 impl Serialize for Expression {
     fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
     where
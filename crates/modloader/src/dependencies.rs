@@ -0,0 +1,650 @@
+//! Working out everything a mod depends on, directly or not, and what's wrong with it, and which
+//! mods its maps actually use.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+use arborio_gfxloader::autotiler::Tileset;
+use arborio_maploader::binel::BinElAttr;
+use arborio_maploader::map_struct::{Attribute, CelesteMap};
+use arborio_walker::{open_module, ConfigSourceTrait};
+
+use crate::everest_yaml::{EverestModuleVersion, EverestYaml, EverestYamlDependency};
+use crate::module::{CelesteModule, ModuleID, ARBORIO_MODULE_ID, CELESTE_MODULE_ID};
+
+/// Dependencies which aren't mods we can load.
+const IGNORED: [&str; 2] = ["Everest", "EverestCore"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DependencyProblem {
+    Missing {
+        module: String,
+        dependency: String,
+        required: EverestModuleVersion,
+    },
+    Outdated {
+        module: String,
+        dependency: String,
+        required: EverestModuleVersion,
+        installed: EverestModuleVersion,
+    },
+    /// The names along the cycle, starting and ending with the same mod.
+    Cycle(Vec<String>),
+}
+
+impl fmt::Display for DependencyProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DependencyProblem::Missing {
+                module,
+                dependency,
+                required,
+            } => write!(
+                f,
+                "{module} needs {dependency} {}, which isn't installed",
+                required.to_string()
+            ),
+            DependencyProblem::Outdated {
+                module,
+                dependency,
+                required,
+                installed,
+            } => write!(
+                f,
+                "{module} needs {dependency} {}, but {} is installed",
+                required.to_string(),
+                installed.to_string()
+            ),
+            DependencyProblem::Cycle(names) => {
+                write!(f, "Dependency cycle: {}", names.join(" -> "))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResolvedDependencies {
+    /// Every mod depended on, directly or not, with each one after everything it depends on.
+    pub modules: Vec<String>,
+    pub problems: Vec<DependencyProblem>,
+}
+
+/// Resolve the dependencies of `root`, looking mods up by name with `lookup`. Optional
+/// dependencies are treated like any other if they're installed and skipped if they aren't.
+pub fn resolve_dependencies<'a>(
+    root: &'a EverestYaml,
+    lookup: impl Fn(&str) -> Option<&'a EverestYaml>,
+) -> ResolvedDependencies {
+    let mut resolver = Resolver {
+        lookup,
+        visiting: vec![],
+        done: HashSet::new(),
+        result: ResolvedDependencies::default(),
+    };
+    resolver.visit(root);
+    resolver.result
+}
+
+/// [`resolve_dependencies`] for a loaded module.
+pub fn resolve_module_dependencies(
+    modules: &HashMap<ModuleID, CelesteModule>,
+    modules_lookup: &HashMap<String, ModuleID>,
+    module: ModuleID,
+) -> ResolvedDependencies {
+    let Some(root) = modules.get(&module) else { return ResolvedDependencies::default() };
+    resolve_dependencies(&root.everest_metadata, |name| {
+        modules_lookup
+            .get(name)
+            .and_then(|id| modules.get(id))
+            .map(|module| &module.everest_metadata)
+    })
+}
+
+struct Resolver<'a, F> {
+    lookup: F,
+    visiting: Vec<&'a str>,
+    done: HashSet<&'a str>,
+    result: ResolvedDependencies,
+}
+
+impl<'a, F: Fn(&str) -> Option<&'a EverestYaml>> Resolver<'a, F> {
+    fn visit(&mut self, module: &'a EverestYaml) {
+        self.visiting.push(&module.name);
+        let required = module.dependencies.iter().map(|dep| (dep, false));
+        let optional = module.optional_dependencies.iter().map(|dep| (dep, true));
+        for (dep, optional) in required.chain(optional) {
+            if IGNORED.contains(&dep.name.as_str()) {
+                continue;
+            }
+            let Some(found) = (self.lookup)(&dep.name) else {
+                if !optional {
+                    self.result.problems.push(DependencyProblem::Missing {
+                        module: module.name.clone(),
+                        dependency: dep.name.clone(),
+                        required: dep.version.clone(),
+                    });
+                }
+                continue;
+            };
+            if !found.version.satisfies(&dep.version) {
+                self.result.problems.push(DependencyProblem::Outdated {
+                    module: module.name.clone(),
+                    dependency: dep.name.clone(),
+                    required: dep.version.clone(),
+                    installed: found.version.clone(),
+                });
+            }
+
+            if let Some(start) = self.visiting.iter().position(|name| *name == found.name) {
+                let mut cycle = self.visiting[start..]
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect::<Vec<_>>();
+                cycle.push(found.name.clone());
+                self.result.problems.push(DependencyProblem::Cycle(cycle));
+            } else if !self.done.contains(found.name.as_str()) {
+                self.visit(found);
+                self.done.insert(&found.name);
+                self.result.modules.push(found.name.clone());
+            }
+        }
+        self.visiting.pop();
+    }
+}
+
+/// A mod which the maps use, and some of what they use from it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NeededModule {
+    pub name: String,
+    pub version: EverestModuleVersion,
+    pub uses: Vec<String>,
+}
+
+/// Changes to a project's `Dependencies` suggested by what its maps use. These are only
+/// suggestions: a dependency which provides nothing the maps refer to may still be needed for its
+/// code.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DetectedDependencies {
+    pub add: Vec<NeededModule>,
+    pub remove: Vec<String>,
+    /// Whatever no installed mod seems to provide.
+    pub unknown: Vec<String>,
+}
+
+impl DetectedDependencies {
+    pub fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.remove.is_empty()
+    }
+
+    /// Make the suggested additions to `yaml`'s dependencies. Since a dependency may be needed for
+    /// its code, suggested removals are only made for the names in `confirmed_removals`.
+    pub fn apply(&self, yaml: &mut EverestYaml, confirmed_removals: &[String]) {
+        yaml.dependencies.retain(|dep| {
+            !(self.remove.contains(&dep.name) && confirmed_removals.contains(&dep.name))
+        });
+        for needed in &self.add {
+            yaml.dependencies.push(EverestYamlDependency {
+                name: needed.name.clone(),
+                version: needed.version.clone(),
+            });
+        }
+    }
+}
+
+/// Something a map refers to which has to come from some mod.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Used {
+    Entity(String),
+    Trigger(String),
+    Styleground(String),
+    Texture(String),
+    File(String),
+}
+
+impl fmt::Display for Used {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Used::Entity(name) => write!(f, "entity {name}"),
+            Used::Trigger(name) => write!(f, "trigger {name}"),
+            Used::Styleground(name) => write!(f, "styleground {name}"),
+            Used::Texture(path) => write!(f, "texture {path}"),
+            Used::File(path) => write!(f, "file {path}"),
+        }
+    }
+}
+
+impl Used {
+    fn provided_by(&self, module: &CelesteModule) -> bool {
+        match self {
+            Used::Entity(name) => module.entity_config.contains_key(name.as_str()),
+            Used::Trigger(name) => module.trigger_config.contains_key(name.as_str()),
+            Used::Styleground(name) => module.styleground_config.contains_key(name.as_str()),
+            Used::Texture(path) => module
+                .gameplay_atlas
+                .sprites_map
+                .contains_key(path.as_str()),
+            Used::File(path) => module
+                .filesystem_root
+                .as_deref()
+                .and_then(open_module)
+                .and_then(|mut source| source.get_file(Path::new(path)))
+                .is_some(),
+        }
+    }
+
+    /// The mod a name like `MaxHelpingHand/FlagToggleWall` says it comes from.
+    fn prefix(&self) -> Option<&str> {
+        match self {
+            Used::Entity(name) | Used::Trigger(name) | Used::Styleground(name) => {
+                name.split_once('/').map(|(prefix, _)| prefix)
+            }
+            Used::Texture(_) | Used::File(_) => None,
+        }
+    }
+}
+
+/// Work out which mods `maps` use and compare that with `project`'s `Dependencies`. Each object,
+/// texture or file is put down to the mod which provides it in its own configs, atlas or files, or
+/// which its name is prefixed with. Anything Celeste or the project itself provides needs nothing.
+pub fn detect_dependencies<'a>(
+    modules: &HashMap<ModuleID, CelesteModule>,
+    project: ModuleID,
+    maps: impl IntoIterator<Item = &'a CelesteMap>,
+) -> DetectedDependencies {
+    let Some(own) = modules.get(&project) else { return DetectedDependencies::default() };
+    let celeste = modules.get(&CELESTE_MODULE_ID);
+    let arborio = modules.get(&ARBORIO_MODULE_ID);
+    let mut others = modules
+        .iter()
+        .filter(|(id, _)| ![project, *CELESTE_MODULE_ID, *ARBORIO_MODULE_ID].contains(id))
+        .map(|(_, module)| module)
+        .collect::<Vec<_>>();
+    others.sort_by(|a, b| a.everest_metadata.name.cmp(&b.everest_metadata.name));
+    let current = &own.everest_metadata.dependencies;
+    let is_current = |module: &CelesteModule| {
+        current
+            .iter()
+            .any(|dep| dep.name == module.everest_metadata.name)
+    };
+
+    let maps = maps.into_iter().collect::<Vec<_>>();
+    let mut used = maps
+        .iter()
+        .flat_map(|map| used_by_map(map))
+        .collect::<BTreeSet<_>>();
+    // the textures of custom tilesets come from wherever their xml does
+    let tiles_xml = used
+        .iter()
+        .filter_map(|used| match used {
+            Used::File(path) => Some(path.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    for path in tiles_xml {
+        used.extend(tileset_textures(&path, &maps, own, &others));
+    }
+
+    let mut needed = BTreeMap::<String, NeededModule>::new();
+    let mut unknown = vec![];
+    for used in used {
+        if matches!(used.prefix(), Some(prefix) if prefix.eq_ignore_ascii_case("everest")) {
+            continue;
+        }
+        if used.provided_by(own) || matches!(celeste, Some(celeste) if used.provided_by(celeste)) {
+            continue;
+        }
+        let providers = others
+            .iter()
+            .filter(|module| used.provided_by(module))
+            .collect::<Vec<_>>();
+        let named = used.prefix().and_then(|prefix| {
+            others
+                .iter()
+                .find(|module| module.everest_metadata.name == prefix)
+        });
+        let provider = providers
+            .iter()
+            .copied()
+            .chain(named)
+            .find(|module| is_current(module))
+            .or(named)
+            .or_else(|| providers.first().copied());
+        match provider {
+            Some(module) => needed
+                .entry(module.everest_metadata.name.clone())
+                .or_insert_with(|| NeededModule {
+                    name: module.everest_metadata.name.clone(),
+                    version: module.everest_metadata.version.clone(),
+                    uses: vec![],
+                })
+                .uses
+                .push(used.to_string()),
+            // Arborio knows about the vanilla objects without any mod providing them
+            None if used.prefix().is_none()
+                && matches!(arborio, Some(arborio) if used.provided_by(arborio)) => {}
+            None => unknown.push(used.to_string()),
+        }
+    }
+
+    DetectedDependencies {
+        add: needed
+            .values()
+            .filter(|needed| !current.iter().any(|dep| dep.name == needed.name))
+            .cloned()
+            .collect(),
+        remove: current
+            .iter()
+            .filter(|dep| !IGNORED.contains(&dep.name.as_str()) && dep.name != "Celeste")
+            .filter(|dep| !needed.contains_key(&dep.name))
+            .map(|dep| dep.name.clone())
+            .collect(),
+        unknown,
+    }
+}
+
+fn used_by_map(map: &CelesteMap) -> Vec<Used> {
+    let mut result = vec![];
+    for room in &map.levels {
+        result.extend(
+            room.entities
+                .iter()
+                .map(|entity| Used::Entity(entity.name.clone())),
+        );
+        result.extend(
+            room.triggers
+                .iter()
+                .map(|trigger| Used::Trigger(trigger.name.clone())),
+        );
+        for decal in room.fg_decals.iter().chain(room.bg_decals.iter()) {
+            let texture = decal.texture.replace('\\', "/");
+            let texture = texture.strip_suffix(".png").unwrap_or(&texture);
+            result.push(Used::Texture(format!("decals/{texture}")));
+        }
+    }
+    for style in map.foregrounds.iter().chain(map.backgrounds.iter()) {
+        if style.name == "apply" {
+            for child in &style.children {
+                let texture = match child.attributes.get("texture") {
+                    Some(BinElAttr::Text(texture)) => Some(texture.as_str()),
+                    _ => None,
+                };
+                result.extend(used_by_styleground(&child.name, texture));
+            }
+        } else {
+            let texture = match style.attributes.get("texture") {
+                Some(Attribute::Text(texture)) => Some(texture.as_str()),
+                _ => None,
+            };
+            result.extend(used_by_styleground(&style.name, texture));
+        }
+    }
+    // the sidecar can set the tilesets too
+    if let Some(meta) = map.effective_meta() {
+        result.extend(
+            [&meta.fg_tiles, &meta.bg_tiles]
+                .into_iter()
+                .flatten()
+                .map(|path| Used::File(path.clone())),
+        );
+    }
+    result
+}
+
+fn used_by_styleground(name: &str, texture: Option<&str>) -> Option<Used> {
+    if name == "parallax" {
+        texture.map(|texture| Used::Texture(texture.to_owned()))
+    } else {
+        Some(Used::Styleground(name.to_owned()))
+    }
+}
+
+/// The textures of the tilesets in the xml at `path` which `maps` use.
+fn tileset_textures(
+    path: &str,
+    maps: &[&CelesteMap],
+    own: &CelesteModule,
+    others: &[&CelesteModule],
+) -> Vec<Used> {
+    let file = [own].iter().chain(others).find_map(|module| {
+        open_module(module.filesystem_root.as_deref()?)?.get_file(Path::new(path))
+    });
+    let Some(file) = file else { return vec![] };
+    let tilesets = match Tileset::new(file, "tilesets/") {
+        Ok(tilesets) => tilesets,
+        Err(e) => {
+            log::warn!("Could not read tileset {}: {}", path, e);
+            return vec![];
+        }
+    };
+
+    let mut chars = HashSet::new();
+    for map in maps {
+        let Some(meta) = map.effective_meta() else { continue };
+        for room in &map.levels {
+            if meta.fg_tiles.as_deref() == Some(path) {
+                chars.extend(room.solids.tiles.iter().copied());
+            }
+            if meta.bg_tiles.as_deref() == Some(path) {
+                chars.extend(room.bg.tiles.iter().copied());
+            }
+        }
+    }
+    chars
+        .into_iter()
+        .filter_map(|ch| tilesets.get(&ch))
+        .map(|tileset| Used::Texture(tileset.texture.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arborio_maploader::binel::BinEl;
+    use arborio_maploader::map_struct::{CelesteMapEntity, CelesteMapLevel, CelesteMapStyleground};
+    use arborio_maploader::meta_yaml;
+    use arborio_utils::units::{TileGrid, TileSize};
+
+    fn module(
+        name: &str,
+        version: &str,
+        deps: &[(&str, &str)],
+        optional: &[(&str, &str)],
+    ) -> EverestYaml {
+        let list = |deps: &[(&str, &str)]| {
+            deps.iter()
+                .map(|(name, version)| EverestYamlDependency {
+                    name: name.to_string(),
+                    version: version.parse().unwrap(),
+                })
+                .collect()
+        };
+        EverestYaml {
+            name: name.to_owned(),
+            version: version.parse().unwrap(),
+            dll: None,
+            dependencies: list(deps),
+            optional_dependencies: list(optional),
+        }
+    }
+
+    #[test]
+    fn versions_follow_everest_rules() {
+        let version = |s: &str| s.parse::<EverestModuleVersion>().unwrap();
+        assert!(version("1.2.3").satisfies(&version("1.2.3")));
+        assert!(version("1.3.0").satisfies(&version("1.2.3")));
+        assert!(version("1.2.4.1").satisfies(&version("1.2.3.9")));
+        assert!(!version("1.2.2").satisfies(&version("1.2.3")));
+        assert!(!version("2.0.0").satisfies(&version("1.2.3")));
+        assert!(version("1.2").satisfies(&version("1.2.0")));
+    }
+
+    #[test]
+    fn transitive_dependencies_resolve_in_order() {
+        let mods = [
+            module(
+                "Map",
+                "1.0.0",
+                &[("Everest", "1.3000.0"), ("Helper", "1.2.0")],
+                &[("Extra", "1.0.0"), ("Absent", "1.0.0")],
+            ),
+            module(
+                "Helper",
+                "1.1.0",
+                &[("Lib", "2.0.0"), ("Gone", "1.0.0")],
+                &[],
+            ),
+            module("Lib", "2.5.0", &[], &[]),
+            module(
+                "Extra",
+                "1.0.0",
+                &[("Lib", "2.0.0"), ("Cyclic", "1.0.0")],
+                &[],
+            ),
+            module("Cyclic", "1.0.0", &[("Extra", "1.0.0")], &[]),
+        ];
+        let lookup = |name: &str| mods.iter().find(|m| m.name == name);
+        let resolved = resolve_dependencies(&mods[0], lookup);
+
+        assert_eq!(resolved.modules, ["Lib", "Helper", "Cyclic", "Extra"]);
+        assert_eq!(
+            resolved.problems,
+            [
+                DependencyProblem::Outdated {
+                    module: "Map".to_owned(),
+                    dependency: "Helper".to_owned(),
+                    required: "1.2.0".parse().unwrap(),
+                    installed: "1.1.0".parse().unwrap(),
+                },
+                DependencyProblem::Missing {
+                    module: "Helper".to_owned(),
+                    dependency: "Gone".to_owned(),
+                    required: "1.0.0".parse().unwrap(),
+                },
+                DependencyProblem::Cycle(vec![
+                    "Extra".to_owned(),
+                    "Cyclic".to_owned(),
+                    "Extra".to_owned()
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn dependencies_are_detected_from_map_content() {
+        let entity = |name| CelesteMapEntity::new(0, name, 0, 0);
+        let mut map = CelesteMap::default();
+        map.levels.push(CelesteMapLevel {
+            entities: vec![entity("Helper/Spinner"), entity("Other/Thing")],
+            triggers: vec![entity("Mystery/Trigger")],
+            ..CelesteMapLevel::default()
+        });
+        let mut apply = CelesteMapStyleground {
+            name: "apply".to_owned(),
+            ..CelesteMapStyleground::default()
+        };
+        apply.children.push(BinEl::new("Other/Sky"));
+        map.backgrounds.push(apply);
+
+        let project = ModuleID::new();
+        let mut modules = HashMap::new();
+        modules.insert(
+            project,
+            CelesteModule::new(
+                None,
+                module(
+                    "Map",
+                    "1.0.0",
+                    &[
+                        ("Everest", "1.3000.0"),
+                        ("Helper", "1.0.0"),
+                        ("Stale", "1.0.0"),
+                    ],
+                    &[],
+                ),
+            ),
+        );
+        for (name, version) in [("Helper", "1.2.0"), ("Other", "2.1.0"), ("Stale", "1.0.0")] {
+            modules.insert(
+                ModuleID::new(),
+                CelesteModule::new(None, module(name, version, &[], &[])),
+            );
+        }
+
+        let detected = detect_dependencies(&modules, project, [&map]);
+        assert_eq!(
+            detected.add,
+            [NeededModule {
+                name: "Other".to_owned(),
+                version: "2.1.0".parse().unwrap(),
+                uses: vec![
+                    "entity Other/Thing".to_owned(),
+                    "styleground Other/Sky".to_owned()
+                ],
+            }]
+        );
+        assert_eq!(detected.remove, ["Stale"]);
+        assert_eq!(detected.unknown, ["trigger Mystery/Trigger"]);
+
+        let names = |confirmed_removals: &[String]| {
+            let mut yaml = modules[&project].everest_metadata.clone();
+            detected.apply(&mut yaml, confirmed_removals);
+            yaml.dependencies
+                .into_iter()
+                .map(|dep| dep.name)
+                .collect::<Vec<_>>()
+        };
+        // nothing is removed unless asked for
+        assert_eq!(names(&[]), ["Everest", "Helper", "Stale", "Other"]);
+        assert_eq!(
+            names(&["Stale".to_owned(), "Helper".to_owned()]),
+            ["Everest", "Helper", "Other"]
+        );
+    }
+
+    #[test]
+    fn tilesets_set_only_in_the_sidecar_are_detected() {
+        let root = std::env::temp_dir().join(format!("arborio-deps-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("Graphics")).unwrap();
+        std::fs::write(
+            root.join("Graphics/ForegroundTiles.xml"),
+            r#"<Data><Tileset id="z" path="zigzag"></Tileset></Data>"#,
+        )
+        .unwrap();
+        let mut map = CelesteMap {
+            meta_yaml: Some(
+                meta_yaml::from_reader("ForegroundTiles: Graphics/ForegroundTiles.xml".as_bytes())
+                    .unwrap(),
+            ),
+            ..CelesteMap::default()
+        };
+        map.levels.push(CelesteMapLevel {
+            solids: TileGrid::new(TileSize::new(1, 1), 'z'),
+            ..CelesteMapLevel::default()
+        });
+
+        let project = ModuleID::new();
+        let mut modules = HashMap::new();
+        modules.insert(
+            project,
+            CelesteModule::new(None, module("Map", "1.0.0", &[], &[])),
+        );
+        modules.insert(
+            ModuleID::new(),
+            CelesteModule::new(Some(root.clone()), module("Tiles", "1.0.0", &[], &[])),
+        );
+
+        let detected = detect_dependencies(&modules, project, [&map]);
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            detected.add,
+            [NeededModule {
+                name: "Tiles".to_owned(),
+                version: "1.0.0".parse().unwrap(),
+                uses: vec!["file Graphics/ForegroundTiles.xml".to_owned()],
+            }]
+        );
+        // the tileset's texture is looked for in the mods' atlases, and none of these have it
+        assert_eq!(detected.unknown, ["texture tilesets/zigzag"]);
+    }
+}
@@ -15,6 +15,7 @@ pub fn celeste_module_yaml() -> EverestYaml {
         version: EverestModuleVersion(vec![1, 4, 0, 0]),
         dll: None,
         dependencies: vec![],
+        optional_dependencies: vec![],
     }
 }
 
@@ -32,6 +33,12 @@ pub struct EverestYaml {
     pub dll: Option<String>,
     #[serde(rename = "Dependencies", default)]
     pub dependencies: Vec<EverestYamlDependency>,
+    #[serde(
+        rename = "OptionalDependencies",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub optional_dependencies: Vec<EverestYamlDependency>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -45,6 +52,17 @@ pub struct EverestYamlDependency {
 #[derive(PartialEq, Eq, PartialOrd, Clone, Debug, Data)]
 pub struct EverestModuleVersion(pub Vec<i32>);
 
+impl EverestModuleVersion {
+    /// Whether this version can be used where `required` is asked for. Like Everest, the major
+    /// versions have to match and the minor and build can't be older; the revision doesn't matter.
+    pub fn satisfies(&self, required: &EverestModuleVersion) -> bool {
+        let part =
+            |version: &EverestModuleVersion, idx: usize| version.0.get(idx).copied().unwrap_or(0);
+        part(self, 0) == part(required, 0)
+            && (part(self, 1), part(self, 2)) >= (part(required, 1), part(required, 2))
+    }
+}
+
 impl<'de> Deserialize<'de> for EverestModuleVersion {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -1,10 +1,14 @@
 pub mod aggregate;
 pub mod config;
+pub mod dependencies;
 pub mod discovery;
 pub mod everest_yaml;
+pub mod loenn;
 pub mod mapstruct_plus_config;
 pub mod module;
+pub mod packaging;
 pub mod selectable;
+pub mod sprites;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,1096 @@
+//! Entity and trigger configs made from Loenn's Lua plugins, for mods which don't ship any of their
+//! own. Plugins run in a sandbox with no way to reach the filesystem or the os, and whatever they
+//! `require` from Loenn is stubbed out, so only the parts of a plugin which are plain data (or
+//! functions which don't lean on Loenn much) make it into the config. The few Loenn modules which
+//! plugins draw with, `utils.rectangle` and the drawable sprites and rectangles, are stood in for
+//! by plain tables which are turned into draw elements afterwards.
+
+use rlua::{Context, Lua, StdLib, Table, Value};
+use std::collections::HashMap;
+
+use crate::config::{
+    AttributeInfo, AttributeOption, AttributeType, AttributeValue, Color, Const, DrawElement,
+    EntityConfig, EntityDraw, EntityRects, EntityTemplate, Expression, Number, PencilBehavior,
+    Rect, TriggerConfig, Vec2,
+};
+use arborio_utils::interned::intern_str;
+
+/// Where Loenn plugins live inside a mod.
+pub const ENTITIES_DIR: &str = "Loenn/entities";
+pub const TRIGGERS_DIR: &str = "Loenn/triggers";
+pub const LANG_FILE: &str = "Loenn/lang/en_us.lang";
+
+/// How much memory and how many instructions a plugin gets before it's given up on.
+const MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+const INSTRUCTION_LIMIT: u32 = 10_000_000;
+
+const PRELUDE: &str = r#"
+local function stub()
+    return setmetatable({}, {
+        __index = function(_, key)
+            if type(key) == "string" then
+                return stub()
+            end
+        end,
+        __call = function()
+            return stub()
+        end,
+    })
+end
+
+local utils = stub()
+
+function utils.deepcopy(value)
+    if type(value) ~= "table" then
+        return value
+    end
+    local result = {}
+    for k, v in pairs(value) do
+        result[k] = utils.deepcopy(v)
+    end
+    return result
+end
+
+function utils.titleCase(text)
+    return (text:gsub("(%a)([%w_']*)", function(first, rest)
+        return first:upper() .. rest
+    end))
+end
+
+function utils.humanizeVariableName(name)
+    return utils.titleCase((name:gsub("(%l)(%u)", "%1 %2"):gsub("_", " ")))
+end
+
+function utils.rectangle(x, y, width, height)
+    return { x = x, y = y, width = width, height = height }
+end
+
+local drawableSprite = {}
+local spriteMethods = {}
+spriteMethods.__index = spriteMethods
+
+function spriteMethods:setJustification(x, y)
+    self.justificationX, self.justificationY = x, y
+    return self
+end
+
+function spriteMethods:setPosition(x, y)
+    self.x, self.y = x, y
+    return self
+end
+
+function spriteMethods:addPosition(x, y)
+    self.x, self.y = self.x + x, self.y + y
+    return self
+end
+
+function spriteMethods:setScale(x, y)
+    self.scaleX, self.scaleY = x, y
+    return self
+end
+
+function spriteMethods:setColor(color)
+    self.color = color
+    return self
+end
+
+function drawableSprite.fromTexture(texture, data)
+    data = data or {}
+    return setmetatable({
+        _type = "drawableSprite",
+        texture = texture,
+        x = data.x or 0,
+        y = data.y or 0,
+        justificationX = data.justificationX or 0.5,
+        justificationY = data.justificationY or 0.5,
+        scaleX = data.scaleX or 1,
+        scaleY = data.scaleY or 1,
+        rotation = data.rotation or 0,
+        color = data.color,
+    }, spriteMethods)
+end
+
+local drawableRectangle = {}
+
+-- either (mode, x, y, width, height, color, secondaryColor) or (mode, rectangle, color, secondaryColor)
+function drawableRectangle.fromRectangle(mode, x, y, width, height, color, secondaryColor)
+    if type(x) == "table" then
+        x, y, width, height, color, secondaryColor = x.x, x.y, x.width, x.height, y, width
+    end
+    return {
+        _type = "drawableRectangle",
+        mode = mode,
+        x = x,
+        y = y,
+        width = width,
+        height = height,
+        color = color,
+        secondaryColor = secondaryColor,
+    }
+end
+
+local modules = {
+    utils = utils,
+    ["structs.drawable_sprite"] = drawableSprite,
+    ["structs.drawable_rectangle"] = drawableRectangle,
+}
+
+function require(name)
+    local module = modules[name]
+    if module == nil then
+        module = stub()
+        modules[name] = module
+    end
+    return module
+end
+
+unpack = table.unpack
+dofile = nil
+loadfile = nil
+collectgarbage = nil
+"#;
+
+/// Read the `key=value` lines of a Loenn language file.
+pub fn parse_lang(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_owned(), value.trim().to_owned()))
+        .collect()
+}
+
+/// Run an entity plugin and make a config from each of the handlers it returns.
+pub fn load_entities(
+    source: &str,
+    path: &str,
+    lang: &HashMap<String, String>,
+) -> Result<Vec<EntityConfig>, String> {
+    with_handlers(source, path, |ctx, handler| {
+        entity_config(ctx, handler, path, lang)
+    })
+}
+
+/// Run a trigger plugin and make a config from each of the handlers it returns.
+pub fn load_triggers(
+    source: &str,
+    path: &str,
+    lang: &HashMap<String, String>,
+) -> Result<Vec<TriggerConfig>, String> {
+    with_handlers(source, path, |ctx, handler| {
+        trigger_config(ctx, handler, path, lang)
+    })
+}
+
+fn sandbox() -> Lua {
+    let lua = Lua::new_with(StdLib::BASE | StdLib::TABLE | StdLib::STRING | StdLib::MATH);
+    lua.set_memory_limit(Some(MEMORY_LIMIT));
+    let mut budget = 100;
+    lua.set_hook(
+        rlua::HookTriggers {
+            every_nth_instruction: Some(INSTRUCTION_LIMIT / 100),
+            ..Default::default()
+        },
+        move |_, _| {
+            budget -= 1;
+            if budget == 0 {
+                Err(rlua::Error::RuntimeError("plugin took too long".to_owned()))
+            } else {
+                Ok(())
+            }
+        },
+    );
+    lua
+}
+
+fn with_handlers<T>(
+    source: &str,
+    path: &str,
+    mut make: impl for<'lua> FnMut(Context<'lua>, &Table<'lua>) -> rlua::Result<Option<T>>,
+) -> Result<Vec<T>, String> {
+    sandbox()
+        .context(|ctx| {
+            ctx.load(PRELUDE).exec()?;
+            let result = ctx.load(source).set_name(path)?.eval::<Value>()?;
+            let Value::Table(result) = result else { return Ok(vec![]) };
+            // a plugin is either one handler or a list of them
+            let handlers = if result.contains_key("name")? {
+                vec![result]
+            } else {
+                result
+                    .sequence_values::<Table>()
+                    .collect::<rlua::Result<_>>()?
+            };
+            let mut configs = vec![];
+            for handler in handlers {
+                if let Some(config) = make(ctx, &handler)? {
+                    configs.push(config);
+                }
+            }
+            Ok(configs)
+        })
+        .map_err(|e: rlua::Error| e.to_string())
+}
+
+struct Placement {
+    name: String,
+    data: Vec<(String, AttributeValue)>,
+}
+
+fn entity_config<'lua>(
+    ctx: Context<'lua>,
+    handler: &Table<'lua>,
+    path: &str,
+    lang: &HashMap<String, String>,
+) -> rlua::Result<Option<EntityConfig>> {
+    let Some(name) = handler.get::<_, Option<String>>("name")? else { return Ok(None) };
+    let placements = placements(handler)?;
+    let fake = fake_entity(ctx, &placements)?;
+    let lang_prefix = format!("entities.{name}");
+    let plugin = format!("{path} ({name})");
+    let resolve = |key: &str| resolve(handler, key, &fake, &plugin);
+
+    let has_data = |key: &str| {
+        placements
+            .iter()
+            .any(|placement| placement.data.iter().any(|(k, _)| k == key))
+    };
+    let (resizable_x, resizable_y) =
+        bool_pair(resolve("canResize")?)?.unwrap_or((has_data("width"), has_data("height")));
+    let (minimum_size_x, minimum_size_y) = number_pair(resolve("minimumSize")?)?
+        .map(|(x, y)| (x as u32, y as u32))
+        .unwrap_or((
+            if resizable_x { 8 } else { 0 },
+            if resizable_y { 8 } else { 0 },
+        ));
+    let nodes = has_nodes(resolve("nodeLimits")?)?;
+
+    let texture = match resolve("texture")? {
+        Value::String(texture) => Some(texture.to_str()?.to_owned()),
+        _ => None,
+    };
+    let (justify_x, justify_y) = number_pair(resolve("justification")?)?.unwrap_or((0.5, 0.5));
+    let draw = |point: Vec2, rect: Rect| match &texture {
+        Some(texture) => DrawElement::DrawPointImage {
+            texture: Expression::Const(Const::String(texture.clone())),
+            point,
+            justify_x: justify_x as f32,
+            justify_y: justify_y as f32,
+            scale: Vec2 {
+                x: Expression::mk_const(1),
+                y: Expression::mk_const(1),
+            },
+            color: Color::default(),
+            rot: Expression::mk_const(0),
+        },
+        None => DrawElement::DrawRect {
+            rect,
+            color: Color {
+                r: Expression::mk_const(255),
+                g: Expression::mk_const(50),
+                b: Expression::mk_const(50),
+                a: Expression::mk_const(255),
+            },
+            border_color: Color {
+                r: Expression::mk_const(255),
+                g: Expression::mk_const(0),
+                b: Expression::mk_const(0),
+                a: Expression::mk_const(255),
+            },
+            border_thickness: 1,
+        },
+    };
+    // like Loenn, a sprite wins over a texture, which wins over a rectangle
+    let body = match resolve("rectangle")? {
+        Value::Table(rectangle) => lua_rect(&rectangle, &fake)?,
+        _ => None,
+    }
+    .unwrap_or_else(body_rect);
+    let sprites = drawables(resolve("sprite")?, &fake)?;
+    let initial_draw = if sprites.is_empty() {
+        let mut initial_draw = draw(vec2("x", "y"), body.clone());
+        if let DrawElement::DrawRect {
+            color,
+            border_color,
+            ..
+        } = &mut initial_draw
+        {
+            if let Some(fill) = lua_color(resolve("fillColor")?)? {
+                *color = fill;
+            }
+            if let Some(border) = lua_color(resolve("borderColor")?)? {
+                *border_color = border;
+            }
+        }
+        vec![initial_draw]
+    } else {
+        sprites
+    };
+
+    let mut config = EntityConfig::new(&name);
+    config.hitboxes = EntityRects {
+        initial_rects: vec![body],
+        node_rects: if nodes { vec![node_rect()] } else { vec![] },
+    };
+    config.standard_draw = EntityDraw {
+        initial_draw,
+        node_draw: if nodes {
+            vec![draw(vec2("nodex", "nodey"), node_rect())]
+        } else {
+            vec![]
+        },
+    };
+    if nodes {
+        config.selected_draw.node_draw.push(DrawElement::DrawLine {
+            start: vec2("prevnodexorbase", "prevnodeyorbase"),
+            end: vec2("nodex", "nodey"),
+            color: Color {
+                r: Expression::mk_const(255),
+                g: Expression::mk_const(255),
+                b: Expression::mk_const(0),
+                a: Expression::mk_const(200),
+            },
+            arrowhead: true,
+            thickness: 1,
+        });
+    }
+    config.minimum_size_x = minimum_size_x;
+    config.minimum_size_y = minimum_size_y;
+    config.resizable_x = resizable_x;
+    config.resizable_y = resizable_y;
+    config.nodes = nodes;
+    config.pencil = if resizable_x || resizable_y {
+        PencilBehavior::Rect
+    } else if nodes {
+        PencilBehavior::Node
+    } else {
+        PencilBehavior::Line
+    };
+    config.attribute_info = attribute_info(
+        handler,
+        resolve("fieldInformation")?,
+        &placements,
+        &lang_prefix,
+        lang,
+    )?;
+    config.templates = templates(&name, &placements, &lang_prefix, lang);
+    Ok(Some(config))
+}
+
+fn trigger_config<'lua>(
+    ctx: Context<'lua>,
+    handler: &Table<'lua>,
+    path: &str,
+    lang: &HashMap<String, String>,
+) -> rlua::Result<Option<TriggerConfig>> {
+    let Some(name) = handler.get::<_, Option<String>>("name")? else { return Ok(None) };
+    let placements = placements(handler)?;
+    let fake = fake_entity(ctx, &placements)?;
+    let lang_prefix = format!("triggers.{name}");
+    let plugin = format!("{path} ({name})");
+    let resolve = |key: &str| resolve(handler, key, &fake, &plugin);
+
+    let mut config = TriggerConfig::new(&name);
+    config.nodes = has_nodes(resolve("nodeLimits")?)?;
+    config.attribute_info = attribute_info(
+        handler,
+        resolve("fieldInformation")?,
+        &placements,
+        &lang_prefix,
+        lang,
+    )?;
+    config.templates = templates(&name, &placements, &lang_prefix, lang);
+    Ok(Some(config))
+}
+
+/// The handler's placements, with the data of `placements.default` under each of them.
+fn placements(handler: &Table) -> rlua::Result<Vec<Placement>> {
+    let Value::Table(placements) = handler.get::<_, Value>("placements")? else { return Ok(vec![]) };
+    let default = match placements.get::<_, Value>("default")? {
+        Value::Table(default) => placement_data(&default)?,
+        _ => vec![],
+    };
+    let list = if placements.contains_key("name")? || placements.contains_key("data")? {
+        vec![placements]
+    } else {
+        placements
+            .sequence_values::<Table>()
+            .collect::<rlua::Result<_>>()?
+    };
+
+    let mut result = vec![];
+    for placement in list {
+        let mut data = default.clone();
+        for (key, value) in placement_data(&placement)? {
+            data.retain(|(k, _)| *k != key);
+            data.push((key, value));
+        }
+        result.push(Placement {
+            name: placement
+                .get::<_, Option<String>>("name")?
+                .unwrap_or_else(|| "default".to_owned()),
+            data,
+        });
+    }
+    Ok(result)
+}
+
+fn placement_data(placement: &Table) -> rlua::Result<Vec<(String, AttributeValue)>> {
+    let Value::Table(data) = placement.get::<_, Value>("data")? else { return Ok(vec![]) };
+    let mut result = vec![];
+    for pair in data.pairs::<Value, Value>() {
+        let (key, value) = pair?;
+        if let (Value::String(key), Some(value)) = (key, attribute_value(value)?) {
+            result.push((key.to_str()?.to_owned(), value));
+        }
+    }
+    result.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(result)
+}
+
+/// What a handler's function fields are called with in place of a real entity: the first
+/// placement, sitting at the origin.
+fn fake_entity<'lua>(ctx: Context<'lua>, placements: &[Placement]) -> rlua::Result<Table<'lua>> {
+    let entity = ctx.create_table()?;
+    entity.set("x", 0)?;
+    entity.set("y", 0)?;
+    entity.set("nodes", ctx.create_table()?)?;
+    for (key, value) in placements.first().map_or(&[][..], |p| &p.data[..]) {
+        match value {
+            AttributeValue::String(s) => entity.set(key.as_str(), s.as_str())?,
+            AttributeValue::Float(f) => entity.set(key.as_str(), *f)?,
+            AttributeValue::Int(i) => entity.set(key.as_str(), *i)?,
+            AttributeValue::Bool(b) => entity.set(key.as_str(), *b)?,
+        }
+    }
+    Ok(entity)
+}
+
+/// A handler field, or what it returns if it's a function of `(room, entity)`. A function which
+/// fails is treated as if it weren't there, since it most likely wanted something from Loenn, and
+/// is logged against `plugin`.
+fn resolve<'lua>(
+    handler: &Table<'lua>,
+    key: &str,
+    fake: &Table<'lua>,
+    plugin: &str,
+) -> rlua::Result<Value<'lua>> {
+    match handler.get::<_, Value>(key)? {
+        Value::Function(function) => match function.call::<_, Value>((Value::Nil, fake.clone())) {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                log::warn!("Loenn plugin {plugin}: {key} failed, ignoring it: {e}");
+                Ok(Value::Nil)
+            }
+        },
+        value => Ok(value),
+    }
+}
+
+/// `nodeLimits` is `{min, max}`, where a max of -1 means any number of nodes.
+fn has_nodes(node_limits: Value) -> rlua::Result<bool> {
+    Ok(matches!(
+        number_pair(node_limits)?,
+        Some((_, max)) if max != 0.0
+    ))
+}
+
+fn attribute_info<'lua>(
+    handler: &Table<'lua>,
+    field_information: Value<'lua>,
+    placements: &[Placement],
+    lang_prefix: &str,
+    lang: &HashMap<String, String>,
+) -> rlua::Result<HashMap<String, AttributeInfo>> {
+    let field_information = match field_information {
+        Value::Table(info) => Some(info),
+        _ => None,
+    };
+    let ignored = match handler.get::<_, Value>("ignoredFields")? {
+        Value::Table(ignored) => ignored
+            .sequence_values::<String>()
+            .collect::<rlua::Result<Vec<_>>>()?,
+        _ => vec![],
+    };
+
+    let mut result = HashMap::new();
+    for placement in placements {
+        for (key, value) in &placement.data {
+            if ["x", "y", "width", "height"].contains(&key.as_str()) || result.contains_key(key) {
+                continue;
+            }
+            let info = match &field_information {
+                Some(info) => match info.get::<_, Value>(key.as_str())? {
+                    Value::Table(info) => Some(info),
+                    _ => None,
+                },
+                None => None,
+            };
+            let field_type = match &info {
+                Some(info) => info.get::<_, Option<String>>("fieldType")?,
+                None => None,
+            };
+            // like Loenn, numbers are floats unless they're asked to be integers
+            let ty = match field_type.as_deref() {
+                Some("integer") => AttributeType::Int,
+                Some("number") => AttributeType::Float,
+                Some("boolean") => AttributeType::Bool,
+                Some(_) => AttributeType::String,
+                None => match value.ty() {
+                    AttributeType::Int => AttributeType::Float,
+                    ty => ty,
+                },
+            };
+            let options = match &info {
+                Some(info) => match info.get::<_, Value>("options")? {
+                    Value::Table(options) => attribute_options(options, ty)?,
+                    _ => vec![],
+                },
+                None => vec![],
+            };
+            result.insert(
+                key.clone(),
+                AttributeInfo {
+                    display_name: lang
+                        .get(&format!("{lang_prefix}.attributes.name.{key}"))
+                        .cloned(),
+                    ty,
+                    default: convert(value.clone(), ty),
+                    options,
+                    ignore: ignored.contains(key),
+                },
+            );
+        }
+    }
+    Ok(result)
+}
+
+/// Options come as a list of values, a list of `{name, value}` pairs, or a table of names to values.
+fn attribute_options(options: Table, ty: AttributeType) -> rlua::Result<Vec<AttributeOption>> {
+    let mut listed = vec![];
+    let mut named = vec![];
+    for pair in options.pairs::<Value, Value>() {
+        match pair? {
+            (Value::Integer(idx), Value::Table(pair)) => {
+                let name = pair.get::<_, Value>(1)?;
+                let value = match pair.get::<_, Value>(2)? {
+                    Value::Nil => name.clone(),
+                    value => value,
+                };
+                if let (Some(name), Some(value)) = (attribute_value(name)?, attribute_value(value)?)
+                {
+                    listed.push((idx, option(option_text(&name), value, ty)));
+                }
+            }
+            (Value::Integer(idx), value) => {
+                if let Some(value) = attribute_value(value)? {
+                    listed.push((idx, option(option_text(&value), value, ty)));
+                }
+            }
+            (Value::String(name), value) => {
+                if let Some(value) = attribute_value(value)? {
+                    named.push(option(name.to_str()?.to_owned(), value, ty));
+                }
+            }
+            _ => {}
+        }
+    }
+    listed.sort_by_key(|(idx, _)| *idx);
+    named.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(listed
+        .into_iter()
+        .map(|(_, option)| option)
+        .chain(named)
+        .collect())
+}
+
+fn option(name: String, value: AttributeValue, ty: AttributeType) -> AttributeOption {
+    AttributeOption {
+        name,
+        value: convert(value, ty),
+    }
+}
+
+fn option_text(value: &AttributeValue) -> String {
+    match value {
+        AttributeValue::String(s) => s.clone(),
+        AttributeValue::Float(f) => f.to_string(),
+        AttributeValue::Int(i) => i.to_string(),
+        AttributeValue::Bool(b) => b.to_string(),
+    }
+}
+
+fn templates(
+    name: &str,
+    placements: &[Placement],
+    lang_prefix: &str,
+    lang: &HashMap<String, String>,
+) -> Vec<EntityTemplate> {
+    placements
+        .iter()
+        .map(|placement| EntityTemplate {
+            name: intern_str(
+                &lang
+                    .get(&format!("{lang_prefix}.placements.name.{}", placement.name))
+                    .cloned()
+                    .unwrap_or_else(|| format!("{name} ({})", placement.name)),
+            ),
+            keywords: vec![],
+            attributes: placement
+                .data
+                .iter()
+                .filter(|(key, _)| !["x", "y", "width", "height"].contains(&key.as_str()))
+                .map(|(key, value)| (intern_str(key), value.clone()))
+                .collect(),
+        })
+        .collect()
+}
+
+fn attribute_value(value: Value) -> rlua::Result<Option<AttributeValue>> {
+    Ok(match value {
+        Value::Boolean(b) => Some(AttributeValue::Bool(b)),
+        Value::Integer(i) => Some(AttributeValue::Int(i as i32)),
+        Value::Number(n) => Some(AttributeValue::Float(n as f32)),
+        Value::String(s) => Some(AttributeValue::String(s.to_str()?.to_owned())),
+        _ => None,
+    })
+}
+
+fn convert(value: AttributeValue, ty: AttributeType) -> AttributeValue {
+    match (value, ty) {
+        (AttributeValue::Int(i), AttributeType::Float) => AttributeValue::Float(i as f32),
+        (AttributeValue::Float(f), AttributeType::Int) => AttributeValue::Int(f as i32),
+        (value, AttributeType::String) => AttributeValue::String(option_text(&value)),
+        (value, _) => value,
+    }
+}
+
+fn numbers(value: Value) -> rlua::Result<Option<Vec<f64>>> {
+    let Value::Table(table) = value else { return Ok(None) };
+    let mut result = vec![];
+    for value in table.sequence_values::<Value>() {
+        match value? {
+            Value::Integer(i) => result.push(i as f64),
+            Value::Number(n) => result.push(n),
+            _ => return Ok(None),
+        }
+    }
+    Ok(Some(result))
+}
+
+fn number_pair(value: Value) -> rlua::Result<Option<(f64, f64)>> {
+    Ok(match numbers(value)?.as_deref() {
+        Some([x, y, ..]) => Some((*x, *y)),
+        _ => None,
+    })
+}
+
+fn bool_pair(value: Value) -> rlua::Result<Option<(bool, bool)>> {
+    let Value::Table(table) = value else { return Ok(None) };
+    Ok(
+        match (table.get::<_, Value>(1)?, table.get::<_, Value>(2)?) {
+            (Value::Boolean(x), Value::Boolean(y)) => Some((x, y)),
+            _ => None,
+        },
+    )
+}
+
+/// The drawable sprites and rectangles a `sprite` function returns, either one or a list of them.
+/// Their positions are relative to the fake entity, so they're drawn relative to the real one.
+fn drawables(value: Value, fake: &Table) -> rlua::Result<Vec<DrawElement>> {
+    let Value::Table(table) = value else { return Ok(vec![]) };
+    let list = if table.contains_key("_type")? {
+        vec![table]
+    } else {
+        table
+            .sequence_values::<Value>()
+            .filter_map(|value| match value {
+                Ok(Value::Table(table)) => Some(Ok(table)),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            })
+            .collect::<rlua::Result<_>>()?
+    };
+
+    let mut result = vec![];
+    for drawable in list {
+        match drawable.get::<_, Option<String>>("_type")?.as_deref() {
+            Some("drawableSprite") => {
+                let Some(texture) = drawable.get::<_, Option<String>>("texture")? else { continue };
+                let scale_x = number_field(&drawable, "scaleX", 1.0)?;
+                let scale_y = number_field(&drawable, "scaleY", 1.0)?;
+                let rotation = number_field(&drawable, "rotation", 0.0)?;
+                result.push(DrawElement::DrawPointImage {
+                    texture: Expression::Const(Const::String(texture)),
+                    point: Vec2 {
+                        x: offset("x", number_field(&drawable, "x", 0.0)?),
+                        y: offset("y", number_field(&drawable, "y", 0.0)?),
+                    },
+                    justify_x: number_field(&drawable, "justificationX", 0.5)? as f32,
+                    justify_y: number_field(&drawable, "justificationY", 0.5)? as f32,
+                    scale: Vec2 {
+                        x: Expression::Const(Const::Number(Number(scale_x))),
+                        y: Expression::Const(Const::Number(Number(scale_y))),
+                    },
+                    color: lua_color(drawable.get("color")?)?.unwrap_or_default(),
+                    // Loenn rotates by radians
+                    rot: Expression::Const(Const::Number(Number(rotation.to_degrees()))),
+                });
+            }
+            Some("drawableRectangle") => {
+                let Some(rect) = lua_rect(&drawable, fake)? else { continue };
+                let color = lua_color(drawable.get("color")?)?.unwrap_or_default();
+                let clear = Color {
+                    a: Expression::mk_const(0),
+                    ..Color::default()
+                };
+                let (color, border_color, border_thickness) =
+                    match drawable.get::<_, Option<String>>("mode")?.as_deref() {
+                        Some("line") => (clear, color, 1),
+                        Some("bordered") => {
+                            let border = lua_color(drawable.get("secondaryColor")?)?;
+                            (color.clone(), border.unwrap_or(color), 1)
+                        }
+                        _ => (color, clear, 0),
+                    };
+                result.push(DrawElement::DrawRect {
+                    rect,
+                    color,
+                    border_color,
+                    border_thickness,
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(result)
+}
+
+/// A `{x, y, width, height}` rectangle relative to the fake entity. Sides as long as the fake
+/// entity's follow the size of the real one.
+fn lua_rect(rect: &Table, fake: &Table) -> rlua::Result<Option<Rect>> {
+    let size = |key: &str| -> rlua::Result<Option<Expression>> {
+        let value = number_field(rect, key, 0.0)?;
+        if value <= 0.0 {
+            return Ok(None);
+        }
+        Ok(Some(if value == number_field(fake, key, 0.0)? {
+            expr(key)
+        } else {
+            Expression::Const(Const::Number(Number(value)))
+        }))
+    };
+    let (Some(width), Some(height)) = (size("width")?, size("height")?) else { return Ok(None) };
+    Ok(Some(Rect {
+        topleft: Vec2 {
+            x: offset("x", number_field(rect, "x", 0.0)?),
+            y: offset("y", number_field(rect, "y", 0.0)?),
+        },
+        size: Vec2 {
+            x: width,
+            y: height,
+        },
+    }))
+}
+
+/// A number in a table, or `default` if it's missing or not a finite number.
+fn number_field(table: &Table, key: &str, default: f64) -> rlua::Result<f64> {
+    Ok(match table.get::<_, Value>(key)? {
+        Value::Integer(i) => i as f64,
+        Value::Number(n) if n.is_finite() => n,
+        _ => default,
+    })
+}
+
+/// `var` moved by `by`.
+fn offset(var: &str, by: f64) -> Expression {
+    if by < 0.0 {
+        expr(&format!("{var} - {}", -by))
+    } else if by > 0.0 {
+        expr(&format!("{var} + {by}"))
+    } else {
+        expr(var)
+    }
+}
+
+/// Loenn colors are `{r, g, b, a}` from 0 to 1, with the alpha optional.
+fn lua_color(value: Value) -> rlua::Result<Option<Color>> {
+    let channel = |c: f64| Expression::mk_const((c.clamp(0.0, 1.0) * 255.0).round() as i32);
+    Ok(match numbers(value)?.as_deref() {
+        Some([r, g, b]) => Some(Color {
+            r: channel(*r),
+            g: channel(*g),
+            b: channel(*b),
+            a: Expression::mk_const(255),
+        }),
+        Some([r, g, b, a, ..]) => Some(Color {
+            r: channel(*r),
+            g: channel(*g),
+            b: channel(*b),
+            a: channel(*a),
+        }),
+        _ => None,
+    })
+}
+
+fn expr(text: &str) -> Expression {
+    text.parse().expect("built in expressions parse")
+}
+
+fn vec2(x: &str, y: &str) -> Vec2 {
+    Vec2 {
+        x: expr(x),
+        y: expr(y),
+    }
+}
+
+/// The same box as the default config: the entity's size, or 8x8 around its position if it has
+/// none.
+fn body_rect() -> Rect {
+    Rect {
+        topleft: vec2(
+            "match width + height { 0 => x - 4, _ => x }",
+            "match width + height { 0 => y - 4, _ => y }",
+        ),
+        size: vec2(
+            "match width { 0 => 8, _ => width }",
+            "match height { 0 => 8, _ => height }",
+        ),
+    }
+}
+
+fn node_rect() -> Rect {
+    Rect {
+        topleft: vec2("nodex - 2", "nodey - 2"),
+        size: vec2("4", "4"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SPRING: &str = r#"
+local utils = require("utils")
+local enums = require("consts.celeste_enums")
+
+local spring = {}
+
+spring.name = "MyHelper/Spring"
+spring.justification = {0.5, 1.0}
+spring.nodeLimits = {0, -1}
+spring.fieldInformation = {
+    strength = {
+        fieldType = "integer",
+    },
+    mode = {
+        options = {"Normal", "Weak"},
+        editable = false,
+    },
+    color = {
+        fieldType = "color",
+    },
+    direction = {
+        options = enums.spring_directions,
+    },
+}
+spring.placements = {
+    default = {
+        data = {
+            strength = 2,
+            mode = "Normal",
+            color = "ffffff",
+        },
+    },
+    {
+        name = "normal",
+    },
+    {
+        name = "weak",
+        data = {
+            mode = "Weak",
+            speed = 1,
+        },
+    },
+}
+
+function spring.texture(room, entity)
+    return entity.mode == "Weak" and "objects/spring/weak" or "objects/spring/00"
+end
+
+local block = {
+    name = "MyHelper/Block",
+    fillColor = {1.0, 0.5, 0.0},
+    placements = {
+        name = "block",
+        data = {
+            width = 16,
+            height = 8,
+        },
+    },
+}
+
+return {spring, block}
+"#;
+
+    #[test]
+    fn plugins_become_configs() {
+        let lang = parse_lang(
+            "# comment\nentities.MyHelper/Spring.placements.name.weak=Weak Spring\n\
+             entities.MyHelper/Spring.attributes.name.mode=Mode",
+        );
+        let configs = load_entities(SPRING, "spring.lua", &lang).unwrap();
+        assert_eq!(configs.len(), 2);
+
+        let spring = &configs[0];
+        assert_eq!(spring.entity_name, "MyHelper/Spring");
+        assert!(spring.nodes && !spring.resizable_x && !spring.resizable_y);
+        assert_eq!(spring.templates.len(), 2);
+        assert_eq!(
+            spring.templates[0].name,
+            intern_str("MyHelper/Spring (normal)")
+        );
+        assert_eq!(spring.templates[1].name, intern_str("Weak Spring"));
+        assert_eq!(
+            spring.templates[1].attributes[&intern_str("mode")],
+            AttributeValue::String("Weak".to_owned())
+        );
+
+        let info = &spring.attribute_info;
+        assert_eq!(info["strength"].ty, AttributeType::Int);
+        assert_eq!(info["strength"].default, AttributeValue::Int(2));
+        assert_eq!(info["speed"].ty, AttributeType::Float);
+        assert_eq!(info["color"].ty, AttributeType::String);
+        assert_eq!(info["mode"].display_name.as_deref(), Some("Mode"));
+        assert_eq!(
+            info["mode"].options,
+            vec![
+                option(
+                    "Normal".to_owned(),
+                    AttributeValue::String("Normal".to_owned()),
+                    AttributeType::String
+                ),
+                option(
+                    "Weak".to_owned(),
+                    AttributeValue::String("Weak".to_owned()),
+                    AttributeType::String
+                ),
+            ]
+        );
+        match &spring.standard_draw.initial_draw[..] {
+            [DrawElement::DrawPointImage {
+                texture, justify_y, ..
+            }] => {
+                assert_eq!(texture.to_string(), "\"objects/spring/00\"");
+                assert_eq!(*justify_y, 1.0);
+            }
+            other => panic!("{other:?}"),
+        }
+
+        let block = &configs[1];
+        assert!(block.resizable_x && block.resizable_y && !block.nodes);
+        assert_eq!(block.pencil, PencilBehavior::Rect);
+        assert!(block.attribute_info.is_empty());
+        match &block.standard_draw.initial_draw[..] {
+            [DrawElement::DrawRect { color, .. }] => {
+                assert_eq!(color.g, Expression::mk_const(128));
+                assert_eq!(color.a, Expression::mk_const(255));
+            }
+            other => panic!("{other:?}"),
+        }
+    }
+
+    const LAMP: &str = r#"
+local drawableSprite = require("structs.drawable_sprite")
+local utils = require("utils")
+
+local lamp = {
+    name = "MyHelper/Lamp",
+    placements = {
+        name = "lamp",
+        data = {
+            width = 16,
+            height = 8,
+        },
+    },
+}
+
+function lamp.sprite(room, entity)
+    local base = drawableSprite.fromTexture("objects/lamp/base", entity)
+    base:setJustification(0.5, 1.0)
+    local glow = drawableSprite.fromTexture("objects/lamp/glow", entity):addPosition(0, -8)
+    glow.rotation = math.pi / 2
+    glow:setColor({1.0, 0.0, 0.0})
+    return {base, glow}
+end
+
+function lamp.rectangle(room, entity)
+    return utils.rectangle(entity.x - 8, entity.y, entity.width, 4)
+end
+
+-- there's no room to look at, so this fails and is left out
+function lamp.nodeLimits(room, entity)
+    return room.nodeLimits
+end
+
+return lamp
+"#;
+
+    #[test]
+    fn sprites_and_rectangles_are_drawn() {
+        let configs = load_entities(LAMP, "lamp.lua", &HashMap::new()).unwrap();
+        let lamp = &configs[0];
+        assert!(!lamp.nodes);
+        match &lamp.standard_draw.initial_draw[..] {
+            [DrawElement::DrawPointImage {
+                texture: base,
+                point: base_point,
+                justify_y,
+                ..
+            }, DrawElement::DrawPointImage {
+                texture: glow,
+                point: glow_point,
+                color,
+                rot,
+                ..
+            }] => {
+                assert_eq!(base.to_string(), "\"objects/lamp/base\"");
+                assert_eq!(*base_point, vec2("x", "y"));
+                assert_eq!(*justify_y, 1.0);
+                assert_eq!(glow.to_string(), "\"objects/lamp/glow\"");
+                assert_eq!(*glow_point, vec2("x", "y - 8"));
+                assert_eq!(color.g, Expression::mk_const(0));
+                assert_eq!(*rot, Expression::mk_const(90));
+            }
+            other => panic!("{other:?}"),
+        }
+        assert_eq!(
+            lamp.hitboxes.initial_rects,
+            vec![Rect {
+                topleft: vec2("x - 8", "y"),
+                size: Vec2 {
+                    x: expr("width"),
+                    y: Expression::mk_const(4),
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn plugins_are_sandboxed() {
+        let lang = HashMap::new();
+        assert!(load_triggers("return io.open('x')", "a.lua", &lang).is_err());
+        assert!(load_triggers("return os.exit()", "b.lua", &lang).is_err());
+        assert!(load_triggers("while true do end", "c.lua", &lang).is_err());
+
+        let triggers = load_triggers(
+            "return {name = 'MyHelper/Flag', nodeLimits = {1, 1}, placements = {name = 'flag', data = {flag = '', state = true}}}",
+            "d.lua",
+            &lang,
+        )
+        .unwrap();
+        assert_eq!(triggers.len(), 1);
+        assert!(triggers[0].nodes);
+        assert_eq!(triggers[0].attribute_info["state"].ty, AttributeType::Bool);
+    }
+}
@@ -1,11 +1,14 @@
 use std::ffi::{OsStr, OsString};
 use std::io;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use arborio_gfxloader::atlas_img::Atlas;
 use arborio_gfxloader::autotiler::{Autotiler, Tileset};
-use arborio_maploader::map_struct::{from_reader, save_as, CelesteMap};
+use arborio_maploader::map_struct::{from_reader_tolerant, save_as, CelesteMap};
+use arborio_maploader::meta_yaml;
+use arborio_maploader::repair::MapWarning;
 use arborio_utils::interned::{intern_str, InternedMap};
 use arborio_utils::uuid_cls;
 use arborio_utils::vizia::prelude::Data;
@@ -14,21 +17,30 @@ use arborio_walker::{open_module, ConfigSource};
 
 use crate::config::{EntityConfig, StylegroundConfig, TriggerConfig};
 use crate::everest_yaml::EverestYaml;
+use crate::loenn;
+use crate::sprites::{parse_sprites, SpriteDefinition, SPRITES_XML};
 
 #[derive(Debug, Clone)] // Clone should just increase the refcount on each arc, right?
 pub struct CelesteModule {
     pub filesystem_root: Option<PathBuf>,
     pub everest_metadata: EverestYaml,
     pub gameplay_atlas: Atlas,
+    /// The sprites defined in the module's `Graphics/Sprites.xml`.
+    pub sprites: InternedMap<Arc<SpriteDefinition>>,
     pub tilers: InternedMap<Arc<Autotiler>>,
     pub entity_config: InternedMap<Arc<EntityConfig>>,
     pub trigger_config: InternedMap<Arc<TriggerConfig>>,
     pub styleground_config: InternedMap<Arc<StylegroundConfig>>,
     pub maps: Vec<String>,
+    /// The names of the prefabs saved in `Arborio/prefabs`.
+    pub prefabs: Vec<String>,
 }
 
 uuid_cls!(ModuleID);
 
+/// Where a project keeps its prefabs, relative to its root.
+pub const PREFABS_DIR: &str = "Arborio/prefabs";
+
 #[derive(Eq, PartialEq, Hash, Debug, Clone, Data)]
 pub struct MapPath {
     pub module: ModuleID,
@@ -46,16 +58,24 @@ impl CelesteModule {
             filesystem_root: root,
             everest_metadata: metadata,
             gameplay_atlas: Atlas::default(),
+            sprites: InternedMap::new(),
             tilers: InternedMap::new(),
             entity_config: InternedMap::new(),
             trigger_config: InternedMap::new(),
             styleground_config: InternedMap::new(),
             maps: vec![],
+            prefabs: vec![],
         }
     }
 
     pub fn load(&mut self, source: &mut ConfigSource) {
         self.gameplay_atlas.load(source, "Gameplay");
+        if let Some(text) = read_text(source, Path::new(SPRITES_XML)) {
+            match parse_sprites(&text) {
+                Ok(sprites) => self.sprites = sprites,
+                Err(e) => log::error!("Failed loading {} from {}: {}", SPRITES_XML, source, e),
+            }
+        }
 
         for path in source.list_all_files(&PathBuf::from("Arborio/tilers")) {
             if path.to_str().is_some() {
@@ -116,6 +136,7 @@ impl CelesteModule {
                 log::error!("Path disappeared from {}: {:?}", source, path);
             }
         }
+        self.load_loenn_plugins(source);
         for path in source.list_all_files(&PathBuf::from("Arborio/stylegrounds")) {
             if let Some(f) = source.get_file(&path) {
                 match serde_yaml::from_reader::<_, StylegroundConfig>(f) {
@@ -146,6 +167,60 @@ impl CelesteModule {
                 }
             }
         }
+
+        for path in source.list_all_files(&PathBuf::from(PREFABS_DIR)) {
+            if path.extension() == Some(OsStr::new("yaml")) {
+                if let Some(name) = path.file_stem().and_then(OsStr::to_str) {
+                    self.prefabs.push(name.to_owned());
+                }
+            }
+        }
+    }
+
+    /// Make configs from the mod's Loenn plugins for anything it doesn't have a config for already.
+    fn load_loenn_plugins(&mut self, source: &mut ConfigSource) {
+        let lang = read_text(source, Path::new(loenn::LANG_FILE))
+            .map(|text| loenn::parse_lang(&text))
+            .unwrap_or_default();
+
+        for path in source.list_all_files(&PathBuf::from(loenn::ENTITIES_DIR)) {
+            if path.extension() != Some(OsStr::new("lua")) {
+                continue;
+            }
+            let Some(text) = read_text(source, &path) else { continue };
+            match loenn::load_entities(&text, &path.to_string_lossy(), &lang) {
+                Ok(configs) => {
+                    for mut config in configs {
+                        if config.templates.is_empty() {
+                            config.templates.push(config.default_template());
+                        }
+                        self.entity_config
+                            .entry(intern_str(&config.entity_name))
+                            .or_insert_with(|| Arc::new(config));
+                    }
+                }
+                Err(e) => log::warn!("Failed loading Loenn plugin {}: {}", path.display(), e),
+            }
+        }
+        for path in source.list_all_files(&PathBuf::from(loenn::TRIGGERS_DIR)) {
+            if path.extension() != Some(OsStr::new("lua")) {
+                continue;
+            }
+            let Some(text) = read_text(source, &path) else { continue };
+            match loenn::load_triggers(&text, &path.to_string_lossy(), &lang) {
+                Ok(configs) => {
+                    for mut config in configs {
+                        if config.templates.is_empty() {
+                            config.templates.push(config.default_template());
+                        }
+                        self.trigger_config
+                            .entry(intern_str(&config.trigger_name))
+                            .or_insert_with(|| Arc::new(config));
+                    }
+                }
+                Err(e) => log::warn!("Failed loading Loenn plugin {}: {}", path.display(), e),
+            }
+        }
     }
 
     pub fn module_kind(&self) -> CelesteModuleKind {
@@ -172,7 +247,12 @@ impl CelesteModule {
         }
     }
 
-    pub fn load_map_static(root: &Path, sid: &str) -> Result<CelesteMap, io::Error> {
+    /// Load a map, skipping or repairing any damaged parts. What was done is returned alongside
+    /// the map.
+    pub fn load_map_static(
+        root: &Path,
+        sid: &str,
+    ) -> Result<(CelesteMap, Vec<MapWarning>), io::Error> {
         let mut config = match open_module(root) {
             None => {
                 return Err(io::Error::new(
@@ -192,7 +272,16 @@ impl CelesteModule {
             Some(r) => r,
         };
 
-        from_reader(reader)
+        let (mut map, mut warnings) = from_reader_tolerant(reader)?;
+        let sidecar = PathBuf::from("Maps").join(sid.to_string() + ".meta.yaml");
+        if let Some(reader) = config.get_file(&sidecar) {
+            warnings.extend(meta_yaml::load_into(
+                &mut map,
+                reader,
+                &sidecar.to_string_lossy(),
+            ));
+        }
+        Ok((map, warnings))
     }
 
     pub fn create_map(&mut self, sid: String) {
@@ -213,3 +302,9 @@ pub enum CelesteModuleKind {
     Zip,
     Directory,
 }
+
+fn read_text(source: &mut ConfigSource, path: &Path) -> Option<String> {
+    let mut text = String::new();
+    source.get_file(path)?.read_to_string(&mut text).ok()?;
+    Some(text)
+}
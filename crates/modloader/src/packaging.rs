@@ -0,0 +1,339 @@
+use regex::Regex;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::everest_yaml::{EverestModuleVersion, EverestYaml};
+
+/// A file in the root of a mod which lists more paths to leave out of its release zip.
+pub const IGNORE_FILE: &str = ".arborioignore";
+
+/// What never belongs in a release: version control, files only the editors read, backups and the
+/// clutter operating systems leave behind.
+pub const DEFAULT_IGNORE: &[&str] = &[
+    ".git/",
+    ".gitignore",
+    ".gitattributes",
+    ".vs/",
+    ".vscode/",
+    IGNORE_FILE,
+    "/Arborio/prefabs/",
+    "*.bak",
+    "*.tmp",
+    "*~",
+    "*.zip",
+    ".DS_Store",
+    "Thumbs.db",
+    "desktop.ini",
+];
+
+/// Paths to leave out when packing a mod, written like a simple `.gitignore`: one pattern per
+/// line, `#` starts a comment, `*` and `?` match within a path component and `**` across them. A
+/// pattern ending in `/` only matches folders. A pattern containing another `/` is matched against
+/// the whole path from the root of the mod, otherwise against the name of each file and folder.
+#[derive(Debug, Clone)]
+pub struct PackIgnore {
+    patterns: Vec<IgnorePattern>,
+}
+
+#[derive(Debug, Clone)]
+struct IgnorePattern {
+    regex: Regex,
+    whole_path: bool,
+    dirs_only: bool,
+}
+
+impl PackIgnore {
+    pub fn new<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            patterns: patterns
+                .into_iter()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(IgnorePattern::new)
+                .collect(),
+        }
+    }
+
+    /// The default patterns plus any listed in the mod's `IGNORE_FILE`.
+    pub fn load(root: &Path) -> io::Result<Self> {
+        let extra = match std::fs::read_to_string(root.join(IGNORE_FILE)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self::new(
+            DEFAULT_IGNORE.iter().copied().chain(extra.lines()),
+        ))
+    }
+
+    /// Whether `path`, relative to the root of the mod, should be left out.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let Some(path) = zip_name(path).ok() else { return false };
+        let name = path.rsplit('/').next().unwrap_or(&path);
+        self.patterns.iter().any(|pattern| {
+            (is_dir || !pattern.dirs_only)
+                && pattern
+                    .regex
+                    .is_match(if pattern.whole_path { &path } else { name })
+        })
+    }
+}
+
+impl IgnorePattern {
+    fn new(line: &str) -> Self {
+        let dirs_only = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+        let whole_path = line.contains('/');
+        let line = line.trim_start_matches('/');
+
+        let mut regex = "^".to_owned();
+        let mut chars = line.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    regex.push_str(".*");
+                }
+                '*' => regex.push_str("[^/]*"),
+                '?' => regex.push_str("[^/]"),
+                ch => regex.push_str(&regex::escape(ch.encode_utf8(&mut [0; 4]))),
+            }
+        }
+        regex.push('$');
+
+        Self {
+            regex: Regex::new(&regex).unwrap(),
+            whole_path,
+            dirs_only,
+        }
+    }
+}
+
+/// List the files of an unpacked mod which would go into its release, relative to its root.
+pub fn module_files(root: &Path, ignore: &PackIgnore) -> io::Result<Vec<PathBuf>> {
+    if !root.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not a mod folder", root.display()),
+        ));
+    }
+
+    let mut files = vec![];
+    let walker = WalkDir::new(root)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            let path = entry.path().strip_prefix(root).unwrap();
+            !ignore.is_ignored(path, entry.file_type().is_dir())
+        });
+    for entry in walker {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            files.push(entry.path().strip_prefix(root).unwrap().to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+/// Zip up an unpacked mod folder so it can be dropped straight into a Mods directory, leaving out
+/// whatever `ignore` matches. Returns the number of files written.
+///
+/// The zip is written next to `output` and only moved there once it's complete, so a failure
+/// leaves whatever was at `output` alone instead of a broken zip.
+pub fn pack_module(root: &Path, output: &Path, ignore: &PackIgnore) -> io::Result<usize> {
+    let mut temp_name = OsString::from(".");
+    temp_name.push(output.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "the output has no file name")
+    })?);
+    temp_name.push(".tmp");
+    let temp = output.with_file_name(temp_name);
+
+    let result = write_zip(root, &temp, output, ignore)
+        .and_then(|count| fs::rename(&temp, output).map(|_| count));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn write_zip(root: &Path, temp: &Path, output: &Path, ignore: &PackIgnore) -> io::Result<usize> {
+    let files = module_files(root, ignore)?;
+
+    let mut writer = ZipWriter::new(File::create(temp)?);
+    // don't try to zip ourselves if someone asks us to put the zip inside the mod
+    let ours = [Some(temp.canonicalize()?), output.canonicalize().ok()];
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let mut count = 0;
+    for file in files {
+        let path = root.join(&file);
+        if ours.contains(&Some(path.canonicalize()?)) {
+            continue;
+        }
+
+        writer.start_file(zip_name(&file)?, options)?;
+        io::copy(&mut File::open(path)?, &mut writer)?;
+        count += 1;
+    }
+
+    writer.finish()?;
+    Ok(count)
+}
+
+/// Check that the everest.yaml of an unpacked mod will load in Everest, given the `files` which
+/// will be released with it.
+pub fn validate_everest_yaml(root: &Path, files: &[PathBuf]) -> Result<EverestYaml, Vec<String>> {
+    let Some(filename) = ["everest.yaml", "everest.yml"]
+        .into_iter()
+        .find(|filename| files.iter().any(|file| file == Path::new(filename))) else {
+        return Err(vec!["everest.yaml: missing".to_owned()]);
+    };
+    let yaml = File::open(root.join(filename))
+        .map_err(|e| e.to_string())
+        .and_then(|mut file| EverestYaml::from_reader(&mut file).map_err(|e| e.to_string()))
+        .map_err(|e| vec![format!("{filename}: {e}")])?;
+
+    let mut problems = vec![];
+    let bad_version = |version: &EverestModuleVersion| {
+        !(2..=4).contains(&version.0.len()) || version.0.iter().any(|part| *part < 0)
+    };
+    if yaml.name.trim().is_empty() {
+        problems.push("the mod has no name".to_owned());
+    }
+    if bad_version(&yaml.version) {
+        problems.push(format!(
+            "version {} should have two to four parts",
+            yaml.version.to_string()
+        ));
+    }
+    if let Some(dll) = &yaml.dll {
+        let path = dll.replace('\\', "/");
+        let path = path.trim_start_matches("./");
+        if !files
+            .iter()
+            .any(|file| matches!(zip_name(file), Ok(name) if name == path))
+        {
+            problems.push(format!("the DLL {dll} is not part of the release"));
+        }
+    }
+    let dependencies = yaml
+        .dependencies
+        .iter()
+        .chain(yaml.optional_dependencies.iter());
+    for (idx, dep) in dependencies.clone().enumerate() {
+        if dep.name.trim().is_empty() {
+            problems.push("a dependency has no name".to_owned());
+        } else if dep.name == yaml.name {
+            problems.push(format!("{} depends on itself", dep.name));
+        } else if dependencies
+            .clone()
+            .take(idx)
+            .any(|other| other.name == dep.name)
+        {
+            problems.push(format!("{} is listed more than once", dep.name));
+        }
+        if bad_version(&dep.version) {
+            problems.push(format!(
+                "the version of {} should have two to four parts",
+                dep.name
+            ));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(yaml)
+    } else {
+        Err(problems
+            .into_iter()
+            .map(|problem| format!("{filename}: {problem}"))
+            .collect())
+    }
+}
+
+fn zip_name(path: &Path) -> io::Result<String> {
+    let parts = path
+        .iter()
+        .map(|part| {
+            part.to_str().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Non-unicode path in mod: {}", path.display()),
+                )
+            })
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(parts.join("/"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ignore_patterns_match_like_gitignore() {
+        let ignore = PackIgnore::new(DEFAULT_IGNORE.iter().copied().chain([
+            "# comment",
+            "",
+            "Maps/wip/",
+            "Graphics/**/*.ase",
+            "/notes.txt",
+        ]));
+        let ignored = |path: &str, is_dir| ignore.is_ignored(Path::new(path), is_dir);
+
+        assert!(ignored(".git", true));
+        assert!(!ignored(".git", false));
+        assert!(ignored("Maps/me/map.bin.bak", false));
+        assert!(ignored("Graphics/Atlases/Gameplay/.DS_Store", false));
+        assert!(ignored("Arborio/prefabs", true));
+        assert!(!ignored("Arborio/tilers", true));
+        assert!(ignored("Maps/wip", true));
+        assert!(!ignored("Maps/me/wip", true));
+        assert!(ignored(
+            "Graphics/Atlases/Gameplay/decals/me/rock.ase",
+            false
+        ));
+        assert!(!ignored(
+            "Graphics/Atlases/Gameplay/decals/me/rock.png",
+            false
+        ));
+        assert!(ignored("notes.txt", false));
+        assert!(!ignored("Dialog/notes.txt", false));
+        assert!(!ignored("everest.yaml", false));
+    }
+
+    #[test]
+    fn a_failed_pack_leaves_the_output_alone() {
+        let root = std::env::temp_dir().join(format!("arborio-pack-{}", std::process::id()));
+        let module = root.join("MyMod");
+        fs::create_dir_all(module.join("Maps")).unwrap();
+        fs::write(
+            module.join("everest.yaml"),
+            "- Name: MyMod\n  Version: 1.0.0\n",
+        )
+        .unwrap();
+        fs::write(module.join("Maps/map.bin"), "map").unwrap();
+        let output = root.join("MyMod.zip");
+        fs::write(&output, "old release").unwrap();
+        let ignore = PackIgnore::new(DEFAULT_IGNORE.iter().copied());
+
+        let failed = pack_module(&root.join("Missing"), &output, &ignore);
+        let after_failure = fs::read(&output).unwrap();
+        let packed = pack_module(&module, &output, &ignore);
+        let after_success = fs::read(&output).unwrap();
+        let leftovers = fs::read_dir(&root).unwrap().count();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(failed.is_err());
+        assert_eq!(after_failure, b"old release");
+        assert_eq!(packed.unwrap(), 2);
+        assert!(after_success.starts_with(b"PK"));
+        // just the mod and the zip, no temporary file
+        assert_eq!(leftovers, 2);
+    }
+}
@@ -0,0 +1,238 @@
+//! The sprite bank in `Graphics/Sprites.xml`, which names the animations most entities are drawn
+//! with. A mod's Sprites.xml adds to the game's, replacing any sprite with the same id.
+
+use arborio_maploader::binel::xml::take_element_as_text;
+use arborio_maploader::binel::{BinEl, BinElAttr};
+use arborio_utils::interned::{intern_str, InternedMap};
+use arborio_utils::units::*;
+use std::sync::Arc;
+
+pub const SPRITES_XML: &str = "Graphics/Sprites.xml";
+
+/// How a sprite's frames line up with the point it is drawn at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpriteOrigin {
+    /// A fraction of the frame's size, like `DrawPointImage`'s justification.
+    Justify(f32, f32),
+    /// A point in pixels from the frame's top left corner.
+    Pixels(f32, f32),
+    Center,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteAnimation {
+    pub id: String,
+    /// Appended to the sprite's path to get the frames' texture names, minus their numbers.
+    pub path: String,
+    pub first_frame: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SpriteDefinition {
+    pub path: Option<String>,
+    /// The animation the sprite starts out playing.
+    pub start: Option<String>,
+    /// Another sprite whose animations and origin this one starts from.
+    pub copy: Option<String>,
+    pub origin: Option<SpriteOrigin>,
+    pub position: Option<Vector2D<f32, UnknownUnit>>,
+    pub animations: Vec<SpriteAnimation>,
+}
+
+/// The first frame of an animation: the gameplay texture to draw, its justification and where it
+/// is drawn relative to the entity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteFrame {
+    pub texture: String,
+    pub justify: Vector2D<f32, UnknownUnit>,
+    pub offset: Vector2D<f32, UnknownUnit>,
+}
+
+/// Read every sprite defined in a Sprites.xml.
+pub fn parse_sprites(text: &str) -> Result<InternedMap<Arc<SpriteDefinition>>, String> {
+    let mut root =
+        take_element_as_text(text.trim_start_matches('\u{FEFF}')).map_err(|e| e.to_string())?;
+    Ok(root
+        .drain()
+        .map(|mut sprite| {
+            let mut definition = SpriteDefinition {
+                path: text_attr(&sprite, "path").map(str::to_owned),
+                start: text_attr(&sprite, "start").map(str::to_owned),
+                copy: text_attr(&sprite, "copy").map(str::to_owned),
+                ..SpriteDefinition::default()
+            };
+            let (mut justify, mut origin, mut center) = (None, None, None);
+            for child in sprite.drain() {
+                let point = || Vector2D::new(float_attr(&child, "x"), float_attr(&child, "y"));
+                match child.name.as_str() {
+                    "Justify" => justify = Some(SpriteOrigin::Justify(point().x, point().y)),
+                    "Origin" => origin = Some(SpriteOrigin::Pixels(point().x, point().y)),
+                    "Center" => center = Some(SpriteOrigin::Center),
+                    "Position" => definition.position = Some(point()),
+                    "Anim" | "Loop" => definition.animations.push(SpriteAnimation {
+                        id: text_attr(&child, "id").unwrap_or_default().to_owned(),
+                        path: text_attr(&child, "path").unwrap_or_default().to_owned(),
+                        first_frame: text_attr(&child, "frames").map_or(0, first_frame),
+                    }),
+                    _ => {}
+                }
+            }
+            definition.origin = justify.or(origin).or(center);
+            (intern_str(&sprite.name), Arc::new(definition))
+        })
+        .collect())
+}
+
+/// Work out the first frame of `animation` of the sprite `id`, or of the animation it starts with
+/// if `animation` is None. `dimensions` gives the size of a gameplay texture, if it exists.
+pub fn resolve_sprite_frame(
+    sprites: &InternedMap<Arc<SpriteDefinition>>,
+    id: &str,
+    animation: Option<&str>,
+    dimensions: impl Fn(&str) -> Option<Size2D<u16, UnknownUnit>>,
+) -> Result<SpriteFrame, String> {
+    // a sprite with its own settings first, then the ones it copies from
+    let mut chain: Vec<&SpriteDefinition> = vec![];
+    let mut next = Some(id);
+    while let Some(name) = next {
+        let definition = sprites
+            .get(name)
+            .ok_or_else(|| format!("No such sprite: {name}"))?;
+        if chain
+            .iter()
+            .any(|other| std::ptr::eq(*other, &**definition))
+        {
+            return Err(format!("Sprite {id} copies itself"));
+        }
+        chain.push(definition);
+        next = definition.copy.as_deref();
+    }
+
+    let animation = animation
+        .filter(|animation| !animation.is_empty())
+        .or_else(|| chain.iter().find_map(|def| def.start.as_deref()))
+        .or_else(|| {
+            chain
+                .iter()
+                .find_map(|def| def.animations.first())
+                .map(|anim| anim.id.as_str())
+        })
+        .ok_or_else(|| format!("Sprite {id} has no animations"))?;
+    let anim = chain
+        .iter()
+        .find_map(|def| def.animations.iter().find(|anim| anim.id == animation))
+        .ok_or_else(|| format!("Sprite {id} has no animation {animation}"))?;
+    let path = chain
+        .iter()
+        .find_map(|def| def.path.as_deref())
+        .unwrap_or_default();
+
+    let name = format!("{path}{}", anim.path);
+    let texture = frame_texture(&name, anim.first_frame, &dimensions)
+        .ok_or_else(|| format!("No frame {} of {name}", anim.first_frame))?;
+    let size = dimensions(&texture).unwrap().cast::<f32>();
+    let justify = match chain.iter().find_map(|def| def.origin) {
+        Some(SpriteOrigin::Justify(x, y)) => Vector2D::new(x, y),
+        Some(SpriteOrigin::Pixels(x, y)) => Vector2D::new(x / size.width, y / size.height),
+        Some(SpriteOrigin::Center) => Vector2D::new(0.5, 0.5),
+        None => Vector2D::zero(),
+    };
+    let offset = chain
+        .iter()
+        .find_map(|def| def.position)
+        .unwrap_or_else(Vector2D::zero);
+
+    Ok(SpriteFrame {
+        texture,
+        justify,
+        offset,
+    })
+}
+
+/// Find frame `index` of an animation the way the game does: by trying the number with more and
+/// more zeros in front of it. The first frame may also be the bare name.
+fn frame_texture(
+    name: &str,
+    index: usize,
+    dimensions: impl Fn(&str) -> Option<Size2D<u16, UnknownUnit>>,
+) -> Option<String> {
+    if index == 0 && dimensions(name).is_some() {
+        return Some(name.to_owned());
+    }
+    (1..=6)
+        .map(|width| format!("{name}{index:0width$}"))
+        .find(|texture| dimensions(texture).is_some())
+}
+
+/// The first frame of a frame list such as `0-3,5*2`.
+fn first_frame(frames: &str) -> usize {
+    let first = frames.split(',').next().unwrap_or_default();
+    let first = first.split(['-', '*']).next().unwrap_or_default();
+    first.trim().parse().unwrap_or(0)
+}
+
+fn text_attr<'a>(el: &'a BinEl, name: &str) -> Option<&'a str> {
+    match el.attributes.get(name) {
+        Some(BinElAttr::Text(text)) => Some(text),
+        _ => None,
+    }
+}
+
+fn float_attr(el: &BinEl, name: &str) -> f32 {
+    text_attr(el, name)
+        .and_then(|text| text.trim().parse().ok())
+        .unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SPRITES: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<Sprites>
+  <!-- a comment between sprites -->
+  <booster path="objects/booster/" start="loop">
+    <Center/>
+    <Loop id="loop" path="booster" delay="0.1" frames="0-4"/>
+    <Anim id="pop" path="outline" frames="2-5"/>
+  </booster>
+  <boosterRed copy="booster" path="objects/booster/red"/>
+  <player path="characters/player/" start="idle">
+    <Origin x="16" y="32"/>
+    <Position x="0" y="-2"/>
+    <Loop id="idle" path="idle" frames="3*2,0"/>
+  </player>
+</Sprites>"#;
+
+    #[test]
+    fn sprites_resolve_to_first_frames() {
+        let sprites = parse_sprites(SPRITES).unwrap();
+        let textures = [
+            "objects/booster/booster00",
+            "objects/booster/outline2",
+            "objects/booster/redbooster",
+            "characters/player/idle03",
+        ];
+        let dimensions = |texture: &str| textures.contains(&texture).then(|| Size2D::new(32, 32));
+        let resolve = |id, animation| resolve_sprite_frame(&sprites, id, animation, dimensions);
+
+        let booster = resolve("booster", None).unwrap();
+        assert_eq!(booster.texture, "objects/booster/booster00");
+        assert_eq!(booster.justify, Vector2D::new(0.5, 0.5));
+        assert_eq!(
+            resolve("booster", Some("pop")).unwrap().texture,
+            "objects/booster/outline2"
+        );
+        let red = resolve("boosterRed", None).unwrap();
+        assert_eq!(red.texture, "objects/booster/redbooster");
+        assert_eq!(red.justify, Vector2D::new(0.5, 0.5));
+
+        let player = resolve("player", None).unwrap();
+        assert_eq!(player.texture, "characters/player/idle03");
+        assert_eq!(player.justify, Vector2D::new(0.5, 1.0));
+        assert_eq!(player.offset, Vector2D::new(0.0, -2.0));
+
+        assert!(resolve("player", Some("dash")).is_err());
+        assert!(resolve("madeline", None).is_err());
+    }
+}
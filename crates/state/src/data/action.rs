@@ -5,6 +5,7 @@ use std::collections::HashSet;
 
 use crate::data::project_map::{LevelState, MapState, MapStateUpdate};
 use crate::data::selection::AppSelection;
+use arborio_maploader::ids::{entity_ids, reassign_taken_ids};
 use arborio_maploader::map_struct::{
     CelesteMapDecal, CelesteMapEntity, CelesteMapLevel, CelesteMapLevelUpdate,
     CelesteMapStyleground,
@@ -79,6 +80,10 @@ pub fn apply_map_action(
             }
             MapAction::AddRoom { idx, mut room } => {
                 let idx = idx.unwrap_or(map.data.levels.len());
+                // pasted rooms bring their ids along
+                let mut taken = entity_ids(map.data.levels.iter().map(|room| &room.data));
+                let mut next_id = map.data.next_entity_id();
+                reassign_taken_ids(&mut room, &mut taken, &mut next_id);
                 if room.name.is_empty()
                     || map
                         .data
@@ -113,7 +118,42 @@ pub fn apply_map_action(
                     Err("Out of range".to_owned())
                 }
             }
-            MapAction::RoomAction { idx, event } => {
+            MapAction::AddFiller { idx, rect } => {
+                let idx = idx.unwrap_or(map.data.filler.len());
+                if idx <= map.data.filler.len() {
+                    map.data.filler.insert(idx, rect);
+                    Ok(MapAction::DeleteFiller { idx })
+                } else {
+                    Err("Out of range".to_owned())
+                }
+            }
+            MapAction::MoveFiller { idx, mut rect } => {
+                if let Some(rect_ref) = map.data.filler.get_mut(idx) {
+                    std::mem::swap(rect_ref, &mut rect);
+                    Ok(MapAction::MoveFiller { idx, rect })
+                } else {
+                    Err("Out of range".to_owned())
+                }
+            }
+            MapAction::DeleteFiller { idx } => {
+                if idx < map.data.filler.len() {
+                    let rect = map.data.filler.remove(idx);
+                    Ok(MapAction::AddFiller {
+                        idx: Some(idx),
+                        rect,
+                    })
+                } else {
+                    Err("Out of range".to_owned())
+                }
+            }
+            MapAction::RoomAction { idx, mut event } => {
+                // ids are unique across the whole map, so they have to be handed out from here
+                if let RoomAction::EntityAdd { entity, genid, .. } = &mut event {
+                    if *genid {
+                        entity.id = map.data.next_entity_id();
+                        *genid = false;
+                    }
+                }
                 if let Some(room) = map.data.levels.get_mut(idx) {
                     room.cache.borrow_mut().render_cache_valid = false;
                     Ok(MapAction::RoomAction {
@@ -203,19 +243,13 @@ fn apply_room_event(room: &mut LevelState, event: RoomAction) -> Result<RoomActi
             Ok(RoomAction::ObjectTileUpdate { offset, data })
         }
         RoomAction::EntityAdd {
-            mut entity,
-            trigger,
-            genid,
+            entity, trigger, ..
         } => {
-            let id = if genid {
-                let id = room.data.next_id();
-                entity.id = id;
-                id
-            } else if room.entity(entity.id, trigger).is_some() {
+            // genid has already been dealt with by apply_map_action
+            let id = entity.id;
+            if room.entity(id, trigger).is_some() {
                 return Err("Entity/trigger already exists".to_owned());
-            } else {
-                entity.id
-            };
+            }
             if trigger {
                 room.data.triggers.push(*entity);
             } else {
@@ -234,6 +268,14 @@ fn apply_room_event(room: &mut LevelState, event: RoomAction) -> Result<RoomActi
                 Err("No such entity".to_owned())
             }
         }
+        RoomAction::SetEntities {
+            mut entities,
+            mut triggers,
+        } => {
+            std::mem::swap(&mut room.data.entities, &mut entities);
+            std::mem::swap(&mut room.data.triggers, &mut triggers);
+            Ok(RoomAction::SetEntities { entities, triggers })
+        }
         RoomAction::EntityRemove { id, trigger } => {
             let entities = if trigger {
                 &mut room.data.triggers
@@ -341,6 +383,17 @@ pub enum MapAction {
     DeleteRoom {
         idx: usize,
     },
+    AddFiller {
+        idx: Option<usize>, // made absolute through mutation
+        rect: MapRectStrict,
+    },
+    MoveFiller {
+        idx: usize,
+        rect: MapRectStrict,
+    },
+    DeleteFiller {
+        idx: usize,
+    },
     RoomAction {
         idx: usize,
         event: RoomAction,
@@ -380,6 +433,11 @@ pub enum RoomAction {
         id: i32,
         trigger: bool,
     },
+    /// Replace all of a room's entities and triggers at once, e.g. to renumber them.
+    SetEntities {
+        entities: Vec<CelesteMapEntity>,
+        triggers: Vec<CelesteMapEntity>,
+    },
     DecalAdd {
         fg: bool,
         decal: Box<CelesteMapDecal>,
@@ -1,5 +1,6 @@
 use crate::data::action::{MapAction, RoomAction, StylegroundSelection};
 use arborio_maploader::map_struct::{CelesteMap, CelesteMapEntity};
+use arborio_maploader::repair::MapWarning;
 use arborio_modloader::aggregate::ModuleAggregate;
 use arborio_modloader::discovery::LoaderThreadMessage;
 use arborio_modloader::module::{CelesteModule, MapPath, ModuleID, CELESTE_MODULE_ID};
@@ -13,7 +14,7 @@ use std::cell::RefCell;
 use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::Sender;
 use std::time;
 
@@ -95,10 +96,12 @@ pub enum AppEvent {
     LoadMap {
         path: MapPath,
         map: RefCell<Option<Box<CelesteMap>>>,
+        warnings: Vec<MapWarning>,
     },
     OpenInstallationTab,
     OpenConfigEditorTab,
     OpenLogsTab,
+    OpenFindReplaceTab,
     SelectTab {
         idx: usize,
     },
@@ -208,6 +211,14 @@ pub enum AppEvent {
     ClearSelection {
         tab: usize,
     },
+    /// Save the current tool's selection as a prefab in the current project.
+    SavePrefab {
+        name: String,
+    },
+    /// Place a prefab from the current project, switching to the tool which deals in it.
+    PlacePrefab {
+        name: String,
+    },
     SelectObjects {
         tab: usize,
         selection: HashSet<AppSelection>,
@@ -350,6 +361,12 @@ impl AppState {
         }
     }
 
+    /// Where the current project lives, if it's an unpacked folder which can be written to.
+    pub fn current_project_root(&self) -> Option<PathBuf> {
+        let module = self.modules.get(&self.current_project_id()?)?;
+        module.unpacked().map(Path::to_path_buf)
+    }
+
     pub fn current_palette_unwrap(&self) -> &ModuleAggregate {
         if let Some(AppTab::Map(result)) = self.tabs.get(self.current_tab) {
             &self
@@ -1,17 +1,17 @@
 use crate::data::app::{AppEvent, AppState};
 use crate::data::config_editor::ConfigSearchResult;
 use crate::data::project_map::{MapEvent, MapState};
+use crate::data::selection::AppSelectable;
 use crate::data::tabs::{AppTab, ConfigEditorTab, MapTab};
 use crate::data::{load_map, AppConfigSetter, MapID};
+use crate::prefab::{load_prefab, save_prefab};
+use crate::tools::ToolSpec;
 use arborio_modloader::aggregate::ModuleAggregate;
 use arborio_modloader::discovery::LoaderThreadMessage;
 use arborio_modloader::everest_yaml::{EverestModuleVersion, EverestYaml};
-use arborio_utils::units::*;
-use arborio_utils::uuid::next_uuid;
 use arborio_utils::vizia::prelude::*;
 use log::Level;
 use std::cell::RefCell;
-use std::collections::HashSet;
 
 impl AppState {
     pub fn apply(&mut self, cx: &mut EventContext, event: AppEvent) {
@@ -54,6 +54,18 @@ impl AppState {
                     idx: self.tabs.len() - 1,
                 });
             }
+            AppEvent::OpenFindReplaceTab => {
+                for (i, tab) in self.tabs.iter().enumerate() {
+                    if matches!(tab, AppTab::FindReplace) {
+                        cx.emit(AppEvent::SelectTab { idx: i });
+                        return;
+                    }
+                }
+                self.tabs.push(AppTab::FindReplace);
+                cx.emit(AppEvent::SelectTab {
+                    idx: self.tabs.len() - 1,
+                });
+            }
             AppEvent::OpenLogsTab => {
                 self.error_message.clear();
                 for (i, tab) in self.tabs.iter().enumerate() {
@@ -89,10 +101,13 @@ impl AppState {
                         if let Some(module_root) = module.filesystem_root.clone() {
                             let path = path.clone();
                             cx.spawn(move |cx| {
-                                if let Some(map_struct) = load_map(&module_root, &path.sid) {
+                                if let Some((map_struct, warnings)) =
+                                    load_map(&module_root, &path.sid)
+                                {
                                     cx.emit(AppEvent::LoadMap {
                                         path: path.clone(),
                                         map: RefCell::new(Some(Box::new(map_struct))),
+                                        warnings,
                                     })
                                     .unwrap();
                                 }
@@ -101,23 +116,34 @@ impl AppState {
                     }
                 }
             }
-            AppEvent::LoadMap { path, map } => {
+            AppEvent::LoadMap {
+                path,
+                map,
+                warnings,
+            } => {
                 if let Some(map) = map.borrow_mut().take() {
+                    for warning in &warnings {
+                        log::warn!("{}: {}", path.sid, warning);
+                    }
+                    if !warnings.is_empty() {
+                        self.error_message = format!(
+                            "{} had {} problem(s) which were repaired or skipped:\n{}",
+                            path.sid,
+                            warnings.len(),
+                            warnings
+                                .iter()
+                                .map(ToString::to_string)
+                                .collect::<Vec<_>>()
+                                .join("\n")
+                        );
+                    }
                     let id = self
                         .loaded_maps_lookup
                         .get(&path)
                         .copied()
                         .unwrap_or_else(MapID::new);
                     if !self.loaded_maps.contains_key(&id) {
-                        self.tabs.push(AppTab::Map(MapTab {
-                            nonce: next_uuid(),
-                            id,
-                            current_room: 0,
-                            current_selected: HashSet::new(),
-                            styleground_selected: None,
-                            transform: MapToScreen::identity(),
-                            preview_pos: MapPointStrict::zero(),
-                        }));
+                        self.tabs.push(AppTab::Map(MapTab::new(id)));
                         cx.emit(AppEvent::SelectTab {
                             idx: self.tabs.len() - 1,
                         });
@@ -126,7 +152,7 @@ impl AppState {
                     let palette = ModuleAggregate::new(
                         &self.modules,
                         &self.modules_lookup,
-                        &map.meta,
+                        &map.effective_meta(),
                         path.module,
                         true,
                     );
@@ -185,6 +211,7 @@ impl AppState {
                         version: EverestModuleVersion(vec![0, 0, 0]),
                         dll: None,
                         dependencies: vec![],
+                        optional_dependencies: vec![],
                     };
                     if let Err(e) = std::fs::create_dir(&path) {
                         log::error!("Could not create mod: {}", e);
@@ -331,6 +358,59 @@ impl AppState {
                     }
                 }
             }
+            AppEvent::SavePrefab { name } => {
+                let Some(root) = self.current_project_root() else {
+                    log::error!("Prefabs can only be saved in unpacked projects");
+                    return;
+                };
+                let tool = self.current_tool.borrow_mut().take();
+                let Some(mut tool) = tool else { return };
+                let copied = tool.copy(self);
+                *self.current_tool.borrow_mut() = Some(tool);
+                let Some((prefab, events)) = copied else {
+                    log::error!("Select some objects or rooms to save them as a prefab");
+                    return;
+                };
+                for event in events {
+                    cx.emit(event);
+                }
+                if let Err(e) = save_prefab(&root, &name, &prefab) {
+                    log::error!("{}", e);
+                    return;
+                }
+                // don't wait for hot reload to notice the new file
+                let project = self.current_project_id();
+                if let Some(module) = project.and_then(|id| self.modules.get_mut(&id)) {
+                    if !module.prefabs.contains(&name) {
+                        module.prefabs.push(name);
+                        self.modules_version += 1;
+                    }
+                }
+            }
+            AppEvent::PlacePrefab { name } => {
+                let Some(root) = self.current_project_root() else { return };
+                let prefab = match load_prefab(&root, &name) {
+                    Ok(prefab) => prefab,
+                    Err(e) => {
+                        log::error!("{}", e);
+                        return;
+                    }
+                };
+                let spec = match prefab {
+                    AppSelectable::InRoom(_) => ToolSpec::Selection,
+                    AppSelectable::Rooms(_) | AppSelectable::RoomsAndFiller(_, _) => ToolSpec::Room,
+                };
+                if self.current_toolspec != spec {
+                    self.apply(cx, AppEvent::SelectTool { spec });
+                }
+                let tool = self.current_tool.borrow_mut().take();
+                let Some(mut tool) = tool else { return };
+                let events = tool.paste(self, prefab);
+                *self.current_tool.borrow_mut() = Some(tool);
+                for event in events {
+                    cx.emit(event);
+                }
+            }
             AppEvent::SelectStyleground { tab, styleground } => {
                 if let Some(AppTab::Map(map_tab)) = self.tabs.get_mut(tab) {
                     map_tab.styleground_selected = styleground;
@@ -0,0 +1,358 @@
+use arborio_maploader::meta_yaml::{
+    MapMetaAudioStateYaml, MapMetaCassetteModifierYaml, MapMetaCheckpointYaml, MapMetaModeYaml,
+    MapMetaMountainYaml, MapMetaScreenTitleYaml, MapMetaScreenYaml,
+};
+use arborio_utils::vizia::prelude::*;
+
+/// The metadata for one side of a chapter, from the `Modes` list in map.meta.yaml.
+#[derive(Lens, Clone, Debug, Default, PartialEq)]
+pub struct MapMetaModeData {
+    pub heart_is_end: bool,
+    pub inventory: String,
+    pub start_level: String,
+    pub seeker_slowdown: bool,
+    pub theo_in_bubble: bool,
+    pub ignore_level_audio_layer_data: bool,
+    pub path: String,
+    pub poem_id: String,
+    pub music: String,
+    pub ambience: String,
+    pub checkpoints: Vec<MapMetaCheckpointData>,
+}
+
+#[derive(Lens, Clone, Debug, Default, PartialEq)]
+pub struct MapMetaCheckpointData {
+    pub level: String,
+    pub name: String,
+    pub dreaming: bool,
+    pub inventory: String,
+    pub core_mode: String,
+    pub music: String,
+    pub ambience: String,
+}
+
+#[derive(Lens, Clone, Debug, PartialEq)]
+pub struct MapMetaMountainData {
+    pub model_directory: String,
+    pub texture_directory: String,
+    pub background_music: String,
+    pub background_ambience: String,
+    pub star_fog_color: String,
+    pub show_core: bool,
+    pub show_snow: bool,
+}
+
+/// The parts of `CompleteScreen` or `LoadingVignetteScreen` which can be edited.
+#[derive(Lens, Clone, Debug, Default, PartialEq)]
+pub struct MapMetaScreenData {
+    pub atlas: String,
+    pub title_a_side: String,
+    pub title_b_side: String,
+    pub title_c_side: String,
+    pub title_full_clear: String,
+}
+
+#[derive(Lens, Clone, Debug, PartialEq)]
+pub struct MapMetaCassetteModifierData {
+    pub tempo_mult: f32,
+    pub lead_beats: i32,
+    pub beats_per_tick: i32,
+    pub ticks_per_swap: i32,
+    pub blocks: i32,
+    pub beats_max: i32,
+    pub beat_index_offset: i32,
+    pub old_behavior: bool,
+}
+
+// these match the defaults everest uses when a field is left out
+
+impl Default for MapMetaMountainData {
+    fn default() -> Self {
+        Self {
+            model_directory: String::new(),
+            texture_directory: String::new(),
+            background_music: String::new(),
+            background_ambience: String::new(),
+            star_fog_color: String::new(),
+            show_core: false,
+            show_snow: true,
+        }
+    }
+}
+
+impl Default for MapMetaCassetteModifierData {
+    fn default() -> Self {
+        Self {
+            tempo_mult: 1.0,
+            lead_beats: 16,
+            beats_per_tick: 4,
+            ticks_per_swap: 2,
+            blocks: 2,
+            beats_max: 256,
+            beat_index_offset: 0,
+            old_behavior: false,
+        }
+    }
+}
+
+/// The value to save for a field: nothing if it is the default and wasn't set before.
+pub(crate) fn keep<T: PartialEq>(value: T, default: T, old: &Option<T>) -> Option<T> {
+    if value != default || old.is_some() {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+fn audio_state(
+    music: &str,
+    ambience: &str,
+    old: &Option<MapMetaAudioStateYaml>,
+) -> Option<MapMetaAudioStateYaml> {
+    let template = old.clone().unwrap_or_default();
+    let result = MapMetaAudioStateYaml {
+        music: keep(music.to_owned(), String::new(), &template.music),
+        ambience: keep(ambience.to_owned(), String::new(), &template.ambience),
+        ..template
+    };
+    keep(result, MapMetaAudioStateYaml::default(), old)
+}
+
+impl From<&MapMetaModeYaml> for MapMetaModeData {
+    fn from(mode: &MapMetaModeYaml) -> Self {
+        let audio_state = mode.audio_state.clone().unwrap_or_default();
+        Self {
+            heart_is_end: mode.heart_is_end.unwrap_or_default(),
+            inventory: mode.inventory.clone().unwrap_or_default(),
+            start_level: mode.start_level.clone().unwrap_or_default(),
+            seeker_slowdown: mode.seeker_slowdown.unwrap_or_default(),
+            theo_in_bubble: mode.theo_in_bubble.unwrap_or_default(),
+            ignore_level_audio_layer_data: mode.ignore_level_audio_layer_data.unwrap_or_default(),
+            path: mode.path.clone().unwrap_or_default(),
+            poem_id: mode.poem_id.clone().unwrap_or_default(),
+            music: audio_state.music.unwrap_or_default(),
+            ambience: audio_state.ambience.unwrap_or_default(),
+            checkpoints: mode.checkpoints.iter().flatten().map(Into::into).collect(),
+        }
+    }
+}
+
+impl MapMetaModeData {
+    /// Write this mode over `old`, keeping anything in it which isn't edited here.
+    pub fn to_yaml(&self, old: Option<&MapMetaModeYaml>) -> MapMetaModeYaml {
+        let template = old.cloned().unwrap_or_default();
+        let old_checkpoints = template.checkpoints.clone().unwrap_or_default();
+        let checkpoints = self
+            .checkpoints
+            .iter()
+            .enumerate()
+            .map(|(idx, checkpoint)| checkpoint.to_yaml(old_checkpoints.get(idx)))
+            .collect();
+        MapMetaModeYaml {
+            heart_is_end: keep(self.heart_is_end, false, &template.heart_is_end),
+            inventory: keep(self.inventory.clone(), String::new(), &template.inventory),
+            start_level: keep(
+                self.start_level.clone(),
+                String::new(),
+                &template.start_level,
+            ),
+            seeker_slowdown: keep(self.seeker_slowdown, false, &template.seeker_slowdown),
+            theo_in_bubble: keep(self.theo_in_bubble, false, &template.theo_in_bubble),
+            ignore_level_audio_layer_data: keep(
+                self.ignore_level_audio_layer_data,
+                false,
+                &template.ignore_level_audio_layer_data,
+            ),
+            path: keep(self.path.clone(), String::new(), &template.path),
+            poem_id: keep(self.poem_id.clone(), String::new(), &template.poem_id),
+            audio_state: audio_state(&self.music, &self.ambience, &template.audio_state),
+            checkpoints: keep(checkpoints, vec![], &template.checkpoints),
+            other: template.other,
+        }
+    }
+}
+
+impl From<&MapMetaCheckpointYaml> for MapMetaCheckpointData {
+    fn from(checkpoint: &MapMetaCheckpointYaml) -> Self {
+        let audio_state = checkpoint.audio_state.clone().unwrap_or_default();
+        Self {
+            level: checkpoint.level.clone().unwrap_or_default(),
+            name: checkpoint.name.clone().unwrap_or_default(),
+            dreaming: checkpoint.dreaming.unwrap_or_default(),
+            inventory: checkpoint.inventory.clone().unwrap_or_default(),
+            core_mode: checkpoint.core_mode.clone().unwrap_or_default(),
+            music: audio_state.music.unwrap_or_default(),
+            ambience: audio_state.ambience.unwrap_or_default(),
+        }
+    }
+}
+
+impl MapMetaCheckpointData {
+    pub fn to_yaml(&self, old: Option<&MapMetaCheckpointYaml>) -> MapMetaCheckpointYaml {
+        let template = old.cloned().unwrap_or_default();
+        MapMetaCheckpointYaml {
+            level: keep(self.level.clone(), String::new(), &template.level),
+            name: keep(self.name.clone(), String::new(), &template.name),
+            dreaming: keep(self.dreaming, false, &template.dreaming),
+            inventory: keep(self.inventory.clone(), String::new(), &template.inventory),
+            core_mode: keep(self.core_mode.clone(), String::new(), &template.core_mode),
+            audio_state: audio_state(&self.music, &self.ambience, &template.audio_state),
+            other: template.other,
+        }
+    }
+}
+
+impl From<&MapMetaMountainYaml> for MapMetaMountainData {
+    fn from(mountain: &MapMetaMountainYaml) -> Self {
+        let default = Self::default();
+        Self {
+            model_directory: mountain
+                .mountain_model_directory
+                .clone()
+                .unwrap_or_default(),
+            texture_directory: mountain
+                .mountain_texture_directory
+                .clone()
+                .unwrap_or_default(),
+            background_music: mountain.background_music.clone().unwrap_or_default(),
+            background_ambience: mountain.background_ambience.clone().unwrap_or_default(),
+            star_fog_color: mountain.star_fog_color.clone().unwrap_or_default(),
+            show_core: mountain.show_core.unwrap_or(default.show_core),
+            show_snow: mountain.show_snow.unwrap_or(default.show_snow),
+        }
+    }
+}
+
+impl MapMetaMountainData {
+    /// Write this section over `old`. Returns `None` if there was no section and nothing is set.
+    pub fn to_yaml(&self, old: &Option<MapMetaMountainYaml>) -> Option<MapMetaMountainYaml> {
+        let default = Self::default();
+        let template = old.clone().unwrap_or_default();
+        let result = MapMetaMountainYaml {
+            mountain_model_directory: keep(
+                self.model_directory.clone(),
+                default.model_directory,
+                &template.mountain_model_directory,
+            ),
+            mountain_texture_directory: keep(
+                self.texture_directory.clone(),
+                default.texture_directory,
+                &template.mountain_texture_directory,
+            ),
+            background_music: keep(
+                self.background_music.clone(),
+                default.background_music,
+                &template.background_music,
+            ),
+            background_ambience: keep(
+                self.background_ambience.clone(),
+                default.background_ambience,
+                &template.background_ambience,
+            ),
+            star_fog_color: keep(
+                self.star_fog_color.clone(),
+                default.star_fog_color,
+                &template.star_fog_color,
+            ),
+            show_core: keep(self.show_core, default.show_core, &template.show_core),
+            show_snow: keep(self.show_snow, default.show_snow, &template.show_snow),
+            other: template.other,
+        };
+        keep(result, MapMetaMountainYaml::default(), old)
+    }
+}
+
+impl From<&MapMetaScreenYaml> for MapMetaScreenData {
+    fn from(screen: &MapMetaScreenYaml) -> Self {
+        let title = screen.title.clone().unwrap_or_default();
+        Self {
+            atlas: screen.atlas.clone().unwrap_or_default(),
+            title_a_side: title.a_side.unwrap_or_default(),
+            title_b_side: title.b_side.unwrap_or_default(),
+            title_c_side: title.c_side.unwrap_or_default(),
+            title_full_clear: title.full_clear.unwrap_or_default(),
+        }
+    }
+}
+
+impl MapMetaScreenData {
+    /// Write this section over `old`. Returns `None` if there was no section and nothing is set.
+    pub fn to_yaml(&self, old: &Option<MapMetaScreenYaml>) -> Option<MapMetaScreenYaml> {
+        let template = old.clone().unwrap_or_default();
+        let old_title = template.title.clone().unwrap_or_default();
+        let title = MapMetaScreenTitleYaml {
+            a_side: keep(self.title_a_side.clone(), String::new(), &old_title.a_side),
+            b_side: keep(self.title_b_side.clone(), String::new(), &old_title.b_side),
+            c_side: keep(self.title_c_side.clone(), String::new(), &old_title.c_side),
+            full_clear: keep(
+                self.title_full_clear.clone(),
+                String::new(),
+                &old_title.full_clear,
+            ),
+            other: old_title.other,
+        };
+        let result = MapMetaScreenYaml {
+            atlas: keep(self.atlas.clone(), String::new(), &template.atlas),
+            title: keep(title, MapMetaScreenTitleYaml::default(), &template.title),
+            other: template.other,
+        };
+        keep(result, MapMetaScreenYaml::default(), old)
+    }
+}
+
+impl From<&MapMetaCassetteModifierYaml> for MapMetaCassetteModifierData {
+    fn from(modifier: &MapMetaCassetteModifierYaml) -> Self {
+        let default = Self::default();
+        Self {
+            tempo_mult: modifier.tempo_mult.unwrap_or(default.tempo_mult),
+            lead_beats: modifier.lead_beats.unwrap_or(default.lead_beats),
+            beats_per_tick: modifier.beats_per_tick.unwrap_or(default.beats_per_tick),
+            ticks_per_swap: modifier.ticks_per_swap.unwrap_or(default.ticks_per_swap),
+            blocks: modifier.blocks.unwrap_or(default.blocks),
+            beats_max: modifier.beats_max.unwrap_or(default.beats_max),
+            beat_index_offset: modifier
+                .beat_index_offset
+                .unwrap_or(default.beat_index_offset),
+            old_behavior: modifier.old_behavior.unwrap_or(default.old_behavior),
+        }
+    }
+}
+
+impl MapMetaCassetteModifierData {
+    /// Write this section over `old`. Returns `None` if there was no section and nothing is set.
+    pub fn to_yaml(
+        &self,
+        old: &Option<MapMetaCassetteModifierYaml>,
+    ) -> Option<MapMetaCassetteModifierYaml> {
+        let default = Self::default();
+        let template = old.clone().unwrap_or_default();
+        let result = MapMetaCassetteModifierYaml {
+            tempo_mult: keep(self.tempo_mult, default.tempo_mult, &template.tempo_mult),
+            lead_beats: keep(self.lead_beats, default.lead_beats, &template.lead_beats),
+            beats_per_tick: keep(
+                self.beats_per_tick,
+                default.beats_per_tick,
+                &template.beats_per_tick,
+            ),
+            ticks_per_swap: keep(
+                self.ticks_per_swap,
+                default.ticks_per_swap,
+                &template.ticks_per_swap,
+            ),
+            blocks: keep(self.blocks, default.blocks, &template.blocks),
+            beats_max: keep(self.beats_max, default.beats_max, &template.beats_max),
+            beat_index_offset: keep(
+                self.beat_index_offset,
+                default.beat_index_offset,
+                &template.beat_index_offset,
+            ),
+            old_behavior: keep(
+                self.old_behavior,
+                default.old_behavior,
+                &template.old_behavior,
+            ),
+            other: template.other,
+        };
+        keep(result, MapMetaCassetteModifierYaml::default(), old)
+    }
+}
@@ -2,6 +2,7 @@ pub mod action;
 pub mod app;
 pub mod app_apply;
 pub mod config_editor;
+pub mod meta_yaml;
 pub mod project_map;
 pub mod selection;
 pub mod sid;
@@ -16,7 +17,10 @@ use std::path::{Path, PathBuf};
 
 use crate::data::action::{MapAction, RoomAction};
 use crate::data::project_map::MapEvent;
+use crate::lint::LintRule;
+use crate::tile_image::{default_tile_colors, TileColor};
 use arborio_maploader::map_struct::{save_as, CelesteMap};
+use arborio_maploader::repair::MapWarning;
 use arborio_modloader::module::{CelesteModule, CelesteModuleKind, MapPath};
 use arborio_utils::uuid_cls;
 use arborio_utils::vizia::prelude::*;
@@ -34,6 +38,12 @@ pub struct AppConfig {
     pub draw_interval: f32,
     pub snap: bool,
     pub advanced: bool,
+    /// The map checks which shouldn't be run.
+    #[serde(default)]
+    pub disabled_lints: Vec<LintRule>,
+    /// Which tile each color stands for when turning images into tiles and back.
+    #[serde(default = "default_tile_colors")]
+    pub tile_colors: Vec<TileColor>,
 }
 
 impl Default for AppConfig {
@@ -45,6 +55,8 @@ impl Default for AppConfig {
             draw_interval: 8.0,
             snap: true,
             advanced: false,
+            disabled_lints: vec![],
+            tile_colors: default_tile_colors(),
         }
     }
 }
@@ -82,7 +94,7 @@ pub struct Progress {
     pub status: String,
 }
 
-fn load_map(module_root: &Path, sid: &str) -> Option<CelesteMap> {
+fn load_map(module_root: &Path, sid: &str) -> Option<(CelesteMap, Vec<MapWarning>)> {
     match CelesteModule::load_map_static(module_root, sid) {
         Ok(m) => Some(m),
         Err(e) => {
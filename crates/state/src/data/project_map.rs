@@ -1,15 +1,22 @@
 use crate::data::action::{apply_map_action, MapAction, RoomAction};
 use crate::data::app::{step_modules_lookup, AppEvent, AppState};
+use crate::data::meta_yaml::{
+    keep, MapMetaCassetteModifierData, MapMetaModeData, MapMetaMountainData, MapMetaScreenData,
+};
+use crate::data::selection::AppSelection;
 use crate::data::tabs::{AppTab, MapTab};
 use crate::data::{save, EventPhase, MapID, UNDO_BUFFER_SIZE};
 use crate::tools::selection::{add_float_to_float, drop_float};
+use arborio_maploader::from_binel::ElementLayout;
+use arborio_maploader::ids::next_entity_id;
 use arborio_maploader::map_struct::{
     CelesteMap, CelesteMapDecal, CelesteMapEntity, CelesteMapLevel, CelesteMapMeta,
     CelesteMapMetaAudioState, CelesteMapMetaMode, CelesteMapStyleground, FieldEntry,
 };
+use arborio_maploader::meta_yaml::{sidecar_path, MapMetaYaml};
 use arborio_modloader::aggregate::ModuleAggregate;
 use arborio_modloader::discovery::LoaderThreadMessage;
-use arborio_modloader::everest_yaml::EverestModuleVersion;
+use arborio_modloader::everest_yaml::{EverestModuleVersion, EverestYamlDependency};
 use arborio_modloader::module::CelesteModuleKind;
 use arborio_modloader::module::{MapPath, ModuleID};
 use arborio_utils::units::*;
@@ -67,6 +74,27 @@ pub struct MapStateData {
 
     pub ambience: String,
     pub music: String,
+
+    // from map.meta.yaml
+    pub parent: String,
+    pub jumpthru: String,
+    /// One entry per side: A, B and C.
+    pub modes: Vec<MapMetaModeData>,
+    pub mountain: MapMetaMountainData,
+    pub complete_screen: MapMetaScreenData,
+    pub loading_vignette_screen: MapMetaScreenData,
+    pub cassette_modifier: MapMetaCassetteModifierData,
+
+    /// How the map's root element was laid out when it was loaded.
+    pub layout: ElementLayout,
+    pub package: Option<String>,
+    pub lookup: Vec<String>,
+    /// The meta element the map was loaded with. Saving starts from it so that fields which were
+    /// never set stay unset.
+    pub meta: Option<CelesteMapMeta>,
+    /// The map.meta.yaml the map was loaded with. Saving starts from it so that anything which
+    /// can't be edited here is kept.
+    pub meta_yaml: Option<MapMetaYaml>,
 }
 
 pub struct MapStateCache {
@@ -112,6 +140,14 @@ pub struct MapStateUpdate {
 
     pub ambience: Option<String>,
     pub music: Option<String>,
+
+    pub parent: Option<String>,
+    pub jumpthru: Option<String>,
+    pub modes: Option<Vec<MapMetaModeData>>,
+    pub mountain: Option<MapMetaMountainData>,
+    pub complete_screen: Option<MapMetaScreenData>,
+    pub loading_vignette_screen: Option<MapMetaScreenData>,
+    pub cassette_modifier: Option<MapMetaCassetteModifierData>,
 }
 
 impl MapStateData {
@@ -131,6 +167,11 @@ impl MapStateData {
         }
     }
 
+    /// The id to give the next entity or trigger added anywhere in the map.
+    pub fn next_entity_id(&self) -> i32 {
+        next_entity_id(self.levels.iter().map(|room| &room.data))
+    }
+
     pub fn level_at(&self, pt: MapPointStrict) -> Option<usize> {
         for (idx, room) in self.levels.iter().enumerate() {
             if room.data.bounds.contains(pt) {
@@ -231,6 +272,28 @@ impl MapStateData {
         if let Some(x) = patch.music.as_mut() {
             std::mem::swap(&mut self.music, x);
         }
+
+        if let Some(x) = patch.parent.as_mut() {
+            std::mem::swap(&mut self.parent, x);
+        }
+        if let Some(x) = patch.jumpthru.as_mut() {
+            std::mem::swap(&mut self.jumpthru, x);
+        }
+        if let Some(x) = patch.modes.as_mut() {
+            std::mem::swap(&mut self.modes, x);
+        }
+        if let Some(x) = patch.mountain.as_mut() {
+            std::mem::swap(&mut self.mountain, x);
+        }
+        if let Some(x) = patch.complete_screen.as_mut() {
+            std::mem::swap(&mut self.complete_screen, x);
+        }
+        if let Some(x) = patch.loading_vignette_screen.as_mut() {
+            std::mem::swap(&mut self.loading_vignette_screen, x);
+        }
+        if let Some(x) = patch.cassette_modifier.as_mut() {
+            std::mem::swap(&mut self.cassette_modifier, x);
+        }
     }
 }
 
@@ -249,6 +312,18 @@ impl MapState {
 
     pub fn new(x: CelesteMap, path: MapPath, palette: ModuleAggregate) -> Self {
         //let side = path.sid.parse::<SIDFields>().map(|f| f.mode).unwrap_or_default().idx();
+        let yaml = x.meta_yaml.clone().unwrap_or_default();
+        // the meta tab shows what everest will use, but sidecar values are only saved there
+        let effective_meta = x.effective_meta();
+        let mut modes = yaml
+            .modes
+            .iter()
+            .flatten()
+            .map(MapMetaModeData::from)
+            .collect::<Vec<_>>();
+        if modes.len() < 3 {
+            modes.resize_with(3, Default::default);
+        }
         let mut result = Self {
             data: MapStateData {
                 filler: x.filler,
@@ -285,6 +360,30 @@ impl MapState {
                 ignore_level_audio_layer_data: false,
                 ambience: "".to_string(),
                 music: "".to_string(),
+                parent: yaml.parent.clone().unwrap_or_default(),
+                jumpthru: yaml.jumpthru.clone().unwrap_or_default(),
+                modes,
+                mountain: yaml.mountain.as_ref().map(Into::into).unwrap_or_default(),
+                complete_screen: yaml
+                    .complete_screen
+                    .as_ref()
+                    .map(Into::into)
+                    .unwrap_or_default(),
+                loading_vignette_screen: yaml
+                    .loading_vignette_screen
+                    .as_ref()
+                    .map(Into::into)
+                    .unwrap_or_default(),
+                cassette_modifier: yaml
+                    .cassette_modifier
+                    .as_ref()
+                    .map(Into::into)
+                    .unwrap_or_default(),
+                layout: x.layout,
+                package: x.package,
+                lookup: x.lookup,
+                meta: x.meta.clone(),
+                meta_yaml: x.meta_yaml,
             },
             cache: MapStateCache {
                 dirty: false,
@@ -295,7 +394,7 @@ impl MapState {
                 palette,
             },
         };
-        if let Some(meta) = x.meta {
+        if let Some(meta) = effective_meta {
             result.data.apply(&mut MapStateUpdate::new(meta, None))
         }
         result
@@ -304,7 +403,11 @@ impl MapState {
 
 impl From<MapStateData> for CelesteMap {
     fn from(sself: MapStateData) -> Self {
-        let meta = sself.clone_meta();
+        let mut meta = sself.clone_meta();
+        let meta_yaml = sself.clone_meta_yaml(&meta);
+        if let Some(meta_yaml) = &meta_yaml {
+            meta_yaml.restore_bin_values(&mut meta, sself.meta.as_ref());
+        }
         CelesteMap {
             filler: sself.filler,
             background_color: sself.background_color,
@@ -312,6 +415,10 @@ impl From<MapStateData> for CelesteMap {
             backgrounds: sself.backgrounds,
             levels: sself.levels.into_iter().map(|x| x.into()).collect(),
             meta: Some(meta),
+            layout: sself.layout,
+            package: sself.package,
+            lookup: sself.lookup,
+            meta_yaml,
         }
     }
 }
@@ -326,42 +433,100 @@ fn if_not_default(s: String) -> Option<String> {
 
 impl MapStateData {
     pub fn clone_meta(&self) -> CelesteMapMeta {
-        CelesteMapMeta {
+        let template = self.meta.clone().unwrap_or_else(|| CelesteMapMeta {
             override_aside_meta: Some(true),
-            color_grade: if_not_default(self.color_grade.clone()),
-            dreaming: Some(self.dreaming),
-            fg_tiles: if_not_default(self.fg_tiles.clone()),
-            bg_tiles: if_not_default(self.bg_tiles.clone()),
-            animated_tiles: if_not_default(self.animated_tiles.clone()),
-            sprites: if_not_default(self.sprites.clone()),
-            portraits: if_not_default(self.portraits.clone()),
-            intro_type: if_not_default(self.intro_type.clone()),
-            cassette_note_color: if_not_default(self.cassette_note_color.clone()),
-            title_text_color: if_not_default(self.title_text_color.clone()),
-            title_base_color: if_not_default(self.title_base_color.clone()),
-            title_accent_color: if_not_default(self.title_accent_color.clone()),
-            icon: if_not_default(self.icon.clone()),
-            interlude: Some(self.interlude),
-            wipe: if_not_default(self.wipe.clone()),
-            bloom_base: Some(self.bloom_base),
-            bloom_strength: Some(self.bloom_strength),
-            darkness_alpha: Some(self.darkness_alpha),
-            cassette_song: if_not_default(self.cassette_song.clone()),
-            core_mode: if_not_default(self.core_mode.clone()),
-            postcard_sound_id: if_not_default(self.postcard_sound_id.clone()),
+            ..CelesteMapMeta::default()
+        });
+        let text = |value: &String, old: &Option<String>| keep(value.clone(), String::new(), old);
+        let mode = template.mode.unwrap_or_default();
+        let audio_state = match mode.audio_state {
+            Some(audio_state) => Some(CelesteMapMetaAudioState {
+                ambience: self.ambience.clone(),
+                music: self.music.clone(),
+                ..audio_state
+            }),
+            None if self.ambience.is_empty() && self.music.is_empty() => None,
+            None => Some(CelesteMapMetaAudioState {
+                ambience: self.ambience.clone(),
+                music: self.music.clone(),
+                layout: Default::default(),
+            }),
+        };
+        CelesteMapMeta {
+            color_grade: text(&self.color_grade, &template.color_grade),
+            dreaming: keep(self.dreaming, false, &template.dreaming),
+            fg_tiles: text(&self.fg_tiles, &template.fg_tiles),
+            bg_tiles: text(&self.bg_tiles, &template.bg_tiles),
+            animated_tiles: text(&self.animated_tiles, &template.animated_tiles),
+            sprites: text(&self.sprites, &template.sprites),
+            portraits: text(&self.portraits, &template.portraits),
+            intro_type: text(&self.intro_type, &template.intro_type),
+            cassette_note_color: text(&self.cassette_note_color, &template.cassette_note_color),
+            title_text_color: text(&self.title_text_color, &template.title_text_color),
+            title_base_color: text(&self.title_base_color, &template.title_base_color),
+            title_accent_color: text(&self.title_accent_color, &template.title_accent_color),
+            icon: text(&self.icon, &template.icon),
+            interlude: keep(self.interlude, false, &template.interlude),
+            wipe: text(&self.wipe, &template.wipe),
+            bloom_base: keep(self.bloom_base, 0.0, &template.bloom_base),
+            bloom_strength: keep(self.bloom_strength, 0.0, &template.bloom_strength),
+            darkness_alpha: keep(self.darkness_alpha, 0.0, &template.darkness_alpha),
+            cassette_song: text(&self.cassette_song, &template.cassette_song),
+            core_mode: text(&self.core_mode, &template.core_mode),
+            postcard_sound_id: text(&self.postcard_sound_id, &template.postcard_sound_id),
             mode: Some(CelesteMapMetaMode {
-                heart_is_end: Some(self.heart_is_end),
-                inventory: if_not_default(self.inventory.clone()),
-                start_level: if_not_default(self.start_level.clone()),
-                seeker_slowdown: Some(self.seeker_slowdown),
-                theo_in_bubble: Some(self.theo_in_bubble),
-                ignore_level_audio_layer_data: Some(self.ignore_level_audio_layer_data),
-                audio_state: Some(CelesteMapMetaAudioState {
-                    ambience: self.ambience.clone(),
-                    music: self.music.clone(),
-                }),
+                heart_is_end: keep(self.heart_is_end, false, &mode.heart_is_end),
+                inventory: text(&self.inventory, &mode.inventory),
+                start_level: text(&self.start_level, &mode.start_level),
+                seeker_slowdown: keep(self.seeker_slowdown, false, &mode.seeker_slowdown),
+                theo_in_bubble: keep(self.theo_in_bubble, false, &mode.theo_in_bubble),
+                ignore_level_audio_layer_data: keep(
+                    self.ignore_level_audio_layer_data,
+                    false,
+                    &mode.ignore_level_audio_layer_data,
+                ),
+                audio_state,
+                layout: mode.layout,
             }),
-            modes: None,
+            ..template
+        }
+    }
+
+    /// Build the map.meta.yaml to save. Returns `None` if the map didn't have one and nothing
+    /// which needs one has been set.
+    pub fn clone_meta_yaml(&self, meta: &CelesteMapMeta) -> Option<MapMetaYaml> {
+        let template = self.meta_yaml.clone().unwrap_or_default();
+        let old_modes = template.modes.clone().unwrap_or_default();
+        let mut modes = self
+            .modes
+            .iter()
+            .enumerate()
+            .map(|(idx, mode)| mode.to_yaml(old_modes.get(idx)))
+            .collect::<Vec<_>>();
+        while modes.len() > old_modes.len() && modes.last() == Some(&Default::default()) {
+            modes.pop();
+        }
+        let mut result = MapMetaYaml {
+            parent: keep(self.parent.clone(), String::new(), &template.parent),
+            jumpthru: keep(self.jumpthru.clone(), String::new(), &template.jumpthru),
+            modes: if modes.is_empty() && template.modes.is_none() {
+                None
+            } else {
+                Some(modes)
+            },
+            mountain: self.mountain.to_yaml(&template.mountain),
+            complete_screen: self.complete_screen.to_yaml(&template.complete_screen),
+            loading_vignette_screen: self
+                .loading_vignette_screen
+                .to_yaml(&template.loading_vignette_screen),
+            cassette_modifier: self.cassette_modifier.to_yaml(&template.cassette_modifier),
+            ..template
+        };
+        result.update_from(meta);
+        if self.meta_yaml.is_none() && result == MapMetaYaml::default() {
+            None
+        } else {
+            Some(result)
         }
     }
 }
@@ -435,6 +600,7 @@ impl MapStateUpdate {
                 .or(modebase.ignore_level_audio_layer_data),
             music,
             ambience,
+            ..Self::default()
         }
     }
 }
@@ -634,10 +800,16 @@ impl AppState {
                     log::error!("Internal error: rename map: create_dir_all: {}", e);
                     return;
                 }
-                if let Err(e) = std::fs::rename(old_path, new_path) {
+                if let Err(e) = std::fs::rename(&old_path, &new_path) {
                     log::error!("Internal error: rename map: rename: {}", e);
                     return;
                 }
+                let old_sidecar = sidecar_path(&old_path);
+                if old_sidecar.exists() {
+                    if let Err(e) = std::fs::rename(old_sidecar, sidecar_path(&new_path)) {
+                        log::error!("Failed to rename map.meta.yaml: {}", e);
+                    }
+                }
 
                 module.maps[index] = sid.clone();
                 state.cache.path.sid = sid;
@@ -655,6 +827,51 @@ impl AppState {
                 });
                 self.tabs.push(AppTab::MapMeta(map));
             }
+            MapEvent::JumpTo { room, selection } => {
+                let idx = self
+                    .tabs
+                    .iter()
+                    .position(|tab| matches!(tab, AppTab::Map(MapTab { id, .. }) if *id == map))
+                    .unwrap_or_else(|| {
+                        self.tabs.push(AppTab::Map(MapTab::new(map)));
+                        self.tabs.len() - 1
+                    });
+                cx.emit(AppEvent::SelectTab { idx });
+                let Some(AppTab::Map(map_tab)) = self.tabs.get_mut(idx) else { unreachable!() };
+
+                if let Some(level) = state.data.levels.get(map_tab.current_room) {
+                    level.cache.borrow_mut().render_cache_valid = false;
+                }
+                if let Some(room) = room.filter(|&room| room < state.data.levels.len()) {
+                    let level = &state.data.levels[room];
+                    map_tab.current_room = room;
+                    // keep the zoom, but move the object, or failing that the room's corner, near
+                    // the corner of the view
+                    let offset = match selection {
+                        Some(AppSelection::EntityBody(id, trigger)) => level
+                            .entity(id, trigger)
+                            .map(|entity| MapVectorStrict::new(entity.x, entity.y)),
+                        Some(AppSelection::Decal(id, fg)) => level
+                            .decal(id, fg)
+                            .map(|decal| MapVectorStrict::new(decal.x, decal.y)),
+                        _ => None,
+                    };
+                    let (target, margin) = match offset {
+                        Some(offset) => (level.data.bounds.origin + offset, 160.0),
+                        None => (level.data.bounds.origin, 32.0),
+                    };
+                    let corner = map_tab.transform.transform_point(target.cast());
+                    map_tab.transform = map_tab
+                        .transform
+                        .then_translate(ScreenPoint::new(margin, margin) - corner);
+                    level.cache.borrow_mut().render_cache_valid = false;
+                }
+                map_tab.current_selected.clear();
+                cx.emit(AppEvent::SelectObjects {
+                    tab: idx,
+                    selection: selection.into_iter().collect(),
+                });
+            }
             MapEvent::Delete => {
                 let Some(root) = module.unpacked() else {
                     log::error!("Internal error: tried to delete a packed map");
@@ -674,10 +891,16 @@ impl AppState {
                     .join("Maps")
                     .join(&state.cache.path.sid)
                     .with_extension("bin");
-                if let Err(e) = std::fs::remove_file(old_path) {
+                if let Err(e) = std::fs::remove_file(&old_path) {
                     log::error!("Failed to delete map: {}", e);
                     return;
                 }
+                let old_sidecar = sidecar_path(&old_path);
+                if old_sidecar.exists() {
+                    if let Err(e) = std::fs::remove_file(old_sidecar) {
+                        log::error!("Failed to delete map.meta.yaml: {}", e);
+                    }
+                }
                 module.maps.remove(idx);
                 self.loaded_maps.remove(&map);
                 self.modules_version += 1;
@@ -720,6 +943,14 @@ impl AppState {
                     .everest_metadata
                     .save(state.filesystem_root.as_ref().unwrap());
             }
+            ProjectEvent::SetDependencies { dependencies } => {
+                state.everest_metadata.dependencies = dependencies;
+                state
+                    .everest_metadata
+                    .save(state.filesystem_root.as_ref().unwrap());
+                // the project tab lists the dependencies
+                self.modules_version += 1;
+            }
             ProjectEvent::SetPath { path } => {
                 let old_path = state.filesystem_root.as_ref().unwrap();
                 if let Err(e) = std::fs::rename(old_path, &path) {
@@ -795,6 +1026,7 @@ impl AppState {
 pub enum ProjectEvent {
     SetName { name: String },
     SetVersion { version: EverestModuleVersion },
+    SetDependencies { dependencies: Vec<EverestYamlDependency> },
     SetPath { path: PathBuf },
     NewMap,
     Delete,
@@ -807,6 +1039,11 @@ pub enum MapEvent {
     Save,
     OpenMeta,
     Delete,
+    /// Show a room of the map in its editor tab, optionally selecting an object in it.
+    JumpTo {
+        room: Option<usize>,
+        selection: Option<AppSelection>,
+    },
     SetName {
         sid: String,
     },
@@ -879,10 +1116,6 @@ impl LevelState {
         self.data.tile(pt, foreground)
     }
 
-    pub fn next_id(&self) -> i32 {
-        self.data.next_id()
-    }
-
     pub fn occupancy_field(&self) -> TileGrid<FieldEntry> {
         self.data.occupancy_field()
     }
@@ -1,5 +1,5 @@
 use arborio_maploader::map_struct::{CelesteMapDecal, CelesteMapEntity, CelesteMapLevel};
-use arborio_utils::units::{TileGrid, TilePoint};
+use arborio_utils::units::{MapRectStrict, TileGrid, TilePoint};
 use serde::{Deserialize, Serialize};
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug, Hash)]
@@ -31,6 +31,7 @@ impl AppSelection {
 pub enum AppSelectable {
     InRoom(Vec<AppInRoomSelectable>),
     Rooms(Vec<CelesteMapLevel>),
+    RoomsAndFiller(Vec<CelesteMapLevel>, Vec<MapRectStrict>),
 }
 
 #[derive(Serialize, Deserialize)]
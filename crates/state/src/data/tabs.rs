@@ -20,6 +20,7 @@ pub enum AppTab {
     ConfigEditor(ConfigEditorTab),
     Logs,
     MapMeta(MapID),
+    FindReplace,
 }
 
 #[derive(Debug, Lens, Clone)]
@@ -57,6 +58,7 @@ impl Default for ConfigEditorTab {
                 height: 0,
                 attributes: Default::default(),
                 nodes: vec![],
+                layout: Default::default(),
             },
         }
     }
@@ -87,6 +89,20 @@ pub struct MapTab {
     pub preview_pos: MapPointStrict,
 }
 
+impl MapTab {
+    pub fn new(id: MapID) -> Self {
+        Self {
+            nonce: next_uuid(),
+            id,
+            current_room: 0,
+            current_selected: HashSet::new(),
+            styleground_selected: None,
+            transform: MapToScreen::identity(),
+            preview_pos: MapPointStrict::zero(),
+        }
+    }
+}
+
 impl PartialEq for MapTab {
     fn eq(&self, other: &Self) -> bool {
         self.nonce == other.nonce
@@ -0,0 +1,138 @@
+//! Find and replace across maps. Only maps which are open are searched, since that's where a
+//! replacement can be undone; each map's changes are applied as one action batch. Maps in scope
+//! which aren't open are reported so they can be opened first.
+
+use arborio_maploader::replace::{ChangedObject, Replacement, RoomChange};
+use arborio_modloader::module::MapPath;
+use arborio_utils::units::TilePoint;
+use arborio_utils::vizia::prelude::Data;
+use std::fmt;
+
+use crate::data::action::{MapAction, RoomAction};
+use crate::data::app::AppState;
+use crate::data::config_editor::SearchScope;
+use crate::data::project_map::MapState;
+use crate::data::selection::AppSelection;
+use crate::data::MapID;
+
+/// Something a replacement would change, and where.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplaceMatch {
+    pub map: MapID,
+    pub room: usize,
+    /// The object which would change, if it's a single one.
+    pub selection: Option<AppSelection>,
+    pub location: String,
+    pub description: String,
+}
+
+impl Data for ReplaceMatch {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl fmt::Display for ReplaceMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.description)
+    }
+}
+
+/// The maps in a search scope, sorted by SID.
+#[derive(Debug, Default)]
+pub struct ScopeMaps {
+    pub open: Vec<MapID>,
+    /// Maps which would need to be opened to be searched.
+    pub closed: Vec<MapPath>,
+}
+
+/// The maps in `scope`. `targets` is what the scope can choose between, as for the config
+/// editor's search.
+pub fn maps_in_scope(app: &AppState, scope: &SearchScope, targets: &[SearchScope]) -> ScopeMaps {
+    let mut open = app
+        .loaded_maps
+        .iter()
+        .filter(|(_, map)| scope.filter_map(&map.cache.path, targets))
+        .map(|(id, map)| (map.cache.path.sid.clone(), *id))
+        .collect::<Vec<_>>();
+    open.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut closed = app
+        .modules
+        .iter()
+        .flat_map(|(id, module)| {
+            module.maps.iter().map(|sid| MapPath {
+                module: *id,
+                sid: sid.clone(),
+            })
+        })
+        .filter(|path| {
+            scope.filter_map(path, targets) && !app.loaded_maps_lookup.contains_key(path)
+        })
+        .collect::<Vec<_>>();
+    closed.sort_by(|a, b| a.sid.cmp(&b.sid));
+
+    ScopeMaps {
+        open: open.into_iter().map(|(_, id)| id).collect(),
+        closed,
+    }
+}
+
+pub fn find_matches(
+    app: &AppState,
+    maps: &[MapID],
+    replacement: &Replacement,
+) -> Vec<ReplaceMatch> {
+    let mut result = vec![];
+    for id in maps {
+        let Some(map) = app.loaded_maps.get(id) else { continue };
+        for (room_idx, room) in map.data.levels.iter().enumerate() {
+            for change in replacement.in_room(&room.data) {
+                result.push(ReplaceMatch {
+                    map: *id,
+                    room: room_idx,
+                    selection: selection(&change),
+                    location: format!("{} {}", map.cache.path.sid, room.data.name),
+                    description: change.description,
+                });
+            }
+        }
+    }
+    result
+}
+
+/// The actions which make the replacement in `map`, to be sent as one batch.
+pub fn replace_actions(map: &MapState, replacement: &Replacement) -> Vec<MapAction> {
+    let mut result = vec![];
+    for (idx, room) in map.data.levels.iter().enumerate() {
+        for change in replacement.in_room(&room.data) {
+            let event = match change.object {
+                ChangedObject::Entity { entity, trigger } => RoomAction::EntityUpdate {
+                    entity: Box::new(entity),
+                    trigger,
+                },
+                ChangedObject::Tiles { fg, tiles } => RoomAction::TileUpdate {
+                    fg,
+                    offset: TilePoint::zero(),
+                    data: tiles,
+                },
+                ChangedObject::Decal { decal, fg } => RoomAction::DecalUpdate {
+                    fg,
+                    decal: Box::new(decal),
+                },
+            };
+            result.push(MapAction::RoomAction { idx, event });
+        }
+    }
+    result
+}
+
+fn selection(change: &RoomChange) -> Option<AppSelection> {
+    match &change.object {
+        ChangedObject::Entity { entity, trigger } => {
+            Some(AppSelection::EntityBody(entity.id, *trigger))
+        }
+        ChangedObject::Tiles { .. } => None,
+        ChangedObject::Decal { decal, fg } => Some(AppSelection::Decal(decal.id, *fg)),
+    }
+}
@@ -1,5 +1,6 @@
 use arborio_utils::units::*;
 use arborio_utils::vizia::prelude::*;
+use indexmap::IndexMap;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::hash::Hash;
@@ -10,12 +11,12 @@ use std::sync::Arc;
 use crate::auto_saver::AutoSaver;
 use crate::data::action::StylegroundSelection;
 use crate::data::app::AppState;
-use crate::data::project_map::MapStateData;
+use crate::data::project_map::{LevelState, MapStateData};
 use crate::data::selection::AppSelection;
 use crate::data::tabs::{AppTab, MapTab};
 use crate::data::MapID;
 use arborio_maploader::map_struct::{
-    Attribute, CelesteMapEntity, CelesteMapLevel, CelesteMapStyleground,
+    Attribute, CelesteMapDecal, CelesteMapEntity, CelesteMapLevel, CelesteMapStyleground,
 };
 use arborio_modloader::aggregate::ModuleAggregate;
 use arborio_modloader::config::AttributeInfo;
@@ -116,47 +117,81 @@ pub fn current_selected_entity_lens(
     })
 }
 
-#[derive(Clone)]
-pub enum CurrentSelectedEntitiesAllLens<T> {
-    F1(Arc<dyn 'static + Send + Sync + Fn(&AppState, &CelesteMapEntity) -> Option<T>>),
-    F2(
-        Arc<
-            dyn 'static
-                + Send
-                + Sync
-                + for<'a> Fn(&'a AppState, &'a CelesteMapEntity) -> Option<&'a T>,
-        >,
-    ),
-    //L(Lens<Source=CelesteMapEntity, Target=T>>),
+/// Something in a room which can be selected and edited several at a time.
+pub trait SelectableObject: 'static {
+    /// The objects of this kind which are selected in `room`.
+    fn selected<'a>(
+        room: &'a LevelState,
+        selection: &'a HashSet<AppSelection>,
+    ) -> Box<dyn Iterator<Item = &'a Self> + 'a>;
+}
+
+impl SelectableObject for CelesteMapEntity {
+    fn selected<'a>(
+        room: &'a LevelState,
+        selection: &'a HashSet<AppSelection>,
+    ) -> Box<dyn Iterator<Item = &'a Self> + 'a> {
+        Box::new(
+            selection
+                .iter()
+                .filter_map(AppSelection::entity_info)
+                .filter_map(|(eid, trigger)| room.entity(eid, trigger)),
+        )
+    }
 }
 
-impl<T: 'static + PartialEq + Clone> CurrentSelectedEntitiesAllLens<T> {
-    pub fn new_computed<
-        F: 'static + Send + Sync + Clone + Fn(&AppState, &CelesteMapEntity) -> Option<T>,
-    >(
+impl SelectableObject for CelesteMapDecal {
+    fn selected<'a>(
+        room: &'a LevelState,
+        selection: &'a HashSet<AppSelection>,
+    ) -> Box<dyn Iterator<Item = &'a Self> + 'a> {
+        Box::new(selection.iter().filter_map(|sel| match sel {
+            AppSelection::Decal(id, fg) => room.decal(*id, *fg),
+            _ => None,
+        }))
+    }
+}
+
+/// A value which all of the selected objects of kind `S` share, or nothing if they differ.
+pub enum CurrentSelectedAllLens<S, T> {
+    F1(Arc<dyn 'static + Send + Sync + Fn(&AppState, &S) -> Option<T>>),
+    F2(Arc<dyn 'static + Send + Sync + for<'a> Fn(&'a AppState, &'a S) -> Option<&'a T>>),
+    //L(Lens<Source=S, Target=T>>),
+}
+
+pub type CurrentSelectedEntitiesAllLens<T> = CurrentSelectedAllLens<CelesteMapEntity, T>;
+pub type CurrentSelectedDecalsAllLens<T> = CurrentSelectedAllLens<CelesteMapDecal, T>;
+
+impl<S, T> Clone for CurrentSelectedAllLens<S, T> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::F1(f) => Self::F1(f.clone()),
+            Self::F2(f) => Self::F2(f.clone()),
+        }
+    }
+}
+
+impl<S: SelectableObject, T: 'static + PartialEq + Clone> CurrentSelectedAllLens<S, T> {
+    pub fn new_computed<F: 'static + Send + Sync + Clone + Fn(&AppState, &S) -> Option<T>>(
         f: F,
     ) -> Self {
         Self::F1(Arc::new(f))
     }
 
     pub fn new_referenced<
-        F: 'static
-            + Send
-            + Sync
-            + Clone
-            + for<'a> Fn(&'a AppState, &'a CelesteMapEntity) -> Option<&'a T>,
+        F: 'static + Send + Sync + Clone + for<'a> Fn(&'a AppState, &'a S) -> Option<&'a T>,
     >(
         f: F,
     ) -> Self {
         Self::F2(Arc::new(f))
     }
 
-    // pub fn new_lensed<L: Lens<Source=CelesteMapEntity, Target=T>>(l: L) -> Self {
+    // pub fn new_lensed<L: Lens<Source=S, Target=T>>(l: L) -> Self {
     //     Self::L(Rc::new(l))
     // }
 }
 
-impl<T: 'static + PartialEq + Clone> Lens for CurrentSelectedEntitiesAllLens<T> {
+impl<S: SelectableObject, T: 'static + PartialEq + Clone> Lens for CurrentSelectedAllLens<S, T> {
     type Source = AppState;
     type Target = T;
 
@@ -169,30 +204,27 @@ impl<T: 'static + PartialEq + Clone> Lens for CurrentSelectedEntitiesAllLens<T>
                              })) = source.tabs.get(source.current_tab) else { return map(None) };
         let Some(room) = source.loaded_maps.get(map_id).and_then(|map| map.data.levels.get(*current_room)) else { return map(None); };
 
-        let mut ent_iter = current_selected
-            .iter()
-            .filter_map(AppSelection::entity_info)
-            .filter_map(|(eid, trigger)| room.entity(eid, trigger));
-        let Some(ent_first) = ent_iter.next() else { return map(None) };
+        let mut iter = S::selected(room, current_selected);
+        let Some(first) = iter.next() else { return map(None) };
         match self {
-            CurrentSelectedEntitiesAllLens::F1(f) => {
-                let res_first = f(source, ent_first);
-                if ent_iter.all(|ent| res_first == f(source, ent)) {
+            CurrentSelectedAllLens::F1(f) => {
+                let res_first = f(source, first);
+                if iter.all(|obj| res_first == f(source, obj)) {
                     map(res_first.as_ref())
                 } else {
                     map(None)
                 }
             }
-            CurrentSelectedEntitiesAllLens::F2(f) => {
-                let res_first = f(source, ent_first);
-                if ent_iter.all(|ent| res_first == f(source, ent)) {
+            CurrentSelectedAllLens::F2(f) => {
+                let res_first = f(source, first);
+                if iter.all(|obj| res_first == f(source, obj)) {
                     map(res_first)
                 } else {
                     map(None)
                 }
-            } // CurrentSelectedEntitiesAllLens::L(l) => {
-              //     l.view(ent_first, |res_first| {
-              //         if ent_iter.all(|ent| l.view(ent, |res| res == res_first)) {
+            } // CurrentSelectedAllLens::L(l) => {
+              //     l.view(first, |res_first| {
+              //         if iter.all(|obj| l.view(obj, |res| res == res_first)) {
               //             map(res_first)
               //         } else {
               //             map(None)
@@ -557,33 +589,81 @@ impl<T: 'static> Lens for HashSetLenLens<T> {
     }
 }
 
+/// The lookups the map lenses below need, so they work on both the `HashMap`s in configs and the
+/// `IndexMap`s that keep an object's attributes in file order.
+pub trait KeyedMap {
+    type Key;
+    type Value;
+
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn get(&self, key: &Self::Key) -> Option<&Self::Value>;
+    fn keys<'a>(&'a self) -> Box<dyn Iterator<Item = &'a Self::Key> + 'a>;
+}
+
+impl<K: Hash + Eq, V> KeyedMap for HashMap<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        HashMap::get(self, key)
+    }
+
+    fn keys<'a>(&'a self) -> Box<dyn Iterator<Item = &'a K> + 'a> {
+        Box::new(HashMap::keys(self))
+    }
+}
+
+impl<K: Hash + Eq, V> KeyedMap for IndexMap<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn len(&self) -> usize {
+        IndexMap::len(self)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        IndexMap::get(self, key)
+    }
+
+    fn keys<'a>(&'a self) -> Box<dyn Iterator<Item = &'a K> + 'a> {
+        Box::new(IndexMap::keys(self))
+    }
+}
+
 #[derive(Debug)]
-pub struct HashMapLenLens<K, V> {
-    p: PhantomData<(K, V)>,
+pub struct HashMapLenLens<M> {
+    p: PhantomData<M>,
 }
 
-impl<K, V> HashMapLenLens<K, V> {
+impl<M> HashMapLenLens<M> {
     pub fn new() -> Self {
         Self { p: PhantomData }
     }
 }
 
-impl<K, V> Default for HashMapLenLens<K, V> {
+impl<M> Default for HashMapLenLens<M> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<K, V> Clone for HashMapLenLens<K, V> {
+impl<M> Clone for HashMapLenLens<M> {
     fn clone(&self) -> Self {
         Self::new()
     }
 }
 
-impl<K, V> Copy for HashMapLenLens<K, V> {}
+impl<M> Copy for HashMapLenLens<M> {}
 
-impl<K: 'static, V: 'static> Lens for HashMapLenLens<K, V> {
-    type Source = HashMap<K, V>;
+impl<M: KeyedMap + 'static> Lens for HashMapLenLens<M> {
+    type Source = M;
     type Target = usize;
 
     fn view<O, F: FnOnce(Option<&Self::Target>) -> O>(&self, source: &Self::Source, map: F) -> O {
@@ -591,10 +671,13 @@ impl<K: 'static, V: 'static> Lens for HashMapLenLens<K, V> {
     }
 }
 
-pub fn hash_map_nth_key_lens<K: Ord + 'static, V: 'static>(
+pub fn hash_map_nth_key_lens<M: KeyedMap + 'static>(
     idx: usize,
-) -> impl Lens<Source = HashMap<K, V>, Target = K> + Copy + Send + Sync {
-    ClosureLens::new(move |source: &HashMap<K, V>| {
+) -> impl Lens<Source = M, Target = M::Key> + Copy + Send + Sync
+where
+    M::Key: Ord,
+{
+    ClosureLens::new(move |source: &M| {
         let mut keys = source.keys().collect::<Vec<_>>();
         keys.sort();
         keys.get(idx).copied()
@@ -677,9 +760,9 @@ impl<L1: Copy, L2: Copy, T> Copy for HashMapIndexWithLens<L1, L2, T> {}
 
 impl<L1, L2, T: 'static + Debug> Lens for HashMapIndexWithLens<L1, L2, T>
 where
-    L1: Lens<Target = HashMap<<L2 as Lens>::Target, T>>,
+    L1: Lens,
+    <L1 as Lens>::Target: KeyedMap<Key = <L2 as Lens>::Target, Value = T>,
     L2: Lens<Source = <L1 as Lens>::Source>,
-    <L2 as Lens>::Target: Hash + Eq,
 {
     type Source = <L1 as Lens>::Source;
     type Target = T;
@@ -889,6 +972,7 @@ impl Lens for TabTextLens {
                     name.push_str(" - Meta");
                     name
                 }
+                AppTab::FindReplace => "Find and Replace".to_owned(),
             }))
         } else {
             map(None)
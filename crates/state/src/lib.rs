@@ -1,6 +1,12 @@
 pub mod auto_saver;
 pub mod data;
+pub mod find_replace;
 pub mod lenses;
+pub mod lint;
+pub mod object_search;
 pub mod palette_item;
+pub mod prefab;
+pub mod release;
 pub mod rendering;
+pub mod tile_image;
 pub mod tools;
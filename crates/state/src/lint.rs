@@ -0,0 +1,336 @@
+//! Checks for mistakes in a map which won't stop it from loading but will break it in game.
+
+use arborio_maploader::map_struct::{CelesteMap, CelesteMapEntity, CelesteMapLevel};
+use arborio_modloader::aggregate::ModuleAggregate;
+use arborio_utils::vizia::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::data::selection::AppSelection;
+use crate::rendering::decal_texture;
+
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, Hash, enum_iterator::Sequence, Data, Serialize, Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum LintRule {
+    DuplicateId,
+    OutOfBounds,
+    OverlappingRooms,
+    RoomSize,
+    NoSpawn,
+    MissingRoom,
+    UnknownEntity,
+    MissingTexture,
+}
+
+impl LintRule {
+    /// The name used to refer to the rule on the command line and in the settings.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LintRule::DuplicateId => "duplicate-id",
+            LintRule::OutOfBounds => "out-of-bounds",
+            LintRule::OverlappingRooms => "overlapping-rooms",
+            LintRule::RoomSize => "room-size",
+            LintRule::NoSpawn => "no-spawn",
+            LintRule::MissingRoom => "missing-room",
+            LintRule::UnknownEntity => "unknown-entity",
+            LintRule::MissingTexture => "missing-texture",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            LintRule::DuplicateId => "Entities or triggers which share an id",
+            LintRule::OutOfBounds => "Entities or nodes outside their room",
+            LintRule::OverlappingRooms => "Rooms which overlap",
+            LintRule::RoomSize => "Rooms whose size isn't a multiple of 8",
+            LintRule::NoSpawn => "Rooms without a player spawn",
+            LintRule::MissingRoom => "Start levels and checkpoints naming a missing room",
+            LintRule::UnknownEntity => "Entities and triggers without a config",
+            LintRule::MissingTexture => "Decals whose texture isn't in the atlas",
+        }
+    }
+}
+
+impl fmt::Display for LintRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for LintRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        enum_iterator::all::<LintRule>()
+            .find(|rule| rule.name() == s)
+            .ok_or_else(|| format!("Unknown lint rule {s}"))
+    }
+}
+
+/// Something a lint rule found, and where.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintFinding {
+    pub rule: LintRule,
+    /// The index of the room the finding is in, if any.
+    pub room: Option<usize>,
+    /// The object the finding is about, if any.
+    pub selection: Option<AppSelection>,
+    /// Where the finding is, as a person would describe it.
+    pub location: String,
+    pub description: String,
+}
+
+impl Data for LintFinding {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} [{}]", self.location, self.description, self.rule)
+    }
+}
+
+/// Check `map` with every rule which isn't in `disabled`. The rules which need to know which
+/// entities and textures exist are skipped if there is no `palette`.
+pub fn lint_map(
+    map: &CelesteMap,
+    palette: Option<&ModuleAggregate>,
+    disabled: &[LintRule],
+) -> Vec<LintFinding> {
+    let mut findings = vec![];
+    let mut add = |rule: LintRule,
+                   room: Option<usize>,
+                   selection: Option<AppSelection>,
+                   description: String| {
+        if !disabled.contains(&rule) {
+            let location = match room {
+                Some(idx) => format!("room {}", map.levels[idx].name),
+                None => "map".to_owned(),
+            };
+            findings.push(LintFinding {
+                rule,
+                room,
+                selection,
+                location,
+                description,
+            });
+        }
+    };
+
+    let mut ids: HashMap<i32, (usize, bool)> = HashMap::new();
+    for (idx, room) in map.levels.iter().enumerate() {
+        for (trigger, entity) in objects(room) {
+            let selection = Some(AppSelection::EntityBody(entity.id, trigger));
+            match ids.get(&entity.id) {
+                Some(&(other_idx, _)) => add(
+                    LintRule::DuplicateId,
+                    Some(idx),
+                    selection,
+                    format!(
+                        "{} has id {}, which is already used in room {}",
+                        entity.name, entity.id, map.levels[other_idx].name
+                    ),
+                ),
+                None => {
+                    ids.insert(entity.id, (idx, trigger));
+                }
+            }
+
+            let size = room.bounds.size;
+            let inside = |x: i32, y: i32| x >= 0 && y >= 0 && x <= size.width && y <= size.height;
+            if !inside(entity.x, entity.y) {
+                add(
+                    LintRule::OutOfBounds,
+                    Some(idx),
+                    selection,
+                    format!(
+                        "{} (id {}) at ({}, {}) is outside the room",
+                        entity.name, entity.id, entity.x, entity.y
+                    ),
+                );
+            }
+            for (node_idx, node) in entity.nodes.iter().enumerate() {
+                if !inside(node.x, node.y) {
+                    add(
+                        LintRule::OutOfBounds,
+                        Some(idx),
+                        Some(AppSelection::EntityNode(entity.id, node_idx, trigger)),
+                        format!(
+                            "Node {} of {} (id {}) at ({}, {}) is outside the room",
+                            node_idx + 1,
+                            entity.name,
+                            entity.id,
+                            node.x,
+                            node.y
+                        ),
+                    );
+                }
+            }
+
+            if let Some(palette) = palette {
+                let known = if trigger {
+                    palette.trigger_config.contains_key(entity.name.as_str())
+                } else {
+                    palette.entity_config.contains_key(entity.name.as_str())
+                };
+                if !known {
+                    add(
+                        LintRule::UnknownEntity,
+                        Some(idx),
+                        selection,
+                        format!(
+                            "No config for {} {}",
+                            if trigger { "trigger" } else { "entity" },
+                            entity.name
+                        ),
+                    );
+                }
+            }
+        }
+
+        for other_idx in idx + 1..map.levels.len() {
+            if room.bounds.intersects(&map.levels[other_idx].bounds) {
+                add(
+                    LintRule::OverlappingRooms,
+                    Some(idx),
+                    None,
+                    format!("Overlaps room {}", map.levels[other_idx].name),
+                );
+            }
+        }
+
+        let size = room.bounds.size;
+        if size.width % 8 != 0 || size.height % 8 != 0 {
+            add(
+                LintRule::RoomSize,
+                Some(idx),
+                None,
+                format!("Size {}x{} is not a multiple of 8", size.width, size.height),
+            );
+        }
+
+        if !room.entities.iter().any(|entity| entity.name == "player") {
+            add(
+                LintRule::NoSpawn,
+                Some(idx),
+                None,
+                "No player spawn".to_owned(),
+            );
+        }
+
+        if let Some(palette) = palette {
+            let decals = room.fg_decals.iter().map(|decal| (true, decal));
+            let decals = decals.chain(room.bg_decals.iter().map(|decal| (false, decal)));
+            for (fg, decal) in decals {
+                let texture = decal_texture(decal);
+                if palette.gameplay_atlas.sprite_dimensions(&texture).is_none() {
+                    add(
+                        LintRule::MissingTexture,
+                        Some(idx),
+                        Some(AppSelection::Decal(decal.id, fg)),
+                        format!("Decal texture {texture} is not in the atlas"),
+                    );
+                }
+            }
+        }
+    }
+
+    let room_exists = |name: &str| map.levels.iter().any(|room| room.name == name);
+    let mut levels = vec![];
+    if let Some(start_level) = map
+        .meta
+        .as_ref()
+        .and_then(|meta| meta.mode.as_ref())
+        .and_then(|mode| mode.start_level.as_ref())
+    {
+        levels.push(("Start level".to_owned(), start_level.clone()));
+    }
+    if let Some(meta) = &map.meta_yaml {
+        let sides = ["A", "B", "C"];
+        for (side, mode) in meta.modes.iter().flatten().enumerate() {
+            let side = sides.get(side).copied().unwrap_or("?");
+            if let Some(start_level) = &mode.start_level {
+                levels.push((format!("{side}-side start level"), start_level.clone()));
+            }
+            for checkpoint in mode.checkpoints.iter().flatten() {
+                if let Some(level) = &checkpoint.level {
+                    let name = checkpoint.name.as_deref().unwrap_or("unnamed");
+                    levels.push((format!("{side}-side checkpoint {name}"), level.clone()));
+                }
+            }
+        }
+    }
+    for (what, level) in levels {
+        if !level.is_empty() && !room_exists(&level) {
+            add(
+                LintRule::MissingRoom,
+                None,
+                None,
+                format!("{what} {level} is not a room in this map"),
+            );
+        }
+    }
+
+    findings
+}
+
+/// All the entities and triggers in a room, along with whether each is a trigger.
+fn objects(room: &CelesteMapLevel) -> impl Iterator<Item = (bool, &CelesteMapEntity)> {
+    let entities = room.entities.iter().map(|entity| (false, entity));
+    entities.chain(room.triggers.iter().map(|trigger| (true, trigger)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arborio_utils::units::MapRectStrict;
+
+    #[test]
+    fn rules_find_problems() {
+        let mut map = CelesteMap::default();
+        let mut room = CelesteMapLevel {
+            name: "a-00".to_owned(),
+            bounds: MapRectStrict::new((0, 0).into(), (320, 184).into()),
+            ..CelesteMapLevel::default()
+        };
+        room.entities.push(CelesteMapEntity::new(1, "player", 16, 16));
+        room.entities.push(CelesteMapEntity::new(1, "spring", 400, 16));
+        map.levels.push(room);
+        map.levels.push(CelesteMapLevel {
+            name: "a-01".to_owned(),
+            bounds: MapRectStrict::new((316, 0).into(), (100, 184).into()),
+            ..CelesteMapLevel::default()
+        });
+
+        let rules = |disabled: &[LintRule]| {
+            lint_map(&map, None, disabled)
+                .into_iter()
+                .map(|finding| (finding.rule, finding.room))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            rules(&[]),
+            [
+                (LintRule::DuplicateId, Some(0)),
+                (LintRule::OutOfBounds, Some(0)),
+                (LintRule::OverlappingRooms, Some(0)),
+                (LintRule::RoomSize, Some(1)),
+                (LintRule::NoSpawn, Some(1)),
+            ]
+        );
+        assert_eq!(
+            rules(&[LintRule::OverlappingRooms, LintRule::NoSpawn]),
+            [
+                (LintRule::DuplicateId, Some(0)),
+                (LintRule::OutOfBounds, Some(0)),
+                (LintRule::RoomSize, Some(1)),
+            ]
+        );
+    }
+}
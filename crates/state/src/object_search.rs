@@ -0,0 +1,160 @@
+//! Finding entities, triggers and decals in a map by what they're called or what they contain.
+
+use arborio_maploader::map_struct::{Attribute, CelesteMapEntity, CelesteMapLevel};
+use arborio_utils::vizia::prelude::*;
+use std::fmt;
+
+use crate::data::selection::AppSelection;
+use crate::data::MapID;
+
+/// An object which matched a search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectMatch {
+    pub map: MapID,
+    pub room: usize,
+    pub selection: AppSelection,
+    /// Where the object is and what it is, as a person would describe it.
+    pub description: String,
+    /// What about the object matched, if not its name.
+    pub matched: Option<String>,
+}
+
+impl Data for ObjectMatch {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl fmt::Display for ObjectMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.matched {
+            Some(matched) => write!(f, "{} ({matched})", self.description),
+            None => write!(f, "{}", self.description),
+        }
+    }
+}
+
+/// Look through every room of `map` for objects matching `query`, ignoring case. Entities and
+/// triggers match by name, by any attribute value or `name=value` pair, or by id if the query is a
+/// number. Decals match by texture.
+pub fn search_objects<'a>(
+    map: MapID,
+    rooms: impl IntoIterator<Item = &'a CelesteMapLevel>,
+    query: &str,
+) -> Vec<ObjectMatch> {
+    let query = query.trim().to_ascii_lowercase();
+    if query.is_empty() {
+        return vec![];
+    }
+    let id = query.trim_start_matches('#').parse::<i32>().ok();
+
+    let mut result = vec![];
+    for (idx, room) in rooms.into_iter().enumerate() {
+        let entities = room.entities.iter().map(|entity| (entity, false));
+        let triggers = room.triggers.iter().map(|trigger| (trigger, true));
+        for (entity, trigger) in entities.chain(triggers) {
+            let matched =
+                if Some(entity.id) == id || entity.name.to_ascii_lowercase().contains(&query) {
+                    None
+                } else if let Some(attribute) = matching_attribute(entity, &query) {
+                    Some(attribute)
+                } else {
+                    continue;
+                };
+            result.push(ObjectMatch {
+                map,
+                room: idx,
+                selection: AppSelection::EntityBody(entity.id, trigger),
+                description: format!("{}: {} #{}", room.name, entity.name, entity.id),
+                matched,
+            });
+        }
+
+        let fg = room.fg_decals.iter().map(|decal| (decal, true));
+        let bg = room.bg_decals.iter().map(|decal| (decal, false));
+        for (decal, fg) in fg.chain(bg) {
+            if decal.texture.to_ascii_lowercase().contains(&query) {
+                result.push(ObjectMatch {
+                    map,
+                    room: idx,
+                    selection: AppSelection::Decal(decal.id, fg),
+                    description: format!(
+                        "{}: {} decal {}",
+                        room.name,
+                        if fg { "fg" } else { "bg" },
+                        decal.texture
+                    ),
+                    matched: None,
+                });
+            }
+        }
+    }
+    result
+}
+
+fn matching_attribute(entity: &CelesteMapEntity, query: &str) -> Option<String> {
+    let mut attributes = entity.attributes.iter().collect::<Vec<_>>();
+    attributes.sort_by_key(|(name, _)| name.as_str());
+    attributes.into_iter().find_map(|(name, value)| {
+        let value = match value {
+            Attribute::Bool(b) => b.to_string(),
+            Attribute::Int(i) => i.to_string(),
+            Attribute::Float(f) => f.to_string(),
+            Attribute::Text(s) => s.clone(),
+        };
+        let pair = format!("{name}={value}");
+        pair.to_ascii_lowercase().contains(query).then_some(pair)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arborio_maploader::map_struct::CelesteMapDecal;
+
+    #[test]
+    fn objects_are_found_by_name_attribute_id_and_texture() {
+        let mut room = CelesteMapLevel {
+            name: "lvl_a-00".to_owned(),
+            ..CelesteMapLevel::default()
+        };
+        room.triggers.push(CelesteMapEntity {
+            width: 8,
+            height: 8,
+            ..CelesteMapEntity::new(12, "flagTrigger", 0, 0)
+                .with_attributes(&[("flag", Attribute::Text("door_open".to_owned()))])
+        });
+        room.bg_decals.push(CelesteMapDecal {
+            id: 3,
+            x: 0,
+            y: 0,
+            scale_x: 1.0,
+            scale_y: 1.0,
+            texture: "1-forsakencity/FlagTrigger.png".to_owned(),
+            rotation: 0.0,
+            color: None,
+            depth: None,
+            layout: Default::default(),
+        });
+        let rooms = [CelesteMapLevel::default(), room];
+        let map = MapID::new();
+
+        let found = search_objects(map, &rooms, "FLAGtrigger");
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].map, map);
+        assert_eq!(found[0].room, 1);
+        assert_eq!(found[0].selection, AppSelection::EntityBody(12, true));
+        assert_eq!(found[1].selection, AppSelection::Decal(3, false));
+
+        let found = search_objects(map, &rooms, "door");
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].to_string(),
+            "lvl_a-00: flagTrigger #12 (flag=door_open)"
+        );
+
+        assert_eq!(search_objects(map, &rooms, "#12").len(), 1);
+        assert!(search_objects(map, &rooms, "13").is_empty());
+        assert!(search_objects(map, &rooms, " ").is_empty());
+    }
+}
@@ -197,6 +197,7 @@ pub fn instantiate_entity(
         width,
         height,
         nodes,
+        layout: Default::default(),
     };
     for (attr, info) in config.attribute_info.iter() {
         if !entity.attributes.contains_key(attr) {
@@ -263,6 +264,7 @@ pub fn instantiate_trigger(
         width,
         height,
         nodes,
+        layout: Default::default(),
     };
     for (attr, info) in config.attribute_info.iter() {
         if !entity.attributes.contains_key(attr) {
@@ -331,5 +333,9 @@ pub fn instantiate_decal(
         } else {
             this.0.to_string()
         },
+        rotation: 0.0,
+        color: None,
+        depth: None,
+        layout: Default::default(),
     }
 }
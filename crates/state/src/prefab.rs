@@ -0,0 +1,79 @@
+//! Selections and rooms saved to a project under a name, so that they can be placed again and
+//! again. They're stored the same way as copied objects, one yaml file each.
+
+use arborio_modloader::module::PREFABS_DIR;
+use std::path::{Path, PathBuf};
+
+use crate::data::selection::AppSelectable;
+
+pub fn prefab_path(root: &Path, name: &str) -> Result<PathBuf, String> {
+    if name.trim().is_empty() {
+        return Err("Prefabs need a name".to_owned());
+    }
+    if name.starts_with('.') || name.contains(['/', '\\', ':']) {
+        return Err(format!("{name} is not a valid prefab name"));
+    }
+    Ok(root.join(PREFABS_DIR).join(format!("{name}.yaml")))
+}
+
+pub fn save_prefab(root: &Path, name: &str, prefab: &AppSelectable) -> Result<(), String> {
+    let path = prefab_path(root, name)?;
+    let contents =
+        serde_yaml::to_string(prefab).map_err(|e| format!("Could not save prefab {name}: {e}"))?;
+    std::fs::create_dir_all(path.parent().unwrap())
+        .and_then(|_| std::fs::write(&path, contents))
+        .map_err(|e| format!("Could not write {}: {e}", path.display()))
+}
+
+pub fn load_prefab(root: &Path, name: &str) -> Result<AppSelectable, String> {
+    let path = prefab_path(root, name)?;
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Could not open {}: {e}", path.display()))?;
+    serde_yaml::from_str(&contents).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::selection::AppInRoomSelectable;
+    use arborio_maploader::map_struct::CelesteMapEntity;
+
+    #[test]
+    fn prefabs_survive_a_round_trip() {
+        let root = std::env::temp_dir().join(format!("arborio-prefabs-{}", std::process::id()));
+        let entity = CelesteMapEntity::new(7, "spring", 8, 16);
+        let prefab = AppSelectable::InRoom(vec![AppInRoomSelectable::Entity(entity, false)]);
+
+        save_prefab(&root, "spring pit", &prefab).unwrap();
+        let loaded = load_prefab(&root, "spring pit");
+        std::fs::remove_dir_all(&root).unwrap();
+        let Ok(AppSelectable::InRoom(objects)) = loaded else { panic!("wrong prefab kind") };
+        assert!(
+            matches!(&objects[..], [AppInRoomSelectable::Entity(e, false)] if e.name == "spring")
+        );
+
+        assert!(save_prefab(&root, "../escape", &prefab).is_err());
+        assert!(save_prefab(&root, " ", &prefab).is_err());
+    }
+
+    #[test]
+    fn dotted_names_get_their_own_files() {
+        let root =
+            std::env::temp_dir().join(format!("arborio-prefabs-dots-{}", std::process::id()));
+        let prefab = |name| {
+            AppSelectable::InRoom(vec![AppInRoomSelectable::Entity(
+                CelesteMapEntity::new(1, name, 0, 0),
+                false,
+            )])
+        };
+
+        save_prefab(&root, "v1.2", &prefab("spring")).unwrap();
+        save_prefab(&root, "v1.5", &prefab("spinner")).unwrap();
+        let loaded = load_prefab(&root, "v1.2");
+        std::fs::remove_dir_all(&root).unwrap();
+        let Ok(AppSelectable::InRoom(objects)) = loaded else { panic!("wrong prefab kind") };
+        assert!(
+            matches!(&objects[..], [AppInRoomSelectable::Entity(e, false)] if e.name == "spring")
+        );
+    }
+}
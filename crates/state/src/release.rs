@@ -0,0 +1,58 @@
+//! Looking an unpacked mod over before it is packed for release, so that a broken everest.yaml or
+//! map is caught before anyone downloads it.
+
+use arborio_maploader::map_struct::from_reader_tolerant;
+use arborio_maploader::meta_yaml;
+use arborio_modloader::aggregate::ModuleAggregate;
+use arborio_modloader::packaging::validate_everest_yaml;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use crate::lint::{lint_map, LintRule};
+
+/// Everything which should stop the mod at `root` from being released: problems with its
+/// everest.yaml, and maps among `files` which don't load cleanly or which fail a lint rule that
+/// isn't `disabled`. Maps are checked as they are saved on disk, since that's what gets packed.
+pub fn release_problems(
+    root: &Path,
+    files: &[PathBuf],
+    palette: Option<&ModuleAggregate>,
+    disabled: &[LintRule],
+) -> Vec<String> {
+    let mut problems = validate_everest_yaml(root, files).err().unwrap_or_default();
+
+    let maps = files.iter().filter(|file| {
+        file.starts_with("Maps") && file.extension().and_then(OsStr::to_str) == Some("bin")
+    });
+    for file in maps {
+        let name = file.display();
+        let path = root.join(file);
+        let loaded = File::open(&path).and_then(|file| from_reader_tolerant(BufReader::new(file)));
+        let (mut map, mut warnings) = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                problems.push(format!("{name}: {e}"));
+                continue;
+            }
+        };
+        let sidecar = meta_yaml::sidecar_path(&path);
+        if let Ok(file) = File::open(&sidecar) {
+            warnings.extend(meta_yaml::load_into(
+                &mut map,
+                BufReader::new(file),
+                &sidecar.to_string_lossy(),
+            ));
+        }
+
+        problems.extend(warnings.iter().map(|warning| format!("{name}: {warning}")));
+        problems.extend(
+            lint_map(&map, palette, disabled)
+                .iter()
+                .map(|finding| format!("{name}: {finding}")),
+        );
+    }
+
+    problems
+}
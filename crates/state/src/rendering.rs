@@ -1,24 +1,110 @@
 use arborio_gfxloader::autotiler::{TextureTile, TileReference};
+use arborio_gfxloader::render::{PixmapRenderer, Renderer, Shape};
 use arborio_maploader::map_struct::{
-    Attribute, CelesteMapDecal, CelesteMapEntity, CelesteMapLevel, CelesteMapStyleground,
-    FieldEntry,
+    Attribute, CelesteMap, CelesteMapDecal, CelesteMapEntity, CelesteMapLevel,
+    CelesteMapStyleground, FieldEntry,
 };
 use arborio_modloader::aggregate::ModuleAggregate;
 use arborio_modloader::config::{Const, DrawElement, EntityConfig, Number};
 use arborio_modloader::mapstruct_plus_config::{make_entity_env, make_node_env};
 use arborio_utils::units::*;
-use arborio_utils::vizia::prelude::Canvas;
-use arborio_utils::vizia::vg::{Color, Paint, Path};
+use arborio_utils::vizia::vg::Color;
+use std::cmp::Reverse;
 use std::collections::{HashMap, HashSet};
 use std::ops::Deref;
 
 use crate::data::project_map::LevelState;
 use crate::data::selection::AppSelection;
 
+pub const FILLER_COLOR: Color = Color {
+    r: 0.40,
+    g: 0.40,
+    b: 0.40,
+    a: 1.00,
+};
+pub const ROOM_EMPTY_COLOR: Color = Color {
+    r: 0.13,
+    g: 0.25,
+    b: 0.13,
+    a: 1.00,
+};
+
+/// Draw everything in a room, in room coordinates, in the order the game layers it.
+pub fn draw_room(
+    palette: &ModuleAggregate,
+    canvas: &mut dyn Renderer,
+    room: &LevelState,
+    selection: &HashSet<AppSelection>,
+    triggers: bool,
+) {
+    draw_tiles(palette, canvas, room, false);
+    draw_decals(palette, canvas, &room.data, false);
+    if triggers {
+        draw_triggers(palette, canvas, &room.data, selection);
+    }
+    draw_entities(palette, canvas, &room.data, selection);
+    draw_tiles(palette, canvas, room, true);
+    draw_decals(palette, canvas, &room.data, true);
+    draw_objtiles_float(palette, canvas, room);
+}
+
+/// Draw one room of a map, or the whole map with its filler if `room` is None, into an image in
+/// memory. Triggers are left out, since they aren't visible in game.
+pub fn render_map(
+    palette: &ModuleAggregate,
+    map: &CelesteMap,
+    room: Option<usize>,
+) -> Result<PixmapRenderer, String> {
+    let rooms = match room {
+        Some(idx) => vec![map.levels.get(idx).ok_or("No such room")?],
+        None => map.levels.iter().collect(),
+    };
+    let filler = if room.is_some() { &[][..] } else { &map.filler };
+    let bounds = rooms
+        .iter()
+        .map(|room| room.bounds)
+        .chain(filler.iter().copied())
+        .reduce(|a, b| a.union(&b))
+        .ok_or("Map has no rooms to render")?;
+
+    let mut canvas = PixmapRenderer::new(bounds.width() as u32, bounds.height() as u32)?;
+    canvas.translate(-bounds.min_x() as f32, -bounds.min_y() as f32);
+
+    let mut path = Shape::new();
+    for filler in filler {
+        path.rect(
+            filler.origin.x as f32,
+            filler.origin.y as f32,
+            filler.width() as f32,
+            filler.height() as f32,
+        );
+    }
+    canvas.fill_path(&path, FILLER_COLOR);
+
+    let nil = HashSet::new();
+    for room in rooms {
+        let mut path = Shape::new();
+        path.rect(
+            room.bounds.origin.x as f32,
+            room.bounds.origin.y as f32,
+            room.bounds.width() as f32,
+            room.bounds.height() as f32,
+        );
+        canvas.fill_path(&path, ROOM_EMPTY_COLOR);
+
+        canvas.save();
+        canvas.translate(room.bounds.min_x() as f32, room.bounds.min_y() as f32);
+        draw_room(palette, &mut canvas, &room.clone().into(), &nil, false);
+        canvas.restore();
+    }
+
+    Ok(canvas)
+}
+
 pub fn draw_entity(
     config: &EntityConfig,
     palette: &ModuleAggregate,
-    canvas: &mut Canvas,
+    canvas: &mut dyn Renderer,
     entity: &CelesteMapEntity,
     field: &TileGrid<FieldEntry>,
     selected: bool,
@@ -65,7 +151,7 @@ pub fn draw_entity(
 
 fn draw_entity_directive(
     palette: &ModuleAggregate,
-    canvas: &mut Canvas,
+    canvas: &mut dyn Renderer,
     draw: &DrawElement,
     env: &HashMap<&str, Const>,
     field: &TileGrid<FieldEntry>,
@@ -82,25 +168,22 @@ fn draw_entity_directive(
             let y = rect.topleft.y.evaluate(env)?.as_number()?.to_int() as f32;
             let width = rect.size.x.evaluate(env)?.as_number()?.to_int() as f32;
             let height = rect.size.y.evaluate(env)?.as_number()?.to_int() as f32;
-            let fill = Paint::color(color.evaluate(env)?);
-            let border_color_eval = border_color.evaluate(env)?;
-            let border_thickness = if border_color_eval.a == 0.0 {
+            let fill = color.evaluate(env)?;
+            let border_color = border_color.evaluate(env)?;
+            let border_thickness = if border_color.a == 0.0 {
                 0.0
             } else {
                 *border_thickness as f32
             };
-            let mut border = Paint::color(border_color_eval);
-            border.set_line_width(border_thickness);
-            border.set_anti_alias(false);
             let x = x + border_thickness;
             let y = y + border_thickness;
             let width = width - border_thickness * 2.0;
             let height = height - border_thickness * 2.0;
 
-            let mut path = Path::new();
+            let mut path = Shape::new();
             path.rect(x, y, width, height);
-            canvas.fill_path(&mut path, &fill);
-            canvas.stroke_path(&mut path, &border);
+            canvas.fill_path(&path, fill);
+            canvas.stroke_path(&path, border_color, border_thickness);
         }
         DrawElement::DrawEllipse {
             rect,
@@ -112,25 +195,22 @@ fn draw_entity_directive(
             let y = rect.topleft.y.evaluate(env)?.as_number()?.to_int() as f32;
             let width = rect.size.x.evaluate(env)?.as_number()?.to_int() as f32;
             let height = rect.size.y.evaluate(env)?.as_number()?.to_int() as f32;
-            let fill = Paint::color(color.evaluate(env)?);
-            let border_color_eval = border_color.evaluate(env)?;
-            let border_thickness = if border_color_eval.a == 0.0 {
+            let fill = color.evaluate(env)?;
+            let border_color = border_color.evaluate(env)?;
+            let border_thickness = if border_color.a == 0.0 {
                 0.0
             } else {
                 *border_thickness as f32
             };
-            let mut border = Paint::color(border_color_eval);
-            border.set_line_width(border_thickness);
-            border.set_anti_alias(false);
             let x = x + border_thickness;
             let y = y + border_thickness;
             let width = width - border_thickness * 2.0;
             let height = height - border_thickness * 2.0;
 
-            let mut path = Path::new();
+            let mut path = Shape::new();
             path.ellipse(x + width / 2.0, y + width / 2.0, width / 2.0, height / 2.0);
-            canvas.fill_path(&mut path, &fill);
-            canvas.stroke_path(&mut path, &border);
+            canvas.fill_path(&path, fill);
+            canvas.stroke_path(&path, border_color, border_thickness);
         }
         DrawElement::DrawLine {
             start,
@@ -143,11 +223,9 @@ fn draw_entity_directive(
             let y1 = start.y.evaluate(env)?.as_number()?.to_int() as f32;
             let x2 = end.x.evaluate(env)?.as_number()?.to_int() as f32;
             let y2 = end.y.evaluate(env)?.as_number()?.to_int() as f32;
-            let mut line = Paint::color(color.evaluate(env)?);
-            line.set_line_width(*thickness as f32);
-            line.set_anti_alias(false);
+            let color = color.evaluate(env)?;
 
-            let mut path = Path::new();
+            let mut path = Shape::new();
             path.move_to(x1, y1);
             path.line_to(x2, y2);
             if *arrowhead {
@@ -164,7 +242,7 @@ fn draw_entity_directive(
                 path.line_to(endpoint.x, endpoint.y);
                 path.line_to(tail2.x, tail2.y);
             }
-            canvas.stroke_path(&mut path, &line);
+            canvas.stroke_path(&path, color, *thickness as f32);
         }
         DrawElement::DrawCurve {
             start,
@@ -180,9 +258,7 @@ fn draw_entity_directive(
             // the control point for the quadratic bezier
             let xq = middle.x.evaluate(env)?.as_number()?.to_int() as f32;
             let yq = middle.y.evaluate(env)?.as_number()?.to_int() as f32;
-            let mut line = Paint::color(color.evaluate(env)?);
-            line.set_line_width(*thickness as f32);
-            line.set_anti_alias(false);
+            let color = color.evaluate(env)?;
 
             // the control points for the cubic bezier
             let x2 = (x1 + xq * 2.0) / 3.0;
@@ -190,10 +266,10 @@ fn draw_entity_directive(
             let x3 = (x4 + xq * 2.0) / 3.0;
             let y3 = (y4 + yq * 2.0) / 3.0;
 
-            let mut path = Path::new();
+            let mut path = Shape::new();
             path.move_to(x1, y1);
             path.bezier_to(x2, y2, x3, y3, x4, y4);
-            canvas.stroke_path(&mut path, &line);
+            canvas.stroke_path(&path, color, *thickness as f32);
         }
         DrawElement::DrawPointImage {
             texture,
@@ -225,6 +301,37 @@ fn draw_entity_directive(
                 rot,
             );
         }
+        DrawElement::DrawSprite {
+            sprite,
+            animation,
+            point,
+            scale,
+            color,
+            rot,
+        } => {
+            let sprite = sprite.evaluate(env)?;
+            let sprite = sprite.as_string()?;
+            if sprite.is_empty() {
+                return Ok(());
+            }
+            let animation = animation.evaluate(env)?;
+            let animation = animation.as_string()?;
+            let frame = palette.sprite_frame(&sprite, Some(&*animation))?;
+            let point = point.evaluate_float(env)?.to_point().cast_unit() + frame.offset;
+            let color = color.evaluate(env)?;
+            let scale = scale.evaluate_float(env)?.to_point().cast_unit();
+            let rot = rot.evaluate(env)?.as_number()?.to_float();
+            return palette.gameplay_atlas.draw_sprite(
+                canvas,
+                &frame.texture,
+                point,
+                None,
+                Some(frame.justify),
+                Some(scale),
+                Some(color),
+                rot,
+            );
+        }
         DrawElement::DrawRectImage {
             texture,
             bounds,
@@ -412,7 +519,7 @@ fn draw_entity_directive(
 
 fn draw_tiled(
     palette: &ModuleAggregate,
-    canvas: &mut Canvas,
+    canvas: &mut dyn Renderer,
     sprite: &str,
     bounds: &Rect<f32, UnknownUnit>,
     slice: &Rect<f32, UnknownUnit>,
@@ -468,19 +575,23 @@ where
 
 pub fn draw_decals(
     palette: &ModuleAggregate,
-    canvas: &mut Canvas,
+    canvas: &mut dyn Renderer,
     room: &CelesteMapLevel,
     fg: bool,
 ) {
     let decals = if fg { &room.fg_decals } else { &room.bg_decals };
+    // each layer's decals are drawn together, deepest first
+    let mut decals = decals.iter().collect::<Vec<_>>();
+    decals.sort_by_key(|decal| Reverse(decal.effective_depth(fg)));
     for decal in decals {
         draw_decal(palette, canvas, decal);
     }
 }
 
-pub fn draw_decal(palette: &ModuleAggregate, canvas: &mut Canvas, decal: &CelesteMapDecal) {
+pub fn draw_decal(palette: &ModuleAggregate, canvas: &mut dyn Renderer, decal: &CelesteMapDecal) {
     let texture = decal_texture(decal);
     let scale = Point2D::new(decal.scale_x, decal.scale_y);
+    let color = decal.color.as_deref().and_then(parse_color);
     if let Err(e) = palette.gameplay_atlas.draw_sprite(
         canvas,
         &texture,
@@ -488,8 +599,8 @@ pub fn draw_decal(palette: &ModuleAggregate, canvas: &mut Canvas, decal: &Celest
         None,
         None,
         Some(scale),
-        None,
-        0.0,
+        color,
+        decal.rotation,
     ) {
         log::warn!("Failed drawing decal: {}", e);
         palette
@@ -501,8 +612,8 @@ pub fn draw_decal(palette: &ModuleAggregate, canvas: &mut Canvas, decal: &Celest
                 None,
                 None,
                 Some(scale),
-                None,
-                0.0,
+                color,
+                decal.rotation,
             )
             .unwrap();
     }
@@ -514,7 +625,12 @@ pub fn decal_texture(decal: &CelesteMapDecal) -> String {
     path.to_str().unwrap().to_owned()
 }
 
-pub fn draw_tiles(palette: &ModuleAggregate, canvas: &mut Canvas, room: &LevelState, fg: bool) {
+pub fn draw_tiles(
+    palette: &ModuleAggregate,
+    canvas: &mut dyn Renderer,
+    room: &LevelState,
+    fg: bool,
+) {
     let (tiles, tiles_asset) = if fg {
         (&room.data.solids, palette.autotilers.get("fg").unwrap())
     } else {
@@ -577,7 +693,7 @@ pub fn draw_tiles(palette: &ModuleAggregate, canvas: &mut Canvas, room: &LevelSt
 
 pub fn draw_entities(
     palette: &ModuleAggregate,
-    canvas: &mut Canvas,
+    canvas: &mut dyn Renderer,
     room: &CelesteMapLevel,
     selection: &HashSet<AppSelection>,
 ) {
@@ -600,7 +716,7 @@ pub fn draw_entities(
 
 pub fn draw_triggers(
     palette: &ModuleAggregate,
-    canvas: &mut Canvas,
+    canvas: &mut dyn Renderer,
     room: &CelesteMapLevel,
     selection: &HashSet<AppSelection>,
 ) {
@@ -622,7 +738,7 @@ pub fn draw_triggers(
 
 pub fn draw_stylegrounds(
     palette: &ModuleAggregate,
-    canvas: &mut Canvas,
+    canvas: &mut dyn Renderer,
     preview: MapPointStrict,
     styles: &[CelesteMapStyleground],
     current_room: &str,
@@ -695,19 +811,27 @@ pub fn draw_stylegrounds(
     }
 }
 
-fn parse_color(color: &str) -> Option<Color> {
+pub fn parse_color(color: &str) -> Option<Color> {
     let trimmed = color.trim_start_matches('#');
-    if trimmed.len() == 6 {
-        let r = u8::from_str_radix(&trimmed[0..2], 16).ok()?;
-        let g = u8::from_str_radix(&trimmed[2..4], 16).ok()?;
-        let b = u8::from_str_radix(&trimmed[4..6], 16).ok()?;
-        Some(Color::rgb(r, g, b))
+    if trimmed.len() == 6 || trimmed.len() == 8 {
+        let r = u8::from_str_radix(trimmed.get(0..2)?, 16).ok()?;
+        let g = u8::from_str_radix(trimmed.get(2..4)?, 16).ok()?;
+        let b = u8::from_str_radix(trimmed.get(4..6)?, 16).ok()?;
+        let a = match trimmed.get(6..8) {
+            Some(a) => u8::from_str_radix(a, 16).ok()?,
+            None => 255,
+        };
+        Some(Color::rgba(r, g, b, a))
     } else {
         None
     }
 }
 
-pub fn draw_objtiles_float(palette: &ModuleAggregate, canvas: &mut Canvas, room: &LevelState) {
+pub fn draw_objtiles_float(
+    palette: &ModuleAggregate,
+    canvas: &mut dyn Renderer,
+    room: &LevelState,
+) {
     let Some((float_pos, float_dat)) = &room.floats.obj else { return };
     let rect = TileRect::new(*float_pos, float_dat.size());
     for pt in rect_point_iter(rect, 1) {
@@ -0,0 +1,161 @@
+//! Turning a room's tiles into an image and back, one pixel per tile, so that layouts can be
+//! sketched in pixel art tools.
+
+use arborio_maploader::map_struct::CelesteMapLevel;
+use arborio_utils::units::*;
+use arborio_utils::vizia::prelude::Data;
+use image::{ImageFormat, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::rendering::parse_color;
+
+/// Which tile a color in an image stands for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TileColor {
+    /// A hex color such as `ffffff`.
+    pub color: String,
+    pub tile: char,
+}
+
+impl Data for TileColor {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+pub fn default_tile_colors() -> Vec<TileColor> {
+    [("ffffff", '0'), ("000000", '1')]
+        .into_iter()
+        .map(|(color, tile)| TileColor {
+            color: color.to_owned(),
+            tile,
+        })
+        .collect()
+}
+
+fn rgb(entry: &TileColor) -> Option<[u8; 3]> {
+    let color = parse_color(&entry.color)?;
+    Some([color.r, color.g, color.b].map(|c| (c * 255.0).round() as u8))
+}
+
+/// Each opaque pixel becomes the tile whose color is closest to it. Transparent pixels become
+/// `'\0'`, which tile updates leave alone.
+pub fn image_to_tiles(image: &RgbaImage, colors: &[TileColor]) -> Result<TileGrid<char>, String> {
+    let colors = colors
+        .iter()
+        .map(|entry| {
+            rgb(entry)
+                .map(|rgb| (rgb, entry.tile))
+                .ok_or_else(|| format!("{} is not a color", entry.color))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if colors.is_empty() {
+        return Err("No tile colors are set up".to_owned());
+    }
+    if image.width() == 0 || image.height() == 0 {
+        return Err("Image is empty".to_owned());
+    }
+
+    let distance = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+    let mut tiles = TileGrid::new(
+        TileSize::new(image.width() as i32, image.height() as i32),
+        '\0',
+    );
+    for (x, y, &Rgba([r, g, b, a])) in image.enumerate_pixels() {
+        if a < 128 {
+            continue;
+        }
+        let (_, tile) = colors
+            .iter()
+            .min_by_key(|([cr, cg, cb], _)| distance(r, *cr) + distance(g, *cg) + distance(b, *cb))
+            .unwrap();
+        *tiles.get_mut(TilePoint::new(x as i32, y as i32)).unwrap() = *tile;
+    }
+    Ok(tiles)
+}
+
+/// Tiles without a color, including air unless it has one, are left transparent.
+pub fn tiles_to_image(tiles: &TileGrid<char>, colors: &[TileColor]) -> RgbaImage {
+    let size = tiles.size();
+    let mut image = RgbaImage::new(size.width as u32, size.height as u32);
+    for (pixel, tile) in image.pixels_mut().zip(&tiles.tiles) {
+        if let Some([r, g, b]) = colors
+            .iter()
+            .filter(|entry| entry.tile == *tile)
+            .find_map(rgb)
+        {
+            *pixel = Rgba([r, g, b, 255]);
+        }
+    }
+    image
+}
+
+pub fn load_tile_image(path: &Path, colors: &[TileColor]) -> Result<TileGrid<char>, String> {
+    let image = image::open(path).map_err(|e| format!("Could not open {}: {e}", path.display()))?;
+    image_to_tiles(&image.to_rgba8(), colors)
+}
+
+pub fn save_tile_image(
+    path: &Path,
+    tiles: &TileGrid<char>,
+    colors: &[TileColor],
+) -> Result<(), String> {
+    tiles_to_image(tiles, colors)
+        .save_with_format(path, ImageFormat::Png)
+        .map_err(|e| format!("Could not write {}: {e}", path.display()))
+}
+
+/// A new room exactly the size of `tiles`, with them as its foreground. Tiles which were left
+/// transparent become air.
+pub fn room_from_tiles(tiles: &TileGrid<char>, origin: MapPointStrict) -> CelesteMapLevel {
+    let size = tiles.size();
+    CelesteMapLevel {
+        bounds: MapRectStrict::new(origin, size_tile_to_room(&size).cast_unit()),
+        solids: TileGrid {
+            tiles: tiles
+                .tiles
+                .iter()
+                .map(|&tile| if tile == '\0' { '0' } else { tile })
+                .collect(),
+            stride: tiles.stride,
+        },
+        bg: TileGrid::new(size, '0'),
+        object_tiles: TileGrid::new(size, -1),
+        fg_tiles: TileGrid::new(size, -1),
+        bg_tiles: TileGrid::new(size, -1),
+        ..CelesteMapLevel::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tiles_survive_a_round_trip() {
+        let mut colors = default_tile_colors();
+        colors.push(TileColor {
+            color: "ff0000".to_owned(),
+            tile: '3',
+        });
+        let tiles = TileGrid {
+            tiles: vec!['0', '1', '3', '1', '3', '0'],
+            stride: 3,
+        };
+
+        let image = tiles_to_image(&tiles, &colors);
+        assert_eq!(image.dimensions(), (3, 2));
+        assert_eq!(image.get_pixel(2, 0), &Rgba([255, 0, 0, 255]));
+        assert_eq!(image_to_tiles(&image, &colors).unwrap().tiles, tiles.tiles);
+
+        // near misses snap to the closest color, and transparent pixels are left alone
+        let mut image = image;
+        image.put_pixel(0, 0, Rgba([230, 20, 10, 255]));
+        image.put_pixel(1, 0, Rgba([0, 0, 0, 0]));
+        assert_eq!(
+            image_to_tiles(&image, &colors).unwrap().tiles,
+            ['3', '\0', '3', '1', '3', '0']
+        );
+    }
+}
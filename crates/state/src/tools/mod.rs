@@ -9,6 +9,7 @@ use arborio_utils::vizia::prelude::*;
 use enum_iterator::Sequence;
 
 use crate::data::app::{AppEvent, AppInternalEvent, AppState};
+use crate::data::selection::AppSelectable;
 use arborio_utils::units::*;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Sequence, Data)]
@@ -58,6 +59,17 @@ pub trait Tool {
 
     fn draw(&mut self, canvas: &mut Canvas, state: &AppState, cx: &DrawContext) {}
 
+    /// The current selection as it would be copied, along with any events needed to get it. None
+    /// if nothing which can be copied is selected.
+    fn copy(&mut self, app: &AppState) -> Option<(AppSelectable, Vec<AppEvent>)> {
+        None
+    }
+
+    /// Place copies of some objects which were copied earlier, if this tool deals in them.
+    fn paste(&mut self, app: &AppState, data: AppSelectable) -> Vec<AppEvent> {
+        vec![]
+    }
+
     fn cursor(&self, cx: &mut EventContext) -> CursorIcon {
         CursorIcon::Default
     }
@@ -13,12 +13,38 @@ use arborio_maploader::map_struct::CelesteMapLevel;
 use arborio_utils::units::*;
 
 pub struct RoomTool {
-    pending_selection: HashSet<usize>,
-    current_selection: HashSet<usize>,
+    pending_selection: HashSet<Region>,
+    current_selection: HashSet<Region>,
     status: SelectionStatus,
     draw_phase: EventPhase,
 }
 
+/// Something the room tool can select: a room or a filler rect, by index.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+enum Region {
+    Room(usize),
+    Filler(usize),
+}
+
+impl Region {
+    fn bounds(&self, map: &MapState) -> Option<MapRectStrict> {
+        match *self {
+            Region::Room(idx) => map.data.levels.get(idx).map(|room| room.data.bounds),
+            Region::Filler(idx) => map.data.filler.get(idx).copied(),
+        }
+    }
+
+    fn move_action(&self, bounds: MapRectStrict) -> MapAction {
+        match *self {
+            Region::Room(idx) => MapAction::RoomAction {
+                idx,
+                event: RoomAction::MoveRoom { bounds },
+            },
+            Region::Filler(idx) => MapAction::MoveFiller { idx, rect: bounds },
+        }
+    }
+}
+
 #[derive(Eq, PartialEq, Debug)]
 enum SelectionStatus {
     None,
@@ -31,20 +57,20 @@ enum SelectionStatus {
 #[derive(Eq, PartialEq, Debug)]
 struct DraggingStatus {
     pointer_reference_point: MapPointStrict,
-    selection_reference_points: HashMap<usize, MapPointStrict>,
+    selection_reference_points: HashMap<Region, MapPointStrict>,
 }
 
 #[derive(Eq, PartialEq, Debug)]
 struct ResizingStatus {
     pointer_reference_point: MapPointStrict,
-    selection_reference_sizes: HashMap<usize, MapRectStrict>,
+    selection_reference_sizes: HashMap<Region, MapRectStrict>,
     side: ResizeSide,
 }
 
 impl RoomTool {
     pub fn new(app: &AppState) -> Self {
         RoomTool {
-            current_selection: HashSet::from([app.map_tab_unwrap().current_room]),
+            current_selection: HashSet::from([Region::Room(app.map_tab_unwrap().current_room)]),
             pending_selection: HashSet::new(),
             status: SelectionStatus::None,
             draw_phase: EventPhase::null(),
@@ -94,7 +120,7 @@ impl Tool for RoomTool {
             WindowEvent::MouseDown(MouseButton::Left) => {
                 self.draw_phase = EventPhase::new();
                 if self.status == SelectionStatus::None {
-                    let got = room_at(map, map_pos_unsnapped);
+                    let got = region_at(map, map_pos_unsnapped);
                     if got.is_some() && self.current_selection.contains(&got.unwrap()) {
                         self.status =
                             SelectionStatus::CouldStartDragging(map_pos, map_pos_unsnapped);
@@ -123,7 +149,7 @@ impl Tool for RoomTool {
                     SelectionStatus::None => vec![],
                     SelectionStatus::CouldStartDragging(_, _) => unreachable!(),
                     SelectionStatus::Selecting(ref_pos) => {
-                        self.pending_selection = rooms_in(
+                        self.pending_selection = regions_in(
                             map,
                             MapRectStrict::new(ref_pos, (map_pos - ref_pos).to_size()),
                         );
@@ -139,21 +165,33 @@ impl Tool for RoomTool {
             WindowEvent::MouseDown(MouseButton::Right) => {
                 self.draw_phase = EventPhase::new();
                 if self.status == SelectionStatus::None {
-                    let mut result = CelesteMapLevel::default();
-                    result.bounds.origin = map_pos;
-                    self.current_selection = HashSet::from([map.data.levels.len()]);
+                    // shift-right-click makes filler instead of a room
+                    let (region, action) = if cx.modifiers.contains(Modifiers::SHIFT) {
+                        (
+                            Region::Filler(map.data.filler.len()),
+                            MapAction::AddFiller {
+                                idx: None,
+                                rect: MapRectStrict::new(map_pos, MapSizeStrict::new(64, 64)),
+                            },
+                        )
+                    } else {
+                        let mut result = CelesteMapLevel::default();
+                        result.bounds.origin = map_pos;
+                        (
+                            Region::Room(map.data.levels.len()),
+                            MapAction::AddRoom {
+                                idx: None,
+                                room: Box::new(result),
+                            },
+                        )
+                    };
+                    self.current_selection = HashSet::from([region]);
                     self.status = SelectionStatus::Dragging(DraggingStatus {
                         pointer_reference_point: map_pos,
-                        selection_reference_points: HashMap::from([(
-                            map.data.levels.len(),
-                            map_pos,
-                        )]),
+                        selection_reference_points: HashMap::from([(region, map_pos)]),
                     });
                     let mut events = self.notify_selection(app);
-                    events.push(app.map_action_unique(vec![MapAction::AddRoom {
-                        idx: None,
-                        room: Box::new(result),
-                    }]));
+                    events.push(app.map_action_unique(vec![action]));
                     events
                 } else {
                     vec![]
@@ -168,7 +206,7 @@ impl Tool for RoomTool {
                         Code::ArrowRight => self.nudge(app, map, MapVectorStrict::new(8, 0)),
                         Code::ArrowLeft => self.nudge(app, map, MapVectorStrict::new(-8, 0)),
                         Code::KeyA if cx.modifiers == &Modifiers::CTRL => {
-                            self.current_selection = rooms_in(
+                            self.current_selection = regions_in(
                                 map,
                                 MapRectStrict::new(
                                     MapPointStrict::new(-1000000, -1000000),
@@ -204,13 +242,22 @@ impl Tool for RoomTool {
         }
     }
 
+    fn copy(&mut self, app: &AppState) -> Option<(AppSelectable, Vec<AppEvent>)> {
+        let selected = self.copy_selection(app, app.map_tab_unwrap().id)?;
+        Some((selected, vec![]))
+    }
+
+    fn paste(&mut self, app: &AppState, data: AppSelectable) -> Vec<AppEvent> {
+        self.paste_regions(app, data)
+    }
+
     fn internal_event(
         &mut self,
         event: &AppInternalEvent,
         _cx: &mut EventContext,
     ) -> Vec<AppEvent> {
         if let AppInternalEvent::SelectMeRoom { idx } = event {
-            self.current_selection.insert(*idx);
+            self.current_selection.insert(Region::Room(*idx));
         }
         vec![]
     }
@@ -248,13 +295,12 @@ impl Tool for RoomTool {
         }
 
         let mut path = Path::new();
-        for room in self
+        for region in self
             .pending_selection
             .iter()
             .chain(self.current_selection.iter())
         {
-            if let Some(room) = map.data.levels.get(*room) {
-                let rect = &room.data.bounds;
+            if let Some(rect) = region.bounds(map) {
                 path.rect(
                     rect.min_x() as f32,
                     rect.min_y() as f32,
@@ -267,11 +313,10 @@ impl Tool for RoomTool {
         canvas.fill_path(&mut path, &Paint::color(Color::rgba(255, 255, 0, 128)));
 
         if self.status == SelectionStatus::None {
-            if let Some(room) = room_at(map, map_pos_unsnapped) {
-                if !self.current_selection.contains(&room) {
+            if let Some(region) = region_at(map, map_pos_unsnapped) {
+                if !self.current_selection.contains(&region) {
                     let mut path = Path::new();
-                    if let Some(room) = map.data.levels.get(room) {
-                        let rect = &room.data.bounds;
+                    if let Some(rect) = region.bounds(map) {
                         path.rect(
                             rect.min_x() as f32,
                             rect.min_y() as f32,
@@ -294,7 +339,8 @@ impl Tool for RoomTool {
                 if let MapAction::RoomAction {
                     event: RoomAction::MoveRoom { bounds, .. },
                     ..
-                } = fake_event
+                }
+                | MapAction::MoveFiller { rect: bounds, .. } = fake_event
                 {
                     path.rect(
                         bounds.min_x() as f32,
@@ -345,13 +391,14 @@ impl RoomTool {
 
     fn notify_selection(&self, app: &AppState) -> Vec<AppEvent> {
         if self.current_selection.len() == 1 {
-            vec![AppEvent::SelectRoom {
-                tab: app.current_tab,
-                idx: *self.current_selection.iter().next().unwrap(),
-            }]
-        } else {
-            vec![]
+            if let Some(Region::Room(idx)) = self.current_selection.iter().next() {
+                return vec![AppEvent::SelectRoom {
+                    tab: app.current_tab,
+                    idx: *idx,
+                }];
+            }
         }
+        vec![]
     }
 
     fn confirm_selection(&mut self, app: &AppState) -> Vec<AppEvent> {
@@ -370,19 +417,12 @@ impl RoomTool {
 
         let mut events = vec![];
 
-        for room in self.current_selection.iter() {
+        for region in self.current_selection.iter() {
+            let Some(bounds) = region.bounds(map) else { continue };
             let base = dragging
-                .map(|d| d.selection_reference_points[room])
-                .unwrap_or_else(|| map.data.levels[*room].data.bounds.origin);
-            events.push(MapAction::RoomAction {
-                event: RoomAction::MoveRoom {
-                    bounds: MapRectStrict::new(
-                        base + nudge,
-                        map.data.levels[*room].data.bounds.size,
-                    ),
-                },
-                idx: *room,
-            });
+                .map(|d| d.selection_reference_points[region])
+                .unwrap_or(bounds.origin);
+            events.push(region.move_action(MapRectStrict::new(base + nudge, bounds.size)));
         }
 
         vec![app.batch_action(events, self.draw_phase)]
@@ -422,32 +462,28 @@ impl RoomTool {
 
         let mut events = vec![];
 
-        for room in self.current_selection.iter() {
+        for region in self.current_selection.iter() {
+            let Some(bounds) = region.bounds(map) else { continue };
             let start_rect = dragging
-                .map(|d| d.selection_reference_sizes[room])
-                .unwrap_or_else(|| map.data.levels[*room].data.bounds);
+                .map(|d| d.selection_reference_sizes[region])
+                .unwrap_or(bounds);
             let mut new_rect = MapRectStrict::new(
                 start_rect.origin + pos_vec,
                 start_rect.size + size_vec.to_size(),
             );
             new_rect.size.width = new_rect.size.width.max(8);
             new_rect.size.height = new_rect.size.height.max(8);
-            events.push(MapAction::RoomAction {
-                event: RoomAction::MoveRoom { bounds: new_rect },
-                idx: *room,
-            });
+            events.push(region.move_action(new_rect));
         }
 
         events
     }
 
     fn begin_dragging(&mut self, map: &MapState, pt: MapPointStrict, pt_unsnapped: MapPointStrict) {
-        let selection_reference_sizes = self.current_selection.iter().filter_map(|idx| {
-            map.data
-                .levels
-                .get(*idx)
-                .map(|room| (*idx, room.data.bounds))
-        });
+        let selection_reference_sizes = self
+            .current_selection
+            .iter()
+            .filter_map(|region| Some((*region, region.bounds(map)?)));
         self.status = match self.can_resize(map, pt_unsnapped) {
             ResizeSide::None => SelectionStatus::Dragging(DraggingStatus {
                 pointer_reference_point: pt,
@@ -467,9 +503,7 @@ impl RoomTool {
         let Some(rect) = self
             .current_selection
             .iter()
-            .filter_map(|idx| {
-                Some(map.data.levels.get(*idx)?.data.bounds)
-            })
+            .filter_map(|region| region.bounds(map))
             .find(|rect| rect.contains(pointer))
             else { return ResizeSide::None };
 
@@ -482,41 +516,74 @@ impl RoomTool {
         ResizeSide::from_sides(at_top, at_bottom, at_left, at_right)
     }
 
-    fn delete_all(&self, app: &AppState) -> Vec<AppEvent> {
-        let phase = EventPhase::new();
-        self.current_selection
-            .iter()
-            .map(|&idx| app.map_action(vec![MapAction::DeleteRoom { idx }], phase))
-            .collect()
+    fn delete_all(&mut self, app: &AppState) -> Vec<AppEvent> {
+        if self.current_selection.is_empty() {
+            return vec![];
+        }
+        // delete from the back so the indices of the rest stay valid
+        let mut selection = self.current_selection.drain().collect::<Vec<_>>();
+        selection.sort_unstable_by(|a, b| b.cmp(a));
+        let events = selection
+            .into_iter()
+            .map(|region| match region {
+                Region::Room(idx) => MapAction::DeleteRoom { idx },
+                Region::Filler(idx) => MapAction::DeleteFiller { idx },
+            })
+            .collect();
+        vec![app.map_action_unique(events)]
     }
 
     fn clipboard_copy(&self, app: &AppState, mapid: MapID) -> Vec<AppEvent> {
+        let Some(selected) = self.copy_selection(app, mapid) else { return vec![] };
+        vec![AppEvent::SetClipboard {
+            contents: serde_yaml::to_string(&selected).unwrap(),
+        }]
+    }
+
+    fn copy_selection(&self, app: &AppState, mapid: MapID) -> Option<AppSelectable> {
         if self.current_selection.is_empty() {
-            return vec![];
+            return None;
         }
         let map = app.loaded_maps.get(&mapid).unwrap();
-        vec![AppEvent::SetClipboard {
-            contents: serde_yaml::to_string(&AppSelectable::Rooms(
-                self.current_selection
-                    .iter()
-                    .map(|roomid| map.data.levels.get(*roomid).unwrap().data.clone())
-                    .collect(),
-            ))
-            .unwrap(),
-        }]
+        let mut rooms = vec![];
+        let mut filler = vec![];
+        for region in self.current_selection.iter() {
+            match *region {
+                Region::Room(idx) => rooms.push(map.data.levels.get(idx).unwrap().data.clone()),
+                Region::Filler(idx) => filler.push(*map.data.filler.get(idx).unwrap()),
+            }
+        }
+        Some(AppSelectable::RoomsAndFiller(rooms, filler))
     }
 
     fn clipboard_paste(&mut self, app: &AppState, data: String) -> Vec<AppEvent> {
+        match serde_yaml::from_str(&data) {
+            Ok(selected) => self.paste_regions(app, selected),
+            Err(_) => self.clear_selection(app),
+        }
+    }
+
+    /// Add copies of the rooms and filler in `selected` near the top left of the view. Rooms whose
+    /// names are taken are renamed.
+    fn paste_regions(&mut self, app: &AppState, selected: AppSelectable) -> Vec<AppEvent> {
         let mut result = self.clear_selection(app);
-        let Ok(AppSelectable::Rooms(clipboard_data)) = serde_yaml::from_str(&data) else { return result };
-        if clipboard_data.is_empty() {
+        let (clipboard_rooms, clipboard_filler) = match selected {
+            AppSelectable::Rooms(rooms) => (rooms, vec![]),
+            AppSelectable::RoomsAndFiller(rooms, filler) => (rooms, filler),
+            AppSelectable::InRoom(_) => return result,
+        };
+        if clipboard_rooms.is_empty() && clipboard_filler.is_empty() {
             return result;
         }
         let mut min_pt = MapPointStrict::new(i32::MAX, i32::MAX);
         let mut max_pt = MapPointStrict::new(i32::MIN, i32::MIN);
-        for room in clipboard_data.iter() {
-            min_pt = min_pt.min(room.bounds.min());
-            max_pt = max_pt.max(room.bounds.max());
+        for rect in clipboard_rooms
+            .iter()
+            .map(|room| &room.bounds)
+            .chain(clipboard_filler.iter())
+        {
+            min_pt = min_pt.min(rect.min());
+            max_pt = max_pt.max(rect.max());
         }
         let center = ((min_pt.to_vector() + max_pt.to_vector()) / 2).to_point();
         let real_center = point_lose_precision(
@@ -529,34 +596,47 @@ impl RoomTool {
         let real_center =
             point_tile_to_room(&point_room_to_tile(&real_center.cast_unit())).cast_unit();
         let offset = real_center - center;
-        result.push(
-            app.batch_action_unique(clipboard_data.into_iter().map(|mut room| {
-                MapAction::AddRoom {
-                    idx: None,
-                    room: Box::new({
-                        room.bounds.origin += offset;
-                        room
-                    }),
-                }
-            })),
-        );
+        let rooms = clipboard_rooms
+            .into_iter()
+            .map(|mut room| MapAction::AddRoom {
+                idx: None,
+                room: Box::new({
+                    room.bounds.origin += offset;
+                    room
+                }),
+            });
+        let filler = clipboard_filler
+            .into_iter()
+            .map(|rect| MapAction::AddFiller {
+                idx: None,
+                rect: rect.translate(offset),
+            });
+        result.push(app.batch_action_unique(rooms.chain(filler)));
         result
     }
 }
 
-fn room_at(map: &MapState, pos: MapPointStrict) -> Option<usize> {
-    rooms_in(map, MapRectStrict::new(pos, MapSizeStrict::new(1, 1)))
+/// The room at `pos`, or if there isn't one, the filler rect there.
+fn region_at(map: &MapState, pos: MapPointStrict) -> Option<Region> {
+    let regions = regions_in(map, MapRectStrict::new(pos, MapSizeStrict::new(1, 1)));
+    regions
         .iter()
-        .next()
+        .find(|region| matches!(region, Region::Room(_)))
+        .or_else(|| regions.iter().next())
         .cloned()
 }
 
-fn rooms_in(map: &MapState, rect: MapRectStrict) -> HashSet<usize> {
+fn regions_in(map: &MapState, rect: MapRectStrict) -> HashSet<Region> {
     let rect = rect_normalize(&rect);
     let mut result = HashSet::new();
     for (idx, room) in map.data.levels.iter().enumerate() {
         if room.data.bounds.intersects(&rect) {
-            result.insert(idx);
+            result.insert(Region::Room(idx));
+        }
+    }
+    for (idx, filler) in map.data.filler.iter().enumerate() {
+        if filler.intersects(&rect) {
+            result.insert(Region::Filler(idx));
         }
     }
     result
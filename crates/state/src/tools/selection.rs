@@ -1,6 +1,7 @@
 use std::collections::{HashMap, HashSet};
 
-use arborio_maploader::map_struct::Node;
+use arborio_maploader::ids::assign_new_ids;
+use arborio_maploader::map_struct::{CelesteMapDecal, CelesteMapLevel, Node};
 use arborio_modloader::mapstruct_plus_config::{make_entity_env, make_node_env};
 use arborio_utils::units::*;
 use arborio_utils::vizia::prelude::*;
@@ -12,9 +13,15 @@ use crate::data::project_map::{LevelFloatState, LevelState};
 use crate::data::selection::{AppInRoomSelectable, AppSelectable, AppSelection};
 use crate::data::tabs::MapTab;
 use crate::data::{EventPhase, Layer};
-use crate::rendering::decal_texture;
+use crate::rendering::{decal_texture, parse_color};
 use crate::tools::{generic_nav, Tool};
 
+/// How far past the edge of a decal its rotation handle is.
+const ROTATE_HANDLE_DISTANCE: f32 = 8.0;
+const ROTATE_HANDLE_RADIUS: f32 = 3.0;
+/// The step rotations snap to, in degrees.
+const ROTATE_SNAP: f32 = 15.0;
+
 pub struct SelectionTool {
     pending_selection: HashSet<AppSelection>,
 
@@ -22,13 +29,14 @@ pub struct SelectionTool {
     draw_phase: EventPhase,
 }
 
-#[derive(Eq, PartialEq, Debug)]
+#[derive(PartialEq, Debug)]
 enum SelectionStatus {
     None,
     Selecting(RoomPoint),
     CouldStartDragging(RoomPoint, RoomPoint),
     Dragging(DraggingStatus),
     Resizing(ResizingStatus),
+    Rotating(RotatingStatus),
 }
 
 #[derive(Eq, PartialEq, Debug)]
@@ -44,6 +52,14 @@ struct ResizingStatus {
     side: ResizeSide,
 }
 
+#[derive(PartialEq, Debug)]
+struct RotatingStatus {
+    /// The center of the decal whose handle was grabbed.
+    center: Point2D<f32, RoomSpace>,
+    pointer_reference_angle: f32,
+    selection_reference_angles: HashMap<AppSelection, f32>,
+}
+
 #[derive(Eq, PartialEq, Debug, Copy, Clone)]
 pub enum ResizeSide {
     None,
@@ -189,6 +205,8 @@ impl Tool for SelectionTool {
             .transform_point(screen_pos);
         let map_pos = point_lose_precision(&map_pos_precise);
         let room_pos_unsnapped = (map_pos - room.data.bounds.origin).to_point().cast_unit();
+        let room_pos_precise =
+            (map_pos_precise - room.data.bounds.origin.cast::<f32>().to_vector()).cast_unit();
         let room_pos = if app.config.snap {
             let tile_pos = point_room_to_tile(&room_pos_unsnapped);
             point_tile_to_room(&tile_pos)
@@ -208,6 +226,11 @@ impl Tool for SelectionTool {
             }
             WindowEvent::MouseDown(MouseButton::Left) => {
                 if self.status == SelectionStatus::None {
+                    if let Some(rotating) = self.begin_rotating(app, room, room_pos_precise) {
+                        self.draw_phase = EventPhase::new();
+                        self.status = SelectionStatus::Rotating(rotating);
+                        return vec![];
+                    }
                     let got = self.selectable_at(app, room, app.current_layer, room_pos_unsnapped);
                     if matches!(got, Some(got) if app.map_tab_unwrap().current_selected.contains(&got)) {
                         self.draw_phase = EventPhase::new();
@@ -263,6 +286,7 @@ impl Tool for SelectionTool {
                         pointer_reference_point,
                         ..
                     }) => self.resize(app, room, room_pos - pointer_reference_point),
+                    SelectionStatus::Rotating(_) => self.rotate(app, room, room_pos_precise),
                 });
 
                 events
@@ -312,6 +336,10 @@ impl Tool for SelectionTool {
                                 AppEventStaging::default()
                             }
                         }
+                        Code::KeyR if cx.modifiers.contains(Modifiers::SHIFT) => {
+                            self.rotate_by(app, room, -90.0)
+                        }
+                        Code::KeyR => self.rotate_by(app, room, 90.0),
                         Code::Backspace | Code::Delete => self.delete_all(app, room),
                         _ => AppEventStaging::default(),
                     };
@@ -356,6 +384,26 @@ impl Tool for SelectionTool {
             .finalize(app, self.draw_phase)
     }
 
+    fn copy(&mut self, app: &AppState) -> Option<(AppSelectable, Vec<AppEvent>)> {
+        let room = app.current_room_ref()?;
+        let (result, data) = self.copy_selection(app, room);
+        if data.is_empty() {
+            return None;
+        }
+        Some((
+            AppSelectable::InRoom(data),
+            result.finalize(app, self.draw_phase),
+        ))
+    }
+
+    fn paste(&mut self, app: &AppState, data: AppSelectable) -> Vec<AppEvent> {
+        let AppSelectable::InRoom(data) = data else { return vec![] };
+        if app.current_room_ref().is_none() {
+            return vec![];
+        }
+        self.paste_objects(app, data).finalize_unique(app)
+    }
+
     fn draw(&mut self, canvas: &mut Canvas, state: &AppState, cx: &DrawContext) {
         let Some(room) = state.current_room_ref() else { return };
         canvas.save();
@@ -419,6 +467,32 @@ impl Tool for SelectionTool {
             &vg::Paint::color(vg::Color::rgba(255, 255, 0, 128)),
         );
 
+        // the rotation handles are filled with the decal's tint
+        for selectable in state.map_tab_unwrap().current_selected.iter() {
+            let AppSelection::Decal(id, fg) = selectable else { continue };
+            let Some(decal) = room.decal(*id, *fg) else { continue };
+            let (center, handle) = rotate_handle(state, decal);
+            let mut path = vg::Path::new();
+            path.move_to(center.x, center.y);
+            path.line_to(handle.x, handle.y);
+            canvas.stroke_path(
+                &mut path,
+                &vg::Paint::color(vg::Color::rgb(0, 0, 0)).with_line_width(1.0),
+            );
+            let mut path = vg::Path::new();
+            path.circle(handle.x, handle.y, ROTATE_HANDLE_RADIUS);
+            let tint = decal
+                .color
+                .as_deref()
+                .and_then(parse_color)
+                .unwrap_or_else(vg::Color::white);
+            canvas.fill_path(&mut path, &vg::Paint::color(tint));
+            canvas.stroke_path(
+                &mut path,
+                &vg::Paint::color(vg::Color::rgb(0, 0, 0)).with_line_width(1.0),
+            );
+        }
+
         if self.status == SelectionStatus::None {
             if let Some(sel) = self.selectable_at(state, room, state.current_layer, room_pos) {
                 if !state.map_tab_unwrap().current_selected.contains(&sel) {
@@ -455,16 +529,24 @@ impl Tool for SelectionTool {
             .cast();
         let map_pos = point_lose_precision(&map_pos_precise);
         let room_pos = (map_pos - room.data.bounds.origin).to_point().cast_unit();
+        let room_pos_precise =
+            (map_pos_precise - room.data.bounds.origin.cast::<f32>().to_vector()).cast_unit();
         // let tile_pos = point_room_to_tile(&room_pos);
         // let room_pos_snapped = point_tile_to_room(&tile_pos);
         // let room_pos = if state.snap { room_pos_snapped } else { room_pos };
 
         match &self.status {
+            SelectionStatus::None
+                if self.rotate_handle_at(app, room, room_pos_precise).is_some() =>
+            {
+                CursorIcon::Grab
+            }
             SelectionStatus::CouldStartDragging(_, _) | SelectionStatus::None => {
                 self.can_resize(app, room, room_pos).to_cursor_icon()
             }
             SelectionStatus::Dragging(_) | SelectionStatus::Selecting(_) => CursorIcon::Default,
             SelectionStatus::Resizing(info) => info.side.to_cursor_icon(),
+            SelectionStatus::Rotating(_) => CursorIcon::Grabbing,
         }
     }
 }
@@ -549,19 +631,13 @@ impl SelectionTool {
             }
             AppSelection::Decal(id, fg) => {
                 if let Some(decal) = room.decal(id, fg) {
-                    let dim = app
-                        .current_palette_unwrap()
-                        .gameplay_atlas
-                        .sprite_dimensions(&decal_texture(decal))
-                        .unwrap_or(Size2D::new(16, 16));
-                    let size = dim
-                        .cast()
-                        .cast_unit()
-                        .to_vector()
-                        .component_mul(Vector2D::new(decal.scale_x, decal.scale_y))
-                        .cast()
-                        .to_size()
-                        .abs();
+                    // the bounding box of the rotated decal
+                    let size = decal_size(app, decal);
+                    let (sin, cos) = decal.rotation.to_radians().sin_cos();
+                    let size = RoomSize::new(
+                        ((size.x * cos).abs() + (size.y * sin).abs()) as i32,
+                        ((size.x * sin).abs() + (size.y * cos).abs()) as i32,
+                    );
                     vec![Rect::new(RoomPoint::new(decal.x, decal.y) - size / 2, size)]
                 } else {
                     vec![]
@@ -1210,6 +1286,98 @@ impl SelectionTool {
         (result, result_floats)
     }
 
+    fn rotate_handle_at(
+        &self,
+        app: &AppState,
+        room: &LevelState,
+        pointer: Point2D<f32, RoomSpace>,
+    ) -> Option<AppSelection> {
+        app.map_tab_unwrap()
+            .current_selected
+            .iter()
+            .find(|sel| {
+                let AppSelection::Decal(id, fg) = sel else { return false };
+                let Some(decal) = room.decal(*id, *fg) else { return false };
+                let (_, handle) = rotate_handle(app, decal);
+                (handle - pointer).length() <= ROTATE_HANDLE_RADIUS + 1.0
+            })
+            .copied()
+    }
+
+    fn begin_rotating(
+        &self,
+        app: &AppState,
+        room: &LevelState,
+        pointer: Point2D<f32, RoomSpace>,
+    ) -> Option<RotatingStatus> {
+        let AppSelection::Decal(id, fg) = self.rotate_handle_at(app, room, pointer)? else {
+            return None;
+        };
+        let decal = room.decal(id, fg)?;
+        let center: Point2D<f32, RoomSpace> = Point2D::new(decal.x, decal.y).cast();
+        let selection_reference_angles = app
+            .map_tab_unwrap()
+            .current_selected
+            .iter()
+            .filter_map(|sel| match sel {
+                AppSelection::Decal(id, fg) => {
+                    room.decal(*id, *fg).map(|decal| (*sel, decal.rotation))
+                }
+                _ => None,
+            })
+            .collect();
+        Some(RotatingStatus {
+            center,
+            pointer_reference_angle: angle_around(center, pointer),
+            selection_reference_angles,
+        })
+    }
+
+    #[must_use]
+    fn rotate(
+        &mut self,
+        app: &AppState,
+        room: &LevelState,
+        pointer: Point2D<f32, RoomSpace>,
+    ) -> AppEventStaging {
+        let mut result = AppEventStaging::default();
+        let SelectionStatus::Rotating(rotating) = &self.status else { return result };
+
+        let delta = angle_around(rotating.center, pointer) - rotating.pointer_reference_angle;
+        for (sel, reference) in &rotating.selection_reference_angles {
+            let AppSelection::Decal(id, fg) = sel else { continue };
+            let Some(decal) = room.decal(*id, *fg) else { continue };
+            let mut decal = decal.clone();
+            let mut rotation = reference + delta;
+            if app.config.snap {
+                rotation = (rotation / ROTATE_SNAP).round() * ROTATE_SNAP;
+            }
+            decal.rotation = rotation.rem_euclid(360.0);
+            result.push_room(RoomAction::DecalUpdate {
+                fg: *fg,
+                decal: Box::new(decal),
+            });
+        }
+
+        result
+    }
+
+    #[must_use]
+    fn rotate_by(&mut self, app: &AppState, room: &LevelState, degrees: f32) -> AppEventStaging {
+        let mut result = AppEventStaging::default();
+        for sel in app.map_tab_unwrap().current_selected.iter() {
+            let AppSelection::Decal(id, fg) = sel else { continue };
+            let Some(decal) = room.decal(*id, *fg) else { continue };
+            let mut decal = decal.clone();
+            decal.rotation = (decal.rotation + degrees).rem_euclid(360.0);
+            result.push_room(RoomAction::DecalUpdate {
+                fg: *fg,
+                decal: Box::new(decal),
+            });
+        }
+        result
+    }
+
     #[must_use]
     fn delete_all(&mut self, app: &AppState, room: &LevelState) -> AppEventStaging {
         let (mut result, _) = self.float_tiles(room, app.current_tab, app.map_tab_unwrap());
@@ -1302,6 +1470,19 @@ impl SelectionTool {
     }
 
     pub fn clipboard_copy(&mut self, app: &AppState, room: &LevelState) -> AppEventStaging {
+        let (mut result, clipboard_data) = self.copy_selection(app, room);
+        let s = serde_yaml::to_string(&AppSelectable::InRoom(clipboard_data))
+            .expect("Failed to serialize copied data");
+        result.push_ind(AppEvent::SetClipboard { contents: s });
+        result
+    }
+
+    /// The selected objects, as they would be copied. Selected tiles are floated to get them.
+    fn copy_selection(
+        &mut self,
+        app: &AppState,
+        room: &LevelState,
+    ) -> (AppEventStaging, Vec<AppInRoomSelectable>) {
         let (mut result, float) = self.float_tiles(room, app.current_tab, app.map_tab_unwrap());
         let mut result_float = room.floats.clone();
         add_floats_to_floats(&mut result_float, &float);
@@ -1338,18 +1519,27 @@ impl SelectionTool {
         if let Some((pt, grid)) = result_float.obj.take() {
             clipboard_data.push(AppInRoomSelectable::ObjectTiles(pt, grid));
         }
-        let s = serde_yaml::to_string(&AppSelectable::InRoom(clipboard_data))
-            .expect("Failed to serialize copied data");
-        result.push_ind(AppEvent::SetClipboard { contents: s });
         result.accumulate(floats_to_events(result_float));
-        result
+        (result, clipboard_data)
     }
 
     pub fn clipboard_paste(&mut self, app: &AppState, data: String) -> AppEventStaging {
+        let Ok(AppSelectable::InRoom(clipboard_data)) = serde_yaml::from_str(&data) else {
+            return self.clear_selection(app, &app.current_room_ref().unwrap().floats);
+        };
+        self.paste_objects(app, clipboard_data)
+    }
+
+    /// Add copies of `clipboard_data` centered in the current room. Entities and decals get new
+    /// ids, and attributes which refer to other pasted entities are updated to match.
+    fn paste_objects(
+        &mut self,
+        app: &AppState,
+        clipboard_data: Vec<AppInRoomSelectable>,
+    ) -> AppEventStaging {
         let mut result = self.clear_selection(app, &app.current_room_ref().unwrap().floats);
         let mut result_float = LevelFloatState::default();
 
-        let Ok(AppSelectable::InRoom(clipboard_data)) = serde_yaml::from_str(&data) else { return result };
         let Some(room) = app.current_room_ref() else { return result };
         if clipboard_data.is_empty() {
             return result;
@@ -1406,6 +1596,7 @@ impl SelectionTool {
         let real_center =
             (size_room_to_tile(&room.data.bounds.size.cast_unit::<RoomSpace>()) / 2).to_vector();
         let offset = real_center - center;
+        let mut pasted = CelesteMapLevel::default();
         for obj in clipboard_data {
             match obj {
                 AppInRoomSelectable::FgTiles(point, float) => {
@@ -1424,11 +1615,11 @@ impl SelectionTool {
                         node.x += vector_tile_to_room(&offset).x;
                         node.y += vector_tile_to_room(&offset).y;
                     }
-                    result.push_room(RoomAction::EntityAdd {
-                        entity: Box::new(entity),
-                        trigger,
-                        genid: true,
-                    });
+                    if trigger {
+                        pasted.triggers.push(entity);
+                    } else {
+                        pasted.entities.push(entity);
+                    }
                 }
                 AppInRoomSelectable::Decal(mut decal, fg) => {
                     decal.x += vector_tile_to_room(&offset).x;
@@ -1441,12 +1632,59 @@ impl SelectionTool {
                 }
             }
         }
+        // ids are handed out here rather than when the entities are added, so that pasted
+        // entities which refer to each other can be updated to match
+        let mut next_id = app
+            .current_map_ref()
+            .map_or(1, |map| map.data.next_entity_id());
+        assign_new_ids(&mut pasted, &mut next_id);
+        let entities = pasted.entities.into_iter().map(|entity| (entity, false));
+        let triggers = pasted.triggers.into_iter().map(|entity| (entity, true));
+        for (entity, trigger) in entities.chain(triggers) {
+            result.push_room(RoomAction::EntityAdd {
+                entity: Box::new(entity),
+                trigger,
+                genid: false,
+            });
+        }
         result.accumulate(floats_to_events(result_float));
         result
     }
 }
 
 // oh would it were that rust iterators weren't a fucking pain to write
+/// The size of a decal once it's scaled, before it's rotated.
+fn decal_size(app: &AppState, decal: &CelesteMapDecal) -> Vector2D<f32, RoomSpace> {
+    let dim = app
+        .current_palette_unwrap()
+        .gameplay_atlas
+        .sprite_dimensions(&decal_texture(decal))
+        .unwrap_or(Size2D::new(16, 16));
+    dim.cast()
+        .cast_unit()
+        .to_vector()
+        .component_mul(Vector2D::new(decal.scale_x, decal.scale_y))
+}
+
+/// The center of a decal and where its rotation handle is, which is past its top edge once it's
+/// rotated.
+fn rotate_handle(
+    app: &AppState,
+    decal: &CelesteMapDecal,
+) -> (Point2D<f32, RoomSpace>, Point2D<f32, RoomSpace>) {
+    let center: Point2D<f32, RoomSpace> = Point2D::new(decal.x, decal.y).cast();
+    let distance = decal_size(app, decal).y.abs() / 2.0 + ROTATE_HANDLE_DISTANCE;
+    let (sin, cos) = decal.rotation.to_radians().sin_cos();
+    let handle = center + Vector2D::new(distance * sin, -distance * cos);
+    (center, handle)
+}
+
+/// The angle of `point` around `center` in degrees, clockwise from straight up.
+fn angle_around(center: Point2D<f32, RoomSpace>, point: Point2D<f32, RoomSpace>) -> f32 {
+    let offset = point - center;
+    offset.x.atan2(-offset.y).to_degrees()
+}
+
 fn intersects_any(haystack: &[RoomRect], needle: &RoomRect) -> bool {
     for hay in haystack {
         if hay.intersects(needle) {
@@ -0,0 +1,198 @@
+use arborio_maploader::map_struct::CelesteMapDecal;
+use arborio_state::data::action::RoomAction;
+use arborio_state::data::app::AppState;
+use arborio_state::data::selection::AppSelection;
+use arborio_state::data::tabs::AppTab;
+use arborio_state::data::EventPhase;
+use arborio_state::lenses::{CurrentSelectedDecalsAllLens, IsFailedLens};
+use arborio_state::rendering::parse_color;
+use arborio_utils::vizia::prelude::*;
+
+pub struct DecalTweakerWidget {}
+
+impl DecalTweakerWidget {
+    pub fn new(cx: &mut Context) -> Handle<'_, Self> {
+        Self {}
+            .build(cx, move |cx| {
+                let any_decal_lens = CurrentSelectedDecalsAllLens::new_computed(|_, _| Some(()));
+                Binding::new(cx, IsFailedLens::new(any_decal_lens), move |cx, failed| {
+                    if !failed.get(cx) {
+                        let texture_lens =
+                            CurrentSelectedDecalsAllLens::new_referenced(|_, d| Some(&d.texture));
+                        Binding::new(cx, texture_lens, move |cx, texture| {
+                            let texture = texture
+                                .get_fallible(cx)
+                                .unwrap_or_else(|| "<multiple>".to_owned());
+                            Label::new(cx, &format!("Decal - {texture}"));
+                        });
+                        VStack::new(cx, build_tweaker).class("tweaker_container");
+                    }
+                });
+            })
+            .class("tweaker")
+            .class("decal_tweaker")
+    }
+}
+
+pub fn build_tweaker(cx: &mut Context) {
+    HStack::new(cx, move |cx| {
+        Label::new(cx, "x");
+        Textbox::new(
+            cx,
+            CurrentSelectedDecalsAllLens::new_referenced(|_, d| Some(&d.x)),
+        )
+        .on_edit(edit_x);
+        Label::new(cx, "y");
+        Textbox::new(
+            cx,
+            CurrentSelectedDecalsAllLens::new_referenced(|_, d| Some(&d.y)),
+        )
+        .on_edit(edit_y);
+    });
+    HStack::new(cx, move |cx| {
+        Label::new(cx, "scale x");
+        Textbox::new(
+            cx,
+            CurrentSelectedDecalsAllLens::new_referenced(|_, d| Some(&d.scale_x)),
+        )
+        .on_edit(edit_scale_x);
+        Label::new(cx, "scale y");
+        Textbox::new(
+            cx,
+            CurrentSelectedDecalsAllLens::new_referenced(|_, d| Some(&d.scale_y)),
+        )
+        .on_edit(edit_scale_y);
+    });
+    HStack::new(cx, move |cx| {
+        Label::new(cx, "rotation");
+        Textbox::new(
+            cx,
+            CurrentSelectedDecalsAllLens::new_referenced(|_, d| Some(&d.rotation)),
+        )
+        .on_edit(edit_rotation);
+    });
+    HStack::new(cx, move |cx| {
+        Label::new(cx, "color");
+        Textbox::new(
+            cx,
+            CurrentSelectedDecalsAllLens::new_computed(|_, d| {
+                Some(d.color.clone().unwrap_or_default())
+            }),
+        )
+        .on_edit(edit_color);
+    });
+    HStack::new(cx, move |cx| {
+        Label::new(cx, "depth");
+        Textbox::new(
+            cx,
+            CurrentSelectedDecalsAllLens::new_computed(|_, d| {
+                Some(d.depth.map(|depth| depth.to_string()).unwrap_or_default())
+            }),
+        )
+        .on_edit(edit_depth);
+    });
+}
+
+impl View for DecalTweakerWidget {
+    fn element(&self) -> Option<&'static str> {
+        Some("decal-tweaker")
+    }
+}
+
+fn edit_decal<F: FnMut(&mut CelesteMapDecal)>(cx: &mut EventContext, mut f: F) {
+    let app_state = cx.data::<AppState>().unwrap();
+    let (current_map, current_room, current_selected) = match app_state
+        .tabs
+        .get(app_state.current_tab)
+    {
+        Some(AppTab::Map(map_tab)) => (map_tab.id, map_tab.current_room, &map_tab.current_selected),
+        _ => panic!("How'd you do that"),
+    };
+
+    let phase = EventPhase::new(); // TODO batch correctly (based on timeout)
+
+    let mut events = vec![];
+    for sel in current_selected {
+        if let AppSelection::Decal(id, fg) = sel {
+            if let Some(mut decal) = app_state
+                .loaded_maps
+                .get(&current_map)
+                .and_then(|x| x.data.levels.get(current_room))
+                .and_then(|x| x.decal(*id, *fg))
+                .cloned()
+            {
+                f(&mut decal);
+
+                events.push(current_map.room_action(
+                    current_room,
+                    phase,
+                    RoomAction::DecalUpdate {
+                        fg: *fg,
+                        decal: Box::new(decal),
+                    },
+                ));
+            }
+        }
+    }
+    for event in events {
+        cx.emit(event);
+    }
+}
+
+/// Applies `f` to every selected decal if the textbox parsed, and flags the textbox otherwise.
+fn edit_parsed<T, F>(cx: &mut EventContext, value: Option<T>, f: F)
+where
+    T: Clone,
+    F: Fn(&mut CelesteMapDecal, T),
+{
+    if let Some(value) = value {
+        edit_decal(cx, move |decal| f(decal, value.clone()));
+        cx.toggle_class("validation_error", false);
+    } else {
+        cx.toggle_class("validation_error", true);
+    }
+}
+
+fn edit_x(cx: &mut EventContext, value: String) {
+    edit_parsed(cx, value.parse().ok(), |decal, x| decal.x = x);
+}
+
+fn edit_y(cx: &mut EventContext, value: String) {
+    edit_parsed(cx, value.parse().ok(), |decal, y| decal.y = y);
+}
+
+fn edit_scale_x(cx: &mut EventContext, value: String) {
+    edit_parsed(cx, value.parse().ok(), |decal, scale| decal.scale_x = scale);
+}
+
+fn edit_scale_y(cx: &mut EventContext, value: String) {
+    edit_parsed(cx, value.parse().ok(), |decal, scale| decal.scale_y = scale);
+}
+
+fn edit_rotation(cx: &mut EventContext, value: String) {
+    edit_parsed(cx, value.parse::<f32>().ok(), |decal, rotation| {
+        decal.rotation = rotation.rem_euclid(360.0);
+    });
+}
+
+/// An empty color removes the tint.
+fn edit_color(cx: &mut EventContext, value: String) {
+    let value = value.trim().trim_start_matches('#').to_owned();
+    let color = if value.is_empty() {
+        Some(None)
+    } else {
+        parse_color(&value).map(|_| Some(value))
+    };
+    edit_parsed(cx, color, |decal, color| decal.color = color);
+}
+
+/// An empty depth makes the decal use its layer's depth.
+fn edit_depth(cx: &mut EventContext, value: String) {
+    let value = value.trim();
+    let depth = if value.is_empty() {
+        Some(None)
+    } else {
+        value.parse().ok().map(Some)
+    };
+    edit_parsed(cx, depth, |decal, depth| decal.depth = depth);
+}
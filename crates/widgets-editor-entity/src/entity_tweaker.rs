@@ -258,7 +258,7 @@ fn edit_attribute(cx: &mut EventContext, key: String, value: Attribute) {
 
 fn remove_attribute(cx: &mut EventContext, key: String) {
     edit_entity(cx, move |entity, _| {
-        entity.attributes.remove(&key);
+        entity.attributes.shift_remove(&key);
     });
 }
 
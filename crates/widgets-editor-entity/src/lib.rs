@@ -1 +1,2 @@
+pub mod decal_tweaker;
 pub mod entity_tweaker;
@@ -1,13 +1,20 @@
 use arborio_maploader::map_struct::{CelesteMapLevel, CelesteMapLevelUpdate};
-use arborio_state::data::action::RoomAction;
-use arborio_state::data::app::AppState;
+use arborio_state::data::action::{MapAction, RoomAction};
+use arborio_state::data::app::{AppEvent, AppState};
 use arborio_state::data::tabs::AppTab;
-use arborio_state::data::EventPhase;
+use arborio_state::data::{AppConfig, AppConfigSetter, EventPhase};
 use arborio_state::lenses::{
-    current_room_lens, rect_h_lens, rect_w_lens, rect_x_lens, rect_y_lens, RoomTweakerScopeLens,
+    current_room_lens, rect_h_lens, rect_w_lens, rect_x_lens, rect_y_lens, AutoSaverLens,
+    RoomTweakerScopeLens,
 };
+use arborio_state::rendering::parse_color;
+use arborio_state::tile_image::{load_tile_image, room_from_tiles, save_tile_image, TileColor};
+use arborio_utils::units::{MapPointStrict, TilePoint};
+use arborio_utils::vizia::fonts::icons_names::MINUS;
 use arborio_utils::vizia::prelude::*;
 use arborio_widgets_common::advanced_tweaker::*;
+use dialog::DialogBox;
+use std::path::PathBuf;
 
 pub struct RoomTweakerWidget {}
 
@@ -212,6 +219,93 @@ impl RoomTweakerWidget {
                 });
             }
         });
+
+        Self::tile_image(cx);
+    }
+
+    fn tile_image(cx: &mut Context) {
+        Label::new(cx, "Tile Image").class("module_category");
+        List::new(
+            cx,
+            AppState::config
+                .then(AutoSaverLens::new())
+                .then(AppConfig::tile_colors),
+            move |cx, idx, item| {
+                HStack::new(cx, move |cx| {
+                    Label::new(cx, "Color");
+                    Textbox::new(cx, item.map(|entry| entry.color.clone())).on_edit(
+                        move |cx, value| {
+                            let value = value.trim().trim_start_matches('#').to_owned();
+                            if parse_color(&value).is_some() {
+                                edit_tile_colors(cx, |colors| colors[idx].color = value);
+                                cx.toggle_class("validation_error", false);
+                            } else {
+                                cx.toggle_class("validation_error", true);
+                            }
+                        },
+                    );
+                    Label::new(cx, "Tile");
+                    Textbox::new(cx, item.map(|entry| entry.tile.to_string())).on_edit(
+                        move |cx, value| {
+                            let mut chars = value.chars();
+                            if let (Some(tile), None) = (chars.next(), chars.next()) {
+                                edit_tile_colors(cx, |colors| colors[idx].tile = tile);
+                                cx.toggle_class("validation_error", false);
+                            } else {
+                                cx.toggle_class("validation_error", true);
+                            }
+                        },
+                    );
+                    Label::new(cx, MINUS)
+                        .class("icon")
+                        .class("remove_btn")
+                        .on_press(move |cx| {
+                            edit_tile_colors(cx.as_mut(), |colors| {
+                                colors.remove(idx);
+                            });
+                        });
+                });
+            },
+        );
+        Button::new(
+            cx,
+            |cx| {
+                edit_tile_colors(cx, |colors| {
+                    colors.push(TileColor {
+                        color: "808080".to_owned(),
+                        tile: '0',
+                    })
+                })
+            },
+            |cx| Label::new(cx, "+ Color"),
+        );
+        HStack::new(cx, |cx| {
+            Button::new(
+                cx,
+                |cx| import_tiles(cx, true),
+                |cx| Label::new(cx, "Import FG"),
+            );
+            Button::new(
+                cx,
+                |cx| import_tiles(cx, false),
+                |cx| Label::new(cx, "Import BG"),
+            );
+        });
+        HStack::new(cx, |cx| {
+            Button::new(
+                cx,
+                |cx| export_tiles(cx, true),
+                |cx| Label::new(cx, "Export FG"),
+            );
+            Button::new(
+                cx,
+                |cx| export_tiles(cx, false),
+                |cx| Label::new(cx, "Export BG"),
+            );
+        });
+        Button::new(cx, new_room_from_image, |cx| {
+            Label::new(cx, "New Room From Image")
+        });
     }
 }
 
@@ -233,6 +327,98 @@ fn emit(cx: &mut EventContext, update: CelesteMapLevelUpdate) {
     )); // TODO batch correctly
 }
 
+fn edit_tile_colors(cx: &mut EventContext, f: impl FnOnce(&mut Vec<TileColor>)) {
+    let mut colors = cx.data::<AppState>().unwrap().config.tile_colors.clone();
+    f(&mut colors);
+    cx.emit(AppEvent::EditSettings {
+        setter: AppConfigSetter::TileColors(colors),
+    });
+}
+
+fn choose_image(
+    cx: &mut EventContext,
+    title: &str,
+    mode: dialog::FileSelectionMode,
+) -> Option<PathBuf> {
+    let app = cx.data::<AppState>().unwrap();
+    let mut dialog = dialog::FileSelection::new(title);
+    dialog.mode(mode);
+    if app.config.last_filepath.is_dir() {
+        dialog.path(&app.config.last_filepath);
+    }
+    let path = PathBuf::from(dialog.show().ok()??);
+    if let Some(parent) = path.parent() {
+        cx.emit(AppEvent::EditSettings {
+            setter: AppConfigSetter::LastFilepath(parent.to_owned()),
+        });
+    }
+    Some(path)
+}
+
+fn show_error(message: String) {
+    dialog::Message::new(message).title("Error").show().unwrap();
+}
+
+/// Replace the current room's tiles with the ones drawn in an image, starting from the top left.
+fn import_tiles(cx: &mut EventContext, fg: bool) {
+    let Some(path) = choose_image(cx, "Import Tiles", dialog::FileSelectionMode::Open) else { return };
+    let app = cx.data::<AppState>().unwrap();
+    match load_tile_image(&path, &app.config.tile_colors) {
+        Ok(data) => {
+            let tab = app.map_tab_unwrap();
+            cx.emit(tab.id.room_action(
+                tab.current_room,
+                EventPhase::new(),
+                RoomAction::TileUpdate {
+                    fg,
+                    offset: TilePoint::zero(),
+                    data,
+                },
+            ));
+        }
+        Err(e) => show_error(e),
+    }
+}
+
+fn export_tiles(cx: &mut EventContext, fg: bool) {
+    let Some(path) = choose_image(cx, "Export Tiles", dialog::FileSelectionMode::Save) else { return };
+    let app = cx.data::<AppState>().unwrap();
+    let tab = app.map_tab_unwrap();
+    let room = &app.loaded_maps.get(&tab.id).unwrap().data.levels[tab.current_room].data;
+    let tiles = if fg { &room.solids } else { &room.bg };
+    if let Err(e) = save_tile_image(&path, tiles, &app.config.tile_colors) {
+        show_error(e);
+    }
+}
+
+/// Add a room the size of an image, with the image as its foreground, to the right of the map.
+fn new_room_from_image(cx: &mut EventContext) {
+    let Some(path) = choose_image(cx, "New Room From Image", dialog::FileSelectionMode::Open) else { return };
+    let app = cx.data::<AppState>().unwrap();
+    match load_tile_image(&path, &app.config.tile_colors) {
+        Ok(tiles) => {
+            let tab = app.map_tab_unwrap();
+            let map = &app.loaded_maps.get(&tab.id).unwrap().data;
+            let right = map
+                .levels
+                .iter()
+                .map(|room| room.data.bounds.max_x())
+                .max()
+                .unwrap_or(0);
+            let top = map
+                .levels
+                .get(tab.current_room)
+                .map_or(0, |room| room.data.bounds.min_y());
+            let origin = MapPointStrict::new((right + 7) / 8 * 8 + 8, top);
+            cx.emit(app.map_action_unique(vec![MapAction::AddRoom {
+                idx: None,
+                room: Box::new(room_from_tiles(&tiles, origin)),
+            }]));
+        }
+        Err(e) => show_error(e),
+    }
+}
+
 fn emit_bounds(
     cx: &mut EventContext,
     update_x: Option<i32>,
@@ -318,7 +318,7 @@ impl StyleTweakerWidget {
             },
             |cx, key| {
                 let mut current = current_styleground_impl_lens().get(cx);
-                current.attributes.remove(&key);
+                current.attributes.shift_remove(&key);
                 emit(cx, current);
             },
         );
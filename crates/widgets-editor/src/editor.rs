@@ -7,6 +7,7 @@ use std::time;
 
 use arborio_state::data::app::AppState;
 use arborio_state::rendering;
+use arborio_state::rendering::{FILLER_COLOR, ROOM_EMPTY_COLOR};
 
 lazy_static! {
     static ref PERF_MONITOR: bool = env::var("ARBORIO_PERF_MONITOR").is_ok();
@@ -18,18 +19,6 @@ const BACKDROP_COLOR: Color = Color {
     b: 0.30,
     a: 1.00,
 };
-const FILLER_COLOR: Color = Color {
-    r: 0.40,
-    g: 0.40,
-    b: 0.40,
-    a: 1.00,
-};
-const ROOM_EMPTY_COLOR: Color = Color {
-    r: 0.13,
-    g: 0.25,
-    b: 0.13,
-    a: 1.00,
-};
 const ROOM_DESELECTED_COLOR: Color = Color {
     r: 0.00,
     g: 0.00,
@@ -208,32 +197,18 @@ impl View for EditorWidget {
                     room.data.bounds.height() as u32,
                     Color::rgba(0, 0, 0, 0),
                 );
-                rendering::draw_tiles(app.current_palette_unwrap(), canvas, room, false);
-                rendering::draw_decals(app.current_palette_unwrap(), canvas, &room.data, false);
                 let nil = HashSet::new();
-                rendering::draw_triggers(
-                    app.current_palette_unwrap(),
-                    canvas,
-                    &room.data,
-                    if idx == app.map_tab_unwrap().current_room {
-                        &app.map_tab_unwrap().current_selected
-                    } else {
-                        &nil
-                    },
-                );
-                rendering::draw_entities(
+                rendering::draw_room(
                     app.current_palette_unwrap(),
                     canvas,
-                    &room.data,
+                    room,
                     if idx == app.map_tab_unwrap().current_room {
                         &app.map_tab_unwrap().current_selected
                     } else {
                         &nil
                     },
+                    true,
                 );
-                rendering::draw_tiles(app.current_palette_unwrap(), canvas, room, true);
-                rendering::draw_decals(app.current_palette_unwrap(), canvas, &room.data, true);
-                rendering::draw_objtiles_float(app.current_palette_unwrap(), canvas, room);
 
                 canvas.restore();
                 canvas.set_render_target(RenderTarget::Screen);
@@ -163,6 +163,15 @@ fn build_menu_bar(cx: &mut Context) {
                     cx.emit(AppEvent::OpenConfigEditorTab);
                 },
             );
+            MenuButton::new(
+                cx,
+                move |cx| {
+                    Label::new(cx, "Find and Replace");
+                },
+                move |cx| {
+                    cx.emit(AppEvent::OpenFindReplaceTab);
+                },
+            );
             MenuButton::new(
                 cx,
                 move |cx| {
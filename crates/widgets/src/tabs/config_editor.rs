@@ -397,11 +397,16 @@ fn walk_maps<T>(
                 sid: map.clone(),
             };
             if scope.filter_map(&map_path, targets) {
-                if let Ok(map) =
+                if let Ok((map, _)) =
                     CelesteModule::load_map_static(module.filesystem_root.as_ref().unwrap(), map)
                 {
-                    let palette =
-                        ModuleAggregate::new(modules, &modules_lookup, &map.meta, *name, false);
+                    let palette = ModuleAggregate::new(
+                        modules,
+                        &modules_lookup,
+                        &map.effective_meta(),
+                        *name,
+                        false,
+                    );
                     f(&mut results, filter, &attrs, &map, &map_path, &palette);
                 }
             }
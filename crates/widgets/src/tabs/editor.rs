@@ -1,12 +1,16 @@
 use arborio_modloader::aggregate::ModuleAggregate;
 use arborio_state::data::app::{AppEvent, AppState};
+use arborio_state::data::project_map::MapEvent;
 use arborio_state::data::{AppConfig, AppConfigSetter, Layer};
 use arborio_state::lenses::{current_map_lens, current_palette_lens, AnotherLens, AutoSaverLens};
+use arborio_state::object_search::{search_objects, ObjectMatch};
 use arborio_state::tools::ToolSpec;
 use arborio_utils::vizia::prelude::*;
 
+use arborio_widgets_common::container_model::{ModelContainer, ModelContainerSetter};
 use arborio_widgets_common::list_palette::PaletteWidget;
 use arborio_widgets_editor::editor::EditorWidget;
+use arborio_widgets_editor_entity::decal_tweaker::DecalTweakerWidget;
 use arborio_widgets_editor_entity::entity_tweaker::EntityTweakerWidget;
 use arborio_widgets_editor_room::room_tweaker::RoomTweakerWidget;
 use arborio_widgets_editor_style::style_tweaker::{StyleListWidget, StyleTweakerWidget};
@@ -32,6 +36,8 @@ pub fn build_editor(cx: &mut Context) {
         VStack::new(cx, |cx| {
             build_layer_picker(cx);
             build_palette_widgets(cx);
+            build_prefab_palette(cx);
+            build_object_search(cx);
             build_tweaker_widgets(cx);
         })
         .id("right_bar");
@@ -249,10 +255,113 @@ pub fn build_palette_widgets(cx: &mut Context) {
     });
 }
 
+/// Saved selections and rooms from the current project. Placing one switches to the tool which
+/// deals in it.
+pub fn build_prefab_palette(cx: &mut Context) {
+    VStack::new(cx, |cx| {
+        Label::new(cx, "Prefabs").class("prefab_title");
+        HStack::new(cx, |cx| {
+            ModelContainer { val: "".to_owned() }.build(cx);
+            Textbox::new(cx, ModelContainer::<String>::val)
+                .on_edit(|cx, value| cx.emit(ModelContainerSetter::Val(value)));
+            Label::new(cx, "Save")
+                .class("btn_highlight")
+                .on_press(|cx| {
+                    let name = ModelContainer::<String>::val.get(cx);
+                    cx.emit(AppEvent::SavePrefab { name });
+                });
+        });
+        let project = AnotherLens::new(AppState::modules_version, AppState::current_tab);
+        ScrollView::new(cx, 0.0, 0.0, false, true, move |cx| {
+            Binding::new(cx, project, |cx, _| {
+                let app = cx.data::<AppState>().unwrap();
+                let mut prefabs = app
+                    .current_project_id()
+                    .and_then(|id| app.modules.get(&id))
+                    .map(|module| module.prefabs.clone())
+                    .unwrap_or_default();
+                prefabs.sort();
+                for name in prefabs {
+                    HStack::new(cx, |cx| {
+                        Label::new(cx, &name);
+                    })
+                    .class("palette_item")
+                    .class("list_highlight")
+                    .on_press(move |cx| {
+                        cx.emit(AppEvent::PlacePrefab { name: name.clone() });
+                    });
+                }
+            });
+        });
+    })
+    .id("prefab_palette")
+    .bind(AppState::current_toolspec, |handle, toolspec| {
+        let toolspec = toolspec.get(handle.cx);
+        handle.display(toolspec == ToolSpec::Selection || toolspec == ToolSpec::Room);
+    });
+}
+
+/// Only so many results are listed, since a short query can match most of a chapter.
+const MAX_SEARCH_RESULTS: usize = 200;
+
+pub fn build_object_search(cx: &mut Context) {
+    VStack::new(cx, |cx| {
+        Label::new(cx, "Find Objects").class("object_search_title");
+        ModelContainer { val: "".to_owned() }.build(cx);
+        ModelContainer::<Vec<ObjectMatch>> { val: vec![] }.build(cx);
+        Textbox::new(cx, ModelContainer::<String>::val).on_edit(|cx, query| {
+            let app = cx.data::<AppState>().unwrap();
+            let found = app
+                .current_map_id()
+                .and_then(|id| {
+                    let rooms = app.loaded_maps.get(&id)?.data.levels.iter();
+                    Some(search_objects(id, rooms.map(|room| &room.data), &query))
+                })
+                .unwrap_or_default();
+            cx.emit(ModelContainerSetter::Val(query));
+            cx.emit(ModelContainerSetter::Val(found));
+        });
+        ScrollView::new(cx, 0.0, 0.0, false, true, |cx| {
+            Binding::new(cx, ModelContainer::<Vec<ObjectMatch>>::val, |cx, found| {
+                let found = found.get(cx);
+                if found.len() > MAX_SEARCH_RESULTS {
+                    Label::new(
+                        cx,
+                        &format!("Showing {MAX_SEARCH_RESULTS} of {}", found.len()),
+                    );
+                }
+                for object in found.into_iter().take(MAX_SEARCH_RESULTS) {
+                    let text = object.to_string();
+                    HStack::new(cx, |cx| {
+                        Label::new(cx, &text);
+                    })
+                    .class("palette_item")
+                    .class("list_highlight")
+                    .on_press(move |cx| {
+                        cx.emit(AppEvent::MapEvent {
+                            map: Some(object.map),
+                            event: MapEvent::JumpTo {
+                                room: Some(object.room),
+                                selection: Some(object.selection),
+                            },
+                        });
+                    });
+                }
+            });
+        });
+    })
+    .id("object_search")
+    .bind(AppState::current_toolspec, |handle, toolspec| {
+        let toolspec = toolspec.get(handle.cx);
+        handle.display(toolspec == ToolSpec::Selection);
+    });
+}
+
 pub fn build_tweaker_widgets(cx: &mut Context) {
     Binding::new(cx, AppState::current_toolspec, |cx, tool_idx| {
         let tool_idx = tool_idx.get(cx);
         EntityTweakerWidget::new(cx).display(tool_idx == ToolSpec::Selection);
+        DecalTweakerWidget::new(cx).display(tool_idx == ToolSpec::Selection);
         RoomTweakerWidget::new(cx).display(tool_idx == ToolSpec::Room);
         StyleListWidget::new(cx).display(tool_idx == ToolSpec::Style);
         StyleTweakerWidget::new(cx).display(tool_idx == ToolSpec::Style);
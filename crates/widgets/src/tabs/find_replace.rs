@@ -0,0 +1,260 @@
+use arborio_maploader::replace::{ReplaceTarget, Replacement};
+use arborio_modloader::module::MapPath;
+use arborio_state::data::app::{AppEvent, AppState};
+use arborio_state::data::config_editor::SearchScope;
+use arborio_state::data::project_map::MapEvent;
+use arborio_state::data::EventPhase;
+use arborio_state::find_replace::{find_matches, maps_in_scope, replace_actions, ReplaceMatch};
+use arborio_utils::vizia::fonts::icons_names::DOWN;
+use arborio_utils::vizia::prelude::*;
+use arborio_widgets_common::container_model::{ModelContainer, ModelContainerSetter};
+
+use crate::tabs::config_editor::collect_search_targets;
+
+const TARGETS: [ReplaceTarget; 5] = [
+    ReplaceTarget::EntityName,
+    ReplaceTarget::Attribute,
+    ReplaceTarget::FgTile,
+    ReplaceTarget::BgTile,
+    ReplaceTarget::DecalTexture,
+];
+
+pub fn build_find_replace(cx: &mut Context) {
+    ScrollView::new(cx, 0.0, 0.0, false, true, |cx| {
+        ModelContainer {
+            val: SearchScope::AllOpenMaps,
+        }
+        .build(cx);
+        ModelContainer {
+            val: Replacement {
+                target: ReplaceTarget::EntityName,
+                find: "".to_owned(),
+                replace: "".to_owned(),
+            },
+        }
+        .build(cx);
+        ModelContainer::<Option<Vec<ReplaceMatch>>> { val: None }.build(cx);
+        ModelContainer::<Vec<MapPath>> { val: vec![] }.build(cx);
+
+        build_replace_settings(cx);
+        HStack::new(cx, |cx| {
+            Button::new(cx, find, |cx| Label::new(cx, "Find"));
+            Button::new(cx, replace_all, |cx| Label::new(cx, "Replace All"));
+        });
+        build_closed_maps(cx);
+        build_matches(cx);
+    })
+    .id("find_replace_tab");
+}
+
+fn build_replace_settings(cx: &mut Context) {
+    HStack::new(cx, |cx| {
+        Label::new(cx, "Search Scope");
+        Dropdown::new(
+            cx,
+            |cx| {
+                HStack::new(cx, |cx| {
+                    Label::new(cx, "").bind(ModelContainer::<SearchScope>::val, |handle, scope| {
+                        let text = scope.get(handle.cx).text(handle.cx);
+                        handle.text(&text);
+                    });
+                    Label::new(cx, DOWN).class("icon").class("dropdown_icon");
+                })
+            },
+            |cx| {
+                for target in collect_search_targets(cx) {
+                    let text = target.text(cx);
+                    Label::new(cx, &text)
+                        .class("dropdown_element")
+                        .class("btn_highlight")
+                        .on_press(move |cx| {
+                            cx.emit(PopupEvent::Close);
+                            cx.emit(ModelContainerSetter::Val(target.clone()));
+                        });
+                }
+            },
+        );
+    });
+    HStack::new(cx, |cx| {
+        Label::new(cx, "Replace");
+        Dropdown::new(
+            cx,
+            |cx| {
+                HStack::new(cx, |cx| {
+                    Label::new(
+                        cx,
+                        ModelContainer::<Replacement>::val.then(Replacement::target),
+                    );
+                    Label::new(cx, DOWN).class("icon").class("dropdown_icon");
+                })
+            },
+            |cx| {
+                for target in TARGETS {
+                    Label::new(cx, &format!("{target}"))
+                        .class("dropdown_element")
+                        .class("btn_highlight")
+                        .on_press(move |cx| {
+                            cx.emit(PopupEvent::Close);
+                            edit_replacement(cx, |replacement| replacement.target = target);
+                        });
+                }
+            },
+        );
+    });
+    HStack::new(cx, |cx| {
+        Label::new(cx, "Find");
+        Textbox::new(
+            cx,
+            ModelContainer::<Replacement>::val.then(Replacement::find),
+        )
+        .on_edit(|cx, find| edit_replacement(cx, |replacement| replacement.find = find));
+    });
+    HStack::new(cx, |cx| {
+        Label::new(cx, "Replace with");
+        Textbox::new(
+            cx,
+            ModelContainer::<Replacement>::val.then(Replacement::replace),
+        )
+        .on_edit(|cx, replace| edit_replacement(cx, |replacement| replacement.replace = replace));
+    });
+    let target = ModelContainer::<Replacement>::val.then(Replacement::target);
+    Label::new(cx, "")
+        .bind(target, |handle, target| {
+            handle.text(match target.get(handle.cx) {
+                ReplaceTarget::EntityName => "Entities and triggers of exactly this type",
+                ReplaceTarget::Attribute => {
+                    "Find name=value or just name, replace with a value or name=value"
+                }
+                ReplaceTarget::FgTile | ReplaceTarget::BgTile => "One tileset character each",
+                ReplaceTarget::DecalTexture => {
+                    "A texture, or a folder ending in / to move every decal in it"
+                }
+            });
+        })
+        .class("find_replace_hint");
+}
+
+fn edit_replacement(cx: &mut EventContext, edit: impl FnOnce(&mut Replacement)) {
+    let mut replacement = ModelContainer::<Replacement>::val.get(cx);
+    edit(&mut replacement);
+    cx.emit(ModelContainerSetter::Val(replacement));
+}
+
+/// The maps in scope which weren't searched because they aren't open.
+fn build_closed_maps(cx: &mut Context) {
+    Binding::new(cx, ModelContainer::<Vec<MapPath>>::val, |cx, closed| {
+        let closed = closed.get(cx);
+        if closed.is_empty() {
+            return;
+        }
+        Label::new(
+            cx,
+            &format!(
+                "{} map(s) in scope aren't open and weren't searched. Open them, then search again:",
+                closed.len()
+            ),
+        );
+        for path in &closed {
+            Label::new(cx, &path.sid).class("replace_skipped");
+        }
+        Button::new(
+            cx,
+            move |cx| {
+                for path in &closed {
+                    cx.emit(AppEvent::OpenMap { path: path.clone() });
+                }
+            },
+            |cx| Label::new(cx, "Open Them"),
+        );
+    });
+}
+
+fn build_matches(cx: &mut Context) {
+    Binding::new(
+        cx,
+        ModelContainer::<Option<Vec<ReplaceMatch>>>::val,
+        |cx, matches| {
+            let Some(matches) = matches.get(cx) else { return };
+            if matches.is_empty() {
+                Label::new(cx, "Nothing found");
+            } else {
+                Label::new(cx, &format!("{} match(es)", matches.len()));
+            }
+            for found in matches {
+                Label::new(cx, &found.to_string())
+                    .class("replace_match")
+                    .on_press(move |cx| {
+                        cx.emit(AppEvent::MapEvent {
+                            map: Some(found.map),
+                            event: MapEvent::JumpTo {
+                                room: Some(found.room),
+                                selection: found.selection,
+                            },
+                        })
+                    });
+            }
+        },
+    );
+}
+
+fn find(cx: &mut EventContext) {
+    let replacement = ModelContainer::<Replacement>::val.get(cx);
+    if let Err(e) = replacement.check() {
+        log::error!("{e}");
+        return;
+    }
+    let scope = ModelContainer::<SearchScope>::val.get(cx);
+    let targets = collect_search_targets(cx);
+    let app = cx.data::<AppState>().unwrap();
+    let maps = maps_in_scope(app, &scope, &targets);
+    let matches = find_matches(app, &maps.open, &replacement);
+    cx.emit(ModelContainerSetter::Val(Some(matches)));
+    cx.emit(ModelContainerSetter::Val(maps.closed));
+}
+
+/// Each map gets one batch of actions, so that undoing in a map takes back all of its changes.
+fn replace_all(cx: &mut EventContext) {
+    let replacement = ModelContainer::<Replacement>::val.get(cx);
+    if let Err(e) = replacement.check() {
+        log::error!("{e}");
+        return;
+    }
+    let scope = ModelContainer::<SearchScope>::val.get(cx);
+    let targets = collect_search_targets(cx);
+    let app = cx.data::<AppState>().unwrap();
+    let maps = maps_in_scope(app, &scope, &targets);
+    let events = maps
+        .open
+        .into_iter()
+        .map(|map| (map, replace_actions(&app.loaded_maps[&map], &replacement)))
+        .filter(|(_, actions)| !actions.is_empty())
+        .collect::<Vec<_>>();
+
+    let count = events
+        .iter()
+        .map(|(_, actions)| actions.len())
+        .sum::<usize>();
+    log::info!("Replaced {count} match(es) in {} map(s)", events.len());
+    if !maps.closed.is_empty() {
+        log::warn!(
+            "Skipped {} map(s) in scope which aren't open: {}",
+            maps.closed.len(),
+            maps.closed
+                .iter()
+                .map(|path| path.sid.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    for (map, event) in events {
+        cx.emit(AppEvent::MapEvent {
+            map: Some(map),
+            event: MapEvent::Action {
+                event,
+                merge_phase: EventPhase::new(),
+            },
+        });
+    }
+    cx.emit(ModelContainerSetter::<Option<Vec<ReplaceMatch>>>::Val(None));
+    cx.emit(ModelContainerSetter::Val(maps.closed));
+}
@@ -1,9 +1,18 @@
-use arborio_state::data::action::MapAction;
+use arborio_maploader::ids::renumber_entity_ids;
+use arborio_maploader::stats::{map_stats, MapStats};
+use arborio_state::data::action::{MapAction, RoomAction};
 use arborio_state::data::app::{AppEvent, AppState};
+use arborio_state::data::meta_yaml::{
+    MapMetaCassetteModifierData, MapMetaCheckpointData, MapMetaModeData, MapMetaMountainData,
+    MapMetaScreenData,
+};
 use arborio_state::data::project_map::{MapEvent, MapStateData, MapStateUpdate};
+use arborio_state::data::selection::AppSelection;
 use arborio_state::data::sid::SIDFields;
-use arborio_state::data::{EventPhase, MapID};
-use arborio_state::lenses::{current_map_impl_lens, StaticerLens};
+use arborio_state::data::{AppConfig, AppConfigSetter, EventPhase, MapID};
+use arborio_state::lenses::{current_map_impl_lens, AutoSaverLens, StaticerLens};
+use arborio_state::lint::{lint_map, LintFinding, LintRule};
+use arborio_utils::vizia::fonts::icons_names::MINUS;
 use arborio_utils::vizia::prelude::*;
 use arborio_widgets_common::advanced_tweaker::{
     tweak_attr_check, tweak_attr_text, tweak_attr_text_dropdown,
@@ -15,6 +24,9 @@ pub fn build_map_meta_tab(cx: &mut Context, map: MapID) {
     ScrollView::new(cx, 0.0, 0.0, false, true, move |cx| {
         sid_editor(cx, map);
         meta_tweaker(cx, map);
+        meta_yaml_tweaker(cx);
+        map_checks(cx, map);
+        map_statistics(cx, map);
         map_deleter(cx, map);
     })
     .id("map_meta_tab");
@@ -72,6 +84,8 @@ const INTRO_TYPE_OPTIONS: [&str; 10] = [
 
 const CORE_MODE_OPTIONS: [&str; 3] = ["None", "Hot", "Cold"];
 
+const SIDE_NAMES: [&str; 3] = ["A-Side", "B-Side", "C-Side"];
+
 const INVENTORY_OPTIONS: [&str; 7] = [
     "Default",
     "CH6End",
@@ -361,6 +375,50 @@ macro_rules! edit_check {
     };
 }
 
+/// Change part of a field of `MapStateData` and emit the whole field as an update.
+macro_rules! edit_field {
+    ($cx: expr, $field:ident, |$value:ident| $edit:expr) => {{
+        let mut $value = current_map_impl_lens().then(MapStateData::$field).get($cx);
+        $edit;
+        emit(
+            $cx,
+            MapStateUpdate {
+                $field: Some($value),
+                ..MapStateUpdate::default()
+            },
+        );
+    }};
+}
+
+macro_rules! edit_nested_text {
+    ($cx: expr, $label:expr, $lens:expr, $field:ident, |$value:ident| $place:expr) => {
+        tweak_attr_text($cx, $label, $lens, move |cx, x| {
+            edit_field!(cx, $field, |$value| $place = x);
+            true
+        });
+    };
+}
+
+macro_rules! edit_nested_text_dropdown {
+    ($cx: expr, $label:expr, $lens:expr, $options:expr, $field:ident, |$value:ident| $place:expr) => {
+        tweak_attr_text_dropdown(
+            $cx,
+            $label,
+            $lens,
+            StaticerLens::new($options.into_iter().map(|x| x.to_owned()).collect()),
+            move |cx, x| edit_field!(cx, $field, |$value| $place = x),
+        );
+    };
+}
+
+macro_rules! edit_nested_check {
+    ($cx: expr, $label:expr, $lens:expr, $field:ident, |$value:ident| $place:expr) => {
+        tweak_attr_check($cx, $label, $lens, move |cx, x| {
+            edit_field!(cx, $field, |$value| $place = x)
+        });
+    };
+}
+
 fn emit(cx: &mut EventContext, update: MapStateUpdate) {
     let app = cx.data::<AppState>().unwrap();
     let map = app.current_map_id().unwrap();
@@ -416,6 +474,505 @@ fn meta_tweaker(cx: &mut Context, _map: MapID) {
     edit_text_dropdown!(cx, "Music", music, MUSIC_OPTIONS);
 }
 
+/// The settings which live in the map.meta.yaml next to the map.
+macro_rules! screen_tweaker {
+    ($cx: expr, $field:ident) => {
+        let screen = current_map_impl_lens().then(MapStateData::$field);
+        edit_nested_text!(
+            $cx,
+            "Atlas",
+            screen.then(MapMetaScreenData::atlas),
+            $field,
+            |s| s.atlas
+        );
+        edit_nested_text!(
+            $cx,
+            "A-Side Title",
+            screen.then(MapMetaScreenData::title_a_side),
+            $field,
+            |s| s.title_a_side
+        );
+        edit_nested_text!(
+            $cx,
+            "B-Side Title",
+            screen.then(MapMetaScreenData::title_b_side),
+            $field,
+            |s| s.title_b_side
+        );
+        edit_nested_text!(
+            $cx,
+            "C-Side Title",
+            screen.then(MapMetaScreenData::title_c_side),
+            $field,
+            |s| s.title_c_side
+        );
+        edit_nested_text!(
+            $cx,
+            "Full Clear Title",
+            screen.then(MapMetaScreenData::title_full_clear),
+            $field,
+            |s| s.title_full_clear
+        );
+    };
+}
+
+/// The settings which live in the map.meta.yaml next to the map. Most of them are only read by
+/// Everest, and the file is only written once one of them is set.
+fn meta_yaml_tweaker(cx: &mut Context) {
+    Label::new(cx, "map.meta.yaml").class("module_category");
+    edit_text!(cx, "Parent", parent);
+    edit_text!(cx, "Jumpthru", jumpthru);
+
+    for side in 0..SIDE_NAMES.len() {
+        Label::new(cx, SIDE_NAMES[side]).class("module_category");
+        mode_tweaker(cx, side);
+    }
+
+    Label::new(cx, "Cassette Modifier").class("module_category");
+    let modifier = current_map_impl_lens().then(MapStateData::cassette_modifier);
+    edit_nested_text!(
+        cx,
+        "Tempo Multiplier",
+        modifier.then(MapMetaCassetteModifierData::tempo_mult),
+        cassette_modifier,
+        |m| m.tempo_mult
+    );
+    edit_nested_text!(
+        cx,
+        "Lead Beats",
+        modifier.then(MapMetaCassetteModifierData::lead_beats),
+        cassette_modifier,
+        |m| m.lead_beats
+    );
+    edit_nested_text!(
+        cx,
+        "Beats Per Tick",
+        modifier.then(MapMetaCassetteModifierData::beats_per_tick),
+        cassette_modifier,
+        |m| m.beats_per_tick
+    );
+    edit_nested_text!(
+        cx,
+        "Ticks Per Swap",
+        modifier.then(MapMetaCassetteModifierData::ticks_per_swap),
+        cassette_modifier,
+        |m| m.ticks_per_swap
+    );
+    edit_nested_text!(
+        cx,
+        "Blocks",
+        modifier.then(MapMetaCassetteModifierData::blocks),
+        cassette_modifier,
+        |m| m.blocks
+    );
+    edit_nested_text!(
+        cx,
+        "Beats Max",
+        modifier.then(MapMetaCassetteModifierData::beats_max),
+        cassette_modifier,
+        |m| m.beats_max
+    );
+    edit_nested_text!(
+        cx,
+        "Beat Index Offset",
+        modifier.then(MapMetaCassetteModifierData::beat_index_offset),
+        cassette_modifier,
+        |m| m.beat_index_offset
+    );
+    edit_nested_check!(
+        cx,
+        "Old Behavior",
+        modifier.then(MapMetaCassetteModifierData::old_behavior),
+        cassette_modifier,
+        |m| m.old_behavior
+    );
+
+    Label::new(cx, "Mountain").class("module_category");
+    let mountain = current_map_impl_lens().then(MapStateData::mountain);
+    edit_nested_text!(
+        cx,
+        "Model Directory",
+        mountain.then(MapMetaMountainData::model_directory),
+        mountain,
+        |m| m.model_directory
+    );
+    edit_nested_text!(
+        cx,
+        "Texture Directory",
+        mountain.then(MapMetaMountainData::texture_directory),
+        mountain,
+        |m| m.texture_directory
+    );
+    edit_nested_text_dropdown!(
+        cx,
+        "Background Music",
+        mountain.then(MapMetaMountainData::background_music),
+        MUSIC_OPTIONS,
+        mountain,
+        |m| m.background_music
+    );
+    edit_nested_text_dropdown!(
+        cx,
+        "Background Ambience",
+        mountain.then(MapMetaMountainData::background_ambience),
+        AMBIENCE_OPTIONS,
+        mountain,
+        |m| m.background_ambience
+    );
+    edit_nested_text!(
+        cx,
+        "Star Fog Color",
+        mountain.then(MapMetaMountainData::star_fog_color),
+        mountain,
+        |m| m.star_fog_color
+    );
+    edit_nested_check!(
+        cx,
+        "Show Core",
+        mountain.then(MapMetaMountainData::show_core),
+        mountain,
+        |m| m.show_core
+    );
+    edit_nested_check!(
+        cx,
+        "Show Snow",
+        mountain.then(MapMetaMountainData::show_snow),
+        mountain,
+        |m| m.show_snow
+    );
+
+    Label::new(cx, "Complete Screen").class("module_category");
+    screen_tweaker!(cx, complete_screen);
+    Label::new(cx, "Loading Vignette Screen").class("module_category");
+    screen_tweaker!(cx, loading_vignette_screen);
+}
+
+fn mode_tweaker(cx: &mut Context, side: usize) {
+    let mode = current_map_impl_lens()
+        .then(MapStateData::modes)
+        .index(side);
+    edit_nested_check!(
+        cx,
+        "Heart Is End",
+        mode.then(MapMetaModeData::heart_is_end),
+        modes,
+        |modes| modes[side].heart_is_end
+    );
+    edit_nested_text_dropdown!(
+        cx,
+        "Inventory",
+        mode.then(MapMetaModeData::inventory),
+        INVENTORY_OPTIONS,
+        modes,
+        |modes| modes[side].inventory
+    );
+    edit_nested_text!(
+        cx,
+        "Start Level",
+        mode.then(MapMetaModeData::start_level),
+        modes,
+        |modes| modes[side].start_level
+    );
+    edit_nested_check!(
+        cx,
+        "Seeker Slowdown",
+        mode.then(MapMetaModeData::seeker_slowdown),
+        modes,
+        |modes| modes[side].seeker_slowdown
+    );
+    edit_nested_check!(
+        cx,
+        "Theo In Bubble",
+        mode.then(MapMetaModeData::theo_in_bubble),
+        modes,
+        |modes| modes[side].theo_in_bubble
+    );
+    edit_nested_check!(
+        cx,
+        "Ignore Level Audio Layer Data",
+        mode.then(MapMetaModeData::ignore_level_audio_layer_data),
+        modes,
+        |modes| modes[side].ignore_level_audio_layer_data
+    );
+    edit_nested_text!(
+        cx,
+        "Path",
+        mode.then(MapMetaModeData::path),
+        modes,
+        |modes| modes[side].path
+    );
+    edit_nested_text!(
+        cx,
+        "Poem ID",
+        mode.then(MapMetaModeData::poem_id),
+        modes,
+        |modes| modes[side].poem_id
+    );
+    edit_nested_text_dropdown!(
+        cx,
+        "Music",
+        mode.then(MapMetaModeData::music),
+        MUSIC_OPTIONS,
+        modes,
+        |modes| modes[side].music
+    );
+    edit_nested_text_dropdown!(
+        cx,
+        "Ambience",
+        mode.then(MapMetaModeData::ambience),
+        AMBIENCE_OPTIONS,
+        modes,
+        |modes| modes[side].ambience
+    );
+
+    Label::new(cx, "Checkpoints");
+    List::new(
+        cx,
+        mode.then(MapMetaModeData::checkpoints),
+        move |cx, idx, item| {
+            VStack::new(cx, move |cx| {
+                HStack::new(cx, move |cx| {
+                    Label::new(cx, &format!("Checkpoint {}", idx + 1)).class("label");
+                    Label::new(cx, MINUS)
+                        .class("icon")
+                        .class("remove_btn")
+                        .on_press(move |cx| {
+                            remove_checkpoint(cx.as_mut(), side, idx);
+                        });
+                });
+                edit_nested_text!(
+                    cx,
+                    "Level",
+                    item.then(MapMetaCheckpointData::level),
+                    modes,
+                    |modes| modes[side].checkpoints[idx].level
+                );
+                edit_nested_text!(
+                    cx,
+                    "Name",
+                    item.then(MapMetaCheckpointData::name),
+                    modes,
+                    |modes| modes[side].checkpoints[idx].name
+                );
+                edit_nested_check!(
+                    cx,
+                    "Dreaming",
+                    item.then(MapMetaCheckpointData::dreaming),
+                    modes,
+                    |modes| modes[side].checkpoints[idx].dreaming
+                );
+                edit_nested_text_dropdown!(
+                    cx,
+                    "Inventory",
+                    item.then(MapMetaCheckpointData::inventory),
+                    INVENTORY_OPTIONS,
+                    modes,
+                    |modes| modes[side].checkpoints[idx].inventory
+                );
+                edit_nested_text_dropdown!(
+                    cx,
+                    "Core Mode",
+                    item.then(MapMetaCheckpointData::core_mode),
+                    CORE_MODE_OPTIONS,
+                    modes,
+                    |modes| modes[side].checkpoints[idx].core_mode
+                );
+                edit_nested_text_dropdown!(
+                    cx,
+                    "Music",
+                    item.then(MapMetaCheckpointData::music),
+                    MUSIC_OPTIONS,
+                    modes,
+                    |modes| modes[side].checkpoints[idx].music
+                );
+                edit_nested_text_dropdown!(
+                    cx,
+                    "Ambience",
+                    item.then(MapMetaCheckpointData::ambience),
+                    AMBIENCE_OPTIONS,
+                    modes,
+                    |modes| modes[side].checkpoints[idx].ambience
+                );
+            });
+        },
+    );
+    Button::new(
+        cx,
+        move |cx| add_checkpoint(cx, side),
+        |cx| Label::new(cx, "+ Checkpoint"),
+    );
+}
+
+fn add_checkpoint(cx: &mut EventContext, side: usize) {
+    edit_field!(cx, modes, |modes| modes[side]
+        .checkpoints
+        .push(MapMetaCheckpointData::default()));
+}
+
+fn remove_checkpoint(cx: &mut EventContext, side: usize, idx: usize) {
+    edit_field!(cx, modes, |modes| modes[side].checkpoints.remove(idx));
+}
+
+fn map_checks(cx: &mut Context, map: MapID) {
+    Label::new(cx, "Checks").class("module_category");
+    for rule in enum_iterator::all::<LintRule>() {
+        let lens = AppState::config
+            .then(AutoSaverLens::new())
+            .then(AppConfig::disabled_lints)
+            .map(move |disabled| !disabled.contains(&rule));
+        tweak_attr_check(cx, rule.description(), lens, move |cx, enabled| {
+            let mut disabled = cx.data::<AppState>().unwrap().config.disabled_lints.clone();
+            disabled.retain(|other| *other != rule);
+            if !enabled {
+                disabled.push(rule);
+            }
+            cx.emit(AppEvent::EditSettings {
+                setter: AppConfigSetter::DisabledLints(disabled),
+            });
+        });
+    }
+
+    VStack::new(cx, move |cx| {
+        ModelContainer::<Option<Vec<LintFinding>>> { val: None }.build(cx);
+        Button::new(
+            cx,
+            move |cx| {
+                let app = cx.data::<AppState>().unwrap();
+                let state = &app.loaded_maps[&map];
+                let findings = lint_map(
+                    &state.data.clone().into(),
+                    Some(&state.cache.palette),
+                    &app.config.disabled_lints,
+                );
+                cx.emit(ModelContainerSetter::Val(Some(findings)));
+            },
+            move |cx| Label::new(cx, "Run Checks"),
+        );
+        HStack::new(cx, move |cx| {
+            Button::new(
+                cx,
+                move |cx| renumber_ids(cx, map, false),
+                move |cx| Label::new(cx, "Fix Duplicate IDs"),
+            );
+            Button::new(
+                cx,
+                move |cx| renumber_ids(cx, map, true),
+                move |cx| Label::new(cx, "Compact IDs"),
+            );
+        });
+        Binding::new(
+            cx,
+            ModelContainer::<Option<Vec<LintFinding>>>::val,
+            move |cx, findings| {
+                let Some(findings) = findings.get(cx) else { return };
+                if findings.is_empty() {
+                    Label::new(cx, "No problems found");
+                }
+                for finding in findings {
+                    Label::new(cx, &finding.to_string())
+                        .class("lint_finding")
+                        .on_press(move |cx| {
+                            cx.emit(AppEvent::MapEvent {
+                                map: Some(map),
+                                event: MapEvent::JumpTo {
+                                    room: finding.room,
+                                    selection: finding.selection,
+                                },
+                            })
+                        });
+                }
+            },
+        );
+    });
+}
+
+/// Compacting changes every id, which forgets what players have collected in a released map, so
+/// only fixing duplicates is the safe choice there.
+fn renumber_ids(cx: &mut EventContext, map: MapID, compact: bool) {
+    let levels = &cx.data::<AppState>().unwrap().loaded_maps[&map].data.levels;
+    let mut rooms = levels
+        .iter()
+        .map(|room| room.data.clone())
+        .collect::<Vec<_>>();
+    let count = renumber_entity_ids(&mut rooms, compact);
+    let events = rooms
+        .into_iter()
+        .zip(levels)
+        .enumerate()
+        .filter(|(_, (room, old))| {
+            room.entities != old.data.entities || room.triggers != old.data.triggers
+        })
+        .map(|(idx, (room, _))| MapAction::RoomAction {
+            idx,
+            event: RoomAction::SetEntities {
+                entities: room.entities,
+                triggers: room.triggers,
+            },
+        })
+        .collect::<Vec<_>>();
+    log::info!("Renumbered {} entities and triggers", count);
+    if !events.is_empty() {
+        cx.emit(AppEvent::MapEvent {
+            map: Some(map),
+            event: MapEvent::Action {
+                event: events,
+                merge_phase: EventPhase::new(),
+            },
+        });
+    }
+}
+
+fn map_statistics(cx: &mut Context, map: MapID) {
+    Label::new(cx, "Statistics").class("module_category");
+    VStack::new(cx, move |cx| {
+        ModelContainer::<Option<MapStats>> { val: None }.build(cx);
+        Button::new(
+            cx,
+            move |cx| {
+                let app = cx.data::<AppState>().unwrap();
+                let stats = map_stats(&app.loaded_maps[&map].data.clone().into());
+                cx.emit(ModelContainerSetter::Val(Some(stats)));
+            },
+            move |cx| Label::new(cx, "Count"),
+        );
+        Binding::new(
+            cx,
+            ModelContainer::<Option<MapStats>>::val,
+            move |cx, stats| {
+                let Some(stats) = stats.get(cx) else { return };
+                for line in stats.to_string().lines() {
+                    Label::new(cx, line);
+                }
+                for berry in stats.strawberries {
+                    Label::new(cx, &berry.to_string())
+                        .class("lint_finding")
+                        .on_press(move |cx| {
+                            let app = cx.data::<AppState>().unwrap();
+                            let room = app.loaded_maps[&map]
+                                .data
+                                .levels
+                                .iter()
+                                .position(|room| room.data.name == berry.room);
+                            cx.emit(AppEvent::MapEvent {
+                                map: Some(map),
+                                event: MapEvent::JumpTo {
+                                    room,
+                                    selection: Some(AppSelection::EntityBody(berry.id, false)),
+                                },
+                            })
+                        });
+                }
+                for room in stats.room_stats {
+                    Label::new(cx, &format!("Room {}", room.name)).class("stats_room");
+                    for line in room.to_string().lines() {
+                        Label::new(cx, line);
+                    }
+                }
+            },
+        );
+    });
+}
+
 fn map_deleter(cx: &mut Context, map: MapID) {
     let app = cx.data::<AppState>().unwrap();
     if app.modules[&app.loaded_maps[&map].cache.path.module]
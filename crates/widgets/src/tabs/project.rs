@@ -1,10 +1,21 @@
-use arborio_modloader::module::{CelesteModuleKind, MapPath, ModuleID};
+use arborio_maploader::map_struct::CelesteMap;
+use arborio_maploader::stats::{map_stats, MapStats};
+use arborio_modloader::aggregate::ModuleAggregate;
+use arborio_modloader::dependencies::{
+    detect_dependencies, resolve_module_dependencies, DetectedDependencies,
+};
+use arborio_modloader::module::{CelesteModule, CelesteModuleKind, MapPath, ModuleID};
+use arborio_modloader::packaging::{module_files, pack_module, PackIgnore, IGNORE_FILE};
 use arborio_state::data::app::{AppEvent, AppState};
 use arborio_state::data::project_map::ProjectEvent;
 use arborio_state::lenses::StaticerLens;
+use arborio_state::release::release_problems;
 use arborio_utils::vizia::prelude::*;
 use arborio_widgets_common::confirm_delete::deleter;
+use arborio_widgets_common::container_model::{ModelContainer, ModelContainerSetter};
 use arborio_widgets_common::label_with_pencil::label_with_pencil;
+use dialog::DialogBox;
+use std::path::Path;
 
 pub fn build_project_tab(cx: &mut Context, project: ModuleID) {
     ScrollView::new(cx, 0.0, 0.0, false, true, move |cx| {
@@ -12,6 +23,8 @@ pub fn build_project_tab(cx: &mut Context, project: ModuleID) {
             Binding::new(cx, AppState::modules_version, move |cx, _| {
                 build_title(cx, project);
                 build_map_list(cx, project);
+                build_dependencies(cx, project);
+                build_statistics(cx, project);
                 build_controls(cx, project);
             });
         })
@@ -135,6 +148,282 @@ fn build_map_list(cx: &mut Context, project: ModuleID) {
     }
 }
 
+/// Everything the project needs, directly or not, and anything which would stop Everest loading it.
+fn build_dependencies(cx: &mut Context, project: ModuleID) {
+    let app = cx.data::<AppState>().unwrap();
+    let resolved = resolve_module_dependencies(&app.modules, &app.modules_lookup, project);
+    let installed = resolved
+        .modules
+        .iter()
+        .map(|name| {
+            let version = app
+                .modules_lookup
+                .get(name)
+                .and_then(|id| app.modules.get(id))
+                .map(|module| module.everest_metadata.version.to_string())
+                .unwrap_or_default();
+            format!("{name} {version}")
+        })
+        .collect::<Vec<_>>();
+
+    let editable = matches!(
+        app.modules[&project].module_kind(),
+        CelesteModuleKind::Directory
+    );
+
+    Label::new(cx, "Dependencies").class("module_category");
+    VStack::new(cx, move |cx| {
+        if installed.is_empty() && resolved.problems.is_empty() {
+            Label::new(cx, "None");
+        }
+        for line in installed {
+            Label::new(cx, &line);
+        }
+        for problem in resolved.problems {
+            Label::new(cx, &problem.to_string()).class("dependency_problem");
+        }
+        if !editable {
+            return;
+        }
+
+        ModelContainer::<Option<DependencyPreview>> { val: None }.build(cx);
+        Button::new(
+            cx,
+            move |cx| {
+                let preview =
+                    project_detected_dependencies(cx.data::<AppState>().unwrap(), project);
+                cx.emit(ModelContainerSetter::Val(Some(preview)));
+            },
+            move |cx| Label::new(cx, "Detect"),
+        );
+        Binding::new(
+            cx,
+            ModelContainer::<Option<DependencyPreview>>::val,
+            move |cx, preview| {
+                let Some(preview) = preview.get(cx) else { return };
+                for sid in &preview.failed {
+                    Label::new(
+                        cx,
+                        &format!("Could not load {sid}, so no removals are suggested"),
+                    )
+                    .class("dependency_problem");
+                }
+                for line in detected_lines(&preview.detected) {
+                    Label::new(cx, &line);
+                }
+                for name in preview.detected.remove.clone() {
+                    let ticked = ModelContainer::<Option<DependencyPreview>>::val.map({
+                        let name = name.clone();
+                        move |preview| {
+                            let mut confirmed = preview.iter().flat_map(|p| &p.confirmed_removals);
+                            confirmed.any(|other| *other == name)
+                        }
+                    });
+                    HStack::new(cx, move |cx| {
+                        let label = format!("Remove {name}: nothing in the maps uses it");
+                        Checkbox::new(cx, ticked).on_toggle(move |cx| toggle_removal(cx, &name));
+                        Label::new(cx, &label);
+                    });
+                }
+                if preview.detected.is_empty() {
+                    Label::new(cx, "Dependencies are up to date");
+                    return;
+                }
+                Button::new(
+                    cx,
+                    move |cx| {
+                        // write what was shown rather than looking at the maps again
+                        let preview = ModelContainer::<Option<DependencyPreview>>::val.get(cx);
+                        let Some(preview) = preview else { return };
+                        let app = cx.data::<AppState>().unwrap();
+                        let mut metadata = app.modules[&project].everest_metadata.clone();
+                        preview
+                            .detected
+                            .apply(&mut metadata, &preview.confirmed_removals);
+                        cx.emit(AppEvent::ProjectEvent {
+                            project: Some(project),
+                            event: ProjectEvent::SetDependencies {
+                                dependencies: metadata.dependencies,
+                            },
+                        });
+                        cx.emit(ModelContainerSetter::Val(None::<DependencyPreview>));
+                    },
+                    move |cx| Label::new(cx, "Apply"),
+                );
+            },
+        );
+    })
+    .id("project_dependencies");
+}
+
+/// What Detect found, kept so that Apply makes exactly the changes which were shown.
+#[derive(Debug, Clone, PartialEq)]
+struct DependencyPreview {
+    detected: DetectedDependencies,
+    /// The maps which couldn't be loaded. What they use is unknown, so no removals are suggested.
+    failed: Vec<String>,
+    /// The suggested removals which have been ticked. Nothing is removed otherwise.
+    confirmed_removals: Vec<String>,
+}
+
+impl Data for DependencyPreview {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+fn toggle_removal(cx: &mut EventContext, name: &str) {
+    let preview = ModelContainer::<Option<DependencyPreview>>::val.get(cx);
+    let Some(mut preview) = preview else { return };
+    match preview
+        .confirmed_removals
+        .iter()
+        .position(|other| other == name)
+    {
+        Some(idx) => {
+            preview.confirmed_removals.remove(idx);
+        }
+        None => preview.confirmed_removals.push(name.to_owned()),
+    }
+    cx.emit(ModelContainerSetter::Val(Some(preview)));
+}
+
+/// Compare the project's dependencies with what its maps use. Like the statistics, open maps are
+/// looked at as they are in the editor.
+fn project_detected_dependencies(app: &AppState, project: ModuleID) -> DependencyPreview {
+    let module = &app.modules[&project];
+    let mut maps: Vec<CelesteMap> = vec![];
+    let mut failed = vec![];
+    for sid in &module.maps {
+        let path = MapPath {
+            module: project,
+            sid: sid.clone(),
+        };
+        if let Some(map) = app
+            .loaded_maps_lookup
+            .get(&path)
+            .and_then(|id| app.loaded_maps.get(id))
+        {
+            maps.push(map.data.clone().into());
+        } else if let Some(root) = &module.filesystem_root {
+            match CelesteModule::load_map_static(root, sid) {
+                Ok((map, _)) => maps.push(map),
+                Err(e) => {
+                    log::warn!("Could not load {}: {}", sid, e);
+                    failed.push(sid.clone());
+                }
+            }
+        }
+    }
+    let mut detected = detect_dependencies(&app.modules, project, &maps);
+    if !failed.is_empty() {
+        detected.remove.clear();
+    }
+    DependencyPreview {
+        detected,
+        failed,
+        confirmed_removals: vec![],
+    }
+}
+
+/// The suggested additions and what nothing provides. Removals get a checkbox each instead.
+fn detected_lines(detected: &DetectedDependencies) -> Vec<String> {
+    let mut lines = vec![];
+    for needed in &detected.add {
+        lines.push(format!(
+            "Add {} {}: uses {}",
+            needed.name,
+            needed.version.to_string(),
+            needed.uses.join(", ")
+        ));
+    }
+    for used in &detected.unknown {
+        lines.push(format!("No installed mod provides {used}"));
+    }
+    lines
+}
+
+fn build_statistics(cx: &mut Context, project: ModuleID) {
+    Label::new(cx, "Statistics").class("module_category");
+    VStack::new(cx, move |cx| {
+        ModelContainer::<Option<Vec<String>>> { val: None }.build(cx);
+        Button::new(
+            cx,
+            move |cx| {
+                let lines = project_statistics(cx.data::<AppState>().unwrap(), project);
+                cx.emit(ModelContainerSetter::Val(Some(lines)));
+            },
+            move |cx| Label::new(cx, "Count"),
+        );
+        Binding::new(
+            cx,
+            ModelContainer::<Option<Vec<String>>>::val,
+            move |cx, lines| {
+                for line in lines.get(cx).into_iter().flatten() {
+                    Label::new(cx, &line);
+                }
+            },
+        );
+    })
+    .id("project_statistics");
+}
+
+/// One line of collectible counts per map, then the totals. Open maps are counted as they are in
+/// the editor, unsaved changes included; the rest are loaded from disk.
+fn project_statistics(app: &AppState, project: ModuleID) -> Vec<String> {
+    let module = &app.modules[&project];
+    let mut maps = module.maps.to_vec();
+    maps.sort();
+
+    let summary = |stats: &MapStats| {
+        format!(
+            "{} strawberries, {} golden, {} moon, {} cassettes, {} hearts, {} checkpoints, {} rooms",
+            stats.strawberries.len(),
+            stats.golden_berries,
+            stats.moon_berries,
+            stats.cassettes,
+            stats.hearts,
+            stats.checkpoints,
+            stats.rooms,
+        )
+    };
+    let mut total = MapStats::default();
+    let mut lines = vec![];
+    for sid in maps {
+        let path = MapPath {
+            module: project,
+            sid: sid.clone(),
+        };
+        let stats = if let Some(map) = app
+            .loaded_maps_lookup
+            .get(&path)
+            .and_then(|id| app.loaded_maps.get(id))
+        {
+            map_stats(&map.data.clone().into())
+        } else if let Some(root) = &module.filesystem_root {
+            match CelesteModule::load_map_static(root, &sid) {
+                Ok((map, _)) => map_stats(&map),
+                Err(e) => {
+                    lines.push(format!("{sid}: could not load: {e}"));
+                    continue;
+                }
+            }
+        } else {
+            continue;
+        };
+        lines.push(format!("{sid}: {}", summary(&stats)));
+        total.strawberries.extend(stats.strawberries);
+        total.golden_berries += stats.golden_berries;
+        total.moon_berries += stats.moon_berries;
+        total.cassettes += stats.cassettes;
+        total.hearts += stats.hearts;
+        total.checkpoints += stats.checkpoints;
+        total.rooms += stats.rooms;
+    }
+    lines.push(format!("Total: {}", summary(&total)));
+    lines
+}
+
 fn build_controls(cx: &mut Context, project: ModuleID) {
     let module = cx
         .data::<AppState>()
@@ -146,6 +435,24 @@ fn build_controls(cx: &mut Context, project: ModuleID) {
     let editing = matches!(module.module_kind(), CelesteModuleKind::Directory);
     VStack::new(cx, move |cx| {
         if editing {
+            ModelContainer::<Option<Vec<String>>> { val: None }.build(cx);
+            Button::new(
+                cx,
+                move |cx| {
+                    let lines = package_project(cx.data::<AppState>().unwrap(), project);
+                    cx.emit(ModelContainerSetter::Val(Some(lines)));
+                },
+                move |cx| Label::new(cx, "Package"),
+            );
+            Binding::new(
+                cx,
+                ModelContainer::<Option<Vec<String>>>::val,
+                move |cx, lines| {
+                    for line in lines.get(cx).into_iter().flatten() {
+                        Label::new(cx, &line);
+                    }
+                },
+            );
             deleter(
                 cx,
                 "Delete Project",
@@ -162,3 +469,49 @@ fn build_controls(cx: &mut Context, project: ModuleID) {
     })
     .id("project_controls");
 }
+
+/// Check the project over and, if nothing is wrong, zip it up where the user picks. Returns what
+/// happened, or what has to be fixed first.
+fn package_project(app: &AppState, project: ModuleID) -> Vec<String> {
+    let module = &app.modules[&project];
+    let Some(root) = module.unpacked() else { return vec![] };
+
+    let mut problems = app
+        .loaded_maps
+        .values()
+        .filter(|map| map.cache.path.module == project && map.cache.dirty)
+        .map(|map| format!("{} has unsaved changes", map.cache.path.sid))
+        .collect::<Vec<_>>();
+    let ignore = match PackIgnore::load(root) {
+        Ok(ignore) => ignore,
+        Err(e) => return vec![format!("Could not read {IGNORE_FILE}: {e}")],
+    };
+    let files = match module_files(root, &ignore) {
+        Ok(files) => files,
+        Err(e) => return vec![format!("Could not read the project: {e}")],
+    };
+    let palette = ModuleAggregate::new(&app.modules, &app.modules_lookup, &None, project, false);
+    problems.extend(release_problems(
+        root,
+        &files,
+        Some(&palette),
+        &app.config.disabled_lints,
+    ));
+    if !problems.is_empty() {
+        return problems;
+    }
+
+    let output = dialog::FileSelection::new("Save Release Zip")
+        .mode(dialog::FileSelectionMode::Save)
+        .path(root.parent().unwrap_or(root))
+        .show();
+    let output = match output {
+        Ok(Some(output)) => output,
+        Ok(None) => return vec![],
+        Err(e) => return vec![format!("Could not ask where to save: {e}")],
+    };
+    match pack_module(root, Path::new(&output), &ignore) {
+        Ok(count) => vec![format!("Packed {count} files into {output}")],
+        Err(e) => vec![format!("Could not pack the project: {e}")],
+    }
+}
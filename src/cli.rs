@@ -0,0 +1,771 @@
+use arborio_maploader::binel::{parser, writer, xml, BinFile};
+use arborio_maploader::diff::diff_maps;
+use arborio_maploader::ids::renumber_entity_ids;
+use arborio_maploader::map_struct::{
+    from_binfile_tolerant, from_reader_tolerant, save_as, save_to, CelesteMap,
+};
+use arborio_maploader::merge::merge_maps;
+use arborio_maploader::meta_yaml;
+use arborio_maploader::repair::MapWarning;
+use arborio_maploader::stats::map_stats;
+use arborio_maploader::tmx::{map_from_tmx, map_to_tmx};
+use arborio_modloader::aggregate::ModuleAggregate;
+use arborio_modloader::dependencies::detect_dependencies;
+use arborio_modloader::discovery;
+use arborio_modloader::module::{CelesteModule, ModuleID};
+use arborio_modloader::packaging::{module_files, pack_module, PackIgnore, IGNORE_FILE};
+use arborio_state::lint::{lint_map, LintRule};
+use arborio_state::release::release_problems;
+use arborio_state::rendering::render_map;
+use arborio_walker::open_module;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = "\
+usage: arborio [<command> [<args>...]]
+
+Run without a command to open the editor.
+
+commands:
+    convert <input> <output>     load a map and save it again. if either side is
+                                 a .xml file, convert between .bin and xml text.
+                                 if either side is a .tmx file, convert the
+                                 rooms' tiles, entities and triggers to or from
+                                 a Tiled map
+    diff <old> <new>             list what changed between two maps, room by room
+    merge <base> <ours> <theirs>
+                                 three-way merge two edited copies of a map and
+                                 save the result over <ours>, or to --output.
+                                 exits with 1 if anything conflicted. works as
+                                 a git merge driver: set merge.celeste.driver to
+                                 \"arborio merge %O %A %B\" and mark maps with
+                                 merge=celeste in .gitattributes
+    lint <map>...                check that maps load cleanly and look for
+                                 mistakes such as duplicate ids, objects
+                                 outside their room and rooms without a spawn.
+                                 --allow <rule>,... skips the named rules:
+                                 duplicate-id, out-of-bounds, overlapping-rooms,
+                                 room-size, no-spawn, missing-room,
+                                 unknown-entity, missing-texture. the last two
+                                 need --celeste-root
+    stats <map>...               count rooms, tiles, strawberries (in the order
+                                 the game numbers them), goldens, moon berries,
+                                 cassettes, hearts and checkpoints, and list the
+                                 entities, triggers and decals in each room
+    renumber <map>               give entities that share an id new ones and
+                                 update attributes that refer to them, saving
+                                 over <map> or to --output. --compact numbers
+                                 every entity again from 1 instead
+    render <map> -o <out.png>    draw a map, or just the room named by --room,
+                                 to a png image. needs --celeste-root
+    pack <mod> <output.zip>      zip an unpacked mod folder for release, leaving
+                                 out backups, version control and editor-only
+                                 files as well as anything matching a pattern in
+                                 the mod's .arborioignore. first checks its
+                                 everest.yaml and lints its maps, taking --allow
+                                 like lint; --force packs despite problems
+    deps <mod>                   work out which installed mods the maps of <mod>
+                                 use and suggest dependencies to add to or remove
+                                 from its everest.yaml. --write makes the
+                                 additions, and removes only the suggested
+                                 removals named by --remove <mod>,... since a
+                                 mod may be needed for its code. stops if any
+                                 map fails to load. needs --celeste-root
+    help                         show this message
+
+options:
+    --celeste-root <dir>         load installed mods so that maps and mods can be
+                                 referred to by SID or name instead of by path
+    --force                      let convert, merge and renumber save maps which
+                                 had to be repaired to load, losing whatever was
+                                 skipped";
+
+/// The commands `run` understands.
+const COMMANDS: &[&str] = &[
+    "convert", "diff", "merge", "lint", "stats", "renumber", "render", "pack", "deps", "help",
+];
+
+/// Whether arborio was started to run a command rather than to open the editor. Anything else
+/// on the command line, such as a file the desktop asked it to open, is left to the editor.
+pub fn is_command_line(args: &[String]) -> bool {
+    let Some(first) = args.first() else { return false };
+    COMMANDS.contains(&first.as_str()) || first == "--help" || first == "-h"
+}
+
+/// Entry point for headless use. Returns the process exit code.
+pub fn run(args: Vec<String>) -> i32 {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{USAGE}");
+        return 0;
+    }
+    let mut args = match Args::parse(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return 2;
+        }
+    };
+    if args.positional.is_empty() {
+        eprintln!("{USAGE}");
+        return 2;
+    }
+    let command = args.positional.remove(0);
+    let result = match command.as_str() {
+        "convert" => convert(&args),
+        "diff" => diff(&args),
+        "merge" => merge(&args),
+        "lint" => lint(&args),
+        "stats" => stats(&args),
+        "renumber" => renumber(&args),
+        "render" => render(&args),
+        "pack" => pack(&args),
+        "deps" => deps(&args),
+        "help" => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        _ => Err(format!("Unknown command {command}\n\n{USAGE}")),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    }
+}
+
+/// Options which are given without a value.
+const FLAGS: &[&str] = &["compact", "write", "force"];
+
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    fn parse(args: Vec<String>) -> Result<Self, String> {
+        let mut positional = vec![];
+        let mut options = HashMap::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if let Some(name) = arg.strip_prefix("--").filter(|name| !name.is_empty()) {
+                if let Some((name, value)) = name.split_once('=') {
+                    options.insert(name.to_owned(), value.to_owned());
+                } else if FLAGS.contains(&name) {
+                    options.insert(name.to_owned(), String::new());
+                } else {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("Missing value for --{name}"))?;
+                    options.insert(name.to_owned(), value);
+                }
+            } else if arg == "-o" {
+                let value = args.next().ok_or("Missing value for -o")?;
+                options.insert("output".to_owned(), value);
+            } else {
+                positional.push(arg);
+            }
+        }
+
+        Ok(Self {
+            positional,
+            options,
+        })
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+
+    fn expect_positional(&self, count: usize, what: &str) -> Result<(), String> {
+        if self.positional.len() == count {
+            Ok(())
+        } else {
+            Err(format!("Expected {what}\n\n{USAGE}"))
+        }
+    }
+
+    fn modules(&self) -> Result<Option<HashMap<ModuleID, CelesteModule>>, String> {
+        let Some(root) = self.option("celeste-root") else {
+            return Ok(None);
+        };
+        let root = Path::new(root);
+        if !root.join("Content").is_dir() {
+            return Err(format!(
+                "{} does not look like a Celeste installation",
+                root.display()
+            ));
+        }
+
+        Ok(Some(discovery::load_all(root, |_, _| {}).0))
+    }
+}
+
+/// Load a map either from a filesystem path or, if modules are loaded, by its SID.
+/// Also returns the package name to save the map under. Anything which had to be repaired or
+/// skipped while loading is logged as a warning.
+fn load_map(
+    spec: &str,
+    modules: Option<&HashMap<ModuleID, CelesteModule>>,
+) -> Result<(String, CelesteMap), String> {
+    let (package, map, warnings) = load_map_tolerant(spec, modules)?;
+    for warning in warnings {
+        log::warn!("{spec}: {warning}");
+    }
+    Ok((package, map))
+}
+
+/// Like `load_map`, for commands which save the map again. Saving would drop anything which
+/// was skipped while loading, so a map which needed repairs is refused unless `--force` is given.
+fn load_map_to_save(
+    args: &Args,
+    spec: &str,
+    modules: Option<&HashMap<ModuleID, CelesteModule>>,
+) -> Result<(String, CelesteMap), String> {
+    let (package, map, warnings) = load_map_tolerant(spec, modules)?;
+    if !warnings.is_empty() && !args.flag("force") {
+        let warnings = warnings
+            .iter()
+            .map(|warning| format!("{spec}: {warning}\n"))
+            .collect::<String>();
+        return Err(format!(
+            "{warnings}{spec} had to be repaired to load and saving it would lose what was \
+             skipped; fix it or save anyway with --force"
+        ));
+    }
+    for warning in warnings {
+        log::warn!("{spec}: {warning}");
+    }
+    Ok((package, map))
+}
+
+/// Like `load_map`, but returns what had to be repaired or skipped instead of logging it.
+fn load_map_tolerant(
+    spec: &str,
+    modules: Option<&HashMap<ModuleID, CelesteModule>>,
+) -> Result<(String, CelesteMap, Vec<MapWarning>), String> {
+    let path = Path::new(spec);
+    if path.is_file() {
+        let (mut map, mut warnings) = if is_xml(path) {
+            from_binfile_tolerant(read_binfile(path)?).map_err(|e| format!("{spec}: {e}"))?
+        } else {
+            let file = File::open(path).map_err(|e| format!("Could not open {spec}: {e}"))?;
+            from_reader_tolerant(BufReader::new(file)).map_err(|e| format!("{spec}: {e}"))?
+        };
+        let sidecar = meta_yaml::sidecar_path(path);
+        if let Ok(file) = File::open(&sidecar) {
+            warnings.extend(meta_yaml::load_into(
+                &mut map,
+                BufReader::new(file),
+                &sidecar.to_string_lossy(),
+            ));
+        }
+        let package = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(spec)
+            .to_owned();
+        return Ok((package, map, warnings));
+    }
+
+    if let Some(modules) = modules {
+        for module in modules.values() {
+            if let Some(root) = &module.filesystem_root {
+                if module.maps.iter().any(|sid| sid == spec) {
+                    let (map, warnings) = CelesteModule::load_map_static(root, spec)
+                        .map_err(|e| format!("{spec}: {e}"))?;
+                    return Ok((spec.to_owned(), map, warnings));
+                }
+            }
+        }
+    }
+
+    Err(format!("No such map: {spec}"))
+}
+
+/// Find an unpacked mod either from a filesystem path or, if modules are loaded, by its
+/// everest.yaml name.
+fn find_mod(
+    spec: &str,
+    modules: Option<&HashMap<ModuleID, CelesteModule>>,
+) -> Result<PathBuf, String> {
+    let path = Path::new(spec);
+    if path.is_dir() {
+        return Ok(path.to_path_buf());
+    }
+
+    if let Some(modules) = modules {
+        if let Some(module) = modules
+            .values()
+            .find(|module| module.everest_metadata.name == spec)
+        {
+            return module
+                .unpacked()
+                .map(Path::to_path_buf)
+                .ok_or_else(|| format!("{spec} is not an unpacked mod"));
+        }
+    }
+
+    Err(format!("No such mod: {spec}"))
+}
+
+fn is_xml(path: &Path) -> bool {
+    matches!(path.extension().and_then(OsStr::to_str), Some(ext) if ext.eq_ignore_ascii_case("xml"))
+}
+
+fn is_tmx(path: &Path) -> bool {
+    matches!(path.extension().and_then(OsStr::to_str), Some(ext) if ext.eq_ignore_ascii_case("tmx"))
+}
+
+/// Read a raw element tree from either a .bin or an .xml file.
+fn read_binfile(path: &Path) -> Result<BinFile, String> {
+    let data =
+        std::fs::read(path).map_err(|e| format!("Could not open {}: {e}", path.display()))?;
+    if is_xml(path) {
+        let text = std::str::from_utf8(&data)
+            .map_err(|e| format!("{}: Not valid utf-8: {e}", path.display()))?;
+        xml::take_file(text).map_err(|e| format!("{}: {e}", path.display()))
+    } else {
+        parser::take_file(&data).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
+
+/// Write a raw element tree to either a .bin or an .xml file.
+fn write_binfile(path: &Path, file: &BinFile) -> Result<(), String> {
+    let mut output = BufWriter::new(
+        File::create(path).map_err(|e| format!("Could not save {}: {e}", path.display()))?,
+    );
+    if is_xml(path) {
+        xml::put_file(&mut output, file)
+    } else {
+        writer::put_file(&mut output, file)
+    }
+    .map_err(|e| format!("Could not save {}: {e}", path.display()))
+}
+
+fn convert(args: &Args) -> Result<(), String> {
+    args.expect_positional(2, "an input and an output")?;
+    let input = Path::new(&args.positional[0]);
+    let output = Path::new(&args.positional[1]);
+    if is_tmx(input) || is_tmx(output) {
+        return convert_tmx(args, input, output);
+    }
+    if is_xml(input) || is_xml(output) {
+        // go through the raw element tree so that nothing is lost or normalized
+        return write_binfile(output, &read_binfile(input)?);
+    }
+
+    let modules = args.modules()?;
+    let (package, map) = load_map_to_save(args, &args.positional[0], modules.as_ref())?;
+    save_as(&map, &package, output).map_err(|e| format!("Could not save {}: {e}", output.display()))
+}
+
+fn convert_tmx(args: &Args, input: &Path, output: &Path) -> Result<(), String> {
+    let (package, map) = if is_tmx(input) {
+        let text = std::fs::read_to_string(input)
+            .map_err(|e| format!("Could not open {}: {e}", input.display()))?;
+        let map = map_from_tmx(&text).map_err(|e| format!("{}: {e}", input.display()))?;
+        let package = input
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default()
+            .to_owned();
+        (package, map)
+    } else {
+        let modules = args.modules()?;
+        load_map_to_save(args, &args.positional[0], modules.as_ref())?
+    };
+
+    if is_tmx(output) {
+        std::fs::write(output, map_to_tmx(&map))
+            .map_err(|e| format!("Could not save {}: {e}", output.display()))
+    } else if is_xml(output) {
+        let mut data = vec![];
+        save_to(&map, &package, &mut data)
+            .map_err(|e| format!("Could not save {}: {e}", output.display()))?;
+        let file = parser::take_file(&data).map_err(|e| format!("{}: {e}", output.display()))?;
+        write_binfile(output, &file)
+    } else {
+        save_as(&map, &package, output)
+            .map_err(|e| format!("Could not save {}: {e}", output.display()))
+    }
+}
+
+fn diff(args: &Args) -> Result<(), String> {
+    args.expect_positional(2, "an old and a new map")?;
+    let modules = args.modules()?;
+    let (_, old) = load_map(&args.positional[0], modules.as_ref())?;
+    let (_, new) = load_map(&args.positional[1], modules.as_ref())?;
+
+    let diff = diff_maps(&old, &new);
+    if diff.is_empty() {
+        println!("No differences");
+        return Ok(());
+    }
+    if !diff.map.is_empty() {
+        println!("map");
+        for change in &diff.map {
+            println!("    {change}");
+        }
+    }
+    for room in &diff.rooms {
+        println!("room {}", room.room);
+        for change in &room.changes {
+            println!("    {change}");
+        }
+    }
+    Ok(())
+}
+
+fn merge(args: &Args) -> Result<(), String> {
+    args.expect_positional(3, "a base, our and their map")?;
+    let modules = args.modules()?;
+    let base_path = Path::new(&args.positional[0]);
+    // git gives an empty file as the base when both sides added the map
+    let base = if matches!(std::fs::metadata(base_path), Ok(meta) if meta.len() == 0) {
+        CelesteMap::default()
+    } else {
+        load_map_to_save(args, &args.positional[0], modules.as_ref())?.1
+    };
+    let (package, ours) = load_map_to_save(args, &args.positional[1], modules.as_ref())?;
+    let (_, theirs) = load_map_to_save(args, &args.positional[2], modules.as_ref())?;
+
+    let (merged, conflicts) = merge_maps(&base, &ours, &theirs);
+    let output = Path::new(args.option("output").unwrap_or(&args.positional[1]));
+    save_as(&merged, &package, output)
+        .map_err(|e| format!("Could not save {}: {e}", output.display()))?;
+
+    if conflicts.is_empty() {
+        Ok(())
+    } else {
+        for conflict in &conflicts {
+            println!("{conflict}");
+        }
+        Err(format!(
+            "{} conflict(s); our side was kept for each",
+            conflicts.len()
+        ))
+    }
+}
+
+fn renumber(args: &Args) -> Result<(), String> {
+    args.expect_positional(1, "a map")?;
+    let modules = args.modules()?;
+    let (package, mut map) = load_map_to_save(args, &args.positional[0], modules.as_ref())?;
+
+    let count = renumber_entity_ids(&mut map.levels, args.flag("compact"));
+    let output = Path::new(args.option("output").unwrap_or(&args.positional[0]));
+    save_as(&map, &package, output)
+        .map_err(|e| format!("Could not save {}: {e}", output.display()))?;
+    println!("Renumbered {count} id(s)");
+    Ok(())
+}
+
+fn lint(args: &Args) -> Result<(), String> {
+    if args.positional.is_empty() {
+        return Err(format!("Expected at least one map\n\n{USAGE}"));
+    }
+    let allowed = allowed_lints(args)?;
+    let modules = args.modules()?;
+    // without the installed mods there's no way to tell which entities and textures exist
+    let palette = modules
+        .as_ref()
+        .map(|modules| ModuleAggregate::new_omni(modules, false));
+
+    let mut failures = 0;
+    for spec in &args.positional {
+        match load_map_tolerant(spec, modules.as_ref()) {
+            Ok((_, map, warnings)) => {
+                let findings = lint_map(&map, palette.as_ref(), &allowed);
+                for warning in &warnings {
+                    println!("{spec}: {warning}");
+                }
+                for finding in &findings {
+                    println!("{spec}: {finding}");
+                }
+                if warnings.is_empty() && findings.is_empty() {
+                    println!("{spec}: ok");
+                } else {
+                    failures += 1;
+                }
+            }
+            Err(e) => {
+                println!("{e}");
+                failures += 1;
+            }
+        }
+    }
+
+    if failures == 0 {
+        Ok(())
+    } else {
+        Err(format!("{failures} map(s) had problems"))
+    }
+}
+
+/// The lint rules named by --allow.
+fn allowed_lints(args: &Args) -> Result<Vec<LintRule>, String> {
+    match args.option("allow") {
+        Some(rules) => rules
+            .split(',')
+            .map(|rule| rule.trim().parse())
+            .collect::<Result<Vec<LintRule>, _>>(),
+        None => Ok(vec![]),
+    }
+}
+
+fn stats(args: &Args) -> Result<(), String> {
+    if args.positional.is_empty() {
+        return Err(format!("Expected at least one map\n\n{USAGE}"));
+    }
+    let modules = args.modules()?;
+
+    for spec in &args.positional {
+        let (_, map) = load_map(spec, modules.as_ref())?;
+        let stats = map_stats(&map);
+        println!("{spec}");
+        for line in stats.to_string().lines() {
+            println!("    {line}");
+        }
+        if !stats.strawberries.is_empty() {
+            println!("    strawberries in order:");
+            for berry in &stats.strawberries {
+                println!("        {berry}");
+            }
+        }
+        for room in &stats.room_stats {
+            println!("    room {}", room.name);
+            for line in room.to_string().lines() {
+                println!("        {line}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn render(args: &Args) -> Result<(), String> {
+    args.expect_positional(1, "a map")?;
+    let output = args
+        .option("output")
+        .ok_or_else(|| format!("Expected --output <out.png>\n\n{USAGE}"))?;
+    let Some(modules) = args.modules()? else {
+        return Err(format!(
+            "render needs --celeste-root to load the game's graphics\n\n{USAGE}"
+        ));
+    };
+    let spec = &args.positional[0];
+    let (_, map) = load_map(spec, Some(&modules))?;
+    let room = match args.option("room") {
+        Some(name) => Some(
+            map.levels
+                .iter()
+                .position(|room| room.name == name || room.name == format!("lvl_{name}"))
+                .ok_or_else(|| format!("{spec} has no room {name}"))?,
+        ),
+        None => None,
+    };
+
+    let palette = ModuleAggregate::new_omni(&modules, false);
+    let image = render_map(&palette, &map, room)?;
+    image.save_png(Path::new(output))?;
+    println!("Rendered {spec} to {output}");
+    Ok(())
+}
+
+fn pack(args: &Args) -> Result<(), String> {
+    args.expect_positional(2, "a mod and an output zip")?;
+    let allowed = allowed_lints(args)?;
+    let mut modules = args.modules()?;
+    let root = find_mod(&args.positional[0], modules.as_ref())?;
+    let output = Path::new(&args.positional[1]);
+    let ignore = PackIgnore::load(&root)
+        .map_err(|e| format!("Could not read {}: {e}", root.join(IGNORE_FILE).display()))?;
+    let files = module_files(&root, &ignore)
+        .map_err(|e| format!("Could not read {}: {e}", root.display()))?;
+
+    let palette = match &mut modules {
+        Some(modules) => Some(dependency_aggregate(modules, &root)?),
+        None => None,
+    };
+    let problems = release_problems(&root, &files, palette.as_ref(), &allowed);
+    for problem in &problems {
+        println!("{problem}");
+    }
+    if !problems.is_empty() && !args.flag("force") {
+        return Err(format!(
+            "{} problem(s) found; fix them or pack anyway with --force",
+            problems.len()
+        ));
+    }
+
+    let count = pack_module(&root, output, &ignore)
+        .map_err(|e| format!("Could not pack {}: {e}", root.display()))?;
+    println!("Packed {count} files into {}", output.display());
+    Ok(())
+}
+
+/// What the mod at `root` can use: itself and its dependencies, as the editor lints it with. A mod
+/// which isn't installed is loaded into `modules` first.
+fn dependency_aggregate(
+    modules: &mut HashMap<ModuleID, CelesteModule>,
+    root: &Path,
+) -> Result<ModuleAggregate, String> {
+    let canonical = root.canonicalize().ok();
+    let installed = modules.iter().find(|(_, module)| {
+        canonical.is_some()
+            && module
+                .filesystem_root
+                .as_ref()
+                .and_then(|path| path.canonicalize().ok())
+                == canonical
+    });
+    let id = match installed {
+        Some((id, _)) => *id,
+        None => {
+            let mut source =
+                open_module(root).ok_or_else(|| format!("{} is not a mod", root.display()))?;
+            discovery::load_into(&mut source, modules, &mut HashMap::new())
+                .map_err(|(_, e)| format!("Could not load {}: {e}", root.display()))?
+        }
+    };
+    let modules_lookup = modules
+        .iter()
+        .map(|(id, module)| (module.everest_metadata.name.clone(), *id))
+        .collect();
+    Ok(ModuleAggregate::new(
+        modules,
+        &modules_lookup,
+        &None,
+        id,
+        false,
+    ))
+}
+
+fn deps(args: &Args) -> Result<(), String> {
+    args.expect_positional(1, "a mod")?;
+    let Some(modules) = args.modules()? else {
+        return Err(format!(
+            "deps needs --celeste-root to know what the installed mods provide\n\n{USAGE}"
+        ));
+    };
+    let spec = &args.positional[0];
+    let path = Path::new(spec).canonicalize().ok();
+    let (id, module) = modules
+        .iter()
+        .find(|(_, module)| {
+            module.everest_metadata.name == *spec
+                || (path.is_some() && module.filesystem_root == path)
+        })
+        .ok_or_else(|| format!("No such mod: {spec}"))?;
+    let root = module
+        .filesystem_root
+        .as_deref()
+        .ok_or_else(|| format!("{spec} has no files"))?;
+
+    let mut maps = vec![];
+    for sid in &module.maps {
+        let (map, _) =
+            CelesteModule::load_map_static(root, sid).map_err(|e| format!("{sid}: {e}"))?;
+        maps.push(map);
+    }
+    let detected = detect_dependencies(&modules, *id, &maps);
+    let confirmed_removals = match args.option("remove") {
+        Some(names) => names.split(',').map(str::to_owned).collect::<Vec<_>>(),
+        None => vec![],
+    };
+    if let Some(name) = confirmed_removals
+        .iter()
+        .find(|name| !detected.remove.contains(name))
+    {
+        return Err(format!("{name} is not a suggested removal"));
+    }
+
+    for needed in &detected.add {
+        println!("add {} {}", needed.name, needed.version.to_string());
+        for used in &needed.uses {
+            println!("    for {used}");
+        }
+    }
+    for name in &detected.remove {
+        if confirmed_removals.contains(name) {
+            println!("remove {name}");
+        } else {
+            println!("remove {name} (keeping it unless given to --remove)");
+        }
+    }
+    for used in &detected.unknown {
+        println!("no installed mod provides {used}");
+    }
+    if detected.is_empty() {
+        println!("Dependencies are up to date");
+    } else if args.flag("write") {
+        let root = module
+            .unpacked()
+            .ok_or_else(|| format!("{spec} is not an unpacked mod"))?;
+        let mut metadata = module.everest_metadata.clone();
+        detected.apply(&mut metadata, &confirmed_removals);
+        metadata.save(root);
+        println!("Saved {}", root.join("everest.yaml").display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arborio_maploader::map_struct::{CelesteMapEntity, CelesteMapLevel};
+
+    #[test]
+    fn only_commands_skip_the_editor() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert!(is_command_line(&args(&["stats", "map.bin"])));
+        assert!(is_command_line(&args(&["--help"])));
+        assert!(!is_command_line(&args(&[])));
+        assert!(!is_command_line(&args(&["Maps/map.bin"])));
+        assert!(!is_command_line(&args(&["-psn_0_12345"])));
+    }
+
+    #[test]
+    fn renumber_leaves_a_damaged_map_alone() {
+        let dir = std::env::temp_dir().join(format!("arborio-cli-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("map.xml");
+        let mut map = CelesteMap::default();
+        map.levels.push(CelesteMapLevel {
+            name: "a-00".to_owned(),
+            entities: vec![
+                CelesteMapEntity::new(1, "spring", 8, 8),
+                CelesteMapEntity::new(77, "spring", 16, 8),
+            ],
+            ..CelesteMapLevel::default()
+        });
+        let mut data = vec![];
+        save_to(&map, "map", &mut data).unwrap();
+        let mut text = vec![];
+        xml::put_file(&mut text, &parser::take_file(&data).unwrap()).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        // an entity without an id is skipped when loading
+        let damaged = text.replace(r#" id="77""#, "");
+        assert_ne!(text, damaged);
+        std::fs::write(&path, &damaged).unwrap();
+
+        let args = Args::parse(vec![path.to_string_lossy().into_owned()]).unwrap();
+        let err = renumber(&args).unwrap_err();
+        assert!(err.contains("--force"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), damaged);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,3 +1,4 @@
+mod cli;
 mod logging;
 #[cfg(test)]
 mod tests;
@@ -13,6 +14,11 @@ use arborio_utils::vizia::prelude::*;
 use arborio_widgets::main_widget::main_widget;
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if cli::is_command_line(&args) {
+        std::process::exit(cli::run(args));
+    }
+
     let icon_img = image::load_from_memory(include_bytes!("../icon.png")).unwrap();
     let (width, height) = (icon_img.width(), icon_img.height());
     let app = Application::new(|cx| {
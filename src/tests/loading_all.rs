@@ -1,6 +1,7 @@
 use arborio_maploader::binel::{self, BinEl};
 use arborio_maploader::from_binel::{bin_el_fuzzy_equal, TryFromBinEl};
-use arborio_maploader::map_struct::CelesteMap;
+use arborio_maploader::map_struct::{self, CelesteMap};
+use arborio_maploader::repair::repair_map;
 use arborio_modloader::discovery;
 use arborio_state::data::AppConfig;
 use arborio_walker::{ConfigSourceTrait, FolderSource};
@@ -20,29 +21,30 @@ fn test_saving_all_mods() {
             let mut reader = config.get_file(&path).unwrap();
             let mut file = vec![];
             reader.read_to_end(&mut file).unwrap();
-            let (_, binfile) = binel::parser::take_file(file.as_slice()).unwrap();
+            let binfile = binel::parser::take_file(file.as_slice()).unwrap();
 
             test_saving_one_mod(&binfile.root);
+            test_saving_byte_for_byte(&file);
         }
         discovery::for_each_mod(root, |_, _, name, mut config| {
             for path in config.list_all_files(Path::new("Maps")) {
                 if path.extension() == Some(OsStr::new("bin")) {
-                    let brokens = [
-                        Path::new("Maps/KaydenFox/FactoryMod/1-Factory.bin"),
-                        Path::new("Maps/SpringCollab2020/4-Expert/Mun.bin"),
-                    ];
-                    if brokens.contains(&path.as_path()) {
-                        println!("Skipping {name} {path:?}");
-                        continue;
-                    }
                     println!("testing {name} {path:?}");
 
                     let mut reader = config.get_file(&path).unwrap();
                     let mut file = vec![];
                     reader.read_to_end(&mut file).unwrap();
-                    let (_, binfile) = binel::parser::take_file(file.as_slice()).unwrap();
+                    let mut binfile = binel::parser::take_file(file.as_slice()).unwrap();
+                    let warnings = repair_map(&mut binfile.root);
+                    for warning in &warnings {
+                        println!("    {warning}");
+                    }
 
                     test_saving_one_mod(&binfile.root);
+                    // a repaired map can't be saved the way it was read
+                    if warnings.is_empty() {
+                        test_saving_byte_for_byte(&file);
+                    }
                 }
             }
         });
@@ -56,3 +58,10 @@ fn test_saving_one_mod(bin: &BinEl) {
     let saved = structured.to_binel();
     assert!(bin_el_fuzzy_equal("", bin, &saved));
 }
+
+fn test_saving_byte_for_byte(file: &[u8]) {
+    let map = map_struct::from_reader(file).unwrap();
+    let mut saved = vec![];
+    map_struct::save_to(&map, "", &mut saved).unwrap();
+    assert!(saved == file, "saving an unchanged map changed its bytes");
+}